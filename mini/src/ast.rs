@@ -1,32 +1,383 @@
-/// Abstract syntax tree nodes for the Mini language.
+//! Abstract syntax tree nodes for the Mini language.
+//!
+//! Every expression and statement records the source span it was parsed from,
+//! so later passes can point diagnostics at the exact code involved. The
+//! parser leaves expression types empty; `typeck` fills them in.
+
+use crate::lexer::Span;
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+    /// Value type inferred by `typeck`; stays `None` for calls to functions
+    /// without a return type.
+    pub ty: Option<Type>,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self { Self { kind, span, ty: None } }
+
+    /// The checked value type; only valid after `typeck` accepted the program.
+    pub fn ty(&self) -> Type { self.ty.clone().expect("expression used as a value was not type-checked") }
+
+    /// The direct sub-expressions: an operator's operands, a call's arguments,
+    /// an array's elements or a struct literal's field values.
+    pub fn operands(&self) -> Vec<&Expr> {
+        match &self.kind {
+            ExprKind::UnaryNeg(x) | ExprKind::Not(x) | ExprKind::Cast(x, _) | ExprKind::Field(x, _) => vec![x],
+            ExprKind::Repeat { value: a, count: b } | ExprKind::Index(a, b) => vec![a, b],
+            ExprKind::Add(a, b)
+            | ExprKind::Sub(a, b)
+            | ExprKind::Mul(a, b)
+            | ExprKind::Div(a, b)
+            | ExprKind::Eq(a, b)
+            | ExprKind::Ne(a, b)
+            | ExprKind::Lt(a, b)
+            | ExprKind::Le(a, b)
+            | ExprKind::Gt(a, b)
+            | ExprKind::Ge(a, b)
+            | ExprKind::And(a, b)
+            | ExprKind::Or(a, b) => vec![a, b],
+            ExprKind::Call { args, .. } | ExprKind::Builtin { args, .. } | ExprKind::Array(args) => args.iter().collect(),
+            ExprKind::StructLit { fields, .. } => fields.iter().map(|(_, e)| e).collect(),
+            ExprKind::Interp(parts) => parts
+                .iter()
+                .filter_map(|p| match p {
+                    StrPart::Expr(e) => Some(e),
+                    StrPart::Lit(_) => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// [`Expr::operands`], for changing them.
+    pub fn operands_mut(&mut self) -> Vec<&mut Expr> {
+        match &mut self.kind {
+            ExprKind::UnaryNeg(x) | ExprKind::Not(x) | ExprKind::Cast(x, _) | ExprKind::Field(x, _) => vec![x],
+            ExprKind::Repeat { value: a, count: b } | ExprKind::Index(a, b) => vec![a, b],
+            ExprKind::Add(a, b)
+            | ExprKind::Sub(a, b)
+            | ExprKind::Mul(a, b)
+            | ExprKind::Div(a, b)
+            | ExprKind::Eq(a, b)
+            | ExprKind::Ne(a, b)
+            | ExprKind::Lt(a, b)
+            | ExprKind::Le(a, b)
+            | ExprKind::Gt(a, b)
+            | ExprKind::Ge(a, b)
+            | ExprKind::And(a, b)
+            | ExprKind::Or(a, b) => vec![a, b],
+            ExprKind::Call { args, .. } | ExprKind::Builtin { args, .. } | ExprKind::Array(args) => args.iter_mut().collect(),
+            ExprKind::StructLit { fields, .. } => fields.iter_mut().map(|(_, e)| e).collect(),
+            ExprKind::Interp(parts) => parts
+                .iter_mut()
+                .filter_map(|p| match p {
+                    StrPart::Expr(e) => Some(e),
+                    StrPart::Lit(_) => None,
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Whether `name` is read anywhere in this expression.
+    pub fn reads(&self, name: &str) -> bool {
+        matches!(&self.kind, ExprKind::Var(n) if n == name) || self.operands().into_iter().any(|o| o.reads(name))
+    }
+
+    /// The runtime error for integer overflow in this arithmetic or negation expression.
+    pub fn overflow_message(&self) -> &'static str {
+        match self.kind {
+            ExprKind::Add(..) => "attempt to add with overflow",
+            ExprKind::Sub(..) => "attempt to subtract with overflow",
+            ExprKind::Mul(..) => "attempt to multiply with overflow",
+            ExprKind::Div(..) => "attempt to divide with overflow",
+            _ => "attempt to negate with overflow",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub enum Expr {
-    // integer literals (32-bit for now)
+pub enum ExprKind {
+    // integer literals: `int` is 32-bit, `i64` literals carry a suffix, e.g. `5i64`
     Int(i32),
+    I64(i64),
+    // floating-point literal, e.g. `2.5` or `1e-3`
+    Float(f64),
+    // `true` or `false`
+    Bool(bool),
     // variable reference
     Var(String),
     // unary minus, e.g. `-a`
     UnaryNeg(Box<Expr>),
+    // logical not, e.g. `!done`
+    Not(Box<Expr>),
     // arithmetic binary operators
     Add(Box<Expr>, Box<Expr>),
     Sub(Box<Expr>, Box<Expr>),
     Mul(Box<Expr>, Box<Expr>),
     Div(Box<Expr>, Box<Expr>),
+    // comparisons (operands of the same type; yield bool)
+    Eq(Box<Expr>, Box<Expr>),
+    Ne(Box<Expr>, Box<Expr>),
+    Lt(Box<Expr>, Box<Expr>),
+    Le(Box<Expr>, Box<Expr>),
+    Gt(Box<Expr>, Box<Expr>),
+    Ge(Box<Expr>, Box<Expr>),
+    // short-circuiting logical operators (yield bool)
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    // explicit numeric conversion, e.g. `n as f64`
+    Cast(Box<Expr>, Type),
+    // call of a user-defined function, e.g. `add(a, 2)`
+    Call { name: String, args: Vec<Expr> },
+    // call of a built-in function, e.g. `len(s)`
+    Builtin { func: Builtin, args: Vec<Expr> },
 
-    // strings (literal only for now)
+    // string literal
     Str(String),
+    // interpolated string, e.g. `"x = {x}"`
+    Interp(Vec<StrPart>),
+
+    // array literal, e.g. `[1, 2, 3]` or `[]`
+    Array(Vec<Expr>),
+    // `count` copies of `value`, e.g. `[0; n]`
+    Repeat { value: Box<Expr>, count: Box<Expr> },
+    // bounds-checked element access, e.g. `a[i]`
+    Index(Box<Expr>, Box<Expr>),
+
+    // struct literal, e.g. `Point { x: 1, y: 2 }`; fields may come in any order
+    StructLit { name: Ident, fields: Vec<(Ident, Expr)> },
+    // field read, e.g. `p.x`
+    Field(Box<Expr>, Ident),
 }
 
+/// A piece of an interpolated string: literal text, or an embedded
+/// expression of any type, formatted as `print` would show it.
 #[derive(Debug, Clone)]
-pub enum Stmt {
-    /// `let` declaration with an expression initializer; codegen infers the concrete type.
-    Let { name: String, expr: Expr },
-    /// `print` an identifier (string literals are future work).
-    Print { name: String },
+pub enum StrPart {
+    Lit(String),
+    Expr(Expr),
+}
+
+/// Functions provided by the language rather than declared in the program.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Builtin {
+    /// `len(s)`: the length of a string in bytes, or of an array in elements.
+    Len,
+    /// `push(a, x)`: append `x` to the array variable `a`; has no value.
+    Push,
+    /// `args()`: how many command-line arguments follow the program name.
+    Args,
+    /// `arg(i)`: the `i`th command-line argument, counting from 0.
+    Arg,
+    /// `read_line()`: the next line of standard input with its `\n`, or `""`
+    /// at the end of input.
+    ReadLine,
+    /// `parse_int(s)`: the decimal `int` spelled by `s`, which may have a
+    /// sign and surrounding whitespace.
+    ParseInt,
+}
+
+impl Builtin {
+    pub fn from_name(name: &str) -> Option<Builtin> {
+        match name {
+            "len" => Some(Builtin::Len),
+            "push" => Some(Builtin::Push),
+            "args" => Some(Builtin::Args),
+            "arg" => Some(Builtin::Arg),
+            "read_line" => Some(Builtin::ReadLine),
+            "parse_int" => Some(Builtin::ParseInt),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Builtin::Len => "len",
+            Builtin::Push => "push",
+            Builtin::Args => "args",
+            Builtin::Arg => "arg",
+            Builtin::ReadLine => "read_line",
+            Builtin::ParseInt => "parse_int",
+        }
+    }
+}
+
+/// A name together with where it was written.
+#[derive(Debug, Clone)]
+pub struct Ident {
+    pub name: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    pub span: Span,
+}
+
+impl Stmt {
+    pub fn new(kind: StmtKind, span: Span) -> Self { Self { kind, span } }
 }
 
-/// Top-level container for a parsed Mini program.
 #[derive(Debug, Clone)]
+pub enum StmtKind {
+    /// `let` or `let mut` declaration with an expression initializer; the variable takes the initializer's type.
+    Let { name: Ident, mutable: bool, expr: Expr },
+    /// `name = expr;` to a `let mut` variable. Compound assignment `x += e` is
+    /// parsed as `x = x + e` with `compound` set, so only the formatter tells them apart.
+    Assign { name: Ident, expr: Expr, compound: bool },
+    /// `name[index] = expr;` to an element of a `let mut` array. Compound forms
+    /// desugar like `Assign`, to `a[i] = a[i] op e`; `index` is still evaluated once.
+    IndexAssign { name: Ident, index: Expr, expr: Expr, compound: bool },
+    /// `name.f.g = expr;` to a field, possibly nested, of a `let mut` struct.
+    /// Compound forms desugar like `Assign`, to `p.f = p.f op e`.
+    FieldAssign { name: Ident, path: Vec<Ident>, expr: Expr, compound: bool },
+    /// `print a, b;` writes the values separated by spaces; `println` also
+    /// ends the line, and may have no values at all.
+    Print { args: Vec<Expr>, newline: bool },
+    /// `if cond { ... } else { ... }`; `else if` chains nest in `else_body`.
+    If { cond: Expr, then_body: Vec<Stmt>, else_body: Option<Vec<Stmt>> },
+    /// `while cond { ... }`
+    While { cond: Expr, body: Vec<Stmt> },
+    /// `for var in iter { ... }`; `var` is immutable and scoped to the body.
+    For { var: Ident, iter: ForIter, body: Vec<Stmt> },
+    /// A bare `{ ... }` block introducing a new scope.
+    Block(Vec<Stmt>),
+    /// `return;` or `return expr;` (only valid inside a function body).
+    Return(Option<Expr>),
+    /// An expression evaluated for its side effects, e.g. `log(x);`.
+    Expr(Expr),
+}
+
+/// What a `for` loop runs over.
+#[derive(Debug, Clone)]
+pub enum ForIter {
+    /// `start..end`: integers of one type from `start` up to, but not
+    /// including, `end`, which is evaluated once.
+    Range(Expr, Expr),
+    /// The elements of an array, as many as it holds when the loop starts.
+    Array(Expr),
+}
+
+/// Value types that can be named in function signatures and `as` conversions.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Type {
+    /// 32-bit signed integer.
+    Int,
+    /// 64-bit signed integer.
+    I64,
+    /// 64-bit IEEE float.
+    F64,
+    Bool,
+    Str,
+    /// Growable array of `int`s, written `[int]`. Array values are references:
+    /// a copy shares its elements with the original.
+    Array,
+    /// A declared struct, by name. Struct values are copied on assignment
+    /// and when passed to or returned from functions.
+    Struct(String),
+}
+
+impl Type {
+    /// Whether arithmetic and ordering comparisons apply.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::I64 | Type::F64)
+    }
+
+    /// Look up a type by the name used in source.
+    pub fn from_name(name: &str) -> Option<Type> {
+        Some(match name {
+            "int" => Type::Int,
+            "i64" => Type::I64,
+            "f64" => Type::F64,
+            "bool" => Type::Bool,
+            "string" => Type::Str,
+            _ => return None,
+        })
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Type::Int => "int",
+            Type::I64 => "i64",
+            Type::F64 => "f64",
+            Type::Bool => "bool",
+            Type::Str => "string",
+            Type::Array => "[int]",
+            Type::Struct(name) => name,
+        })
+    }
+}
+
+/// A typed function parameter or struct field, e.g. `a: int`; `span` covers
+/// the whole declaration.
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: Ident,
+    pub ty: Type,
+    pub span: Span,
+}
+
+/// `fn name(params) -> ret { body }`; `ret` is `None` for functions without a value.
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: Ident,
+    pub params: Vec<Param>,
+    pub ret: Option<Type>,
+    pub body: Vec<Stmt>,
+    /// Span of the signature, from `fn` to the return type.
+    pub sig_span: Span,
+    /// Span of the closing `}` of the body.
+    pub end_span: Span,
+    /// Declared `pub`, so files that import this one can call it.
+    pub public: bool,
+}
+
+/// `struct Name { field: type, ... }`.
+#[derive(Debug, Clone)]
+pub struct StructDef {
+    pub name: Ident,
+    pub fields: Vec<Param>,
+    /// Span of the whole declaration.
+    pub span: Span,
+    /// Declared `pub`, so files that import this one can use it.
+    pub public: bool,
+}
+
+impl StructDef {
+    /// The position and type of the field called `name`.
+    pub fn field(&self, name: &str) -> Option<(usize, &Type)> {
+        self.fields.iter().position(|f| f.name.name == name).map(|i| (i, &self.fields[i].ty))
+    }
+}
+
+/// `import "path.mini";`, which makes the `pub` items of that file
+/// available as `name::item`, where `name` is the file's name without `.mini`.
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub path: String,
+    /// Span of the path string.
+    pub path_span: Span,
+    /// Span of the whole statement.
+    pub span: Span,
+}
+
+/// Top-level container for a parsed Mini program.
+///
+/// `structs` and `functions` may appear anywhere in the file; the remaining
+/// top-level statements make up the body of the generated `main`. `imports`
+/// are the file's own; the `modules` loader adds the items of the files
+/// they name to `structs` and `functions`.
+#[derive(Debug, Clone, Default)]
 pub struct Program {
+    pub imports: Vec<Import>,
+    pub structs: Vec<StructDef>,
+    pub functions: Vec<Function>,
     pub stmts: Vec<Stmt>,
 }
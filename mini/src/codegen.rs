@@ -1,23 +1,39 @@
 //! LLVM IR generation for Mini programs using Inkwell.
+//!
+//! Code generation runs on programs accepted by `typeck`: names are known to
+//! resolve and every expression carries its type, so lowering cannot fail.
+//!
+//! Integer arithmetic wraps, and dividing by zero is undefined, unless checks
+//! are enabled with [`Codegen::check_arithmetic`]: then overflow and division
+//! by zero stop the program with a message pointing at the failing expression.
+//! Array indexing is always checked, and stops the program the same way.
+//!
+//! With [`Codegen::emit_debug_info`] the module also carries DWARF for
+//! debuggers; see `debuginfo`.
 
-use anyhow::{anyhow, Result};
 use inkwell::{
+    basic_block::BasicBlock,
     builder::Builder,
     context::Context as LlvmContext,
+    intrinsics::Intrinsic,
     module::Linkage,
-    targets::{CodeModel, FileType, InitializationConfig, RelocMode, TargetMachine, TargetTriple},
-    values::{FunctionValue, IntValue, PointerValue},
-    AddressSpace, OptimizationLevel,
+    targets::{TargetMachine, TargetTriple},
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum},
+    values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue, StructValue},
+    AddressSpace, FloatPredicate, IntPredicate,
 };
 use std::collections::HashMap;
 
-use crate::ast::{Expr, Program, Stmt};
+use crate::ast::{Builtin, Expr, ExprKind, ForIter, Function, Program, Stmt, StmtKind, StrPart, Type};
+use crate::debuginfo::{DebugInfo, Structs};
+use crate::lexer::{SourceMap, Span};
+use crate::runtime::{array_type, Flavor, Piece, Runtime, PARSE_INT_INVALID, PARSE_INT_RANGE};
 
 /// Representation of a Mini variable during codegen.
-#[derive(Clone, Copy)]
-enum Var<'ctx> {
-    Int { alloca: inkwell::values::PointerValue<'ctx> }, // i32*
-    Str { alloca: inkwell::values::PointerValue<'ctx> }, // i8*
+#[derive(Clone)]
+struct Var<'ctx> {
+    ty: Type,
+    alloca: PointerValue<'ctx>, // a slot of `llvm_type(ty)`
 }
 
 /// Generates LLVM IR, keeps track of intrinsics, and records local bindings.
@@ -25,170 +41,958 @@ pub struct Codegen<'ctx> {
     ctx: &'ctx LlvmContext,
     builder: Builder<'ctx>,
     module: inkwell::module::Module<'ctx>,
-    printf: FunctionValue<'ctx>,
-    fmt_int: PointerValue<'ctx>,
-    fmt_str: PointerValue<'ctx>,
+    rt: Runtime<'ctx>,
     vars: HashMap<String, Var<'ctx>>,
+    functions: HashMap<String, FunctionValue<'ctx>>,
+    /// Each declared struct's LLVM type, whose fields are in declaration order.
+    structs: Structs<'ctx>,
+    /// The source that failed runtime checks report positions in.
+    source: Option<SourceMap>,
+    debug: Option<DebugInfo<'ctx>>,
+    /// Whether integer arithmetic is checked.
+    checked: bool,
 }
 
 impl<'ctx> Codegen<'ctx> {
     /// Create a new code generator configured for the supplied target triple.
+    ///
+    /// The triple also picks the runtime `print` lowers to (see `runtime`).
     pub fn new(ctx: &'ctx LlvmContext, triple: &TargetTriple) -> Self {
+        Self::with_flavor(ctx, triple, Flavor::for_triple(triple))
+    }
+
+    /// Like [`Codegen::new`], with an explicit choice of runtime.
+    pub fn with_flavor(ctx: &'ctx LlvmContext, triple: &TargetTriple, flavor: Flavor) -> Self {
         let module = ctx.create_module("mini");
         module.set_triple(triple);
         let builder = ctx.create_builder();
+        let rt = Runtime::emit(ctx, &module, flavor);
+
+        Self { ctx, builder, module, rt, vars: HashMap::new(), functions: HashMap::new(), structs: HashMap::new(), source: None, debug: None, checked: false }
+    }
+
+    /// Name the program's source: failed runtime checks write `error: <what
+    /// happened>` and the position of the failing expression in `file`, whose
+    /// text is `src`, to stderr and exit with status 101. Without a source
+    /// they report no position.
+    pub fn set_source(&mut self, file: &str, src: &str) {
+        self.set_sources(SourceMap::single(file, src));
+    }
 
-        // declare i32 @printf(i8*, ...)
-        let i32_t = ctx.i32_type();
-        let i8ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
-        let printf_ty = i32_t.fn_type(&[i8ptr_t.into()], true);
-        let printf = module.add_function("printf", printf_ty, Some(Linkage::External));
+    /// Like [`Codegen::set_source`], for a program of several files.
+    pub fn set_sources(&mut self, sources: SourceMap) {
+        self.source = Some(sources);
+    }
 
-        // Tiny init fn for global strings; terminate it to keep module valid
-        let void_t = ctx.void_type();
-        let init_fn = module.add_function("__mini_init", void_t.fn_type(&[], false), None);
-        let init_bb = ctx.append_basic_block(init_fn, "entry");
-        builder.position_at_end(init_bb);
-        let fmt_int = builder.build_global_string_ptr("%d\n", ".fmt_int").unwrap().as_pointer_value();
-        let fmt_str = builder.build_global_string_ptr("%s\n", ".fmt_str").unwrap().as_pointer_value();
-        builder.build_return(None).unwrap();
+    /// Describe the program in DWARF: functions, a line table and the stack
+    /// slots of variables, so gdb and lldb can step through it. Needs the
+    /// source from [`Codegen::set_source`], and the module's data layout if
+    /// the target's differs from LLVM's default. `optimized` records whether
+    /// the code will be optimized.
+    pub fn emit_debug_info(&mut self, optimized: bool) {
+        let source = self.source.as_ref().expect("debug info describes the source set with `set_source`");
+        self.debug = Some(DebugInfo::new(self.ctx, &self.module, source, optimized));
+    }
 
-        Self { ctx, builder, module, printf, fmt_int, fmt_str, vars: HashMap::new() }
+    /// Check integer arithmetic in the code emitted from here on: overflowing
+    /// `+ - *`, negation and division, and division by zero, fail like any
+    /// other runtime check.
+    pub fn check_arithmetic(&mut self) {
+        self.checked = true;
     }
 
-    /// Walk the AST, build the `main` function, and populate the module.
-    pub fn emit_program(&mut self, program: &Program) -> Result<()> {
+    /// Walk a type-checked AST, build one LLVM function per Mini function plus
+    /// `main`, and populate the module.
+    pub fn emit_program(&mut self, program: &Program) {
         let i32_t = self.ctx.i32_type();
-        let i8_t = self.ctx.i8_type();
-        let i8ptr_t = i8_t.ptr_type(AddressSpace::default());
 
-        let main_fn = self.module.add_function("main", i32_t.fn_type(&[], false), None);
+        // name every struct before filling in bodies, which may mention structs declared later
+        for s in &program.structs {
+            self.structs.insert(s.name.name.clone(), (self.ctx.opaque_struct_type(&s.name.name), s.clone()));
+        }
+        for s in &program.structs {
+            let fields: Vec<BasicTypeEnum> = s.fields.iter().map(|f| self.llvm_type(f.ty.clone())).collect();
+            self.structs[&s.name.name].0.set_body(&fields, false);
+        }
+
+        // declare every function up front so bodies can call forwards and recurse
+        for f in &program.functions {
+            self.declare_function(f);
+        }
+        for f in &program.functions {
+            self.emit_function(f);
+        }
+
+        // `main` is C's, with the command-line arguments
+        let ptr_t = self.ctx.i8_type().ptr_type(AddressSpace::default());
+        let main_fn = self.module.add_function("main", i32_t.fn_type(&[i32_t.into(), ptr_t.into()], false), None);
         let entry = self.ctx.append_basic_block(main_fn, "entry");
         self.builder.position_at_end(entry);
+        self.vars.clear();
+        // `main` starts where the first statement does
+        let start = program.stmts.first().map_or(Span::new(0, 0), |s| s.span);
+        if let Some(d) = &mut self.debug {
+            d.enter_function(main_fn, start, &[], Some(&Type::Int), &self.structs);
+        }
+        self.set_location(start);
+        let (argc, argv) = (main_fn.get_nth_param(0).unwrap(), main_fn.get_nth_param(1).unwrap());
+        self.builder.build_call(self.rt.set_args, &[argc.into(), argv.into()], "").unwrap();
+
+        self.gen_stmts(&program.stmts);
+        if !self.is_terminated() {
+            self.builder.build_return(Some(&i32_t.const_zero())).unwrap();
+        }
+        self.rt.emit_entry(self.ctx, &self.module, main_fn);
+        if let Some(d) = &self.debug {
+            d.finalize();
+        }
+    }
+
+    /// Add the LLVM declaration for a Mini function.
+    fn declare_function(&mut self, f: &Function) {
+        let params: Vec<BasicMetadataTypeEnum> = f.params.iter().map(|p| self.llvm_type(p.ty.clone()).into()).collect();
+        let fn_ty = match &f.ret {
+            Some(ty) => self.llvm_type(ty.clone()).fn_type(&params, false),
+            None => self.ctx.void_type().fn_type(&params, false),
+        };
+        // internal linkage keeps user names from clashing with libc symbols
+        let value = self.module.add_function(&f.name.name, fn_ty, Some(Linkage::Internal));
+        self.functions.insert(f.name.name.clone(), value);
+    }
+
+    /// Emit the body of a previously declared function.
+    fn emit_function(&mut self, f: &Function) {
+        let value = self.functions[&f.name.name];
+        let entry = self.ctx.append_basic_block(value, "entry");
+        self.builder.position_at_end(entry);
+
+        if let Some(d) = &mut self.debug {
+            let params: Vec<Type> = f.params.iter().map(|p| p.ty.clone()).collect();
+            d.enter_function(value, f.sig_span, &params, f.ret.as_ref(), &self.structs);
+        }
+        self.set_location(f.sig_span);
+
+        // parameters live in stack slots like any other local
+        self.vars.clear();
+        for (i, (param, arg)) in f.params.iter().zip(value.get_param_iter()).enumerate() {
+            let alloca = self.entry_alloca(self.llvm_type(param.ty.clone()), &param.name.name);
+            self.builder.build_store(alloca, arg).unwrap();
+            self.declare_var(alloca, &param.name.name, &param.ty, Some(i as u32 + 1), param.span);
+            self.vars.insert(param.name.name.clone(), Var { ty: param.ty.clone(), alloca });
+        }
+
+        self.gen_stmts(&f.body);
+
+        // falling off the end returns at the closing brace
+        self.set_location(f.end_span);
+        if !self.is_terminated() {
+            match f.ret {
+                None => {
+                    self.builder.build_return(None).unwrap();
+                }
+                // typeck proved every path returns, so a block still open here
+                // (e.g. the join after an if/else that returns on both arms) is dead
+                Some(_) => {
+                    self.builder.build_unreachable().unwrap();
+                }
+            }
+        }
+    }
 
-        for stmt in &program.stmts {
-            match stmt {
-                Stmt::Let { name, expr } => {
-                    match infer_expr_kind(expr) {
-                        ExprKind::Int => {
-                            let v = self.gen_expr_int(expr)?;
-                            let alloca = self.builder.build_alloca(i32_t, name).unwrap();
-                            self.builder.build_store(alloca, v).unwrap();
-                            self.vars.insert(name.clone(), Var::Int { alloca });
-                        }
-                        ExprKind::Str => {
-                            let s = expect_string_literal(expr)?;
-                            let gptr = self.builder
-                                .build_global_string_ptr(&s, &format!(".str{}", self.vars.len()))
-                                .unwrap()
-                                .as_pointer_value();
-                            let alloca = self.builder.build_alloca(i8ptr_t, name).unwrap();
-                            self.builder.build_store(alloca, gptr).unwrap();
-                            self.vars.insert(name.clone(), Var::Str { alloca });
-                        }
+    /// Lower a statement list, stopping at the first statement that ends the block.
+    fn gen_stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            if self.is_terminated() {
+                break; // unreachable code after `return`
+            }
+            self.set_location(stmt.span);
+            self.gen_stmt(stmt);
+        }
+    }
+
+    /// Lower a single statement at the current insertion point.
+    fn gen_stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Let { name, expr, .. } => {
+                let ty = expr.ty();
+                let v = self.gen_value(expr);
+                // every `let` gets its own slot; a shadowed one comes back when `gen_block` restores `vars`
+                let alloca = self.entry_alloca(self.llvm_type(ty.clone()), &name.name);
+                self.builder.build_store(alloca, v).unwrap();
+                self.declare_var(alloca, &name.name, &ty, None, name.span);
+                self.vars.insert(name.name.clone(), Var { ty, alloca });
+            }
+            StmtKind::Assign { name, expr, .. } => {
+                let v = self.gen_value(expr);
+                self.builder.build_store(self.vars[&name.name].alloca, v).unwrap();
+            }
+            StmtKind::IndexAssign { name, index, expr, compound } => {
+                let alloca = self.vars[&name.name].alloca;
+                let a = self.builder.build_load(self.llvm_type(Type::Array), alloca, &name.name).unwrap().into_pointer_value();
+                let i = self.gen_value(index).into_int_value();
+                let v = match (compound, &expr.kind) {
+                    // `a[i] op= e`: the element is read through the index computed above
+                    (true, ExprKind::Add(_, rhs) | ExprKind::Sub(_, rhs) | ExprKind::Mul(_, rhs) | ExprKind::Div(_, rhs)) => {
+                        let slot = self.gen_element(a, i, stmt.span);
+                        let old = self.builder.build_load(self.ctx.i32_type(), slot, "old").unwrap();
+                        let r = self.gen_value(rhs);
+                        self.gen_arith_values(expr, old, r)
                     }
+                    _ => self.gen_value(expr),
+                };
+                // the right-hand side may have pushed to the array and moved its elements
+                let slot = self.gen_element(a, i, stmt.span);
+                self.builder.build_store(slot, v).unwrap();
+            }
+            StmtKind::FieldAssign { name, path, expr, .. } => {
+                // `p.x op= e` reads `p.x` inside `expr`, so the value comes first
+                let v = self.gen_value(expr);
+                let Var { ty, alloca } = self.vars[&name.name].clone();
+                let (mut ty, mut ptr) = (ty, alloca);
+                for field in path {
+                    let Type::Struct(s) = ty else { unreachable!("typeck only allows fields of structs") };
+                    let (st, def) = &self.structs[&*s];
+                    let (i, field_ty) = def.field(&field.name).expect("typeck checked the field");
+                    ptr = self.builder.build_struct_gep(*st, ptr, i as u32, &field.name).unwrap();
+                    ty = field_ty.clone();
                 }
-                Stmt::Print { name } => {
-                    match *self.vars.get(name).ok_or_else(|| anyhow!(format!("undefined variable `{}`", name)))? {
-                        Var::Int { alloca } => {
-                            let v = self.builder.build_load(i32_t, alloca, "ival").unwrap();
-                            self.builder.build_call(self.printf, &[self.fmt_int.into(), v.into()], "").unwrap();
-                        }
-                        Var::Str { alloca } => {
-                            let v = self.builder.build_load(i8ptr_t, alloca, "sval").unwrap();
-                            self.builder.build_call(self.printf, &[self.fmt_str.into(), v.into()], "").unwrap();
-                        }
+                self.builder.build_store(ptr, v).unwrap();
+            }
+            StmtKind::Print { args, newline } => {
+                let mut pieces = Vec::new();
+                for (i, a) in args.iter().enumerate() {
+                    if i > 0 {
+                        pieces.push(Piece::Text(" ".into()));
                     }
+                    self.gen_pieces(a, &mut pieces);
                 }
+                if *newline {
+                    pieces.push(Piece::Text("\n".into()));
+                }
+                self.rt.print(self.ctx, &self.builder, &pieces);
+            }
+            StmtKind::If { cond, then_body, else_body } => {
+                let f = self.current_fn();
+                let c = self.gen_cond(cond);
+                let then_bb = self.ctx.append_basic_block(f, "if.then");
+                let else_bb = self.ctx.append_basic_block(f, "if.else");
+                let end_bb = self.ctx.append_basic_block(f, "if.end");
+                self.builder.build_conditional_branch(c, then_bb, else_bb).unwrap();
+
+                self.builder.position_at_end(then_bb);
+                self.gen_block(then_body);
+                self.branch_if_open(end_bb);
+
+                // an absent `else` still gets a block that falls straight through
+                self.builder.position_at_end(else_bb);
+                if let Some(body) = else_body {
+                    self.gen_block(body);
+                }
+                self.branch_if_open(end_bb);
+
+                self.builder.position_at_end(end_bb);
+            }
+            StmtKind::While { cond, body } => {
+                let f = self.current_fn();
+                let cond_bb = self.ctx.append_basic_block(f, "while.cond");
+                let body_bb = self.ctx.append_basic_block(f, "while.body");
+                let end_bb = self.ctx.append_basic_block(f, "while.end");
+                self.builder.build_unconditional_branch(cond_bb).unwrap();
+
+                // the condition is re-evaluated on every iteration
+                self.builder.position_at_end(cond_bb);
+                let c = self.gen_cond(cond);
+                self.builder.build_conditional_branch(c, body_bb, end_bb).unwrap();
+
+                self.builder.position_at_end(body_bb);
+                self.gen_block(body);
+                self.branch_if_open(cond_bb);
+
+                self.builder.position_at_end(end_bb);
+            }
+            StmtKind::For { var, iter, body } => {
+                let f = self.current_fn();
+                // the end of a range, or the length of an array, is fixed when the loop starts
+                let (ty, array, first, end) = match iter {
+                    ForIter::Range(start, end) => {
+                        let first = self.gen_value(start).into_int_value();
+                        (start.ty(), None, first, self.gen_value(end).into_int_value())
+                    }
+                    ForIter::Array(e) => {
+                        let a = self.gen_value(e).into_pointer_value();
+                        (Type::Int, Some(a), self.ctx.i32_type().const_zero(), self.array_len(a))
+                    }
+                };
+                let counter = self.entry_alloca(end.get_type(), "for.i");
+                self.builder.build_store(counter, first).unwrap();
+                let cond_bb = self.ctx.append_basic_block(f, "for.cond");
+                let body_bb = self.ctx.append_basic_block(f, "for.body");
+                let step_bb = self.ctx.append_basic_block(f, "for.step");
+                let end_bb = self.ctx.append_basic_block(f, "for.end");
+                self.builder.build_unconditional_branch(cond_bb).unwrap();
+
+                self.builder.position_at_end(cond_bb);
+                let i = self.builder.build_load(end.get_type(), counter, "i").unwrap().into_int_value();
+                let more = self.builder.build_int_compare(IntPredicate::SLT, i, end, "more").unwrap();
+                self.builder.build_conditional_branch(more, body_bb, end_bb).unwrap();
+
+                self.builder.position_at_end(body_bb);
+                let saved = self.vars.clone();
+                self.push_scope(stmt.span);
+                let slot = self.entry_alloca(self.llvm_type(ty.clone()), &var.name);
+                let value = match array {
+                    // elements are read from wherever `push` last moved them
+                    Some(a) => {
+                        let data = self.array_data(a);
+                        self.builder.build_load(self.ctx.i32_type(), self.slot(data, i), "elem").unwrap().into_int_value()
+                    }
+                    None => i,
+                };
+                self.builder.build_store(slot, value).unwrap();
+                self.declare_var(slot, &var.name, &ty, None, var.span);
+                self.vars.insert(var.name.clone(), Var { ty, alloca: slot });
+                self.gen_stmts(body);
+                self.vars = saved;
+                self.pop_scope();
+                // stepping belongs to the `for` line
+                self.set_location(stmt.span);
+                self.branch_if_open(step_bb);
+
+                // `i < end` held, so `i + 1` can't overflow
+                self.builder.position_at_end(step_bb);
+                let i = self.builder.build_load(end.get_type(), counter, "i").unwrap().into_int_value();
+                let next = self.builder.build_int_add(i, end.get_type().const_int(1, false), "next").unwrap();
+                self.builder.build_store(counter, next).unwrap();
+                self.builder.build_unconditional_branch(cond_bb).unwrap();
+
+                self.builder.position_at_end(end_bb);
+            }
+            StmtKind::Block(body) => self.gen_block(body),
+            StmtKind::Return(value) => match value {
+                Some(e) => {
+                    let v = self.gen_value(e);
+                    self.builder.build_return(Some(&v)).unwrap();
+                }
+                None => {
+                    self.builder.build_return(None).unwrap();
+                }
+            },
+            StmtKind::Expr(Expr { kind: ExprKind::Call { name, args }, .. }) => {
+                self.gen_call(name, args);
+            }
+            StmtKind::Expr(Expr { kind: ExprKind::Builtin { func: Builtin::Push, args }, .. }) => {
+                let a = self.gen_value(&args[0]);
+                let x = self.gen_value(&args[1]);
+                self.builder.build_call(self.rt.array_push, &[a.into(), x.into()], "").unwrap();
+            }
+            StmtKind::Expr(e) => {
+                self.gen_value(e);
             }
         }
+    }
 
-        self.builder.build_return(Some(&i32_t.const_zero())).unwrap();
-        Ok(())
+    /// Lower a nested block; names first bound inside it are dropped afterwards.
+    fn gen_block(&mut self, stmts: &[Stmt]) {
+        let Some(first) = stmts.first() else { return };
+        let saved = self.vars.clone();
+        self.push_scope(first.span);
+        self.gen_stmts(stmts);
+        self.pop_scope();
+        self.vars = saved;
     }
 
-    /// Generate an integer value for the given expression, enforcing type checks.
-    ///
-    /// Expressions are evaluated eagerly; every branch either returns a concrete
-    /// `i32` value or fails with a semantic error (e.g. using a string in math).
-    fn gen_expr_int(&mut self, expr: &Expr) -> Result<IntValue<'ctx>> {
-        let i32_t = self.ctx.i32_type();
+    /// Attribute the instructions emitted from here on to `span`.
+    fn set_location(&self, span: Span) {
+        if let Some(d) = &self.debug {
+            self.builder.set_current_debug_location(d.location(span));
+        }
+    }
 
-        Ok(match expr {
-            // literal integers map directly to LLVM constants
-            Expr::Int(v) => i32_t.const_int(*v as i64 as u64, true),
-            Expr::Var(name) => {
-                match *self.vars.get(name).ok_or_else(|| anyhow!(format!("undefined variable `{}`", name)))? {
-                    // load previously stored integer variable
-                    Var::Int { alloca } => self.builder.build_load(i32_t, alloca, "loadi").unwrap().into_int_value(),
-                    // prohibit mixing string bindings inside arithmetic expressions
-                    Var::Str { .. } => anyhow::bail!("type error: `{}` is a string, expected integer", name),
-                }
-            }
-            Expr::UnaryNeg(e) => {
-                // recursively evaluate RHS and negate
-                let v = self.gen_expr_int(e)?;
-                self.builder.build_int_neg(v, "neg").unwrap()
-            }
-            Expr::Add(a, b) => {
-                // evaluate operands left-to-right and build the arithmetic instruction
-                let l = self.gen_expr_int(a)?;
-                let r = self.gen_expr_int(b)?;
-                self.builder.build_int_add(l, r, "add").unwrap()
-            }
-            Expr::Sub(a, b) => {
-                let l = self.gen_expr_int(a)?;
-                let r = self.gen_expr_int(b)?;
-                self.builder.build_int_sub(l, r, "sub").unwrap()
-            }
-            Expr::Mul(a, b) => {
-                let l = self.gen_expr_int(a)?;
-                let r = self.gen_expr_int(b)?;
-                self.builder.build_int_mul(l, r, "mul").unwrap()
-            }
-            Expr::Div(a, b) => {
-                let l = self.gen_expr_int(a)?;
-                let r = self.gen_expr_int(b)?;
-                self.builder.build_int_signed_div(l, r, "div").unwrap()
-            }
-            Expr::Str(_) => anyhow::bail!("type error: string literal not allowed in integer expression"),
-        })
-    }
-
-    /// Verify the module and write out an object file using the host target machine.
-    pub fn write_object(&self, triple: &TargetTriple, out_obj: &std::path::Path) -> Result<()> {
-        self.module.verify().map_err(|e| anyhow!(e.to_string()))?;
-        inkwell::targets::Target::initialize_all(&InitializationConfig::default());
-        let target = inkwell::targets::Target::from_triple(triple).map_err(|e| anyhow!(e.to_string()))?;
-        let tm = target
-            .create_target_machine(
-                triple,
-                "generic",
-                "",
-                OptimizationLevel::None,
-                RelocMode::Default,
-                CodeModel::Default,
-            )
-            .ok_or_else(|| anyhow!("create target machine failed"))?;
-        tm.write_to_file(&self.module, FileType::Object, out_obj)
-            .map_err(|e| anyhow!(e.to_string()))
+    /// Open a debug scope for a block starting at `span`.
+    fn push_scope(&mut self, span: Span) {
+        if let Some(d) = &mut self.debug {
+            d.push_block(span);
+        }
     }
-}
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-enum ExprKind { Int, Str }
+    fn pop_scope(&mut self) {
+        if let Some(d) = &mut self.debug {
+            d.pop_block();
+        }
+    }
 
-fn infer_expr_kind(e: &Expr) -> ExprKind {
-    match e {
-        Expr::Str(_) => ExprKind::Str,
-        _ => ExprKind::Int, // everything else is int-typed in this phase
+    /// Tell debuggers that variable `name`, declared at `span`, lives in `slot`;
+    /// `arg` numbers parameters from 1.
+    fn declare_var(&mut self, slot: PointerValue<'ctx>, name: &str, ty: &Type, arg: Option<u32>, span: Span) {
+        if let Some(d) = &mut self.debug {
+            let block = self.builder.get_insert_block().unwrap();
+            d.declare(slot, name, ty, arg, span, block, &self.structs);
+        }
+    }
+
+    /// Whether the current block already ends in a terminator (e.g. `return`).
+    fn is_terminated(&self) -> bool {
+        self.builder.get_insert_block().and_then(|bb| bb.get_terminator()).is_some()
+    }
+
+    /// Fall through to `target` unless the current block already returned.
+    fn branch_if_open(&self, target: BasicBlock<'ctx>) {
+        if !self.is_terminated() {
+            self.builder.build_unconditional_branch(target).unwrap();
+        }
+    }
+
+    /// Lower a call to a user function; `None` for functions without a return type.
+    fn gen_call(&mut self, name: &str, args: &[Expr]) -> Option<BasicValueEnum<'ctx>> {
+        let f = self.functions[name];
+        let vals: Vec<BasicMetadataValueEnum> = args.iter().map(|a| self.gen_value(a).into()).collect();
+        let call = self.builder.build_call(f, &vals, "call").unwrap();
+        call.try_as_basic_value().left()
+    }
+
+    /// Generate the value of `expr`; its LLVM type is `llvm_type(expr.ty())`.
+    fn gen_value(&mut self, expr: &Expr) -> BasicValueEnum<'ctx> {
+        match &expr.kind {
+            // literals map directly to LLVM constants
+            ExprKind::Int(v) => self.ctx.i32_type().const_int(*v as i64 as u64, true).into(),
+            ExprKind::I64(v) => self.ctx.i64_type().const_int(*v as u64, true).into(),
+            ExprKind::Float(v) => self.ctx.f64_type().const_float(*v).into(),
+            ExprKind::Bool(b) => self.ctx.bool_type().const_int(*b as u64, false).into(),
+            ExprKind::Str(s) => self.builder.build_global_string_ptr(s, ".str").unwrap().as_pointer_value().into(),
+            ExprKind::Var(name) => {
+                let var = &self.vars[name];
+                self.builder.build_load(self.llvm_type(var.ty.clone()), var.alloca, name).unwrap()
+            }
+            ExprKind::UnaryNeg(e) => match self.gen_value(e) {
+                BasicValueEnum::FloatValue(v) => self.builder.build_float_neg(v, "fneg").unwrap().into(),
+                // checked negation is a checked `0 - x`
+                v if self.checked => {
+                    let v = v.into_int_value();
+                    self.gen_overflowing("llvm.ssub.with.overflow", expr, v.get_type().const_zero(), v).into()
+                }
+                v => self.builder.build_int_neg(v.into_int_value(), "neg").unwrap().into(),
+            },
+            ExprKind::Not(e) => {
+                let c = self.gen_cond(e);
+                self.builder.build_not(c, "not").unwrap().into()
+            }
+            ExprKind::Add(a, b) | ExprKind::Sub(a, b) | ExprKind::Mul(a, b) | ExprKind::Div(a, b) => {
+                self.gen_arith(expr, a, b)
+            }
+            ExprKind::Eq(a, b) => self.gen_compare(IntPredicate::EQ, FloatPredicate::OEQ, a, b).into(),
+            // `!=` is the one comparison that holds when an operand is NaN
+            ExprKind::Ne(a, b) => self.gen_compare(IntPredicate::NE, FloatPredicate::UNE, a, b).into(),
+            ExprKind::Lt(a, b) => self.gen_compare(IntPredicate::SLT, FloatPredicate::OLT, a, b).into(),
+            ExprKind::Le(a, b) => self.gen_compare(IntPredicate::SLE, FloatPredicate::OLE, a, b).into(),
+            ExprKind::Gt(a, b) => self.gen_compare(IntPredicate::SGT, FloatPredicate::OGT, a, b).into(),
+            ExprKind::Ge(a, b) => self.gen_compare(IntPredicate::SGE, FloatPredicate::OGE, a, b).into(),
+            ExprKind::And(a, b) => self.gen_logical(true, a, b).into(),
+            ExprKind::Or(a, b) => self.gen_logical(false, a, b).into(),
+            ExprKind::Cast(e, to) => self.gen_cast(e, to.clone()),
+            ExprKind::Call { name, args } => self.gen_call(name, args).expect("typeck rejects calls without a value here"),
+            ExprKind::Builtin { func: Builtin::Len, args } => {
+                let v = self.gen_value(&args[0]).into_pointer_value();
+                if args[0].ty() == Type::Array {
+                    return self.array_len(v).into();
+                }
+                self.builder.build_call(self.rt.str_len, &[v.into()], "len").unwrap().try_as_basic_value().left().unwrap()
+            }
+            ExprKind::Builtin { func: Builtin::Push, .. } => unreachable!("typeck rejects `push` as a value"),
+            ExprKind::Builtin { func: Builtin::Args, .. } => self.call_rt(self.rt.args, &[]),
+            ExprKind::Builtin { func: Builtin::Arg, args } => {
+                let i = self.gen_value(&args[0]).into_int_value();
+                let count = self.call_rt(self.rt.args, &[]).into_int_value();
+                // a negative index compares as a huge unsigned one
+                let out = self.builder.build_int_compare(IntPredicate::UGE, i, count, "out.of.bounds").unwrap();
+                self.trap_if(out, expr.span, "argument index out of bounds: the count is {} but the index is {}", &[count, i]);
+                self.call_rt(self.rt.arg, &[i.into()])
+            }
+            ExprKind::Builtin { func: Builtin::ReadLine, .. } => self.call_rt(self.rt.read_line, &[]),
+            ExprKind::Builtin { func: Builtin::ParseInt, args } => {
+                let s = self.gen_value(&args[0]);
+                let out = self.entry_alloca(self.ctx.i32_type(), "parsed");
+                let status = self.call_rt(self.rt.parse_int, &[s.into(), out.into()]).into_int_value();
+                for (code, message) in [(1, PARSE_INT_INVALID), (2, PARSE_INT_RANGE)] {
+                    let failed = self.builder.build_int_compare(IntPredicate::EQ, status, self.ctx.i32_type().const_int(code, false), "failed").unwrap();
+                    self.trap_if(failed, expr.span, message, &[]);
+                }
+                self.builder.build_load(self.ctx.i32_type(), out, "parsed").unwrap()
+            }
+            ExprKind::Interp(parts) => self.gen_interp(parts).into(),
+            ExprKind::Array(items) => {
+                let vals: Vec<_> = items.iter().map(|e| self.gen_value(e).into_int_value()).collect();
+                let n = self.ctx.i32_type().const_int(vals.len() as u64, false);
+                let a = self.gen_array_new(n);
+                let data = self.array_data(a);
+                for (i, v) in vals.into_iter().enumerate() {
+                    let slot = self.slot(data, self.ctx.i32_type().const_int(i as u64, false));
+                    self.builder.build_store(slot, v).unwrap();
+                }
+                a.into()
+            }
+            ExprKind::Repeat { value, count } => {
+                let v = self.gen_value(value).into_int_value();
+                let n = self.gen_value(count).into_int_value();
+                let negative = self.builder.build_int_compare(IntPredicate::SLT, n, n.get_type().const_zero(), "neg.len").unwrap();
+                self.trap_if(negative, expr.span, "array length {} is negative", &[n]);
+                let a = self.gen_array_new(n);
+                let data = self.array_data(a);
+                self.gen_count_loop(n, |cg, i| {
+                    let slot = cg.slot(data, i);
+                    cg.builder.build_store(slot, v).unwrap();
+                });
+                a.into()
+            }
+            ExprKind::Index(base, index) => {
+                let a = self.gen_value(base).into_pointer_value();
+                let i = self.gen_value(index).into_int_value();
+                let slot = self.gen_element(a, i, expr.span);
+                self.builder.build_load(self.ctx.i32_type(), slot, "elem").unwrap()
+            }
+            ExprKind::StructLit { name, fields } => {
+                let (st, def) = self.structs[&name.name].clone();
+                // fields are evaluated in source order and stored in declaration order
+                let mut v = st.get_undef();
+                for (field, e) in fields {
+                    let x = self.gen_value(e);
+                    let (i, _) = def.field(&field.name).expect("typeck checked the field");
+                    v = self.builder.build_insert_value(v, x, i as u32, &field.name).unwrap().into_struct_value();
+                }
+                v.into()
+            }
+            ExprKind::Field(base, field) => {
+                let v = self.gen_value(base).into_struct_value();
+                self.gen_field(v, &base.ty(), &field.name).0
+            }
+        }
+    }
+
+    /// Read field `name` out of `v`, a value of struct type `ty`, along with its type.
+    fn gen_field(&mut self, v: StructValue<'ctx>, ty: &Type, name: &str) -> (BasicValueEnum<'ctx>, Type) {
+        let Type::Struct(s) = ty else { unreachable!("typeck only allows fields of structs") };
+        let (i, field_ty) = self.structs[&**s].1.field(name).expect("typeck checked the field");
+        let field_ty = field_ty.clone();
+        (self.builder.build_extract_value(v, i as u32, name).unwrap(), field_ty)
+    }
+
+    /// A new array of `n` zeros, for `n` known not to be negative.
+    fn gen_array_new(&mut self, n: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let call = self.builder.build_call(self.rt.array_new, &[n.into()], "array").unwrap();
+        call.try_as_basic_value().left().unwrap().into_pointer_value()
     }
-}
 
-fn expect_string_literal(e: &Expr) -> Result<String> {
-    match e {
-        Expr::Str(s) => Ok(s.clone()),
-        _ => anyhow::bail!("expected string literal"),
+    /// The current length of the array `a`.
+    fn array_len(&self, a: PointerValue<'ctx>) -> IntValue<'ctx> {
+        let field = self.builder.build_struct_gep(array_type(self.ctx), a, 0, "len.p").unwrap();
+        self.builder.build_load(self.ctx.i32_type(), field, "len").unwrap().into_int_value()
+    }
+
+    /// Where the elements of the array `a` currently are; `push` may move them.
+    fn array_data(&self, a: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let field = self.builder.build_struct_gep(array_type(self.ctx), a, 2, "data.p").unwrap();
+        let ptr_t = self.ctx.i8_type().ptr_type(AddressSpace::default());
+        self.builder.build_load(ptr_t, field, "data").unwrap().into_pointer_value()
+    }
+
+    /// The address of element `i` in the element block `data`, unchecked.
+    fn slot(&self, data: PointerValue<'ctx>, i: IntValue<'ctx>) -> PointerValue<'ctx> {
+        unsafe { self.builder.build_in_bounds_gep(self.ctx.i32_type(), data, &[i], "slot").unwrap() }
+    }
+
+    /// The address of element `i` of `a`, stopping the program with the
+    /// position of `span` when `i` is out of bounds.
+    fn gen_element(&mut self, a: PointerValue<'ctx>, i: IntValue<'ctx>, span: Span) -> PointerValue<'ctx> {
+        let len = self.array_len(a);
+        // a negative index compares as a huge unsigned one
+        let out = self.builder.build_int_compare(IntPredicate::UGE, i, len, "out.of.bounds").unwrap();
+        self.trap_if(out, span, "index out of bounds: the length is {} but the index is {}", &[len, i]);
+        let data = self.array_data(a);
+        self.slot(data, i)
+    }
+
+    /// Run `body` with `i` counting from 0 up to, but not including, `n`.
+    fn gen_count_loop(&mut self, n: IntValue<'ctx>, body: impl FnOnce(&mut Self, IntValue<'ctx>)) {
+        let f = self.current_fn();
+        let before = self.builder.get_insert_block().unwrap();
+        let cond_bb = self.ctx.append_basic_block(f, "count.cond");
+        let body_bb = self.ctx.append_basic_block(f, "count.body");
+        let end_bb = self.ctx.append_basic_block(f, "count.end");
+        self.builder.build_unconditional_branch(cond_bb).unwrap();
+        self.builder.position_at_end(cond_bb);
+        let i = self.builder.build_phi(n.get_type(), "i").unwrap();
+        let i_v = i.as_basic_value().into_int_value();
+        let more = self.builder.build_int_compare(IntPredicate::SLT, i_v, n, "more").unwrap();
+        self.builder.build_conditional_branch(more, body_bb, end_bb).unwrap();
+        self.builder.position_at_end(body_bb);
+        body(self, i_v);
+        let next = self.builder.build_int_add(i_v, n.get_type().const_int(1, false), "next").unwrap();
+        let body_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(cond_bb).unwrap();
+        i.add_incoming(&[(&n.get_type().const_zero(), before), (&next, body_end)]);
+        self.builder.position_at_end(end_bb);
+    }
+
+    /// Evaluate `e` into the pieces `print` writes for it: literal text stays
+    /// text, and an interpolated string is printed part by part rather than
+    /// built on the heap.
+    fn gen_pieces(&mut self, e: &Expr, pieces: &mut Vec<Piece<'ctx>>) {
+        match &e.kind {
+            ExprKind::Str(s) => pieces.push(Piece::Text(s.clone())),
+            ExprKind::Interp(parts) => {
+                for part in parts {
+                    match part {
+                        StrPart::Lit(s) => pieces.push(Piece::Text(s.clone())),
+                        StrPart::Expr(x) => self.gen_pieces(x, pieces),
+                    }
+                }
+            }
+            _ => {
+                let v = self.gen_value(e);
+                self.value_pieces(v, &e.ty(), pieces);
+            }
+        }
+    }
+
+    /// The pieces printing `v`, a value of type `ty`; a struct is written
+    /// field by field as `P { x: 1, y: 2 }`.
+    fn value_pieces(&mut self, v: BasicValueEnum<'ctx>, ty: &Type, pieces: &mut Vec<Piece<'ctx>>) {
+        let piece = match ty {
+            Type::Int => Piece::Int(v.into_int_value()),
+            Type::I64 => Piece::I64(v.into_int_value()),
+            Type::F64 => Piece::F64(v.into_float_value()),
+            Type::Str => Piece::Str(v.into_pointer_value()),
+            Type::Bool => Piece::Str(self.gen_bool_str(v.into_int_value())),
+            Type::Array => Piece::Array(v.into_pointer_value()),
+            Type::Struct(name) => {
+                let def = self.structs[&**name].1.clone();
+                if def.fields.is_empty() {
+                    return pieces.push(Piece::Text(format!("{name} {{}}")));
+                }
+                for (i, f) in def.fields.iter().enumerate() {
+                    let sep = if i == 0 { format!("{name} {{ ") } else { ", ".to_string() };
+                    pieces.push(Piece::Text(format!("{sep}{}: ", f.name.name)));
+                    let (x, field_ty) = self.gen_field(v.into_struct_value(), ty, &f.name.name);
+                    self.value_pieces(x, &field_ty, pieces);
+                }
+                Piece::Text(" }".to_string())
+            }
+        };
+        pieces.push(piece);
+    }
+
+    /// Build an interpolated string by converting each part to a string and
+    /// concatenating them left to right.
+    fn gen_interp(&mut self, parts: &[StrPart]) -> PointerValue<'ctx> {
+        let mut acc: Option<PointerValue<'ctx>> = None;
+        for part in parts {
+            let s = match part {
+                StrPart::Lit(s) => self.builder.build_global_string_ptr(s, ".str").unwrap().as_pointer_value(),
+                StrPart::Expr(e) => self.gen_to_str(e),
+            };
+            acc = Some(self.concat(acc, s));
+        }
+        acc.unwrap_or_else(|| self.builder.build_global_string_ptr("", ".str").unwrap().as_pointer_value())
+    }
+
+    /// Convert the value of `e` to a string the way `print` writes it.
+    fn gen_to_str(&mut self, e: &Expr) -> PointerValue<'ctx> {
+        let v = self.gen_value(e);
+        let (f, arg) = match e.ty() {
+            Type::Str => return v.into_pointer_value(),
+            Type::Bool => return self.gen_bool_str(v.into_int_value()),
+            Type::Int => {
+                let wide = self.builder.build_int_s_extend(v.into_int_value(), self.ctx.i64_type(), "sext").unwrap();
+                (self.rt.i64_to_str, wide.into())
+            }
+            Type::I64 => (self.rt.i64_to_str, v),
+            Type::F64 => (self.rt.f64_to_str, v),
+            Type::Array | Type::Struct(_) => unreachable!("typeck rejects arrays and structs in strings"),
+        };
+        let call = self.builder.build_call(f, &[arg.into()], "tostr").unwrap();
+        call.try_as_basic_value().left().unwrap().into_pointer_value()
+    }
+
+    /// The value of a call to the runtime routine `f`.
+    fn call_rt(&self, f: FunctionValue<'ctx>, args: &[BasicMetadataValueEnum<'ctx>]) -> BasicValueEnum<'ctx> {
+        self.builder.build_call(f, args, "").unwrap().try_as_basic_value().left().unwrap()
+    }
+
+    /// `acc + s`, or just `s` when there is nothing to append to yet.
+    fn concat(&mut self, acc: Option<PointerValue<'ctx>>, s: PointerValue<'ctx>) -> PointerValue<'ctx> {
+        let Some(acc) = acc else { return s };
+        let call = self.builder.build_call(self.rt.str_concat, &[acc.into(), s.into()], "concat").unwrap();
+        call.try_as_basic_value().left().unwrap().into_pointer_value()
+    }
+
+    /// The constant string `true` or `false` for an `i1`.
+    fn gen_bool_str(&mut self, v: IntValue<'ctx>) -> PointerValue<'ctx> {
+        let t = self.builder.build_global_string_ptr("true", ".true").unwrap().as_pointer_value();
+        let f = self.builder.build_global_string_ptr("false", ".false").unwrap().as_pointer_value();
+        self.builder.build_select(v, t, f, "bstr").unwrap().into_pointer_value()
+    }
+
+    /// LLVM representation of a Mini type.
+    fn llvm_type(&self, ty: Type) -> BasicTypeEnum<'ctx> {
+        match ty {
+            Type::Int => self.ctx.i32_type().into(),
+            Type::I64 => self.ctx.i64_type().into(),
+            Type::F64 => self.ctx.f64_type().into(),
+            Type::Bool => self.ctx.bool_type().into(),
+            // arrays are pointers to their header
+            Type::Str | Type::Array => self.ctx.i8_type().ptr_type(AddressSpace::default()).into(),
+            // structs are held by value, so copying one copies its fields
+            Type::Struct(name) => self.structs[&*name].0.into(),
+        }
+    }
+
+    /// Evaluate a condition to an `i1`: a `bool` as is, an integer compared with zero.
+    fn gen_cond(&mut self, expr: &Expr) -> IntValue<'ctx> {
+        let v = self.gen_value(expr).into_int_value();
+        if expr.ty() == Type::Bool {
+            return v;
+        }
+        let zero = v.get_type().const_zero();
+        self.builder.build_int_compare(IntPredicate::NE, v, zero, "cond").unwrap()
+    }
+
+    /// Lower `&&` / `||` with short-circuit branches joined by a phi node.
+    fn gen_logical(&mut self, is_and: bool, a: &Expr, b: &Expr) -> IntValue<'ctx> {
+        let bool_t = self.ctx.bool_type();
+        let f = self.current_fn();
+
+        let l = self.gen_cond(a);
+        let lhs_bb = self.builder.get_insert_block().unwrap();
+        let rhs_bb = self.ctx.append_basic_block(f, "logic.rhs");
+        let end_bb = self.ctx.append_basic_block(f, "logic.end");
+        // `a && b` skips `b` when `a` is false; `a || b` skips it when `a` is true
+        if is_and {
+            self.builder.build_conditional_branch(l, rhs_bb, end_bb).unwrap();
+        } else {
+            self.builder.build_conditional_branch(l, end_bb, rhs_bb).unwrap();
+        }
+
+        self.builder.position_at_end(rhs_bb);
+        let r = self.gen_cond(b);
+        // `b` may itself have branched, so take the block we actually ended in
+        let rhs_end = self.builder.get_insert_block().unwrap();
+        self.builder.build_unconditional_branch(end_bb).unwrap();
+
+        self.builder.position_at_end(end_bb);
+        let phi = self.builder.build_phi(bool_t, "logic").unwrap();
+        let short = bool_t.const_int(if is_and { 0 } else { 1 }, false);
+        phi.add_incoming(&[(&short, lhs_bb), (&r, rhs_end)]);
+        phi.as_basic_value().into_int_value()
+    }
+
+    /// Build a comparison of two operands of the same type, yielding an `i1`.
+    fn gen_compare(&mut self, int: IntPredicate, float: FloatPredicate, a: &Expr, b: &Expr) -> IntValue<'ctx> {
+        let l = self.gen_value(a);
+        let r = self.gen_value(b);
+        match (l, r) {
+            (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) => {
+                self.builder.build_float_compare(float, l, r, "fcmp").unwrap()
+            }
+            // strings compare by bytes, so compare `str_cmp`'s sign with zero
+            (BasicValueEnum::PointerValue(l), BasicValueEnum::PointerValue(r)) => {
+                let call = self.builder.build_call(self.rt.str_cmp, &[l.into(), r.into()], "strcmp").unwrap();
+                let ord = call.try_as_basic_value().left().unwrap().into_int_value();
+                self.builder.build_int_compare(int, ord, ord.get_type().const_zero(), "cmp").unwrap()
+            }
+            // `bool`s only reach here through `==` and `!=`
+            (l, r) => self.builder.build_int_compare(int, l.into_int_value(), r.into_int_value(), "cmp").unwrap(),
+        }
+    }
+
+    /// Lower `+ - * /` on two operands of the same numeric type, or `+` on two
+    /// strings.
+    fn gen_arith(&mut self, expr: &Expr, a: &Expr, b: &Expr) -> BasicValueEnum<'ctx> {
+        // evaluate operands left-to-right and build the arithmetic instruction
+        let l = self.gen_value(a);
+        let r = self.gen_value(b);
+        self.gen_arith_values(expr, l, r)
+    }
+
+    /// The operator of the arithmetic expression `expr` applied to operand
+    /// values already computed.
+    fn gen_arith_values(&mut self, expr: &Expr, l: BasicValueEnum<'ctx>, r: BasicValueEnum<'ctx>) -> BasicValueEnum<'ctx> {
+        // `+` is the only arithmetic typeck allows on strings
+        if let (BasicValueEnum::PointerValue(l), BasicValueEnum::PointerValue(r)) = (l, r) {
+            return self.concat(Some(l), r).into();
+        }
+        if let (BasicValueEnum::FloatValue(l), BasicValueEnum::FloatValue(r)) = (l, r) {
+            let bld = &self.builder;
+            let v = match expr.kind {
+                ExprKind::Add(..) => bld.build_float_add(l, r, "fadd"),
+                ExprKind::Sub(..) => bld.build_float_sub(l, r, "fsub"),
+                ExprKind::Mul(..) => bld.build_float_mul(l, r, "fmul"),
+                _ => bld.build_float_div(l, r, "fdiv"),
+            };
+            return v.unwrap().into();
+        }
+        let (l, r) = (l.into_int_value(), r.into_int_value());
+        if self.checked {
+            return self.gen_checked_arith(expr, l, r).into();
+        }
+        let bld = &self.builder;
+        let v = match expr.kind {
+            ExprKind::Add(..) => bld.build_int_add(l, r, "add"),
+            ExprKind::Sub(..) => bld.build_int_sub(l, r, "sub"),
+            ExprKind::Mul(..) => bld.build_int_mul(l, r, "mul"),
+            _ => bld.build_int_signed_div(l, r, "div"),
+        };
+        v.unwrap().into()
+    }
+
+    /// Integer `+ - * /` that stops the program instead of overflowing or
+    /// dividing by zero.
+    fn gen_checked_arith(&mut self, expr: &Expr, l: IntValue<'ctx>, r: IntValue<'ctx>) -> IntValue<'ctx> {
+        let name = match expr.kind {
+            ExprKind::Add(..) => "llvm.sadd.with.overflow",
+            ExprKind::Sub(..) => "llvm.ssub.with.overflow",
+            // wasm32 lowers the 64-bit intrinsic to `__multi3`, which no WASI
+            // module links against, so divide the product back instead
+            ExprKind::Mul(..) if self.rt.flavor == Flavor::Wasi && l.get_type().get_bit_width() == 64 => {
+                return self.gen_checked_mul_by_division(expr, l, r);
+            }
+            ExprKind::Mul(..) => "llvm.smul.with.overflow",
+            _ => {
+                let ty = l.get_type();
+                let zero = self.builder.build_int_compare(IntPredicate::EQ, r, ty.const_zero(), "div.zero").unwrap();
+                self.trap_if(zero, expr.span, "attempt to divide by zero", &[]);
+                // the one quotient that doesn't fit: MIN / -1
+                let bld = &self.builder;
+                let min = ty.const_int(1 << (ty.get_bit_width() - 1), false);
+                let l_min = bld.build_int_compare(IntPredicate::EQ, l, min, "").unwrap();
+                let r_neg1 = bld.build_int_compare(IntPredicate::EQ, r, ty.const_all_ones(), "").unwrap();
+                let overflow = bld.build_and(l_min, r_neg1, "div.overflow").unwrap();
+                self.trap_if(overflow, expr.span, expr.overflow_message(), &[]);
+                return self.builder.build_int_signed_div(l, r, "div").unwrap();
+            }
+        };
+        self.gen_overflowing(name, expr, l, r)
+    }
+
+    /// Checked `l * r` without a double-width multiply: the wrapped product
+    /// overflowed unless dividing it by `l` gives back `r`.
+    fn gen_checked_mul_by_division(&mut self, expr: &Expr, l: IntValue<'ctx>, r: IntValue<'ctx>) -> IntValue<'ctx> {
+        let ty = l.get_type();
+        let bld = &self.builder;
+        let eq = |x, y| bld.build_int_compare(IntPredicate::EQ, x, y, "").unwrap();
+        let product = bld.build_int_mul(l, r, "mul").unwrap();
+        // dividing by 0 is undefined and by -1 can itself overflow, so those
+        // factors are handled apart: only -1 * MIN overflows
+        let minus_one = eq(l, ty.const_all_ones());
+        let trivial = bld.build_or(eq(l, ty.const_zero()), minus_one, "").unwrap();
+        let divisor = bld.build_select(trivial, ty.const_int(1, false), l, "divisor").unwrap().into_int_value();
+        let back = bld.build_int_signed_div(product, divisor, "back").unwrap();
+        let lost = bld.build_and(bld.build_not(trivial, "").unwrap(), bld.build_not(eq(back, r), "").unwrap(), "").unwrap();
+        let min = ty.const_int(1 << (ty.get_bit_width() - 1), false);
+        let negated_min = bld.build_and(minus_one, eq(r, min), "").unwrap();
+        let overflow = bld.build_or(lost, negated_min, "mul.overflow").unwrap();
+        self.trap_if(overflow, expr.span, expr.overflow_message(), &[]);
+        product
+    }
+
+    /// Call the `llvm.*.with.overflow` intrinsic `name` on `l` and `r`, trapping
+    /// when it reports overflow, and yield the result.
+    fn gen_overflowing(&mut self, name: &str, expr: &Expr, l: IntValue<'ctx>, r: IntValue<'ctx>) -> IntValue<'ctx> {
+        let intrinsic = Intrinsic::find(name).expect("LLVM provides the overflow intrinsics");
+        let decl = intrinsic.get_declaration(&self.module, &[l.get_type().into()]).unwrap();
+        let pair = self.builder.build_call(decl, &[l.into(), r.into()], "checked").unwrap();
+        let pair = pair.try_as_basic_value().left().unwrap().into_struct_value();
+        let v = self.builder.build_extract_value(pair, 0, "value").unwrap().into_int_value();
+        let overflow = self.builder.build_extract_value(pair, 1, "overflow").unwrap().into_int_value();
+        self.trap_if(overflow, expr.span, expr.overflow_message(), &[]);
+        v
+    }
+
+    /// Stop the program with `error: <message>` and the position of `span`
+    /// when `cond` holds; code after this runs only when it doesn't. Each `{}`
+    /// in `message` is replaced by the next of `values`, in decimal.
+    fn trap_if(&mut self, cond: IntValue<'ctx>, span: Span, message: &str, values: &[IntValue<'ctx>]) {
+        let at = match &self.source {
+            Some(source) => {
+                let (file, line, col) = source.locate(span.start);
+                format!("\n --> {}:{}:{}", file, line, col)
+            }
+            None => String::new(),
+        };
+
+        let f = self.current_fn();
+        let trap_bb = self.ctx.append_basic_block(f, "trap");
+        let ok_bb = self.ctx.append_basic_block(f, "ok");
+        self.builder.build_conditional_branch(cond, trap_bb, ok_bb).unwrap();
+        self.builder.position_at_end(trap_bb);
+        let (msg, len) = if values.is_empty() {
+            let text = format!("error: {}{}\n", message, at);
+            let msg = self.builder.build_global_string_ptr(&text, ".trap").unwrap().as_pointer_value();
+            (msg, self.ctx.i32_type().const_int(text.len() as u64, false))
+        } else {
+            // the message is built on the heap, like an interpolated string
+            let mut acc = None;
+            let head = format!("error: {}", message);
+            let tail = format!("{}\n", at);
+            for (i, part) in head.split("{}").chain([tail.as_str()]).enumerate() {
+                if i > 0 && i <= values.len() {
+                    let wide = self.builder.build_int_s_extend(values[i - 1], self.ctx.i64_type(), "sext").unwrap();
+                    let call = self.builder.build_call(self.rt.i64_to_str, &[wide.into()], "tostr").unwrap();
+                    acc = Some(self.concat(acc, call.try_as_basic_value().left().unwrap().into_pointer_value()));
+                }
+                let s = self.builder.build_global_string_ptr(part, ".trap").unwrap().as_pointer_value();
+                acc = Some(self.concat(acc, s));
+            }
+            let msg = acc.unwrap();
+            let len = self.builder.build_call(self.rt.str_len, &[msg.into()], "len").unwrap();
+            (msg, len.try_as_basic_value().left().unwrap().into_int_value())
+        };
+        self.builder.build_call(self.rt.fail, &[msg.into(), len.into()], "").unwrap();
+        self.builder.build_unreachable().unwrap();
+        self.builder.position_at_end(ok_bb);
+    }
+
+    /// Lower `e as to` with Rust's `as` semantics.
+    ///
+    /// Integers sign-extend or truncate, `bool` zero-extends, and floats
+    /// convert to integers with `llvm.fptosi.sat`: out-of-range values
+    /// saturate and NaN becomes 0, rather than being undefined.
+    fn gen_cast(&mut self, e: &Expr, to: Type) -> BasicValueEnum<'ctx> {
+        let v = self.gen_value(e);
+        let from = e.ty();
+        let target = self.llvm_type(to.clone());
+        let bld = &self.builder;
+        match (&from, &to) {
+            _ if from == to => v,
+            (Type::F64, _) => {
+                let sat = Intrinsic::find("llvm.fptosi.sat").expect("LLVM provides llvm.fptosi.sat");
+                let decl = sat.get_declaration(&self.module, &[target, v.get_type()]).unwrap();
+                bld.build_call(decl, &[v.into()], "fptosi").unwrap().try_as_basic_value().left().unwrap()
+            }
+            (Type::Bool, Type::F64) => bld.build_unsigned_int_to_float(v.into_int_value(), target.into_float_type(), "uitofp").unwrap().into(),
+            (_, Type::F64) => bld.build_signed_int_to_float(v.into_int_value(), target.into_float_type(), "sitofp").unwrap().into(),
+            (Type::Bool, _) => bld.build_int_z_extend(v.into_int_value(), target.into_int_type(), "zext").unwrap().into(),
+            _ => bld.build_int_cast_sign_flag(v.into_int_value(), target.into_int_type(), true, "icast").unwrap().into(),
+        }
+    }
+
+    /// The function currently being emitted.
+    fn current_fn(&self) -> FunctionValue<'ctx> {
+        self.builder.get_insert_block().and_then(|bb| bb.get_parent()).unwrap()
+    }
+
+    /// Create a stack slot in the entry block so loops don't grow the stack.
+    fn entry_alloca<T: BasicType<'ctx>>(&self, ty: T, name: &str) -> PointerValue<'ctx> {
+        let entry = self.current_fn().get_first_basic_block().unwrap();
+        let b = self.ctx.create_builder();
+        match entry.get_first_instruction() {
+            Some(inst) => b.position_before(&inst),
+            None => b.position_at_end(entry),
+        }
+        b.build_alloca(ty, name).unwrap()
+    }
+
+    /// The LLVM module built so far.
+    pub fn module(&self) -> &inkwell::module::Module<'ctx> {
+        &self.module
     }
 }
 
@@ -196,3 +1000,154 @@ fn expect_string_literal(e: &Expr) -> Result<String> {
 pub fn host_triple() -> TargetTriple {
     TargetMachine::get_default_triple()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::runtime::Arch;
+    use crate::{parser::Parser, typeck};
+
+    #[test]
+    fn every_example_lowers_to_valid_ir() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let src = std::fs::read_to_string(&path).unwrap();
+            let mut program = Parser::parse(&src).unwrap();
+            typeck::check(&mut program).unwrap();
+
+            // against every runtime, with and without arithmetic checks
+            for (triple, flavor) in [
+                (host_triple(), Flavor::for_triple(&host_triple())),
+                (TargetTriple::create("wasm32-wasi"), Flavor::Wasi),
+                (TargetTriple::create("x86_64-unknown-linux-gnu"), Flavor::Freestanding(Arch::X86_64)),
+            ] {
+                for checked in [false, true] {
+                    let ctx = LlvmContext::create();
+                    let mut cg = Codegen::with_flavor(&ctx, &triple, flavor);
+                    cg.set_source("example.mini", &src);
+                    if checked {
+                        cg.check_arithmetic();
+                    }
+                    cg.emit_program(&program);
+                    if let Err(e) = cg.module.verify() {
+                        panic!("{} for {} (checked: {}): {}", path.display(), triple, checked, e);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn checks_trap_only_in_checked_code() {
+        let mut program = Parser::parse("let a = 2;\nlet b = a * 3 + -a;\nlet c = b / a;\nlet d = 1.5 * 2.0;\n").unwrap();
+        typeck::check(&mut program).unwrap();
+        let ctx = LlvmContext::create();
+        let mut cg = Codegen::new(&ctx, &host_triple());
+        cg.emit_program(&program);
+        let ir = cg.module.print_to_string().to_string();
+        // the runtime's string allocator always has its out-of-memory exit
+        let fails = |ir: &str| ir.matches("call void @__mini_fail").count();
+        assert!(!ir.contains("with.overflow") && fails(&ir) == 1);
+
+        let ctx = LlvmContext::create();
+        let mut cg = Codegen::new(&ctx, &host_triple());
+        cg.check_arithmetic();
+        cg.emit_program(&program);
+        let ir = cg.module.print_to_string().to_string();
+        for op in ["sadd", "ssub", "smul"] {
+            assert!(ir.contains(&format!("@llvm.{}.with.overflow.i32", op)), "{}", ir);
+        }
+        // two for `/` (zero and MIN / -1), one for each of `*`, `+` and unary `-`
+        assert_eq!(fails(&ir), 1 + 5);
+    }
+
+    #[test]
+    fn indexing_is_bounds_checked_even_in_unchecked_code() {
+        let src = "let mut a = [1, 2];\nlet i = 1;\na[i] = a[0];\nlet r = [0; i];\n";
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        let ctx = LlvmContext::create();
+        let mut cg = Codegen::new(&ctx, &host_triple());
+        cg.set_source("bounds.mini", src);
+        cg.emit_program(&program);
+        let ir = cg.module.print_to_string().to_string();
+        // one unsigned compare per index covers negative indexes too
+        assert_eq!(ir.matches("icmp uge i32").count(), 2, "{}", ir);
+        assert!(ir.contains("index out of bounds: the length is ") && ir.contains("bounds.mini:3:1"), "{}", ir);
+        assert!(ir.contains("is negative") && ir.contains("bounds.mini:4:9"), "{}", ir);
+    }
+
+    #[test]
+    fn each_print_gets_its_own_format_string() {
+        let mut program = Parser::parse("let n = 3;\nlet f = 0.5;\nprint \"n:\", n;\nprintln \" 100%\", f, n > 1, \"{n}i\";\nprintln;\n").unwrap();
+        typeck::check(&mut program).unwrap();
+        let ctx = LlvmContext::create();
+        let mut cg = Codegen::with_flavor(&ctx, &host_triple(), Flavor::Libc);
+        cg.emit_program(&program);
+        let ir = cg.module.print_to_string().to_string();
+        for fmt in [r#"c"n: %d\00""#, r#"c" 100%% %g %s %di\0A\00""#, r#"c"\0A\00""#] {
+            assert!(ir.contains(fmt), "{} in {}", fmt, ir);
+        }
+        // the runtime's own functions print too; count only the program's calls
+        let main = &ir[ir.find("define i32 @main").unwrap()..];
+        let main = &main[..main.find("\n}\n").unwrap()];
+        assert_eq!(main.matches("call i32 (ptr, ...) @printf").count(), 3, "{}", ir);
+    }
+
+    #[test]
+    fn structs_are_named_types_held_by_value() {
+        let src = "struct P { x: int, y: f64 }\nfn f(p: P) -> P { return p; }\nlet mut p = P { y: 0.5, x: 1 };\np.x += 2;\nprintln f(p);\n";
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        let ctx = LlvmContext::create();
+        let mut cg = Codegen::with_flavor(&ctx, &host_triple(), Flavor::Libc);
+        cg.emit_program(&program);
+        let ir = cg.module.print_to_string().to_string();
+        assert!(ir.contains("%P = type { i32, double }"), "{}", ir);
+        assert!(ir.contains("define internal %P @f(%P "), "{}", ir);
+        assert!(ir.contains("getelementptr inbounds %P, ptr %p, i32 0, i32 0"), "{}", ir);
+        // printing a struct is a single format string with a conversion per field
+        assert!(ir.contains(r#"c"P { x: %d, y: %g }\0A\00""#), "{}", ir);
+    }
+
+    #[test]
+    fn debug_info_describes_functions_lines_and_variables() {
+        let src = "struct P { x: int }\nfn f(n: int) -> int {\n    let p = P { x: n };\n    return p.x;\n}\nlet a = f(2);\nif a > 1 {\n    let b = a;\n    println b;\n}\n";
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        let ctx = LlvmContext::create();
+        let mut cg = Codegen::new(&ctx, &host_triple());
+        cg.set_source("dbg/prog.mini", src);
+        cg.emit_debug_info(false);
+        cg.emit_program(&program);
+        cg.module.verify().unwrap();
+        let ir = cg.module.print_to_string().to_string();
+        assert!(ir.contains("!DIFile(filename: \"prog.mini\""), "{}", ir);
+        assert!(ir.contains("!DISubprogram(name: \"f\"") && ir.contains("!DISubprogram(name: \"main\""), "{}", ir);
+        assert!(ir.contains("!DILocalVariable(name: \"n\", arg: 1"), "{}", ir);
+        assert!(ir.contains("!DICompositeType(tag: DW_TAG_structure_type, name: \"P\""), "{}", ir);
+        // `b` lives in the `if` body's block, which starts at its first statement
+        assert!(ir.contains("!DILexicalBlock(") && ir.contains("!DILocalVariable(name: \"b\""), "{}", ir);
+        assert!(ir.contains("!DILocation(line: 9, column: 5"), "{}", ir);
+        assert_eq!(ir.matches("call void @llvm.dbg.declare").count(), 4, "{}", ir);
+    }
+
+    #[test]
+    fn wasi_modules_start_at_start_and_import_only_wasi() {
+        let ctx = LlvmContext::create();
+        let mut cg = Codegen::new(&ctx, &TargetTriple::create("wasm32-wasi"));
+        let mut program = Parser::parse("let x = 1;\nprintln x;\n").unwrap();
+        typeck::check(&mut program).unwrap();
+        cg.emit_program(&program);
+        assert!(cg.module.get_function("_start").is_some());
+        assert!(cg.module.get_function("printf").is_none());
+        let imports: Vec<String> = cg
+            .module
+            .get_functions()
+            .filter(|f| f.count_basic_blocks() == 0)
+            .map(|f| f.get_name().to_string_lossy().into_owned())
+            .collect();
+        assert_eq!(imports, ["fd_write", "fd_read", "proc_exit", "args_sizes_get", "args_get"]);
+    }
+}
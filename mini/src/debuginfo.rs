@@ -0,0 +1,241 @@
+//! DWARF debug information for `mini build -g`.
+//!
+//! [`DebugInfo`] describes a program to gdb and lldb: a compile unit named
+//! after the main source file, with a file for each file it imports, a
+//! subprogram for each Mini function and `main`, a lexical block for each
+//! nested block, and a variable for every stack slot a `let`, parameter or
+//! loop variable lives in. Codegen tags the instructions of each
+//! statement with its position, which becomes the line table.
+
+use inkwell::{
+    basic_block::BasicBlock,
+    context::Context as LlvmContext,
+    debug_info::{
+        AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants, DILocation, DIScope, DIType, DWARFEmissionKind,
+        DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Module},
+    targets::TargetData,
+    types::StructType,
+    values::{FunctionValue, PointerValue},
+    AddressSpace,
+};
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::ast::{StructDef, Type};
+use crate::lexer::{SourceMap, Span};
+use crate::runtime::array_type;
+
+// `DW_ATE_*` encodings of the basic types
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_SIGNED_CHAR: u32 = 0x06;
+
+/// Debug metadata for one module, built alongside its code.
+pub struct DebugInfo<'ctx> {
+    ctx: &'ctx LlvmContext,
+    builder: DebugInfoBuilder<'ctx>,
+    unit: DICompileUnit<'ctx>,
+    /// A file for each file of the program, by the offset its text starts at.
+    files: Vec<(usize, DIFile<'ctx>)>,
+    /// Sizes and field offsets, from the module's data layout.
+    layout: TargetData,
+    sources: SourceMap,
+    optimized: bool,
+    /// The function being emitted, then the blocks nested in it; innermost last.
+    scopes: Vec<DIScope<'ctx>>,
+    /// Debug types by Mini type name.
+    types: HashMap<String, DIType<'ctx>>,
+}
+
+impl<'ctx> DebugInfo<'ctx> {
+    /// Start describing `module`, compiled from `sources`, whose first file
+    /// names the compile unit. The module's data layout must already be
+    /// set; `optimized` tells debuggers that variables may live elsewhere
+    /// than their slots.
+    pub fn new(ctx: &'ctx LlvmContext, module: &Module<'ctx>, sources: &SourceMap, optimized: bool) -> Self {
+        let i32_t = ctx.i32_type();
+        // without these flags LLVM drops the metadata
+        module.add_basic_value_flag("Debug Info Version", FlagBehavior::Warning, i32_t.const_int(3, false));
+        module.add_basic_value_flag("Dwarf Version", FlagBehavior::Warning, i32_t.const_int(4, false));
+
+        let (name, dir) = name_and_dir(sources.file(0).0);
+        let producer = concat!("mini ", env!("CARGO_PKG_VERSION"));
+        // Mini's types are C's, so debuggers read its variables as C
+        let (builder, unit) = module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &name,
+            &dir,
+            producer,
+            optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        let layout = TargetData::create(&module.get_data_layout().as_str().to_string_lossy());
+        let files = sources
+            .files()
+            .map(|(path, _, base)| {
+                let (name, dir) = name_and_dir(path);
+                (base, builder.create_file(&name, &dir))
+            })
+            .collect();
+        Self { ctx, builder, unit, files, layout, sources: sources.clone(), optimized, scopes: vec![], types: HashMap::new() }
+    }
+
+    /// Describe `f`, declared at `span`, and make it the current scope.
+    pub fn enter_function(&mut self, f: FunctionValue<'ctx>, span: Span, params: &[Type], ret: Option<&Type>, structs: &Structs<'ctx>) {
+        let params: Vec<DIType> = params.iter().map(|t| self.di_type(t, structs)).collect();
+        let ret = ret.map(|t| self.di_type(t, structs));
+        let file = self.file(span);
+        let ty = self.builder.create_subroutine_type(file, ret, &params, DIFlags::ZERO);
+        let line = self.line_col(span).0;
+        let name = f.get_name().to_string_lossy();
+        let local = name != "main";
+        let sp = self.builder.create_function(self.unit.as_debug_info_scope(), &name, None, file, line, ty, local, true, line, DIFlags::ZERO, self.optimized);
+        f.set_subprogram(sp);
+        self.scopes = vec![sp.as_debug_info_scope()];
+    }
+
+    /// Open a lexical block starting at `span` inside the current scope.
+    pub fn push_block(&mut self, span: Span) {
+        let (line, col) = self.line_col(span);
+        let block = self.builder.create_lexical_block(self.scope(), self.file(span), line, col);
+        self.scopes.push(block.as_debug_info_scope());
+    }
+
+    /// Close the block opened by the matching [`DebugInfo::push_block`].
+    pub fn pop_block(&mut self) {
+        self.scopes.pop();
+    }
+
+    /// The position of `span` in the current scope.
+    pub fn location(&self, span: Span) -> DILocation<'ctx> {
+        let (line, col) = self.line_col(span);
+        self.builder.create_debug_location(self.ctx, line, col, self.scope(), None)
+    }
+
+    /// Describe the variable `name` of type `ty`, declared at `span` and kept
+    /// in `slot`; `arg` is the 1-based position of a parameter. The
+    /// declaration goes at the end of `block`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn declare(
+        &mut self,
+        slot: PointerValue<'ctx>,
+        name: &str,
+        ty: &Type,
+        arg: Option<u32>,
+        span: Span,
+        block: BasicBlock<'ctx>,
+        structs: &Structs<'ctx>,
+    ) {
+        let ty = self.di_type(ty, structs);
+        let line = self.line_col(span).0;
+        let file = self.file(span);
+        let var = match arg {
+            Some(n) => self.builder.create_parameter_variable(self.scope(), name, n, file, line, ty, true, DIFlags::ZERO),
+            None => self.builder.create_auto_variable(self.scope(), name, file, line, ty, true, DIFlags::ZERO, 0),
+        };
+        self.builder.insert_declare_at_end(slot, Some(var), None, self.location(span), block);
+    }
+
+    /// Resolve the metadata built so far; call once the module is complete.
+    pub fn finalize(&self) {
+        self.builder.finalize();
+    }
+
+    fn scope(&self) -> DIScope<'ctx> {
+        *self.scopes.last().expect("debug locations are only made inside functions")
+    }
+
+    fn line_col(&self, span: Span) -> (u32, u32) {
+        let (_, line, col) = self.sources.locate(span.start);
+        (line as u32, col as u32)
+    }
+
+    /// The file `span` is in.
+    fn file(&self, span: Span) -> DIFile<'ctx> {
+        let base = self.sources.file(span.start).2;
+        self.files.iter().find(|(b, _)| *b == base).expect("every file of the program has a `DIFile`").1
+    }
+
+    /// The debug type of a Mini type: strings are `char *`, arrays point to
+    /// the runtime's header, and structs list their fields at the offsets the
+    /// data layout gives them.
+    fn di_type(&mut self, ty: &Type, structs: &Structs<'ctx>) -> DIType<'ctx> {
+        if let Some(t) = self.types.get(&ty.to_string()) {
+            return *t;
+        }
+        let ptr_bits = self.layout.get_pointer_byte_size(None) as u64 * 8;
+        let t = match ty {
+            Type::Int => self.basic("int", 32, DW_ATE_SIGNED),
+            Type::I64 => self.basic("i64", 64, DW_ATE_SIGNED),
+            Type::F64 => self.basic("f64", 64, DW_ATE_FLOAT),
+            Type::Bool => self.basic("bool", 8, DW_ATE_BOOLEAN),
+            Type::Str => {
+                let char_t = self.basic("char", 8, DW_ATE_SIGNED_CHAR);
+                self.builder.create_pointer_type("string", char_t, ptr_bits, 0, AddressSpace::default()).as_type()
+            }
+            Type::Array => {
+                let int = self.di_type(&Type::Int, structs);
+                let data = self.builder.create_pointer_type("", int, ptr_bits, 0, AddressSpace::default()).as_type();
+                let unit_file = self.files[0].1;
+                let header = self.composite("array", array_type(self.ctx), &[("len", int), ("cap", int), ("data", data)], unit_file, 0);
+                self.builder.create_pointer_type("[int]", header, ptr_bits, 0, AddressSpace::default()).as_type()
+            }
+            Type::Struct(name) => {
+                let (st, def) = &structs[name];
+                let fields: Vec<(&str, DIType)> = def.fields.iter().map(|f| (f.name.name.as_str(), self.di_type(&f.ty, structs))).collect();
+                let line = self.line_col(def.span).0;
+                self.composite(name, *st, &fields, self.file(def.span), line)
+            }
+        };
+        self.types.insert(ty.to_string(), t);
+        t
+    }
+
+    fn basic(&self, name: &str, bits: u64, encoding: u32) -> DIType<'ctx> {
+        self.builder.create_basic_type(name, bits, encoding, DIFlags::ZERO).expect("basic types have names").as_type()
+    }
+
+    /// A struct type named `name` laid out like `st`, with `fields` in
+    /// order, declared at `line` of `file`.
+    fn composite(&self, name: &str, st: StructType<'ctx>, fields: &[(&str, DIType<'ctx>)], file: DIFile<'ctx>, line: u32) -> DIType<'ctx> {
+        let scope = file.as_debug_info_scope();
+        let members: Vec<DIType> = fields
+            .iter()
+            .enumerate()
+            .map(|(i, (field, ty))| {
+                let llvm_t = st.get_field_type_at_index(i as u32).unwrap();
+                let bits = self.layout.get_bit_size(&llvm_t);
+                let align = self.layout.get_abi_alignment(&llvm_t) * 8;
+                let offset = self.layout.offset_of_element(&st, i as u32).unwrap() * 8;
+                self.builder.create_member_type(scope, field, file, line, bits, align, offset, DIFlags::ZERO, *ty).as_type()
+            })
+            .collect();
+        let bits = self.layout.get_bit_size(&st);
+        let align = self.layout.get_abi_alignment(&st) * 8;
+        self.builder.create_struct_type(scope, name, file, line, bits, align, DIFlags::ZERO, None, &members, 0, None, name).as_type()
+    }
+}
+
+/// The file name and directory of `path`. Debuggers look for sources
+/// relative to the directory, so it is absolute.
+fn name_and_dir(path: &str) -> (String, String) {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.into());
+    let name = path.file_name().map_or(String::new(), |n| n.to_string_lossy().into_owned());
+    let dir = path.parent().unwrap_or(Path::new(".")).to_string_lossy().into_owned();
+    (name, dir)
+}
+
+/// Each declared struct's LLVM type and declaration, as codegen keeps them.
+pub type Structs<'ctx> = HashMap<String, (StructType<'ctx>, StructDef)>;
@@ -0,0 +1,204 @@
+//! Compiler diagnostics: errors and warnings tied to source spans, rendered rustc-style.
+
+use std::fmt;
+
+use crate::lexer::{line_col, SourceMap, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        })
+    }
+}
+
+/// A message attached to a source range. The primary label marks where the
+/// problem is (`^^^`); secondary labels add context such as "declared here" (`---`).
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Error, message: message.into(), labels: Vec::new(), notes: Vec::new() }
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self { severity: Severity::Warning, ..Self::error(message) }
+    }
+
+    /// Attach the label pointing at the offending code.
+    pub fn primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: true });
+        self
+    }
+
+    /// Attach a label pointing at related code.
+    pub fn secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label { span, message: message.into(), primary: false });
+        self
+    }
+
+    pub fn note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    /// Span of the primary label, if any.
+    pub fn span(&self) -> Option<Span> {
+        self.labels.iter().find(|l| l.primary).map(|l| l.span)
+    }
+
+    /// Render in the familiar rustc layout:
+    ///
+    /// ```text
+    /// error: undefined variable `y`
+    ///  --> prog.mini:3:9
+    ///   |
+    /// 3 | let x = y + 1;
+    ///   |         ^ not found in this scope
+    ///   |
+    /// ```
+    pub fn render(&self, src: &str, path: &str) -> String {
+        self.render_in(&SourceMap::single(path, src))
+    }
+
+    /// Render like [`Diagnostic::render`] against a program of several
+    /// files. Labels in another file than the primary one follow under a
+    /// `:::` header naming it.
+    pub fn render_in(&self, sources: &SourceMap) -> String {
+        let mut out = format!("{}: {}\n", self.severity, self.message);
+
+        let mut labels = self.labels.clone();
+        labels.sort_by_key(|l| (l.span.start, !l.primary));
+        let gutter = labels.iter().map(|l| sources.locate(l.span.start).1).max().unwrap_or(1).to_string().len();
+        let pad = " ".repeat(gutter);
+
+        if let Some(anchor) = self.labels.iter().find(|l| l.primary).or(labels.first()) {
+            let (path, line, col) = sources.locate(anchor.span.start);
+            out += &format!("{}--> {}:{}:{}\n", pad, path, line, col);
+            out += &format!("{} |\n", pad);
+
+            // the anchor's file first, then the others in order
+            let home = sources.file(anchor.span.start).2;
+            labels.sort_by_key(|l| {
+                let base = sources.file(l.span.start).2;
+                (base != home, base, l.span.start, !l.primary)
+            });
+            let mut last_file = home;
+            let mut last_line = None;
+            for label in &labels {
+                let (path, src, base) = sources.file(label.span.start);
+                let start = label.span.start - base;
+                let (line, col) = line_col(src, start);
+                if base != last_file {
+                    out += &format!("{} |\n{}::: {}:{}:{}\n{} |\n", pad, pad, path, line, col, pad);
+                    last_file = base;
+                    last_line = None;
+                }
+                let text = src.lines().nth(line - 1).unwrap_or("");
+                if last_line != Some(line) {
+                    if matches!(last_line, Some(prev) if line > prev + 1) {
+                        out += "...\n";
+                    }
+                    out += &format!("{:>w$} | {}\n", line, text, w = gutter);
+                    last_line = Some(line);
+                }
+                // underline the part of the span that sits on this line (at least one column)
+                let end = (label.span.end - base).clamp(start, src.len());
+                let len = src[start.min(end)..end].chars().take_while(|&c| c != '\n').count().max(1);
+                let mark = if label.primary { "^" } else { "-" };
+                let underline = format!("{} | {}{} {}", pad, " ".repeat(col - 1), mark.repeat(len), label.message);
+                out += underline.trim_end();
+                out += "\n";
+            }
+            out += &format!("{} |\n", pad);
+        }
+        for note in &self.notes {
+            out += &format!("{} = note: {}\n", pad, note);
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// An ordered collection of diagnostics from one compiler run.
+#[derive(Debug, Clone, Default, thiserror::Error)]
+pub struct Diagnostics(pub Vec<Diagnostic>);
+
+impl Diagnostics {
+    pub fn push(&mut self, d: Diagnostic) {
+        self.0.push(d);
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.0.extend(other.0);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.0.iter()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.0.iter().filter(|d| d.severity == Severity::Error).count()
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.error_count() > 0
+    }
+
+    /// Render every diagnostic, followed by a summary line when there were errors.
+    pub fn render(&self, src: &str, path: &str) -> String {
+        self.render_in(&SourceMap::single(path, src))
+    }
+
+    /// Render every diagnostic against a program of several files.
+    pub fn render_in(&self, sources: &SourceMap) -> String {
+        let mut out: String = self.0.iter().map(|d| d.render_in(sources) + "\n").collect();
+        match self.error_count() {
+            0 => {}
+            1 => out += "error: aborting due to 1 previous error\n",
+            n => out += &format!("error: aborting due to {} previous errors\n", n),
+        }
+        out
+    }
+}
+
+impl fmt::Display for Diagnostics {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, d) in self.0.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", d)?;
+        }
+        Ok(())
+    }
+}
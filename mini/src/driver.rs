@@ -0,0 +1,223 @@
+//! The build pipeline behind `mini build`: lower a checked program with LLVM
+//! and write the requested kind of output.
+
+use anyhow::{anyhow, bail, Result};
+use inkwell::{
+    context::Context as LlvmContext,
+    module::Module,
+    passes::PassBuilderOptions,
+    targets::{CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple},
+    OptimizationLevel,
+};
+use std::path::{Path, PathBuf};
+
+use crate::ast::Program;
+use crate::codegen::{host_triple, Codegen};
+use crate::lexer::SourceMap;
+use crate::link::{cross_linker, link_exe, link_static, link_wasm, link_with, static_linker, wasm_linker};
+use crate::runtime::Flavor;
+
+/// What `mini build` writes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum Emit {
+    /// Textual LLVM IR (`.ll`)
+    Ir,
+    /// LLVM bitcode (`.bc`)
+    Bc,
+    /// Target assembly (`.s`)
+    Asm,
+    /// Object file (`.o`), not linked
+    Obj,
+    /// Linked native executable
+    Exe,
+}
+
+impl Emit {
+    /// File extension used when no output path is given; executables for a
+    /// wasm `target` are `.wasm` modules.
+    pub fn extension(self, target: Option<&str>) -> &'static str {
+        match self {
+            Emit::Ir => "ll",
+            Emit::Bc => "bc",
+            Emit::Asm => "s",
+            Emit::Obj => "o",
+            Emit::Exe if target.is_some_and(|t| t.starts_with("wasm")) => "wasm",
+            Emit::Exe => std::env::consts::EXE_EXTENSION,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    pub emit: Emit,
+    /// 0 through 3, as in `-O2`.
+    pub opt_level: u8,
+    /// Print the module to stderr after optimization, before it is emitted.
+    pub print_ir: bool,
+    /// Stop on integer overflow and division by zero with an error message
+    /// (see `Codegen::check_arithmetic`); usually on for `-O0` builds only.
+    pub checked: bool,
+    /// Include DWARF debug information (`-g`).
+    pub debug: bool,
+    /// Target triple; `None` builds for the host.
+    pub target: Option<String>,
+    /// CPU to tune and select instructions for; `None` means `generic`.
+    pub cpu: Option<String>,
+    /// LLVM target features, as in `+neon,-fp-armv8`.
+    pub features: Option<String>,
+    /// Use Mini's own system-call runtime instead of the C library, for static
+    /// executables that don't link libc.
+    pub freestanding: bool,
+    /// Write intermediate files next to the output and keep them, instead of
+    /// using a temporary directory.
+    pub keep_temps: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self { emit: Emit::Exe, opt_level: 0, print_ir: false, checked: true, debug: false, target: None, cpu: None, features: None, freestanding: false, keep_temps: false }
+    }
+}
+
+/// Compile `program` and write the output selected by `opts.emit` to `output`.
+///
+/// `sources` holds the program's files, which failed runtime checks and
+/// debug info quote positions from.
+///
+/// Returns the path actually written: an executable for a foreign target that
+/// no linker on this machine can produce is left as an object file instead.
+pub fn build(program: &Program, sources: &SourceMap, opts: &BuildOptions, output: &Path) -> Result<PathBuf> {
+    let triple = match &opts.target {
+        Some(t) => TargetTriple::create(t),
+        None => host_triple(),
+    };
+    let cpu = opts.cpu.as_deref().unwrap_or("generic");
+    let tm = target_machine(&triple, cpu, opts.features.as_deref().unwrap_or(""), opts.opt_level)?;
+
+    let flavor = if opts.freestanding {
+        Flavor::freestanding(&triple)
+            .ok_or_else(|| anyhow!("--freestanding supports x86_64 and aarch64 Linux targets, not `{}`", triple.as_str().to_string_lossy()))?
+    } else {
+        Flavor::for_triple(&triple)
+    };
+
+    let ctx = LlvmContext::create();
+    let mut cg = Codegen::with_flavor(&ctx, &triple, flavor);
+    cg.module().set_data_layout(&tm.get_target_data().get_data_layout());
+    cg.set_sources(sources.clone());
+    if opts.debug {
+        cg.emit_debug_info(opts.opt_level > 0);
+    }
+    if opts.checked {
+        cg.check_arithmetic();
+    }
+    cg.emit_program(program);
+    let module = cg.module();
+    module.verify().map_err(|e| anyhow!("invalid LLVM IR: {}", e.to_string()))?;
+    optimize(module, &tm, opts.opt_level)?;
+    if opts.print_ir {
+        eprint!("{}", module.print_to_string().to_string());
+    }
+
+    match opts.emit {
+        Emit::Ir => module.print_to_file(output).map_err(|e| anyhow!(e.to_string()))?,
+        Emit::Bc => {
+            if !module.write_bitcode_to_path(output) {
+                bail!("failed to write bitcode to {}", output.display());
+            }
+        }
+        Emit::Asm => tm.write_to_file(module, FileType::Assembly, output).map_err(|e| anyhow!(e.to_string()))?,
+        Emit::Obj => tm.write_to_file(module, FileType::Object, output).map_err(|e| anyhow!(e.to_string()))?,
+        Emit::Exe => {
+            let name = triple.as_str().to_string_lossy().into_owned();
+            let linker = match flavor {
+                Flavor::Freestanding(_) => static_linker(triple == host_triple()).map(Linker::Static),
+                _ if triple == host_triple() => Some(Linker::System),
+                Flavor::Wasi => wasm_linker().map(Linker::Wasm),
+                Flavor::Libc => cross_linker(&name).map(Linker::Cross),
+            };
+            let Some(linker) = linker else {
+                // nothing can link for this target here; the object file is the useful result
+                let obj = output.with_extension("o");
+                tm.write_to_file(module, FileType::Object, &obj).map_err(|e| anyhow!(e.to_string()))?;
+                eprintln!("warning: no linker for `{}` found; wrote the unlinked object file {}", name, obj.display());
+                return Ok(obj);
+            };
+            // the object file is only an intermediate: keep it in a temp dir unless asked otherwise
+            let temp = tempfile::Builder::new().prefix("mini-").tempdir()?;
+            let obj = if opts.keep_temps {
+                output.with_extension("o")
+            } else {
+                temp.path().join("main.o")
+            };
+            tm.write_to_file(module, FileType::Object, &obj).map_err(|e| anyhow!(e.to_string()))?;
+            match &linker {
+                Linker::System => link_exe(&obj, output)?,
+                Linker::Cross(ld) => link_with(ld, &obj, output)?,
+                Linker::Wasm(ld) => link_wasm(ld, &obj, output)?,
+                Linker::Static(ld) => link_static(ld, &obj, output)?,
+            }
+
+            #[cfg(unix)]
+            if flavor != Flavor::Wasi {
+                use std::os::unix::fs::PermissionsExt;
+                let mut perm = std::fs::metadata(output)?.permissions();
+                perm.set_mode(0o755);
+                std::fs::set_permissions(output, perm)?;
+            }
+        }
+    }
+    Ok(output.to_path_buf())
+}
+
+/// How an executable gets linked.
+enum Linker {
+    /// The host's C toolchain (`link::link_exe`).
+    System,
+    /// A GNU cross toolchain for a foreign Linux target.
+    Cross(PathBuf),
+    /// `wasm-ld` or `rust-lld` for WASI modules.
+    Wasm(PathBuf),
+    /// A plain `ld` for freestanding executables.
+    Static(PathBuf),
+}
+
+/// Run LLVM's standard new-pass-manager pipeline for `-O<opt_level>` over `module`.
+///
+/// `-O0` runs nothing. From `-O1` up this is the same `default<On>` pipeline
+/// clang uses: mem2reg, instcombine, GVN, inlining of Mini functions and so on;
+/// `-O2` and `-O3` also vectorize loops.
+pub fn optimize(module: &Module, tm: &TargetMachine, opt_level: u8) -> Result<()> {
+    if opt_level == 0 {
+        return Ok(());
+    }
+    let opts = PassBuilderOptions::create();
+    opts.set_loop_vectorization(opt_level >= 2);
+    opts.set_loop_slp_vectorization(opt_level >= 2);
+    opts.set_loop_unrolling(true);
+    opts.set_merge_functions(opt_level >= 2);
+    module
+        .run_passes(&format!("default<O{}>", opt_level.min(3)), tm, opts)
+        .map_err(|e| anyhow!("optimization failed: {}", e.to_string()))
+}
+
+/// A target machine for `triple` at the given `-O` level.
+///
+/// `cpu` and `features` are passed to LLVM as-is; use `generic` and `""` for
+/// code that runs on any implementation of the architecture.
+pub fn target_machine(triple: &TargetTriple, cpu: &str, features: &str, opt_level: u8) -> Result<TargetMachine> {
+    Target::initialize_all(&InitializationConfig::default());
+    let target = Target::from_triple(triple).map_err(|e| anyhow!("unknown target `{}`: {}", triple.as_str().to_string_lossy(), e.to_string()))?;
+    let level = match opt_level {
+        0 => OptimizationLevel::None,
+        1 => OptimizationLevel::Less,
+        2 => OptimizationLevel::Default,
+        _ => OptimizationLevel::Aggressive,
+    };
+    // position-independent code links cleanly into the PIE executables toolchains
+    // default to; wasm modules are linked statically
+    let reloc = if triple.as_str().to_string_lossy().starts_with("wasm") { RelocMode::Static } else { RelocMode::PIC };
+    target
+        .create_target_machine(triple, cpu, features, level, reloc, CodeModel::Default)
+        .ok_or_else(|| anyhow!("could not create a target machine for `{}`", triple.as_str().to_string_lossy()))
+}
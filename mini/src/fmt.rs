@@ -0,0 +1,740 @@
+//! Pretty-printer that renders a parsed program in Mini's canonical layout.
+//!
+//! Output is rebuilt from the AST: four-space indentation, one statement per
+//! line, a blank line around each function and struct, and only the
+//! parentheses the parser's binding powers require. [`format_source`] also
+//! keeps what the AST drops: comments stay before the statement they precede
+//! or after the one they follow on its line, and a run of blank lines
+//! between statements becomes one. Formatting formatted code changes nothing.
+
+use crate::ast::{Expr, ExprKind, ForIter, Function, Import, Program, Stmt, StmtKind, StrPart, StructDef};
+use crate::lexer::{Comment, Lexer, Tok};
+use crate::parser::{infix_binding_power, CAST_BP, POSTFIX_BP, PREFIX_BP};
+
+/// Render `program` as formatted source text, without comments.
+pub fn format_program(program: &Program) -> String {
+    let mut p = Printer::default();
+    p.program(program);
+    p.out
+}
+
+/// Render `program`, parsed from `src`, as formatted source text, keeping
+/// the comments and blank lines of `src`.
+pub fn format_source(program: &Program, src: &str) -> String {
+    let (tokens, comments, _) = Lexer::new(src).tokenize_with_comments();
+    let starts = |tok: Tok| tokens.iter().filter(|t| t.tok == tok).map(|t| t.span.start).collect();
+    let mut p = Printer { src, comments: &comments, opens: starts(Tok::LBrace), closes: starts(Tok::RBrace), ..Printer::default() };
+    p.program(program);
+    // comments after the last statement
+    p.leading(src.len());
+    p.out
+}
+
+enum Item<'a> {
+    Import(&'a Import),
+    Struct(&'a StructDef),
+    Function(&'a Function),
+    Stmt(&'a Stmt),
+}
+
+#[derive(Default)]
+struct Printer<'a> {
+    out: String,
+    indent: usize,
+    /// Printing the header of an `if`, `while` or `for`, where a struct
+    /// literal outside brackets needs parentheses.
+    in_header: bool,
+    /// The source and its comments, when formatting with [`format_source`].
+    src: &'a str,
+    comments: &'a [Comment],
+    /// The first comment not printed yet.
+    next: usize,
+    /// Where the last thing printed ended in `src`.
+    last: usize,
+    /// Offsets of every `{` and `}` token in `src`.
+    opens: Vec<usize>,
+    closes: Vec<usize>,
+}
+
+impl Printer<'_> {
+    fn program(&mut self, program: &Program) {
+        // declarations and statements are stored apart; interleave them as written
+        let mut items: Vec<(usize, Item)> = program
+            .imports
+            .iter()
+            .map(|i| (i.span.start, Item::Import(i)))
+            .chain(program.structs.iter().map(|d| (d.span.start, Item::Struct(d))))
+            .chain(program.functions.iter().map(|f| (f.sig_span.start, Item::Function(f))))
+            .chain(program.stmts.iter().map(|s| (s.span.start, Item::Stmt(s))))
+            .collect();
+        items.sort_by_key(|(start, _)| *start);
+
+        let mut prev_decl = false;
+        for (i, (start, item)) in items.iter().enumerate() {
+            // imports are listed like statements, without blank lines between them
+            let is_decl = !matches!(item, Item::Stmt(_) | Item::Import(_));
+            if i > 0 && (is_decl || prev_decl) {
+                self.blank_line();
+            }
+            match item {
+                Item::Import(i) => {
+                    self.leading(*start);
+                    self.out += "import \"";
+                    self.string_text(&i.path);
+                    self.out += "\";";
+                    self.trailing(i.span.end);
+                    self.out.push('\n');
+                }
+                Item::Struct(d) => {
+                    self.leading(*start);
+                    self.struct_def(d);
+                    self.trailing(d.span.end);
+                    self.out.push('\n');
+                }
+                Item::Function(f) => {
+                    self.leading(*start);
+                    self.function(f);
+                    self.trailing(f.end_span.end);
+                    self.out.push('\n');
+                }
+                Item::Stmt(s) => self.stmt(s),
+            }
+            prev_decl = is_decl;
+        }
+    }
+
+    fn line_start(&mut self) {
+        self.out += &"    ".repeat(self.indent);
+    }
+
+    /// End the output with an empty line, unless it already does or a
+    /// block has just opened.
+    fn blank_line(&mut self) {
+        if !self.out.is_empty() && !self.out.ends_with("\n\n") && !self.out.ends_with("{\n") {
+            self.out.push('\n');
+        }
+    }
+
+    /// Print the comments before source offset `pos` on lines of their own,
+    /// keeping a blank line wherever the source has one before them or
+    /// before `pos`.
+    fn leading(&mut self, pos: usize) {
+        while let Some(c) = self.comments.get(self.next).filter(|c| c.span.start < pos) {
+            self.blank_line_if_in_source(c.span.start);
+            self.line_start();
+            self.out += &c.text;
+            self.out.push('\n');
+            self.last = c.span.end;
+            self.next += 1;
+        }
+        self.blank_line_if_in_source(pos);
+    }
+
+    fn blank_line_if_in_source(&mut self, pos: usize) {
+        if self.src.get(self.last..pos).is_some_and(|gap| gap.matches('\n').count() > 1) {
+            self.blank_line();
+        }
+    }
+
+    /// After printing something that ends at source offset `end`: the
+    /// comments inside it, and one starting later on its last line, follow
+    /// it on the same line.
+    fn trailing(&mut self, end: usize) {
+        while let Some(c) = self.comments.get(self.next) {
+            let same_line = c.span.start < end || self.src.get(end..c.span.start).is_some_and(|gap| !gap.contains('\n'));
+            if !same_line {
+                break;
+            }
+            self.out.push(' ');
+            self.out += &c.text;
+            self.next += 1;
+        }
+        self.last = end;
+    }
+
+    /// After the `{` at `open`: a comment on its line before the first thing
+    /// inside, at `first`, follows it.
+    fn after_open(&mut self, open: usize, first: usize) {
+        if self.comments.get(self.next).is_some_and(|c| c.span.start < first) {
+            self.trailing(open + 1);
+        }
+    }
+
+    /// The first `{` at or after source offset `pos`.
+    fn open_after(&self, pos: usize) -> Option<usize> {
+        self.opens.get(self.opens.partition_point(|&o| o < pos)).copied()
+    }
+
+    /// Whether a comment comes before source offset `pos`.
+    fn comment_before(&self, pos: usize) -> bool {
+        self.comments.get(self.next).is_some_and(|c| c.span.start < pos)
+    }
+
+    /// `struct Name {` with one field per line, each followed by a comma.
+    fn struct_def(&mut self, d: &StructDef) {
+        if d.public {
+            self.out += "pub ";
+        }
+        self.out += &format!("struct {} {{", d.name.name);
+        let close = d.span.end.saturating_sub(1);
+        if d.fields.is_empty() && !self.comment_before(close) {
+            self.out.push('}');
+            return;
+        }
+        if let Some(open) = self.open_after(d.name.span.end) {
+            self.after_open(open, d.fields.first().map_or(close, |f| f.span.start));
+        }
+        self.out.push('\n');
+        self.indent += 1;
+        for f in &d.fields {
+            self.leading(f.span.start);
+            self.line_start();
+            self.out += &format!("{}: {},", f.name.name, f.ty);
+            self.trailing(f.span.end);
+            self.out.push('\n');
+        }
+        self.leading_in_block(close);
+        self.indent -= 1;
+        self.out.push('}');
+    }
+
+    /// Comments left before the `}` at `close` that ends a block.
+    fn leading_in_block(&mut self, close: usize) {
+        while self.comment_before(close) {
+            let c = &self.comments[self.next];
+            self.blank_line_if_in_source(c.span.start);
+            self.line_start();
+            self.out += &c.text;
+            self.out.push('\n');
+            self.last = c.span.end;
+            self.next += 1;
+        }
+        self.last = close + 1;
+    }
+
+    fn function(&mut self, f: &Function) {
+        self.out += if f.public { "pub fn " } else { "fn " };
+        self.out += &f.name.name;
+        self.out.push('(');
+        for (i, p) in f.params.iter().enumerate() {
+            if i > 0 {
+                self.out += ", ";
+            }
+            self.out += &format!("{}: {}", p.name.name, p.ty);
+        }
+        self.out.push(')');
+        if let Some(ty) = &f.ret {
+            self.out += &format!(" -> {}", ty);
+        }
+        self.out.push(' ');
+        self.block(&f.body, f.sig_span.end);
+    }
+
+    /// `{ ... }` with the body indented one level; an empty body prints as
+    /// `{}`. The block's `{` is the first one at or after source offset
+    /// `after`; returns where its `}` is, when formatting source.
+    fn block(&mut self, body: &[Stmt], after: usize) -> Option<usize> {
+        let open = self.open_after(after);
+        let inner_end = body.last().map_or(open.map_or(after, |o| o + 1), |s| s.span.end);
+        let close = self.closes.get(self.closes.partition_point(|&c| c < inner_end)).copied();
+        if body.is_empty() && !close.is_some_and(|c| self.comment_before(c)) {
+            self.out += "{}";
+            return close;
+        }
+        self.out.push('{');
+        if let Some(open) = open {
+            self.after_open(open, body.first().map_or(inner_end, |s| s.span.start));
+        }
+        self.out.push('\n');
+        self.indent += 1;
+        for s in body {
+            self.stmt(s);
+        }
+        if let Some(close) = close {
+            self.leading_in_block(close);
+        }
+        self.indent -= 1;
+        self.line_start();
+        self.out.push('}');
+        close
+    }
+
+    fn stmt(&mut self, s: &Stmt) {
+        self.leading(s.span.start);
+        self.line_start();
+        self.stmt_inline(s);
+        self.trailing(s.span.end);
+        self.out.push('\n');
+    }
+
+    /// A statement without its indentation or trailing newline.
+    fn stmt_inline(&mut self, s: &Stmt) {
+        match &s.kind {
+            StmtKind::Let { name, mutable, expr } => {
+                self.out += if *mutable { "let mut " } else { "let " };
+                self.out += &format!("{} = ", name.name);
+                self.expr(expr);
+                self.out.push(';');
+            }
+            StmtKind::Assign { name, expr, compound } => {
+                // a compound assignment was parsed as `x = x op rhs`; print it back as `x op= rhs`
+                match binary(expr) {
+                    Some((op, _, _, rhs)) if *compound => {
+                        self.out += &format!("{} {}= ", name.name, op);
+                        self.expr(rhs);
+                    }
+                    _ => {
+                        self.out += &format!("{} = ", name.name);
+                        self.expr(expr);
+                    }
+                }
+                self.out.push(';');
+            }
+            StmtKind::IndexAssign { name, index, expr, compound } => {
+                self.out += &name.name;
+                self.out.push('[');
+                self.expr(index);
+                self.out += "] ";
+                self.assign_rhs(expr, *compound);
+            }
+            StmtKind::FieldAssign { name, path, expr, compound } => {
+                self.out += &name.name;
+                for field in path {
+                    self.out += &format!(".{}", field.name);
+                }
+                self.out.push(' ');
+                self.assign_rhs(expr, *compound);
+            }
+            StmtKind::Print { args, newline } => {
+                self.out += if *newline { "println" } else { "print" };
+                for (i, a) in args.iter().enumerate() {
+                    self.out += if i == 0 { " " } else { ", " };
+                    self.expr(a);
+                }
+                self.out.push(';');
+            }
+            StmtKind::If { cond, then_body, else_body } => {
+                self.out += "if ";
+                self.header(cond);
+                self.out.push(' ');
+                let close = self.block(then_body, cond.span.end);
+                match else_body.as_deref() {
+                    None => {}
+                    // a lone `if` in the else branch is an `else if` chain
+                    Some([elif @ Stmt { kind: StmtKind::If { .. }, .. }]) => {
+                        self.out += " else ";
+                        self.stmt_inline(elif);
+                    }
+                    Some(body) => {
+                        self.out += " else ";
+                        self.block(body, close.map_or(s.span.start, |c| c + 1));
+                    }
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.out += "while ";
+                self.header(cond);
+                self.out.push(' ');
+                self.block(body, cond.span.end);
+            }
+            StmtKind::For { var, iter, body } => {
+                self.out += &format!("for {} in ", var.name);
+                let header_end = match iter {
+                    ForIter::Range(start, end) => {
+                        self.header(start);
+                        self.out += "..";
+                        self.header(end);
+                        end.span.end
+                    }
+                    ForIter::Array(e) => {
+                        self.header(e);
+                        e.span.end
+                    }
+                };
+                self.out.push(' ');
+                self.block(body, header_end);
+            }
+            StmtKind::Block(body) => {
+                self.block(body, s.span.start);
+            }
+            StmtKind::Return(None) => self.out += "return;",
+            StmtKind::Return(Some(e)) => {
+                self.out += "return ";
+                self.expr(e);
+                self.out.push(';');
+            }
+            StmtKind::Expr(e) => {
+                self.expr(e);
+                self.out.push(';');
+            }
+        }
+    }
+
+    /// `= e;` after an assignment target, or `op= rhs;` for a compound
+    /// assignment, which was parsed as `target = target op rhs`.
+    fn assign_rhs(&mut self, expr: &Expr, compound: bool) {
+        match binary(expr) {
+            Some((op, _, _, rhs)) if compound => {
+                self.out += &format!("{}= ", op);
+                self.expr(rhs);
+            }
+            _ => {
+                self.out += "= ";
+                self.expr(expr);
+            }
+        }
+        self.out.push(';');
+    }
+
+    /// The expression before the body of an `if`, `while` or `for`.
+    fn header(&mut self, e: &Expr) {
+        self.in_header = true;
+        self.expr(e);
+        self.in_header = false;
+    }
+
+    /// An expression inside brackets or parentheses of its own, where a
+    /// struct literal never needs more.
+    fn nested(&mut self, e: &Expr) {
+        let outer = std::mem::replace(&mut self.in_header, false);
+        self.expr(e);
+        self.in_header = outer;
+    }
+
+    fn expr(&mut self, e: &Expr) {
+        match &e.kind {
+            ExprKind::Int(v) => self.out += &v.to_string(),
+            ExprKind::I64(v) => self.out += &format!("{}i64", v),
+            // a float keeps its spelling; `{:?}` keeps a `.0` or exponent, so
+            // the literal reads back as a float
+            ExprKind::Float(v) => match self.src.get(e.span.start..e.span.end).filter(|t| t.parse() == Ok(*v)) {
+                Some(text) => self.out += text,
+                None => self.out += &format!("{:?}", v),
+            },
+            ExprKind::Bool(b) => self.out += &b.to_string(),
+            ExprKind::Str(s) => {
+                self.out.push('"');
+                self.string_text(s);
+                self.out.push('"');
+            }
+            ExprKind::Interp(parts) => {
+                self.out.push('"');
+                for part in parts {
+                    match part {
+                        StrPart::Lit(s) => self.string_text(s),
+                        StrPart::Expr(x) => {
+                            self.out.push('{');
+                            self.nested(x);
+                            self.out.push('}');
+                        }
+                    }
+                }
+                self.out.push('"');
+            }
+            ExprKind::Var(name) => self.out += name,
+            ExprKind::UnaryNeg(x) => self.prefix("-", x),
+            ExprKind::Not(x) => self.prefix("!", x),
+            ExprKind::Cast(x, ty) => {
+                // `as` binds tighter than every other operator but indexing
+                self.postfix_operand(x, false);
+                self.out += &format!(" as {}", ty);
+            }
+            ExprKind::Index(base, index) => {
+                self.postfix_operand(base, true);
+                self.out.push('[');
+                self.nested(index);
+                self.out.push(']');
+            }
+            ExprKind::Field(base, field) => {
+                self.postfix_operand(base, true);
+                self.out += &format!(".{}", field.name);
+            }
+            ExprKind::StructLit { name, fields } => {
+                if self.in_header {
+                    self.out.push('(');
+                }
+                self.out += &name.name;
+                self.out += if fields.is_empty() { " {" } else { " { " };
+                for (i, (field, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        self.out += ", ";
+                    }
+                    self.out += &format!("{}: ", field.name);
+                    self.nested(value);
+                }
+                self.out += if fields.is_empty() { "}" } else { " }" };
+                if self.in_header {
+                    self.out.push(')');
+                }
+            }
+            ExprKind::Call { name, args } => self.call(name, args),
+            ExprKind::Builtin { func, args } => self.call(func.name(), args),
+            ExprKind::Array(items) => {
+                self.out.push('[');
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        self.out += ", ";
+                    }
+                    self.nested(item);
+                }
+                self.out.push(']');
+            }
+            ExprKind::Repeat { value, count } => {
+                self.out.push('[');
+                self.nested(value);
+                self.out += "; ";
+                self.nested(count);
+                self.out.push(']');
+            }
+            _ => {
+                let (op, tok, l, r) = binary(e).expect("remaining kinds are binary operators");
+                // operators are left-associative: a right operand of equal
+                // precedence falls short of the right binding power
+                let (l_bp, r_bp) = infix_binding_power(&tok).unwrap();
+                self.operand(l, l_bp);
+                self.out += &format!(" {} ", op);
+                self.operand(r, r_bp);
+            }
+        }
+    }
+
+    fn prefix(&mut self, op: &str, x: &Expr) {
+        self.out += op;
+        // `- -x` would read like a decrement, so nested negation is parenthesized too;
+        // so is a cast, as `-5 as i64` would negate the literal before converting
+        if binding_power(x) < PREFIX_BP || (op == "-" && (matches!(x.kind, ExprKind::UnaryNeg(_) | ExprKind::Cast(..)) || is_negative_literal(x))) {
+            self.out.push('(');
+            self.expr(x);
+            self.out.push(')');
+        } else {
+            self.expr(x);
+        }
+    }
+
+    /// Print the operand of `as`, or of indexing or a field access (`tight`),
+    /// parenthesized unless it binds at least as tightly.
+    fn postfix_operand(&mut self, x: &Expr, tight: bool) {
+        let min_bp = if tight { POSTFIX_BP } else { CAST_BP };
+        // `-1[0]` would read as `-(1[0])`
+        if binding_power(x) < min_bp || (tight && is_negative_literal(x)) {
+            self.out.push('(');
+            self.expr(x);
+            self.out.push(')');
+        } else {
+            self.expr(x);
+        }
+    }
+
+    /// Print an operand that the parser reads with binding power `min_bp`,
+    /// parenthesized if its own operator binds more loosely.
+    fn operand(&mut self, e: &Expr, min_bp: u8) {
+        if binding_power(e) < min_bp {
+            self.out.push('(');
+            self.expr(e);
+            self.out.push(')');
+        } else {
+            self.expr(e);
+        }
+    }
+
+    fn call(&mut self, name: &str, args: &[Expr]) {
+        self.out += name;
+        self.out.push('(');
+        for (i, a) in args.iter().enumerate() {
+            if i > 0 {
+                self.out += ", ";
+            }
+            self.nested(a);
+        }
+        self.out.push(')');
+    }
+
+    /// Write the text of a string literal with its escapes, braces doubled.
+    fn string_text(&mut self, s: &str) {
+        for c in s.chars() {
+            match c {
+                '\n' => self.out += "\\n",
+                '\t' => self.out += "\\t",
+                '"' => self.out += "\\\"",
+                '\\' => self.out += "\\\\",
+                '{' => self.out += "{{",
+                '}' => self.out += "}}",
+                c => self.out.push(c),
+            }
+        }
+    }
+}
+
+/// Whether `e` is a number literal written with a leading `-`.
+fn is_negative_literal(e: &Expr) -> bool {
+    match e.kind {
+        ExprKind::Int(v) => v < 0,
+        ExprKind::I64(v) => v < 0,
+        ExprKind::Float(v) => v.is_sign_negative(),
+        _ => false,
+    }
+}
+
+/// How tightly the outermost operator of `e` binds; atoms bind tightest.
+fn binding_power(e: &Expr) -> u8 {
+    match &e.kind {
+        ExprKind::UnaryNeg(_) | ExprKind::Not(_) => PREFIX_BP,
+        ExprKind::Cast(..) => CAST_BP,
+        ExprKind::Index(..) | ExprKind::Field(..) => POSTFIX_BP,
+        _ => binary(e).map_or(u8::MAX, |(_, tok, ..)| infix_binding_power(&tok).unwrap().0),
+    }
+}
+
+/// Operator text, token and operands of a binary expression.
+fn binary(e: &Expr) -> Option<(&'static str, Tok, &Expr, &Expr)> {
+    Some(match &e.kind {
+        ExprKind::Or(a, b) => ("||", Tok::OrOr, a, b),
+        ExprKind::And(a, b) => ("&&", Tok::AndAnd, a, b),
+        ExprKind::Eq(a, b) => ("==", Tok::EqEq, a, b),
+        ExprKind::Ne(a, b) => ("!=", Tok::NotEq, a, b),
+        ExprKind::Lt(a, b) => ("<", Tok::Lt, a, b),
+        ExprKind::Le(a, b) => ("<=", Tok::Le, a, b),
+        ExprKind::Gt(a, b) => (">", Tok::Gt, a, b),
+        ExprKind::Ge(a, b) => (">=", Tok::Ge, a, b),
+        ExprKind::Add(a, b) => ("+", Tok::Plus, a, b),
+        ExprKind::Sub(a, b) => ("-", Tok::Minus, a, b),
+        ExprKind::Mul(a, b) => ("*", Tok::Star, a, b),
+        ExprKind::Div(a, b) => ("/", Tok::Slash, a, b),
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::Parser;
+
+    fn fmt(src: &str) -> String {
+        format_program(&Parser::parse(src).unwrap())
+    }
+
+    fn fmt_source(src: &str) -> String {
+        format_source(&Parser::parse(src).unwrap(), src)
+    }
+
+    #[test]
+    fn lays_out_statements_and_functions() {
+        let src = "let x=1;fn add(a:int,b:int)->int{return a+b;}\nif x>0{println x;}else if x<0{println x;}else{let s=\"a\\n\";println s;}\nlet mut y=0;while y<3 {y+=x*2;y=y-1;}\n";
+        let want = "\
+let x = 1;
+
+fn add(a: int, b: int) -> int {
+    return a + b;
+}
+
+if x > 0 {
+    println x;
+} else if x < 0 {
+    println x;
+} else {
+    let s = \"a\\n\";
+    println s;
+}
+let mut y = 0;
+while y < 3 {
+    y += x * 2;
+    y = y - 1;
+}
+";
+        assert_eq!(fmt(src), want);
+    }
+
+    #[test]
+    fn keeps_only_needed_parentheses() {
+        let src = "let a = ((1 + 2)) * 3 - (4 - 5) + (6 * 7) + f((x)) && !(a || b) || -(-y);";
+        assert_eq!(fmt(src), "let a = (1 + 2) * 3 - (4 - 5) + 6 * 7 + f(x) && !(a || b) || -(-y);\n");
+    }
+
+    #[test]
+    fn prints_typed_literals_and_casts() {
+        let src = "let a = 5i64 + -3i64;\nlet b = 1e20 + 2.50 + -0.5;\nlet c = (x + 1) as f64 * (-y as f64);\nlet d = -(n as i64) + -(-7);\nlet e = !true || false;\n";
+        assert_eq!(
+            fmt(src),
+            "let a = 5i64 + -3i64;\nlet b = 1e20 + 2.5 + -0.5;\nlet c = (x + 1) as f64 * -(y as f64);\nlet d = -(n as i64) + -(-7);\nlet e = !true || false;\n"
+        );
+    }
+
+    #[test]
+    fn prints_interpolated_strings_with_escaped_braces() {
+        let src = "let s=\"{{a}} {x+1} {len(t)}\"+\"}}\";";
+        assert_eq!(fmt(src), "let s = \"{{a}} {x + 1} {len(t)}\" + \"}}\";\n");
+    }
+
+    #[test]
+    fn prints_print_statements() {
+        assert_eq!(fmt("print x ;println \"a\",x+1 , f(y);println ;"), "print x;\nprintln \"a\", x + 1, f(y);\nprintln;\n");
+    }
+
+    #[test]
+    fn prints_arrays_indexing_and_for_loops() {
+        let src = "fn f(a:[int])->[int]{return a;}\nlet mut a=[1,2 ,3];let z=[ ];let r=[0;n*2];a[i+1]+=a [0]*2;a[0]=-a[1];\nfor x in a{push(a,x);}for i in 0..len(a){println f(a)[i];}\nlet y=(a)[0]+(-1)[0];\n";
+        assert_eq!(
+            fmt(src),
+            "fn f(a: [int]) -> [int] {\n    return a;\n}\n\nlet mut a = [1, 2, 3];\nlet z = [];\nlet r = [0; n * 2];\na[i + 1] += a[0] * 2;\na[0] = -a[1];\nfor x in a {\n    push(a, x);\n}\nfor i in 0..len(a) {\n    println f(a)[i];\n}\nlet y = a[0] + (-1)[0];\n"
+        );
+    }
+
+    #[test]
+    fn prints_structs_and_parenthesizes_literals_in_headers() {
+        let src = "struct P{x:int,y:[int],}struct E{}\nlet mut p=P{x:1,y:[]};p.x+=E{}.n;p . y=[ 2 ];\nif (P{x:1,y:[]}).x>0{println p.y[0];}\nlet q=(p).x;\n";
+        assert_eq!(
+            fmt(src),
+            "struct P {\n    x: int,\n    y: [int],\n}\n\nstruct E {}\n\nlet mut p = P { x: 1, y: [] };\np.x += E {}.n;\np.y = [2];\nif (P { x: 1, y: [] }).x > 0 {\n    println p.y[0];\n}\nlet q = p.x;\n"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent_on_the_examples() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let src = std::fs::read_to_string(entry.unwrap().path()).unwrap();
+            let once = fmt(&src);
+            assert_eq!(fmt(&once), once);
+        }
+    }
+
+    #[test]
+    fn keeps_comments_where_they_were() {
+        let src = "// header\nstruct P { // point\n x: int, // across\n // down\n y: int,\n}\nfn f() -> int { return 1; // one\n // done\n}\nlet x = g(1, // first\n 2);\nif x > 0 {\n // empty\n}\n// end\n";
+        assert_eq!(
+            fmt_source(src),
+            "// header\nstruct P { // point\n    x: int, // across\n    // down\n    y: int,\n}\n\nfn f() -> int {\n    return 1; // one\n    // done\n}\n\nlet x = g(1, 2); // first\nif x > 0 {\n    // empty\n}\n// end\n"
+        );
+    }
+
+    #[test]
+    fn keeps_one_blank_line_between_statements() {
+        let src = "let a = 1;\n\n\n\nlet b = 2;\nfn f() {\n\n    println a;\n\n    // b\n    println b;\n\n}\n";
+        assert_eq!(fmt_source(src), "let a = 1;\n\nlet b = 2;\n\nfn f() {\n    println a;\n\n    // b\n    println b;\n}\n");
+    }
+
+    #[test]
+    fn keeps_the_spelling_of_float_literals() {
+        assert_eq!(fmt_source("let x = 1e3+-0.000012345;\n"), "let x = 1e3 + -0.000012345;\n");
+        assert_eq!(fmt("let x = 1e3;\n"), "let x = 1000.0;\n");
+    }
+
+    #[test]
+    fn prints_imports_and_pub_items() {
+        let src = "import \"geo.mini\";\nimport  \"lib/util.mini\" ; // helpers\npub struct P{at:geo::Point}\npub fn f(p:P)->geo::Point{return geo::mid(p.at,geo::Point{x:1});}\nprintln f(P{at:util::origin()}).x;\n";
+        assert_eq!(
+            fmt_source(src),
+            "import \"geo.mini\";\nimport \"lib/util.mini\"; // helpers\n\npub struct P {\n    at: geo::Point,\n}\n\npub fn f(p: P) -> geo::Point {\n    return geo::mid(p.at, geo::Point { x: 1 });\n}\n\nprintln f(P { at: util::origin() }).x;\n"
+        );
+    }
+
+    #[test]
+    fn the_examples_are_formatted() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let src = std::fs::read_to_string(&path).unwrap();
+            assert_eq!(fmt_source(&src), src, "{}", path.display());
+        }
+    }
+}
@@ -0,0 +1,399 @@
+//! Compile-time evaluation over the type-checked AST.
+//!
+//! [`fold`] replaces operators whose operands are all constant with the
+//! literal they evaluate to, working up from the leaves, so `(2 + 3) * 5`
+//! reaches codegen as `25`. Constants are computed by the interpreter, which
+//! keeps folded results identical to what the program computes at run time.
+//! Integer division by a constant zero is a compile error; arithmetic that
+//! would overflow is left for run time, with a warning.
+//!
+//! [`remove_dead_bindings`] then drops `let`s whose variable is never read,
+//! when dropping them can't change what the program does.
+
+use std::collections::HashSet;
+
+use crate::ast::{Builtin, Expr, ExprKind, ForIter, Program, Stmt, StmtKind, StrPart, Type};
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity};
+use crate::interp::{Interp, Value};
+
+/// Fold constant expressions throughout `program`.
+///
+/// Like `typeck::check`, this returns the warnings on success and every
+/// diagnostic on failure, in source order.
+pub fn fold(program: &mut Program) -> Result<Diagnostics, Diagnostics> {
+    let mut folder = Folder { eval: Interp::new(std::io::sink()), diags: Diagnostics::default() };
+    for f in &mut program.functions {
+        folder.stmts(&mut f.body);
+    }
+    folder.stmts(&mut program.stmts);
+
+    let mut diags = folder.diags;
+    diags.0.sort_by_key(|d| d.span().map(|s| s.start));
+    if diags.has_errors() {
+        Err(diags)
+    } else {
+        Ok(diags)
+    }
+}
+
+struct Folder {
+    /// Evaluates operators on literals; it never sees a variable or a call.
+    eval: Interp<std::io::Sink>,
+    diags: Diagnostics,
+}
+
+impl Folder {
+    fn stmts(&mut self, stmts: &mut [Stmt]) {
+        for s in stmts {
+            match &mut s.kind {
+                StmtKind::Let { expr, .. }
+                | StmtKind::Assign { expr, .. }
+                | StmtKind::FieldAssign { expr, .. }
+                | StmtKind::Expr(expr)
+                | StmtKind::Return(Some(expr)) => self.expr(expr),
+                StmtKind::If { cond, then_body, else_body } => {
+                    self.expr(cond);
+                    self.stmts(then_body);
+                    if let Some(body) = else_body {
+                        self.stmts(body);
+                    }
+                }
+                StmtKind::While { cond, body } => {
+                    self.expr(cond);
+                    self.stmts(body);
+                }
+                StmtKind::IndexAssign { index, expr, .. } => {
+                    self.expr(index);
+                    self.expr(expr);
+                }
+                StmtKind::For { iter, body, .. } => {
+                    match iter {
+                        ForIter::Range(start, end) => {
+                            self.expr(start);
+                            self.expr(end);
+                        }
+                        ForIter::Array(e) => self.expr(e),
+                    }
+                    self.stmts(body);
+                }
+                StmtKind::Block(body) => self.stmts(body),
+                StmtKind::Print { args, .. } => args.iter_mut().for_each(|a| self.expr(a)),
+                StmtKind::Return(None) => {}
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &mut Expr) {
+        // operands first, so constants build up from the leaves
+        match &mut e.kind {
+            ExprKind::UnaryNeg(x) | ExprKind::Not(x) | ExprKind::Cast(x, _) | ExprKind::Field(x, _) => self.expr(x),
+            ExprKind::Call { args, .. } | ExprKind::Builtin { args, .. } | ExprKind::Array(args) => {
+                args.iter_mut().for_each(|a| self.expr(a))
+            }
+            ExprKind::Repeat { value: a, count: b } | ExprKind::Index(a, b) => {
+                self.expr(a);
+                self.expr(b);
+            }
+            ExprKind::StructLit { fields, .. } => fields.iter_mut().for_each(|(_, x)| self.expr(x)),
+            ExprKind::Interp(parts) => parts.iter_mut().for_each(|p| {
+                if let StrPart::Expr(x) = p {
+                    self.expr(x)
+                }
+            }),
+            kind => {
+                if let Some((a, b)) = operands_mut(kind) {
+                    self.expr(a);
+                    self.expr(b);
+                }
+            }
+        }
+
+        if let ExprKind::Div(_, b) = &e.kind {
+            if matches!(constant(b), Some(Value::Int(0) | Value::I64(0))) {
+                let d = Diagnostic::error("attempt to divide by zero")
+                    .primary(e.span, "division by zero")
+                    .secondary(b.span, "this is always zero");
+                self.diags.push(d);
+                return;
+            }
+        }
+        // `false && x` and `true || x` never evaluate `x`
+        if let ExprKind::And(a, _) | ExprKind::Or(a, _) = &e.kind {
+            let skips = matches!(e.kind, ExprKind::Or(..));
+            if constant(a).is_some_and(|v| v.truthy() == skips) {
+                e.kind = ExprKind::Bool(skips);
+                return;
+            }
+        }
+        if !is_operator(e) || !e.operands().iter().all(|o| constant(o).is_some()) {
+            return;
+        }
+        match self.eval.eval(e) {
+            Ok(Some(v)) => e.kind = literal(v),
+            Ok(None) => {}
+            // overflow: the check (or the wrap, when unchecked) stays at run time
+            Err(d) => self.diags.push(
+                Diagnostic { severity: Severity::Warning, ..d }
+                    .note("checked builds stop here at run time; `--unchecked` builds wrap around"),
+            ),
+        }
+    }
+}
+
+/// Whether `e` is an operator the folder can evaluate. Arrays and structs
+/// are built at run time, as they have no literal to fold into, and the
+/// program's input is only known then.
+fn is_operator(e: &Expr) -> bool {
+    !matches!(
+        e.kind,
+        ExprKind::Int(_)
+            | ExprKind::I64(_)
+            | ExprKind::Float(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Str(_)
+            | ExprKind::Var(_)
+            | ExprKind::Call { .. }
+            | ExprKind::Array(_)
+            | ExprKind::Repeat { .. }
+            | ExprKind::Index(..)
+            | ExprKind::StructLit { .. }
+            | ExprKind::Field(..)
+            | ExprKind::Builtin { func: Builtin::Args | Builtin::Arg | Builtin::ReadLine | Builtin::ParseInt, .. }
+    )
+}
+
+/// The operands of a binary operator, mutably.
+fn operands_mut(kind: &mut ExprKind) -> Option<(&mut Expr, &mut Expr)> {
+    match kind {
+        ExprKind::Add(a, b)
+        | ExprKind::Sub(a, b)
+        | ExprKind::Mul(a, b)
+        | ExprKind::Div(a, b)
+        | ExprKind::Eq(a, b)
+        | ExprKind::Ne(a, b)
+        | ExprKind::Lt(a, b)
+        | ExprKind::Le(a, b)
+        | ExprKind::Gt(a, b)
+        | ExprKind::Ge(a, b)
+        | ExprKind::And(a, b)
+        | ExprKind::Or(a, b) => Some((a, b)),
+        _ => None,
+    }
+}
+
+/// The value of a literal.
+fn constant(e: &Expr) -> Option<Value> {
+    match &e.kind {
+        ExprKind::Int(v) => Some(Value::Int(*v)),
+        ExprKind::I64(v) => Some(Value::I64(*v)),
+        ExprKind::Float(v) => Some(Value::F64(*v)),
+        ExprKind::Bool(b) => Some(Value::Bool(*b)),
+        ExprKind::Str(s) => Some(Value::Str(s.as_str().into())),
+        _ => None,
+    }
+}
+
+fn literal(v: Value) -> ExprKind {
+    match v {
+        Value::Int(v) => ExprKind::Int(v),
+        Value::I64(v) => ExprKind::I64(v),
+        Value::F64(v) => ExprKind::Float(v),
+        Value::Bool(b) => ExprKind::Bool(b),
+        Value::Str(s) => ExprKind::Str(s.to_string()),
+        Value::Array(_) | Value::Struct(_) => unreachable!("operators never make arrays or structs"),
+    }
+}
+
+/// Remove `let` statements whose variable is never read, repeating until
+/// none is left (dropping one binding can leave another unread).
+///
+/// Only initializers without effects go: calls may print, and integer
+/// arithmetic may stop a checked program, so those bindings stay. Variables
+/// are matched by name within a function body, which keeps every binding
+/// that any read of the name might refer to.
+pub fn remove_dead_bindings(program: &mut Program) {
+    for f in &mut program.functions {
+        prune(&mut f.body);
+    }
+    prune(&mut program.stmts);
+}
+
+fn prune(body: &mut Vec<Stmt>) {
+    loop {
+        let mut read = HashSet::new();
+        reads_in_stmts(body, &mut read);
+        if !remove_unread(body, &read) {
+            break;
+        }
+    }
+}
+
+/// Remove unread, effect-free `let`s from `body` and the blocks nested in it;
+/// whether any were removed.
+fn remove_unread(body: &mut Vec<Stmt>, read: &HashSet<String>) -> bool {
+    let before = body.len();
+    body.retain(|s| !matches!(&s.kind, StmtKind::Let { name, expr, .. } if !read.contains(&name.name) && is_pure(expr)));
+    let mut removed = body.len() < before;
+    for s in body {
+        match &mut s.kind {
+            StmtKind::If { then_body, else_body, .. } => {
+                removed |= remove_unread(then_body, read);
+                if let Some(b) = else_body {
+                    removed |= remove_unread(b, read);
+                }
+            }
+            StmtKind::While { body, .. } | StmtKind::For { body, .. } | StmtKind::Block(body) => removed |= remove_unread(body, read),
+            _ => {}
+        }
+    }
+    removed
+}
+
+fn reads_in_stmts(stmts: &[Stmt], read: &mut HashSet<String>) {
+    for s in stmts {
+        match &s.kind {
+            StmtKind::Let { expr, .. } | StmtKind::Expr(expr) | StmtKind::Return(Some(expr)) => reads_in_expr(expr, read),
+            // an assigned variable keeps its `let`, even if nothing reads it
+            StmtKind::Assign { name, expr, .. } => {
+                read.insert(name.name.clone());
+                reads_in_expr(expr, read);
+            }
+            StmtKind::IndexAssign { name, index, expr, .. } => {
+                read.insert(name.name.clone());
+                reads_in_expr(index, read);
+                reads_in_expr(expr, read);
+            }
+            StmtKind::FieldAssign { name, expr, .. } => {
+                read.insert(name.name.clone());
+                reads_in_expr(expr, read);
+            }
+            StmtKind::Print { args, .. } => args.iter().for_each(|a| reads_in_expr(a, read)),
+            StmtKind::If { cond, then_body, else_body } => {
+                reads_in_expr(cond, read);
+                reads_in_stmts(then_body, read);
+                if let Some(b) = else_body {
+                    reads_in_stmts(b, read);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                reads_in_expr(cond, read);
+                reads_in_stmts(body, read);
+            }
+            StmtKind::For { iter, body, .. } => {
+                match iter {
+                    ForIter::Range(start, end) => {
+                        reads_in_expr(start, read);
+                        reads_in_expr(end, read);
+                    }
+                    ForIter::Array(e) => reads_in_expr(e, read),
+                }
+                reads_in_stmts(body, read);
+            }
+            StmtKind::Block(body) => reads_in_stmts(body, read),
+            StmtKind::Return(None) => {}
+        }
+    }
+}
+
+fn reads_in_expr(e: &Expr, read: &mut HashSet<String>) {
+    if let ExprKind::Var(name) = &e.kind {
+        read.insert(name.clone());
+    }
+    for o in e.operands() {
+        reads_in_expr(o, read);
+    }
+}
+
+/// Whether evaluating `e` can have no effect besides producing its value.
+fn is_pure(e: &Expr) -> bool {
+    let traps = matches!(e.kind, ExprKind::Add(..) | ExprKind::Sub(..) | ExprKind::Mul(..) | ExprKind::Div(..) | ExprKind::UnaryNeg(_))
+        && matches!(e.ty(), Type::Int | Type::I64);
+    // indexing is bounds-checked, and a repeat count may be negative
+    let traps = traps || matches!(e.kind, ExprKind::Index(..) | ExprKind::Repeat { .. });
+    // `arg` and `parse_int` check their argument; `read_line` consumes input
+    let traps = traps || matches!(e.kind, ExprKind::Builtin { func: Builtin::Arg | Builtin::ParseInt | Builtin::ReadLine, .. });
+    !traps && !matches!(e.kind, ExprKind::Call { .. }) && e.operands().into_iter().all(is_pure)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{fmt::format_program, parser::Parser, typeck};
+
+    /// Check and fold `src`, returning the folded program in canonical layout.
+    fn folded(src: &str) -> Result<String, Diagnostics> {
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        fold(&mut program)?;
+        Ok(format_program(&program))
+    }
+
+    #[test]
+    fn folds_constant_operators_into_literals() {
+        let src = std::fs::read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/examples/expr.mini")).unwrap();
+        assert!(folded(&src).unwrap().starts_with("let a = 17;\nlet b = 23;\nlet c = -a + 10;\n"));
+
+        let src = "let x = 1;\nlet a = x + 2 * 3;\nlet b = 7 as f64 / 2.0;\nlet c = 1 < 2 && x > 0;\nlet d = 5 > 9 && x > 0;\nlet e = (3 - 4) as i64 * 2i64;\nlet f = 0.1 + 0.2;\n";
+        assert_eq!(
+            folded(src).unwrap(),
+            "let x = 1;\nlet a = x + 6;\nlet b = 3.5;\nlet c = true && x > 0;\nlet d = false;\nlet e = -2i64;\nlet f = 0.30000000000000004;\n"
+        );
+    }
+
+    #[test]
+    fn folds_constant_strings() {
+        let src = "let n = 4;\nlet a = \"ab\" + \"c\";\nlet b = \"{1 + 2} {true} {{x}}\";\nlet c = len(\"four\") + n;\nlet d = \"n = {n}\";\n";
+        assert_eq!(folded(src).unwrap(), "let n = 4;\nlet a = \"abc\";\nlet b = \"3 true {{x}}\";\nlet c = 4 + n;\nlet d = \"n = {n}\";\n");
+    }
+
+    #[test]
+    fn constant_division_by_zero_is_an_error() {
+        let src = "fn f(n: int) -> int {\n    return n / (2 - 2);\n}\nlet q = 1i64 / 0i64;\nlet ok = 1.0 / 0.0;\n";
+        let diags = folded(src).unwrap_err();
+        let errors: Vec<_> = diags.iter().map(|d| (d.message.as_str(), &src[d.span().unwrap().start..d.span().unwrap().end])).collect();
+        assert_eq!(errors, [("attempt to divide by zero", "n / (2 - 2)"), ("attempt to divide by zero", "1i64 / 0i64")]);
+    }
+
+    #[test]
+    fn overflow_is_left_for_run_time_with_a_warning() {
+        let src = "let big = 2147483647 + 1;\n";
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        let warnings = fold(&mut program).unwrap();
+        assert_eq!(warnings.iter().map(|d| (d.severity, d.message.as_str())).collect::<Vec<_>>(), [(Severity::Warning, "attempt to add with overflow")]);
+        assert_eq!(format_program(&program), src);
+    }
+
+    #[test]
+    fn drops_unread_bindings_without_effects() {
+        let src = "fn f() -> int { let s = \"x\"; println s; return 1; }\nlet a = 1;\nlet b = a;\nlet c = f();\nlet d = a + 1;\nlet e = 2.0 * 3.0;\nlet used = 4;\nwhile used > 5 { let tmp = used > 2; }\nprintln used;\n";
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        fold(&mut program).unwrap();
+        remove_dead_bindings(&mut program);
+        // `b` goes, then `a` is unread too, except by `d`, whose arithmetic could trap
+        let names: Vec<_> = program
+            .stmts
+            .iter()
+            .filter_map(|s| match &s.kind {
+                StmtKind::Let { name, .. } => Some(name.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(names, ["a", "c", "d", "used"]);
+        assert!(format_program(&program).contains("while used > 5 {}"));
+
+        // reading input has an effect, and so does a check that may fail
+        let src = "let a = args();\nlet b = read_line();\nlet c = parse_int(\"1\");\nlet d = len(arg(0));\nprintln args() + 1;\n";
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        fold(&mut program).unwrap();
+        remove_dead_bindings(&mut program);
+        assert_eq!(format_program(&program), "let b = read_line();\nlet c = parse_int(\"1\");\nlet d = len(arg(0));\nprintln args() + 1;\n");
+
+        let mut program = Parser::parse("let a = 1;\nlet b = a;\nlet c = b == 2;\n").unwrap();
+        typeck::check(&mut program).unwrap();
+        remove_dead_bindings(&mut program);
+        assert!(program.stmts.is_empty());
+    }
+}
@@ -0,0 +1,780 @@
+//! Tree-walking interpreter: evaluates a type-checked `ast::Program` directly,
+//! without LLVM or a system linker.
+//!
+//! The semantics match a checked build (the default at `-O0`): integer
+//! overflow and division by zero are runtime errors, `as` converts like
+//! Rust's `as` (saturating from `f64`), `print` writes values separated by
+//! spaces with floats in C's `%g` format, a `let` in a block shadows outer
+//! variables until the block ends, indexing outside an array is an error,
+//! structs are copied like compiled ones, and building strings and arrays
+//! fails once they would overflow the compiled runtime's heap. Command-line
+//! arguments and standard input come from [`Interp::args`] and
+//! [`Interp::input`]; without them a program has no arguments and its input
+//! is empty.
+
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+use crate::ast::{Builtin, Expr, ExprKind, ForIter, Function, Program, Stmt, StmtKind, StrPart, StructDef, Type};
+use crate::diagnostics::Diagnostic;
+use crate::lexer::Span;
+use crate::runtime::{ARRAY_HEADER, HEAP_ALIGN, HEAP_SIZE, PARSE_INT_INVALID, PARSE_INT_RANGE};
+
+/// Runtime errors point at the expression that failed.
+pub type RunResult<T> = Result<T, Diagnostic>;
+
+/// Deepest call nesting allowed before reporting a stack overflow.
+const MAX_CALL_DEPTH: usize = 10_000;
+
+/// A runtime value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i32),
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    Str(Rc<str>),
+    /// Shared, like a compiled array's header.
+    Array(Rc<RefCell<Array>>),
+    /// Copied on write, so assigning a struct copies it like compiled code does.
+    Struct(Rc<Record>),
+}
+
+/// The fields of a struct value, in declaration order.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: Rc<str>,
+    pub fields: Vec<(Rc<str>, Value)>,
+}
+
+impl Record {
+    fn field(&self, name: &str) -> &Value {
+        self.fields.iter().find(|(f, _)| &**f == name).map(|(_, v)| v).expect("typeck checked the field")
+    }
+
+    fn field_mut(&mut self, name: &str) -> &mut Value {
+        self.fields.iter_mut().find(|(f, _)| &**f == name).map(|(_, v)| v).expect("typeck checked the field")
+    }
+}
+
+/// The elements of an array, and how many the compiled runtime would have
+/// room for, which decides when a `push` takes more heap.
+#[derive(Debug, PartialEq)]
+pub struct Array {
+    pub items: Vec<i32>,
+    cap: usize,
+}
+
+impl Value {
+    /// An integer or `bool` widened to 64 bits.
+    fn i64(&self) -> i64 {
+        match self {
+            Value::Int(v) => *v as i64,
+            Value::I64(v) => *v,
+            Value::Bool(b) => *b as i64,
+            _ => unreachable!("typeck guarantees an integer here"),
+        }
+    }
+
+    /// Truth value of a condition or logical operand.
+    pub fn truthy(&self) -> bool {
+        match self {
+            Value::Bool(b) => *b,
+            v => v.i64() != 0,
+        }
+    }
+
+    /// Order two values of the same type; `None` when either is NaN.
+    fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::F64(a), Value::F64(b)) => a.partial_cmp(b),
+            (Value::Str(a), Value::Str(b)) => Some(a.as_bytes().cmp(b.as_bytes())),
+            (a, b) => Some(a.i64().cmp(&b.i64())),
+        }
+    }
+
+    /// Convert to `to` the way an `as` expression does.
+    fn cast(self, to: Type) -> Value {
+        match (self, to) {
+            // float to integer saturates, and NaN becomes 0
+            (Value::F64(x), Type::Int) => Value::Int(x as i32),
+            (Value::F64(x), Type::I64) => Value::I64(x as i64),
+            (v @ Value::F64(_), Type::F64) => v,
+            (v, Type::Int) => Value::Int(v.i64() as i32),
+            (v, Type::I64) => Value::I64(v.i64()),
+            (v, Type::F64) => Value::F64(v.i64() as f64),
+            (v, _) => v,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(v) => write!(f, "{}", v),
+            Value::I64(v) => write!(f, "{}", v),
+            Value::F64(v) => f.write_str(&fmt_g(*v)),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Str(s) => f.write_str(s),
+            Value::Array(a) => {
+                let items: Vec<String> = a.borrow().items.iter().map(i32::to_string).collect();
+                write!(f, "[{}]", items.join(", "))
+            }
+            Value::Struct(r) if r.fields.is_empty() => write!(f, "{} {{}}", r.name),
+            Value::Struct(r) => {
+                let fields: Vec<String> = r.fields.iter().map(|(name, v)| format!("{}: {}", name, v)).collect();
+                write!(f, "{} {{ {} }}", r.name, fields.join(", "))
+            }
+        }
+    }
+}
+
+/// Format `v` like C's `printf("%g")`, which is what compiled programs print:
+/// six significant digits, trailing zeros removed, and scientific notation
+/// for exponents below -4 or above 5.
+pub fn fmt_g(v: f64) -> String {
+    if v.is_nan() {
+        return if v.is_sign_negative() { "-nan" } else { "nan" }.into();
+    }
+    if v.is_infinite() {
+        return if v < 0.0 { "-inf" } else { "inf" }.into();
+    }
+    let sci = format!("{:.5e}", v);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    let trim = |s: &str| match s.contains('.') {
+        true => s.trim_end_matches('0').trim_end_matches('.').to_string(),
+        false => s.to_string(),
+    };
+    if (-4..6).contains(&exp) {
+        trim(&format!("{:.*}", (5 - exp) as usize, v))
+    } else {
+        format!("{}e{}{:02}", trim(mantissa), if exp < 0 { '-' } else { '+' }, exp.abs())
+    }
+}
+
+/// How a statement finished.
+enum Flow {
+    Normal,
+    Return(Option<Value>),
+}
+
+/// Interpreter state. Functions and top-level bindings persist across calls to
+/// [`Interp::run`], which is what lets the REPL build a program line by line.
+pub struct Interp<W: Write> {
+    out: W,
+    functions: HashMap<String, Rc<Function>>,
+    structs: HashMap<String, StructDef>,
+    /// Innermost scope last; the first scope holds top-level bindings
+    /// (or, during a call, the callee's parameters).
+    scopes: Vec<HashMap<String, Value>>,
+    depth: usize,
+    /// Bytes of heap the compiled runtime would have handed out for strings
+    /// and arrays so far; like it, nothing is freed.
+    heap_used: usize,
+    /// What `arg(i)` returns, without the program name.
+    args: Vec<String>,
+    /// Where `read_line` reads from.
+    input: Box<dyn BufRead>,
+}
+
+impl<W: Write> Interp<W> {
+    /// Create an interpreter that prints to `out`.
+    pub fn new(out: W) -> Self {
+        Self {
+            out,
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            scopes: vec![HashMap::new()],
+            depth: 0,
+            heap_used: 0,
+            args: vec![],
+            input: Box::new(std::io::empty()),
+        }
+    }
+
+    /// Give the program command-line arguments, not counting its name.
+    pub fn args(mut self, args: Vec<String>) -> Self {
+        self.args = args;
+        self
+    }
+
+    /// Have `read_line` read from `input`.
+    pub fn input(mut self, input: impl BufRead + 'static) -> Self {
+        self.input = Box::new(input);
+        self
+    }
+
+    /// Define the program's structs and functions, then execute its top-level
+    /// statements.
+    pub fn run(&mut self, program: &Program) -> RunResult<()> {
+        for s in &program.structs {
+            self.structs.insert(s.name.name.clone(), s.clone());
+        }
+        for f in &program.functions {
+            self.functions.insert(f.name.name.clone(), Rc::new(f.clone()));
+        }
+        // flush even on failure so output appears before the error report
+        let flow = self.exec_stmts(&program.stmts);
+        self.out.flush().map_err(io_error)?;
+        flow.map(|_| ())
+    }
+
+    /// Evaluate a single expression against the current bindings.
+    pub fn eval(&mut self, expr: &Expr) -> RunResult<Option<Value>> {
+        match &expr.kind {
+            ExprKind::Call { name, args } => self.call(name, args, expr),
+            ExprKind::Builtin { func: Builtin::Push, args } => {
+                let (a, x) = (self.array(&args[0])?, self.value(&args[1])?.i64() as i32);
+                let mut a = a.borrow_mut();
+                // full arrays move to a block twice the size, as in compiled code
+                if a.items.len() == a.cap {
+                    a.cap = (a.cap * 2).max(4);
+                    self.charge(expr.span, 4 * a.cap, "this array does not fit")?;
+                }
+                a.items.push(x);
+                Ok(None)
+            }
+            _ => self.value(expr).map(Some),
+        }
+    }
+
+    /// The writer `print` output goes to.
+    pub fn output(&mut self) -> &mut W {
+        &mut self.out
+    }
+
+    fn exec_stmts(&mut self, stmts: &[Stmt]) -> RunResult<Flow> {
+        for stmt in stmts {
+            if let Flow::Return(v) = self.exec(stmt)? {
+                return Ok(Flow::Return(v));
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    /// Run a nested block; names first bound inside it are dropped afterwards.
+    fn exec_block(&mut self, stmts: &[Stmt]) -> RunResult<Flow> {
+        self.scopes.push(HashMap::new());
+        let flow = self.exec_stmts(stmts);
+        self.scopes.pop();
+        flow
+    }
+
+    fn exec(&mut self, stmt: &Stmt) -> RunResult<Flow> {
+        match &stmt.kind {
+            StmtKind::Let { name, expr, .. } => {
+                let v = self.value(expr)?;
+                self.scopes.last_mut().unwrap().insert(name.name.clone(), v);
+            }
+            StmtKind::Assign { name, expr, .. } => {
+                let v = self.value(expr)?;
+                let slot = self.scopes.iter_mut().rev().find_map(|s| s.get_mut(&name.name));
+                *slot.expect("typeck guarantees every variable is defined") = v;
+            }
+            StmtKind::IndexAssign { name, index, expr, compound } => {
+                let Value::Array(a) = self.lookup(&name.name) else { unreachable!("typeck guarantees an array here") };
+                let i = self.value(index)?.i64();
+                let v = match (compound, &expr.kind) {
+                    // `a[i] op= e` reads the element through the index computed above
+                    (true, ExprKind::Add(_, rhs) | ExprKind::Sub(_, rhs) | ExprKind::Mul(_, rhs) | ExprKind::Div(_, rhs)) => {
+                        let old = element(&a, i, stmt.span)?;
+                        let r = self.value(rhs)?;
+                        arith(expr, Value::Int(old), r)?
+                    }
+                    _ => self.value(expr)?,
+                };
+                element(&a, i, stmt.span)?;
+                a.borrow_mut().items[i as usize] = v.i64() as i32;
+            }
+            StmtKind::FieldAssign { name, path, expr, .. } => {
+                let v = self.value(expr)?;
+                let mut slot = self.scopes.iter_mut().rev().find_map(|s| s.get_mut(&name.name)).expect("typeck guarantees every variable is defined");
+                for field in path {
+                    let Value::Struct(r) = slot else { unreachable!("typeck guarantees a struct here") };
+                    slot = Rc::make_mut(r).field_mut(&field.name);
+                }
+                *slot = v;
+            }
+            StmtKind::Print { args, newline } => {
+                let mut line = Vec::new();
+                for a in args {
+                    line.push(self.display(a)?);
+                }
+                let end = if *newline { "\n" } else { "" };
+                write!(self.out, "{}{}", line.join(" "), end).map_err(io_error)?;
+            }
+            StmtKind::If { cond, then_body, else_body } => {
+                if self.value(cond)?.truthy() {
+                    return self.exec_block(then_body);
+                } else if let Some(body) = else_body {
+                    return self.exec_block(body);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                while self.value(cond)?.truthy() {
+                    if let Flow::Return(v) = self.exec_block(body)? {
+                        return Ok(Flow::Return(v));
+                    }
+                }
+            }
+            StmtKind::For { var, iter, body } => {
+                // the end of a range, or the length of an array, is fixed when the loop starts
+                let (array, start, end) = match iter {
+                    ForIter::Range(start, end) => (None, self.value(start)?, self.value(end)?.i64()),
+                    ForIter::Array(e) => {
+                        let a = self.array(e)?;
+                        let len = a.borrow().items.len() as i64;
+                        (Some(a), Value::Int(0), len)
+                    }
+                };
+                for i in start.i64()..end {
+                    let v = match (&array, &start) {
+                        (Some(a), _) => Value::Int(a.borrow().items[i as usize]),
+                        (None, Value::Int(_)) => Value::Int(i as i32),
+                        (None, _) => Value::I64(i),
+                    };
+                    self.scopes.push(HashMap::from([(var.name.clone(), v)]));
+                    let flow = self.exec_block(body);
+                    self.scopes.pop();
+                    if let Flow::Return(v) = flow? {
+                        return Ok(Flow::Return(v));
+                    }
+                }
+            }
+            StmtKind::Block(body) => return self.exec_block(body),
+            StmtKind::Return(value) => {
+                let v = value.as_ref().map(|e| self.value(e)).transpose()?;
+                return Ok(Flow::Return(v));
+            }
+            StmtKind::Expr(e) => {
+                self.eval(e)?;
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn lookup(&self, name: &str) -> Value {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|s| s.get(name))
+            .cloned()
+            .expect("typeck guarantees every variable is defined")
+    }
+
+    /// Evaluate an expression that produces a value.
+    fn value(&mut self, expr: &Expr) -> RunResult<Value> {
+        let flag = |b: bool| Ok(Value::Bool(b));
+        match &expr.kind {
+            ExprKind::Int(v) => Ok(Value::Int(*v)),
+            ExprKind::I64(v) => Ok(Value::I64(*v)),
+            ExprKind::Float(v) => Ok(Value::F64(*v)),
+            ExprKind::Bool(b) => flag(*b),
+            ExprKind::Str(s) => Ok(Value::Str(s.as_str().into())),
+            ExprKind::Var(name) => Ok(self.lookup(name)),
+            ExprKind::UnaryNeg(e) => match self.value(e)? {
+                Value::Int(v) => v.checked_neg().map(Value::Int).ok_or_else(|| overflow(expr, Type::Int)),
+                Value::I64(v) => v.checked_neg().map(Value::I64).ok_or_else(|| overflow(expr, Type::I64)),
+                Value::F64(v) => Ok(Value::F64(-v)),
+                _ => unreachable!("typeck guarantees a number here"),
+            },
+            ExprKind::Not(e) => flag(!self.value(e)?.truthy()),
+            ExprKind::Add(a, b) | ExprKind::Sub(a, b) | ExprKind::Mul(a, b) | ExprKind::Div(a, b) => {
+                match (self.value(a)?, self.value(b)?) {
+                    (Value::Str(l), Value::Str(r)) => self.alloc(expr, format!("{}{}", l, r)),
+                    (l, r) => arith(expr, l, r),
+                }
+            }
+            ExprKind::Eq(a, b) => flag(self.value(a)? == self.value(b)?),
+            ExprKind::Ne(a, b) => flag(self.value(a)? != self.value(b)?),
+            ExprKind::Lt(a, b) => flag(self.value(a)?.compare(&self.value(b)?) == Some(Ordering::Less)),
+            ExprKind::Le(a, b) => flag(matches!(self.value(a)?.compare(&self.value(b)?), Some(Ordering::Less | Ordering::Equal))),
+            ExprKind::Gt(a, b) => flag(self.value(a)?.compare(&self.value(b)?) == Some(Ordering::Greater)),
+            ExprKind::Ge(a, b) => flag(matches!(self.value(a)?.compare(&self.value(b)?), Some(Ordering::Greater | Ordering::Equal))),
+            // short-circuit: the right operand only runs when it decides the result
+            ExprKind::And(a, b) => flag(self.value(a)?.truthy() && self.value(b)?.truthy()),
+            ExprKind::Or(a, b) => flag(self.value(a)?.truthy() || self.value(b)?.truthy()),
+            ExprKind::Cast(e, ty) => Ok(self.value(e)?.cast(ty.clone())),
+            ExprKind::Call { name, args } => {
+                Ok(self.call(name, args, expr)?.expect("typeck guarantees the callee returns a value"))
+            }
+            ExprKind::Builtin { func: Builtin::Len, args } => match self.value(&args[0])? {
+                Value::Str(s) => Ok(Value::Int(s.len() as i32)),
+                Value::Array(a) => Ok(Value::Int(a.borrow().items.len() as i32)),
+                _ => unreachable!("typeck guarantees a string or an array here"),
+            },
+            ExprKind::Builtin { func: Builtin::Push, .. } => unreachable!("typeck rejects `push` as a value"),
+            ExprKind::Builtin { func: Builtin::Args, .. } => Ok(Value::Int(self.args.len() as i32)),
+            ExprKind::Builtin { func: Builtin::Arg, args } => {
+                let i = self.value(&args[0])?.i64();
+                match usize::try_from(i).ok().and_then(|i| self.args.get(i)) {
+                    Some(a) => Ok(Value::Str(a.as_str().into())),
+                    None => Err(Diagnostic::error(format!(
+                        "argument index out of bounds: the count is {} but the index is {}",
+                        self.args.len(),
+                        i
+                    ))
+                    .primary(expr.span, "no such argument")),
+                }
+            }
+            ExprKind::Builtin { func: Builtin::ReadLine, .. } => {
+                // show a prompt printed without a newline before waiting for the answer
+                self.out.flush().map_err(io_error)?;
+                let mut line = Vec::new();
+                self.input
+                    .read_until(b'\n', &mut line)
+                    .map_err(|e| Diagnostic::error(format!("failed to read input: {}", e)).primary(expr.span, "while reading this line"))?;
+                self.alloc(expr, String::from_utf8_lossy(&line).into_owned())
+            }
+            ExprKind::Builtin { func: Builtin::ParseInt, args } => match self.value(&args[0])? {
+                Value::Str(s) => parse_int(&s).map(Value::Int).map_err(|message| {
+                    let label = if message == PARSE_INT_RANGE { "does not fit in `int`" } else { "not a whole number" };
+                    Diagnostic::error(message).primary(expr.span, label)
+                }),
+                _ => unreachable!("typeck guarantees a string here"),
+            },
+            ExprKind::Array(items) => {
+                let items = items.iter().map(|e| Ok(self.value(e)?.i64() as i32)).collect::<RunResult<Vec<_>>>()?;
+                self.reserve_array(expr, items.len())?;
+                Ok(array(items))
+            }
+            ExprKind::Repeat { value, count } => {
+                let v = self.value(value)?.i64() as i32;
+                let n = self.value(count)?.i64();
+                if n < 0 {
+                    return Err(Diagnostic::error(format!("array length {} is negative", n)).primary(expr.span, "negative length"));
+                }
+                // reserving first turns a length past the heap's size into an error, not a huge Vec
+                self.reserve_array(expr, n as usize)?;
+                Ok(array(vec![v; n as usize]))
+            }
+            ExprKind::Index(base, index) => {
+                let a = self.array(base)?;
+                let i = self.value(index)?.i64();
+                Ok(Value::Int(element(&a, i, expr.span)?))
+            }
+            ExprKind::StructLit { name, fields } => {
+                // fields are evaluated in source order and stored in declaration order
+                let mut values = HashMap::new();
+                for (field, e) in fields {
+                    values.insert(field.name.as_str(), self.value(e)?);
+                }
+                let def = &self.structs[&name.name];
+                let fields = def.fields.iter().map(|f| (f.name.name.as_str().into(), values.remove(f.name.name.as_str()).unwrap())).collect();
+                Ok(Value::Struct(Rc::new(Record { name: name.name.as_str().into(), fields })))
+            }
+            ExprKind::Field(base, field) => match self.value(base)? {
+                Value::Struct(r) => Ok(r.field(&field.name).clone()),
+                _ => unreachable!("typeck guarantees a struct here"),
+            },
+            // built piece by piece, allocating what compiled code allocates
+            ExprKind::Interp(parts) => {
+                let mut out: Option<Value> = None;
+                for part in parts {
+                    let piece = match part {
+                        StrPart::Lit(s) => Value::Str(s.as_str().into()),
+                        StrPart::Expr(e) => match self.value(e)? {
+                            v @ (Value::Str(_) | Value::Bool(_)) => Value::Str(v.to_string().into()),
+                            v => self.alloc(expr, v.to_string())?,
+                        },
+                    };
+                    out = Some(match out {
+                        Some(acc) => self.alloc(expr, format!("{}{}", acc, piece))?,
+                        None => piece,
+                    });
+                }
+                Ok(out.unwrap_or_else(|| Value::Str("".into())))
+            }
+        }
+    }
+
+    /// The text `print` writes for `e`. Like compiled code, an interpolated
+    /// string is written piece by piece, without taking heap space.
+    fn display(&mut self, e: &Expr) -> RunResult<String> {
+        let ExprKind::Interp(parts) = &e.kind else { return Ok(self.value(e)?.to_string()) };
+        let mut out = String::new();
+        for part in parts {
+            match part {
+                StrPart::Lit(s) => out += s,
+                StrPart::Expr(x) => out += &self.display(x)?,
+            }
+        }
+        Ok(out)
+    }
+
+    /// Make `s` a string value, counting it against the heap compiled programs
+    /// allocate strings from, so the interpreter runs out where they do.
+    fn alloc(&mut self, site: &Expr, s: String) -> RunResult<Value> {
+        self.charge(site.span, s.len() + 1, "this string does not fit")?;
+        Ok(Value::Str(s.into()))
+    }
+
+    /// Take the heap a compiled array of `len` elements takes: a header, then
+    /// room for exactly its elements.
+    fn reserve_array(&mut self, site: &Expr, len: usize) -> RunResult<()> {
+        self.charge(site.span, ARRAY_HEADER as usize, "this array does not fit")?;
+        self.charge(site.span, 4 * len, "this array does not fit")
+    }
+
+    /// Take `bytes` of heap, rounded up as the compiled allocator rounds them;
+    /// running out is an error at `span`, with `label` saying what didn't fit.
+    fn charge(&mut self, span: Span, bytes: usize, label: &str) -> RunResult<()> {
+        let align = HEAP_ALIGN as usize;
+        self.heap_used += bytes.div_ceil(align) * align;
+        if self.heap_used > HEAP_SIZE as usize {
+            let message = format!("out of memory: strings and arrays have used up the {} MiB heap", HEAP_SIZE >> 20);
+            return Err(Diagnostic::error(message).primary(span, label));
+        }
+        Ok(())
+    }
+
+    /// Evaluate an expression typeck proved is an array.
+    fn array(&mut self, e: &Expr) -> RunResult<Rc<RefCell<Array>>> {
+        match self.value(e)? {
+            Value::Array(a) => Ok(a),
+            _ => unreachable!("typeck guarantees an array here"),
+        }
+    }
+
+    /// Call a user function with a fresh set of scopes holding its parameters.
+    fn call(&mut self, name: &str, args: &[Expr], site: &Expr) -> RunResult<Option<Value>> {
+        let f = Rc::clone(&self.functions[name]);
+        let mut params = HashMap::new();
+        for (p, arg) in f.params.iter().zip(args) {
+            params.insert(p.name.name.clone(), self.value(arg)?);
+        }
+        if self.depth == MAX_CALL_DEPTH {
+            return Err(Diagnostic::error("stack overflow")
+                .primary(site.span, format!("call to `{}` nested more than {} deep", name, MAX_CALL_DEPTH)));
+        }
+
+        self.depth += 1;
+        let caller = std::mem::replace(&mut self.scopes, vec![params]);
+        let flow = self.exec_stmts(&f.body);
+        self.scopes = caller;
+        self.depth -= 1;
+
+        Ok(match flow? {
+            Flow::Return(v) => v,
+            Flow::Normal => None,
+        })
+    }
+}
+
+/// Apply the arithmetic operator `expr` to operands of the same numeric type.
+///
+/// Integer results that don't fit their type are errors, as in a checked
+/// build; `int` operands are computed in 64 bits, where they can't overflow,
+/// and range-checked afterwards.
+fn arith(expr: &Expr, l: Value, r: Value) -> RunResult<Value> {
+    if let (Value::F64(x), Value::F64(y)) = (&l, &r) {
+        return Ok(Value::F64(match expr.kind {
+            ExprKind::Add(..) => x + y,
+            ExprKind::Sub(..) => x - y,
+            ExprKind::Mul(..) => x * y,
+            _ => x / y,
+        }));
+    }
+    let (x, y) = (l.i64(), r.i64());
+    let v = match expr.kind {
+        ExprKind::Add(..) => x.checked_add(y),
+        ExprKind::Sub(..) => x.checked_sub(y),
+        ExprKind::Mul(..) => x.checked_mul(y),
+        _ if y == 0 => {
+            return Err(Diagnostic::error("attempt to divide by zero").primary(expr.span, "division by zero"))
+        }
+        _ => x.checked_div(y),
+    };
+    let v = match l {
+        Value::Int(_) => v.and_then(|v| i32::try_from(v).ok()).map(Value::Int),
+        _ => v.map(Value::I64),
+    };
+    v.ok_or_else(|| overflow(expr, expr.ty()))
+}
+
+/// A new array value holding `items`, with no room to spare.
+fn array(items: Vec<i32>) -> Value {
+    let cap = items.len();
+    Value::Array(Rc::new(RefCell::new(Array { items, cap })))
+}
+
+/// Element `i` of `a`, or the error compiled code stops with, at `span`,
+/// when `i` is out of bounds.
+fn element(a: &RefCell<Array>, i: i64, span: Span) -> RunResult<i32> {
+    let items = &a.borrow().items;
+    usize::try_from(i).ok().and_then(|i| items.get(i).copied()).ok_or_else(|| {
+        Diagnostic::error(format!("index out of bounds: the length is {} but the index is {}", items.len(), i))
+            .primary(span, "index out of bounds")
+    })
+}
+
+/// What `parse_int` makes of `s`: an optional sign and decimal digits, with
+/// ASCII whitespace around them; the error messages are the compiled runtime's.
+fn parse_int(s: &str) -> Result<i32, &'static str> {
+    let t = s.trim_matches(|c: char| c.is_ascii_whitespace());
+    let digits = t.strip_prefix(['-', '+']).unwrap_or(t);
+    if digits.is_empty() || !digits.bytes().all(|c| c.is_ascii_digit()) {
+        return Err(PARSE_INT_INVALID);
+    }
+    t.parse().map_err(|_| PARSE_INT_RANGE)
+}
+
+/// The error for an integer result that doesn't fit in `ty`.
+fn overflow(expr: &Expr, ty: Type) -> Diagnostic {
+    Diagnostic::error(expr.overflow_message()).primary(expr.span, format!("result does not fit in `{}`", ty))
+}
+
+fn io_error(e: std::io::Error) -> Diagnostic {
+    Diagnostic::error(format!("failed to write output: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parser::Parser, typeck};
+
+    /// Parse, check and interpret `src`, returning what it printed.
+    fn run(src: &str) -> RunResult<String> {
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        let mut interp = Interp::new(Vec::new());
+        interp.run(&program)?;
+        Ok(String::from_utf8(interp.out).unwrap())
+    }
+
+    #[test]
+    fn runs_the_examples() {
+        let dir = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+        let src = |name: &str| std::fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(run(&src("hello.mini")).unwrap(), "42\nhi\n");
+        assert_eq!(run(&src("control.mini")).unwrap(), "28\n36\nbig\n");
+        assert_eq!(run(&src("functions.mini")).unwrap(), "55\n12\nbig\n20\n");
+    }
+
+    #[test]
+    fn integer_overflow_is_a_runtime_error() {
+        let cases = [
+            ("let big = 2147483647;\nlet x = big + 1;\n", "attempt to add with overflow", "big + 1"),
+            ("let min = -2147483647 - 1;\nprintln min;\nlet y = -min;\n", "attempt to negate with overflow", "-min"),
+            ("let min = -2147483648;\nlet q = min / -1;\n", "attempt to divide with overflow", "min / -1"),
+            ("let a = 3037000500i64;\nlet b = a * a;\n", "attempt to multiply with overflow", "a * a"),
+        ];
+        for (src, message, at) in cases {
+            let err = run(src).unwrap_err();
+            assert_eq!(err.message, message);
+            assert_eq!(err.span().map(|s| &src[s.start..s.end]), Some(at));
+        }
+        // in range of the 64-bit type, a 32-bit result is still checked
+        assert_eq!(run("let a = 65536 as i64 * 65536i64;\nprintln a;\n").unwrap(), "4294967296\n");
+        assert!(run("let a = 65536 * 65536;\n").is_err());
+    }
+
+    #[test]
+    fn assignment_updates_the_visible_variable_and_let_shadows_in_blocks() {
+        let src = "let mut i = 0;\nwhile i < 3 { i += 1; }\nprintln i;\n{ let i = \"inner\"; println i; }\nprintln i;\n{ let mut i = 10; i *= 2; println i; }\nprintln i;\n";
+        assert_eq!(run(src).unwrap(), "3\ninner\n3\n20\n3\n");
+        // a function body may shadow its parameters
+        let src = "fn count(n: int) -> int { let mut n = n; let mut steps = 0; while n > 1 { n /= 2; steps += 1; } return steps; }\nlet s = count(40);\nprintln s;\n";
+        assert_eq!(run(src).unwrap(), "5\n");
+    }
+
+    #[test]
+    fn logical_operators_short_circuit() {
+        let src = "fn boom() -> int { let z = 1 / 0; return z; }\nlet a = 0 && boom();\nlet b = 1 || boom();\nprintln a;\nprintln b;\n";
+        assert_eq!(run(src).unwrap(), "false\ntrue\n");
+    }
+
+    #[test]
+    fn i64_f64_and_bool_values() {
+        let src = "let a = 9223372036854775807i64;\nlet b = -a - 1i64;\nprintln b;\nlet c = 2147483647 as i64 * 2i64;\nprintln c;\nlet d = 7 as f64 / 2.0;\nprintln d;\nlet e = 1e300 * 1e300;\nprintln e;\nlet f = e as int;\nprintln f;\nlet g = -2.9 as int;\nprintln g;\nlet h = true as int + (2.5 > 1.0) as int;\nprintln h;\nlet i = 0.0 / 0.0 != 0.0 / 0.0;\nprintln i;\n";
+        assert_eq!(
+            run(src).unwrap(),
+            "-9223372036854775808\n4294967294\n3.5\ninf\n2147483647\n-2\n2\ntrue\n"
+        );
+    }
+
+    #[test]
+    fn strings_concatenate_compare_and_interpolate() {
+        let src = "let s = \"ab\" + \"c\";\nlet n = len(s);\nlet lt = \"ab\" < s;\nlet msg = \"{s}: {n} {lt} {n as f64 / 4.0} {{x}}\";\nprintln msg;\nlet e = len(\"\");\nprintln e;\n";
+        assert_eq!(run(src).unwrap(), "abc: 3 true 0.75 {x}\n0\n");
+    }
+
+    #[test]
+    fn arrays_are_shared_and_indexing_is_bounds_checked() {
+        let src = "let mut a = [1, 2];\nlet b = a;\npush(a, 3);\na[0] += 10;\nfor x in b { print \"{x},\"; push(a, x); }\nprintln;\nprintln b, len(b);\nfor i in -2..0 { print \"{i},\"; }\nprintln;\n";
+        assert_eq!(run(src).unwrap(), "11,2,3,\n[11, 2, 3, 11, 2, 3] 6\n-2,-1,\n");
+        let src = "let a = [0; 3];\nlet i = len(a);\nlet x = a[i];\n";
+        let err = run(src).unwrap_err();
+        assert_eq!(err.message, "index out of bounds: the length is 3 but the index is 3");
+        assert_eq!(err.span().map(|s| &src[s.start..s.end]), Some("a[i]"));
+        let err = run("let n = -1;\nlet a = [0; n];\n").unwrap_err();
+        assert_eq!(err.message, "array length -1 is negative");
+        let err = run("let big = [0; 2147483647];\n").unwrap_err();
+        assert_eq!(err.message, "out of memory: strings and arrays have used up the 32 MiB heap");
+    }
+
+    #[test]
+    fn reads_arguments_and_lines_of_input() {
+        let src = "println args(), arg(1);\nlet mut total = 0;\nlet mut line = read_line();\nwhile line != \"\" {\n    print line;\n    total += parse_int(line);\n    line = read_line();\n}\nprintln total, read_line() == \"\";\n";
+        let mut program = Parser::parse(src).unwrap();
+        typeck::check(&mut program).unwrap();
+        let mut interp = Interp::new(Vec::new()).args(vec!["-v".into(), "x y".into()]).input(&b"1\n -22 \n+3"[..]);
+        interp.run(&program).unwrap();
+        // the last line has no `\n` to keep
+        assert_eq!(String::from_utf8(interp.out).unwrap(), "2 x y\n1\n -22 \n+3-18 true\n");
+
+        // without input, there are no arguments and no lines
+        assert_eq!(run("println args(), len(read_line());\n").unwrap(), "0 0\n");
+        let err = run("let s = arg(0);\n").unwrap_err();
+        assert_eq!(err.message, "argument index out of bounds: the count is 0 but the index is 0");
+        for (text, message) in [("12a", PARSE_INT_INVALID), ("-", PARSE_INT_INVALID), ("", PARSE_INT_INVALID), ("2147483648", PARSE_INT_RANGE)] {
+            assert_eq!(run(&format!("let n = parse_int(\"{}\");\n", text)).unwrap_err().message, message);
+        }
+        assert_eq!(run("println parse_int(\"-2147483648\"), parse_int(\"\\t007\\n\");\n").unwrap(), "-2147483648 7\n");
+    }
+
+    #[test]
+    fn structs_are_copied_and_print_field_by_field() {
+        let src = "struct P { x: int, tag: string, xs: [int] }\nstruct E {}\nlet mut p = P { xs: [1], tag: \"a\", x: 1 };\nlet mut q = p;\nq.x += 10;\nlet mut xs = q.xs;\npush(xs, 2);\nfn bump(p: P) -> P { let mut p = p; p.x = 7; return p; }\nprintln p, q, bump(p).x, p.x;\nprintln E {};\n";
+        // the copies share the array, like any other copy of an array
+        assert_eq!(run(src).unwrap(), "P { x: 1, tag: a, xs: [1, 2] } P { x: 11, tag: a, xs: [1, 2] } 7 1\nE {}\n");
+    }
+
+    #[test]
+    fn print_separates_values_with_spaces_and_println_ends_the_line() {
+        let src = "let n = 2;\nprint \"n =\", n;\nprint \";\";\nprintln \" half:\", n as f64 / 4.0, n > 1, \"{n}{n}\";\nprintln;\nprintln n;\n";
+        assert_eq!(run(src).unwrap(), "n = 2; half: 0.5 true 22\n\n2\n");
+    }
+
+    #[test]
+    fn floats_print_like_printf_g() {
+        let cases = [
+            (0.1 + 0.2, "0.3"),
+            (2.0 / 3.0, "0.666667"),
+            (1e20, "1e+20"),
+            (123456789.0, "1.23457e+08"),
+            (-0.000012345, "-1.2345e-05"),
+            (0.0001, "0.0001"),
+            (100000.0, "100000"),
+            (999999.5, "1e+06"),
+            (-0.0, "-0"),
+            (1e-300, "1e-300"),
+        ];
+        for (v, want) in cases {
+            assert_eq!(fmt_g(v), want, "{:?}", v);
+        }
+    }
+
+    #[test]
+    fn division_by_zero_is_a_runtime_error() {
+        let src = "let a = 1;\nprintln a;\nlet b = a / (a - 1);\n";
+        let err = run(src).unwrap_err();
+        assert_eq!(err.message, "attempt to divide by zero");
+        assert_eq!(err.span().map(|s| &src[s.start..s.end]), Some("a / (a - 1)"));
+    }
+}
@@ -0,0 +1,50 @@
+//! In-process execution through LLVM's JIT: runs a program's `main` without
+//! writing an object file or invoking a system linker.
+
+use anyhow::{anyhow, Result};
+use inkwell::{
+    targets::{InitializationConfig, Target},
+    OptimizationLevel,
+};
+
+use std::ffi::{c_char, CString};
+
+use crate::codegen::Codegen;
+
+/// Signature of the `main` that codegen emits: C's, with `argc` and `argv`.
+type MainFn = unsafe extern "C" fn(i32, *const *const c_char) -> i32;
+
+extern "C" {
+    // `print` goes through C stdio, whose buffers Rust's own flushing doesn't cover.
+    fn fflush(stream: *mut std::ffi::c_void) -> i32;
+}
+
+/// JIT-compile the module built by `cg` and run its `main` with `args` as
+/// its command line, program name first, returning the program's exit status.
+///
+/// Output is written by the host's C `printf`, exactly as in a native build,
+/// and is flushed before this returns; input is read with the host's
+/// `getchar`.
+pub fn run_main(cg: &Codegen, args: &[String]) -> Result<i32> {
+    let module = cg.module();
+    module.verify().map_err(|e| anyhow!(e.to_string()))?;
+    Target::initialize_native(&InitializationConfig::default()).map_err(|e| anyhow!(e))?;
+    let ee = module
+        .create_jit_execution_engine(OptimizationLevel::None)
+        .map_err(|e| anyhow!(e.to_string()))?;
+
+    // a NUL can't be passed on a real command line either
+    let args = args.iter().map(|a| CString::new(a.as_str())).collect::<Result<Vec<_>, _>>()?;
+    let mut argv: Vec<*const c_char> = args.iter().map(|a| a.as_ptr()).collect();
+    argv.push(std::ptr::null());
+
+    // SAFETY: codegen always emits `main` as `i32 (i32, ptr)`, `argv` is
+    // NULL-terminated like C's and outlives the call, and so does the
+    // execution engine.
+    unsafe {
+        let main = ee.get_function::<MainFn>("main")?;
+        let status = main.call(args.len() as i32, argv.as_ptr());
+        fflush(std::ptr::null_mut());
+        Ok(status)
+    }
+}
@@ -0,0 +1,540 @@
+//! Tokenizer for Mini source: turns the whole file into a flat token stream with byte spans.
+
+use crate::diagnostics::{Diagnostic, Diagnostics};
+
+/// Half-open byte range `start..end` into the source text.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Self { Self { start, end } }
+
+    /// Smallest span covering both `self` and `other`.
+    pub fn to(self, other: Span) -> Span { Span::new(self.start.min(other.start), self.end.max(other.end)) }
+}
+
+/// Compute the 1-based line and column of a byte offset.
+pub fn line_col(src: &str, offset: usize) -> (usize, usize) {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count() + 1;
+    let col = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, col)
+}
+
+/// The text of every file in a program, laid end to end, so that a span
+/// from any of them is an offset into [`SourceMap::text`].
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    text: String,
+    /// Each file's path and the offset its text starts at, in order.
+    files: Vec<(String, usize)>,
+}
+
+impl SourceMap {
+    /// The map of a program that is all in one file.
+    pub fn single(path: &str, src: &str) -> Self {
+        let mut map = SourceMap::default();
+        map.add(path, src);
+        map
+    }
+
+    /// Append a file; returns the offset its text starts at.
+    pub fn add(&mut self, path: &str, src: &str) -> usize {
+        // a newline keeps the end-of-file span of one file out of the next
+        if !self.files.is_empty() {
+            self.text.push('\n');
+        }
+        let base = self.text.len();
+        self.text += src;
+        self.files.push((path.to_string(), base));
+        base
+    }
+
+    /// Every file's text, in the order they were added.
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Each file's path, text and starting offset.
+    pub fn files(&self) -> impl Iterator<Item = (&str, &str, usize)> {
+        (0..self.files.len()).map(|i| self.file_by_index(i))
+    }
+
+    /// The file `offset` falls in: its path, text and starting offset.
+    pub fn file(&self, offset: usize) -> (&str, &str, usize) {
+        self.file_by_index(self.files.partition_point(|(_, base)| *base <= offset).saturating_sub(1))
+    }
+
+    fn file_by_index(&self, i: usize) -> (&str, &str, usize) {
+        let (path, base) = &self.files[i];
+        let end = self.files.get(i + 1).map_or(self.text.len(), |(_, next)| next - 1);
+        (path, &self.text[*base..end], *base)
+    }
+
+    /// The path of the file `offset` falls in, with the 1-based line and
+    /// column of `offset` in it.
+    pub fn locate(&self, offset: usize) -> (&str, usize, usize) {
+        let (path, src, base) = self.file(offset);
+        let (line, col) = line_col(src, offset - base);
+        (path, line, col)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Tok {
+    /// Unsuffixed integer literal; its range is checked by the parser, which
+    /// knows whether it is negated.
+    Int(u64),
+    /// Integer literal with an `i64` suffix.
+    I64(u64),
+    Float(f64),
+    Str(String),
+    /// String literal with `{expr}` interpolations.
+    Interp(Vec<StrPiece>),
+    Ident(String),
+    // keywords
+    Let,
+    Mut,
+    Print,
+    Println,
+    Fn,
+    Struct,
+    Pub,
+    Import,
+    Return,
+    If,
+    Else,
+    While,
+    For,
+    In,
+    True,
+    False,
+    As,
+    // punctuation and operators
+    Plus,
+    Minus,
+    Star,
+    Slash,
+    // compound assignment
+    PlusEq,
+    MinusEq,
+    StarEq,
+    SlashEq,
+    LParen,
+    RParen,
+    LBrace,
+    RBrace,
+    LBracket,
+    RBracket,
+    Comma,
+    Semi,
+    Colon,
+    /// `::` between a module and one of its items, e.g. `math::sq`.
+    ColonColon,
+    Arrow,
+    /// `.` before a field name, e.g. `p.x`.
+    Dot,
+    /// `..` in a range, e.g. `0..n`.
+    DotDot,
+    Assign,
+    EqEq,
+    NotEq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    AndAnd,
+    OrOr,
+    Bang,
+    Eof,
+}
+
+impl Tok {
+    /// Human-readable form used in "expected X, found Y" messages.
+    pub fn describe(&self) -> String {
+        let s = match self {
+            Tok::Int(v) => return format!("integer `{}`", v),
+            Tok::I64(v) => return format!("integer `{}i64`", v),
+            Tok::Float(v) => return format!("float `{:?}`", v),
+            Tok::Str(_) | Tok::Interp(_) => return "string literal".into(),
+            Tok::Ident(name) => return format!("identifier `{}`", name),
+            Tok::Eof => return "end of file".into(),
+            Tok::Let => "let",
+            Tok::Mut => "mut",
+            Tok::Print => "print",
+            Tok::Println => "println",
+            Tok::Fn => "fn",
+            Tok::Struct => "struct",
+            Tok::Pub => "pub",
+            Tok::Import => "import",
+            Tok::Return => "return",
+            Tok::If => "if",
+            Tok::Else => "else",
+            Tok::While => "while",
+            Tok::For => "for",
+            Tok::In => "in",
+            Tok::True => "true",
+            Tok::False => "false",
+            Tok::As => "as",
+            Tok::Plus => "+",
+            Tok::Minus => "-",
+            Tok::Star => "*",
+            Tok::Slash => "/",
+            Tok::PlusEq => "+=",
+            Tok::MinusEq => "-=",
+            Tok::StarEq => "*=",
+            Tok::SlashEq => "/=",
+            Tok::LParen => "(",
+            Tok::RParen => ")",
+            Tok::LBrace => "{",
+            Tok::RBrace => "}",
+            Tok::LBracket => "[",
+            Tok::RBracket => "]",
+            Tok::Comma => ",",
+            Tok::Semi => ";",
+            Tok::Colon => ":",
+            Tok::ColonColon => "::",
+            Tok::Arrow => "->",
+            Tok::Dot => ".",
+            Tok::DotDot => "..",
+            Tok::Assign => "=",
+            Tok::EqEq => "==",
+            Tok::NotEq => "!=",
+            Tok::Lt => "<",
+            Tok::Le => "<=",
+            Tok::Gt => ">",
+            Tok::Ge => ">=",
+            Tok::AndAnd => "&&",
+            Tok::OrOr => "||",
+            Tok::Bang => "!",
+        };
+        format!("`{}`", s)
+    }
+}
+
+/// A piece of an interpolated string literal: text, or the tokens between
+/// `{` and `}` (ending in an `Eof` at the `}`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum StrPiece {
+    Lit(String),
+    Code(Vec<Token>),
+}
+
+/// A token together with the source range it was read from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub tok: Tok,
+    pub span: Span,
+}
+
+/// A `//` comment. The parser never sees comments; `mini fmt` puts them back.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// From `//` to the end of the line, trailing whitespace removed.
+    pub text: String,
+    pub span: Span,
+}
+
+pub struct Lexer<'a> {
+    s: &'a str,
+    i: usize,
+    diags: Diagnostics,
+    comments: Vec<Comment>,
+}
+
+impl<'a> Lexer<'a> {
+    /// Construct a lexer over a slice of source.
+    pub fn new(s: &'a str) -> Self { Self::starting_at(s, 0) }
+
+    /// Construct a lexer over `s` that starts at byte `start`. Spans stay
+    /// offsets into all of `s`, so the files of a program can be laid end
+    /// to end and each lexed in place.
+    pub fn starting_at(s: &'a str, start: usize) -> Self { Self { s, i: start, diags: Diagnostics::default(), comments: Vec::new() } }
+
+    /// Tokenize the whole input; the token list always ends with a `Tok::Eof` token.
+    ///
+    /// Lexical errors don't stop the scan: the bad input is reported, skipped
+    /// or replaced by a placeholder token, and lexing carries on so the parser
+    /// can report further problems in the same run.
+    pub fn tokenize(self) -> (Vec<Token>, Diagnostics) {
+        let (toks, _, diags) = self.tokenize_with_comments();
+        (toks, diags)
+    }
+
+    /// Like [`Lexer::tokenize`], also returning the comments skipped between
+    /// tokens, in source order.
+    pub fn tokenize_with_comments(mut self) -> (Vec<Token>, Vec<Comment>, Diagnostics) {
+        let mut toks = Vec::new();
+        loop {
+            let Some(t) = self.next_token() else { continue };
+            let done = t.tok == Tok::Eof;
+            toks.push(t);
+            if done {
+                return (toks, self.comments, self.diags);
+            }
+        }
+    }
+
+    fn error(&mut self, span: Span, msg: String, label: &str) {
+        self.diags.push(Diagnostic::error(msg).primary(span, label));
+    }
+
+    /// Skip whitespace and `//` line comments, keeping the comments.
+    fn skip_trivia(&mut self) {
+        let b = self.s.as_bytes();
+        loop {
+            while self.i < b.len() && b[self.i].is_ascii_whitespace() { self.i += 1; }
+            if self.s[self.i..].starts_with("//") {
+                let start = self.i;
+                while self.i < b.len() && b[self.i] != b'\n' { self.i += 1; }
+                let text = self.s[start..self.i].trim_end().to_string();
+                self.comments.push(Comment { span: Span::new(start, start + text.len()), text });
+            } else {
+                return;
+            }
+        }
+    }
+
+    /// Read the next token; `None` means invalid input was skipped.
+    fn next_token(&mut self) -> Option<Token> {
+        self.skip_trivia();
+        let b = self.s.as_bytes();
+        let n = b.len();
+        let start = self.i;
+        let tok = |t: Tok, end: usize| Some(Token { tok: t, span: Span::new(start, end) });
+        if self.i >= n {
+            return tok(Tok::Eof, n);
+        }
+        let c = b[self.i] as char;
+
+        // number (allow leading digits; unary handled in parser)
+        if c.is_ascii_digit() {
+            let t = self.number();
+            return tok(t, self.i);
+        }
+
+        // ident or keyword
+        if c.is_ascii_alphabetic() || c == '_' {
+            self.i += 1;
+            while self.i < n {
+                let ch = b[self.i] as char;
+                if ch.is_ascii_alphanumeric() || ch == '_' { self.i += 1; } else { break; }
+            }
+            let t = match &self.s[start..self.i] {
+                "let" => Tok::Let,
+                "mut" => Tok::Mut,
+                "print" => Tok::Print,
+                "println" => Tok::Println,
+                "fn" => Tok::Fn,
+                "struct" => Tok::Struct,
+                "pub" => Tok::Pub,
+                "import" => Tok::Import,
+                "return" => Tok::Return,
+                "if" => Tok::If,
+                "else" => Tok::Else,
+                "while" => Tok::While,
+                "for" => Tok::For,
+                "in" => Tok::In,
+                "true" => Tok::True,
+                "false" => Tok::False,
+                "as" => Tok::As,
+                name => Tok::Ident(name.to_string()),
+            };
+            return tok(t, self.i);
+        }
+
+        if c == '"' {
+            let t = self.string_literal();
+            return tok(t, self.i);
+        }
+
+        // two-char operators
+        let next = b.get(self.i + 1).map(|&x| x as char);
+        let two = match (c, next) {
+            ('=', Some('=')) => Some(Tok::EqEq),
+            ('!', Some('=')) => Some(Tok::NotEq),
+            ('<', Some('=')) => Some(Tok::Le),
+            ('>', Some('=')) => Some(Tok::Ge),
+            ('&', Some('&')) => Some(Tok::AndAnd),
+            ('|', Some('|')) => Some(Tok::OrOr),
+            ('-', Some('>')) => Some(Tok::Arrow),
+            (':', Some(':')) => Some(Tok::ColonColon),
+            ('.', Some('.')) => Some(Tok::DotDot),
+            ('+', Some('=')) => Some(Tok::PlusEq),
+            ('-', Some('=')) => Some(Tok::MinusEq),
+            ('*', Some('=')) => Some(Tok::StarEq),
+            ('/', Some('=')) => Some(Tok::SlashEq),
+            _ => None,
+        };
+        if let Some(t) = two {
+            self.i += 2;
+            return tok(t, self.i);
+        }
+
+        // single-char tokens
+        let t = match c {
+            '+' => Tok::Plus,
+            '-' => Tok::Minus,
+            '*' => Tok::Star,
+            '/' => Tok::Slash,
+            '(' => Tok::LParen,
+            ')' => Tok::RParen,
+            '{' => Tok::LBrace,
+            '}' => Tok::RBrace,
+            '[' => Tok::LBracket,
+            ']' => Tok::RBracket,
+            ',' => Tok::Comma,
+            ';' => Tok::Semi,
+            ':' => Tok::Colon,
+            '.' => Tok::Dot,
+            '=' => Tok::Assign,
+            '<' => Tok::Lt,
+            '>' => Tok::Gt,
+            '!' => Tok::Bang,
+            _ => {
+                let ch = self.s[start..].chars().next().unwrap();
+                self.i += ch.len_utf8();
+                self.error(Span::new(start, self.i), format!("unexpected character `{}`", ch), "not valid here");
+                return None;
+            }
+        };
+        self.i += 1;
+        tok(t, self.i)
+    }
+
+    /// Read an integer or float literal, with an optional `i64`/`f64` suffix.
+    ///
+    /// A float has a fractional part (`1.5`), an exponent (`1e-3`) or both.
+    fn number(&mut self) -> Tok {
+        let b = self.s.as_bytes();
+        let start = self.i;
+        let digits = |i: &mut usize| {
+            while *i < b.len() && b[*i].is_ascii_digit() { *i += 1; }
+        };
+        digits(&mut self.i);
+        let mut float = false;
+        // `1.` without digits is left alone so the `.` is reported where it is
+        if b.get(self.i) == Some(&b'.') && b.get(self.i + 1).is_some_and(u8::is_ascii_digit) {
+            self.i += 1;
+            digits(&mut self.i);
+            float = true;
+        }
+        if matches!(b.get(self.i), Some(b'e' | b'E')) {
+            let mut j = self.i + 1;
+            if matches!(b.get(j), Some(b'+' | b'-')) { j += 1; }
+            if b.get(j).is_some_and(u8::is_ascii_digit) {
+                self.i = j;
+                digits(&mut self.i);
+                float = true;
+            }
+        }
+        let text = &self.s[start..self.i];
+
+        let suffix_start = self.i;
+        while self.i < b.len() && (b[self.i].is_ascii_alphanumeric() || b[self.i] == b'_') { self.i += 1; }
+        let mut suffix = &self.s[suffix_start..self.i];
+        let span = Span::new(start, self.i);
+        if !matches!(suffix, "" | "i64" | "f64") || (float && suffix == "i64") {
+            // report the suffix, then carry on as if it weren't there
+            let d = Diagnostic::error(format!("invalid suffix `{}` for {} literal", suffix, if float { "float" } else { "number" }))
+                .primary(Span::new(suffix_start, self.i), "invalid suffix")
+                .note(if float { "the valid suffix is `f64`" } else { "the valid suffixes are `i64` and `f64`" });
+            self.diags.push(d);
+            suffix = "";
+        }
+        if float || suffix == "f64" {
+            let v: f64 = text.parse().unwrap();
+            if v.is_infinite() {
+                let msg = format!("floating-point literal `{}` is out of range for `f64`", &self.s[start..self.i]);
+                self.error(span, msg, "too large to represent");
+            }
+            return Tok::Float(v);
+        }
+        let v = text.parse::<u64>().unwrap_or_else(|_| {
+            let msg = format!("integer literal `{}` is out of range for `i64`", &self.s[start..self.i]);
+            self.error(span, msg, "does not fit in 64 bits");
+            0
+        });
+        if suffix.is_empty() { Tok::Int(v) } else { Tok::I64(v) }
+    }
+
+    // Minimal escapes for our language's string literals: \n \t \" \\ {{ }}
+    /// Read and unescape a string literal starting at the opening quote.
+    ///
+    /// Each `{expr}` inside is tokenized on its own, making the literal an
+    /// interpolated string. An unterminated literal is reported and ends at
+    /// the end of its line.
+    fn string_literal(&mut self) -> Tok {
+        let start = self.i;
+        let body = start + 1; // after the opening quote
+        let mut out = String::new();
+        let mut pieces = Vec::new();
+        let mut it = self.s[body..].char_indices().peekable();
+        while let Some((off, c)) = it.next() {
+            match c {
+                '"' => {
+                    self.i = body + off + 1;
+                    if pieces.is_empty() {
+                        return Tok::Str(out);
+                    }
+                    if !out.is_empty() {
+                        pieces.push(StrPiece::Lit(out));
+                    }
+                    return Tok::Interp(pieces);
+                }
+                '\n' => break,
+                '\\' => match it.peek().copied() {
+                    Some((_, '\n')) | None => break,
+                    Some((o, esc)) => {
+                        it.next();
+                        match esc {
+                            'n' => out.push('\n'),
+                            't' => out.push('\t'),
+                            '"' => out.push('"'),
+                            '\\' => out.push('\\'),
+                            other => {
+                                let span = Span::new(body + off, body + o + other.len_utf8());
+                                self.error(span, format!("unsupported escape `\\{}`", other), "unknown escape");
+                            }
+                        }
+                    }
+                },
+                '{' | '}' if it.peek().map(|&(_, n)| n) == Some(c) => {
+                    it.next();
+                    out.push(c);
+                }
+                '{' => {
+                    let open = body + off;
+                    // the expression ends at the first `}`; it can't contain quotes or span lines
+                    let close = self.s[open..].find(['}', '"', '\n']).map(|i| open + i).filter(|&i| self.s[i..].starts_with('}'));
+                    let Some(close) = close else {
+                        self.error(Span::new(open, open + 1), "unclosed `{` in string literal".into(), "expected a matching `}`");
+                        out.push(c);
+                        continue;
+                    };
+                    let inner = Lexer { s: &self.s[..close], i: open + 1, diags: Diagnostics::default(), comments: Vec::new() };
+                    let (toks, diags) = inner.tokenize();
+                    self.diags.extend(diags);
+                    if !out.is_empty() {
+                        pieces.push(StrPiece::Lit(std::mem::take(&mut out)));
+                    }
+                    pieces.push(StrPiece::Code(toks));
+                    while it.next_if(|&(o, _)| body + o <= close).is_some() {}
+                }
+                '}' => {
+                    let span = Span::new(body + off, body + off + 1);
+                    self.error(span, "unmatched `}` in string literal".into(), "write `}}` for a literal `}`");
+                    out.push(c);
+                }
+                c => out.push(c),
+            }
+        }
+        let end = self.s[start..].find('\n').map_or(self.s.len(), |i| start + i);
+        self.i = end;
+        self.error(Span::new(start, end), "unterminated string literal".into(), "missing closing `\"`");
+        Tok::Str(out)
+    }
+}
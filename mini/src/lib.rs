@@ -1,4 +1,17 @@
 pub mod ast;
+pub mod diagnostics;
+pub mod lexer;
 pub mod parser;
+pub mod modules;
+pub mod typeck;
+pub mod fold;
+pub mod fmt;
 pub mod codegen;
+pub mod debuginfo;
+pub mod runtime;
+pub mod driver;
+pub mod jit;
+pub mod interp;
+pub mod repl;
+pub mod lsp;
 pub mod link;
@@ -1,8 +1,11 @@
 //! Platform-specific linking helpers for turning object files into executables.
 
 use anyhow::{bail, Result};
+use std::path::{Path, PathBuf};
 
 /// Invoke the appropriate system linker to produce a runnable binary.
+// Each OS block returns early; only one of them is compiled in.
+#[allow(clippy::needless_return)]
 pub fn link_exe(obj: &std::path::Path, out_exe: &std::path::Path) -> Result<()> {
     #[cfg(target_os = "macos")]
     {
@@ -98,3 +101,76 @@ pub fn link_exe(obj: &std::path::Path, out_exe: &std::path::Path) -> Result<()>
     #[cfg(not(any(target_os = "macos", target_os = "linux", target_os = "windows")))]
     compile_error!("Unsupported OS: this compiler currently supports macOS, Linux, and Windows.");
 }
+
+/// A C compiler driver on `PATH` that links for `triple`, if there is one.
+///
+/// GNU cross toolchains are named after the triple without its vendor, as in
+/// `aarch64-linux-gnu-gcc` for `aarch64-unknown-linux-gnu`.
+pub fn cross_linker(triple: &str) -> Option<PathBuf> {
+    let parts: Vec<&str> = triple.split('-').collect();
+    let mut names = vec![format!("{}-gcc", triple)];
+    if let [arch, "unknown" | "pc", rest @ ..] = parts.as_slice() {
+        names.push(format!("{}-{}-gcc", arch, rest.join("-")));
+    }
+    names.iter().find_map(|n| which::which(n).ok())
+}
+
+/// Link `obj` into `out_exe` with a cross toolchain found by [`cross_linker`].
+pub fn link_with(linker: &Path, obj: &Path, out_exe: &Path) -> Result<()> {
+    let status = std::process::Command::new(linker)
+        .args([obj.to_str().unwrap(), "-o", out_exe.to_str().unwrap()])
+        .status()?;
+    if !status.success() {
+        bail!("{} failed", linker.display());
+    }
+    Ok(())
+}
+
+/// A WebAssembly linker: `wasm-ld`, or the `rust-lld` that ships with Rust toolchains.
+pub fn wasm_linker() -> Option<PathBuf> {
+    if let Ok(ld) = which::which("wasm-ld") {
+        return Some(ld);
+    }
+    if let Ok(lld) = which::which("rust-lld") {
+        return Some(lld);
+    }
+    // rustup keeps rust-lld inside the sysroot rather than on PATH
+    let sysroot = std::process::Command::new("rustc").args(["--print", "sysroot"]).output().ok()?;
+    let rustlib = PathBuf::from(String::from_utf8(sysroot.stdout).ok()?.trim()).join("lib/rustlib");
+    std::fs::read_dir(rustlib)
+        .ok()?
+        .filter_map(|e| e.ok())
+        .map(|e| e.path().join("bin").join(format!("rust-lld{}", std::env::consts::EXE_SUFFIX)))
+        .find(|p| p.is_file())
+}
+
+/// Link a wasm object into a module that starts at `_start` and exports its memory.
+pub fn link_wasm(linker: &Path, obj: &Path, out_wasm: &Path) -> Result<()> {
+    let mut cmd = std::process::Command::new(linker);
+    if linker.file_stem().is_some_and(|s| s == "rust-lld") {
+        cmd.args(["-flavor", "wasm"]);
+    }
+    let status = cmd.args([obj.to_str().unwrap(), "-o", out_wasm.to_str().unwrap()]).status()?;
+    if !status.success() {
+        bail!("{} failed", linker.display());
+    }
+    Ok(())
+}
+
+/// A linker for freestanding executables: `ld.lld` links for any architecture,
+/// the system `ld` only for the `host`.
+pub fn static_linker(host: bool) -> Option<PathBuf> {
+    which::which("ld.lld").ok().or_else(|| if host { which::which("ld").ok() } else { None })
+}
+
+/// Link a freestanding object, which brings its own `_start`, into a static
+/// executable with no C library or dynamic loader.
+pub fn link_static(linker: &Path, obj: &Path, out_exe: &Path) -> Result<()> {
+    let status = std::process::Command::new(linker)
+        .args(["-static", "-e", "_start", obj.to_str().unwrap(), "-o", out_exe.to_str().unwrap()])
+        .status()?;
+    if !status.success() {
+        bail!("{} failed", linker.display());
+    }
+    Ok(())
+}
@@ -0,0 +1,438 @@
+//! Language server for editors, spoken over stdin and stdout by `mini lsp`.
+//!
+//! Every open document is parsed and checked again on each change, the same
+//! way `mini check` does, and its diagnostics are published. The checker's
+//! [`Index`] of definitions and uses answers hover (the definition with its
+//! type), go-to-definition and completion of the names in scope. Documents
+//! are synced whole, so the server never applies edits itself.
+//!
+//! The files a document imports are read from disk, found as `mini check`
+//! finds them with `MINI_PATH` as the search path. Problems in them are
+//! shown at the document's `import`, linking to where they are.
+
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use serde_json::{json, Value};
+
+use crate::ast::Import;
+use crate::diagnostics::{Diagnostic, Diagnostics, Severity};
+use crate::fold;
+use crate::lexer::{SourceMap, Span};
+use crate::modules::Loader;
+use crate::typeck::{self, Index, SymbolKind};
+
+/// Serve requests from `input` until the client sends `exit` or hangs up.
+pub fn run<R: BufRead, W: Write>(mut input: R, out: W) -> io::Result<()> {
+    let mut server = Server { out, docs: HashMap::new() };
+    while let Some(msg) = read_message(&mut input)? {
+        if msg["method"] == "exit" {
+            break;
+        }
+        server.handle(&msg)?;
+    }
+    Ok(())
+}
+
+/// An open document and what checking it found.
+struct Document {
+    /// The document's text, then that of the files it imports.
+    sources: SourceMap,
+    index: Index,
+    /// The document's `import`s.
+    imports: Vec<Import>,
+    /// The `pub` items of the files the document imports, which it can use
+    /// as `module::item`.
+    imported: HashSet<String>,
+}
+
+impl Document {
+    fn src(&self) -> &str {
+        self.sources.file(0).1
+    }
+
+    /// The LSP location of `span`, which may be in an imported file.
+    fn location(&self, uri: &str, span: Span) -> Value {
+        let (path, src, base) = self.sources.file(span.start);
+        let uri = if base == 0 { uri.to_string() } else { file_uri(path) };
+        json!({ "uri": uri, "range": range(src, Span::new(span.start - base, span.end - base)) })
+    }
+}
+
+struct Server<W: Write> {
+    out: W,
+    /// Open documents by URI.
+    docs: HashMap<String, Document>,
+}
+
+impl<W: Write> Server<W> {
+    fn handle(&mut self, msg: &Value) -> io::Result<()> {
+        let params = &msg["params"];
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default().to_string();
+        let result = match msg["method"].as_str().unwrap_or_default() {
+            "initialize" => json!({
+                "capabilities": {
+                    // 1: every change sends the whole document
+                    "textDocumentSync": 1,
+                    "hoverProvider": true,
+                    "definitionProvider": true,
+                    "completionProvider": {},
+                },
+                "serverInfo": { "name": "mini", "version": env!("CARGO_PKG_VERSION") },
+            }),
+            "shutdown" => Value::Null,
+            "textDocument/didOpen" => return self.update(uri, params["textDocument"]["text"].as_str()),
+            // with full sync the last change holds the whole text
+            "textDocument/didChange" => {
+                let text = params["contentChanges"].as_array().and_then(|c| c.last()).and_then(|c| c["text"].as_str());
+                return self.update(uri, text);
+            }
+            "textDocument/didClose" => {
+                self.docs.remove(&uri);
+                return self.notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": [] }));
+            }
+            "textDocument/hover" => self.at(&uri, params, |doc, offset| {
+                let (span, symbol) = doc.index.reference_at(offset)?;
+                let value = format!("```mini\n{}\n```", symbol.detail);
+                Some(json!({ "contents": { "kind": "markdown", "value": value }, "range": range(doc.src(), span) }))
+            }),
+            "textDocument/definition" => self.at(&uri, params, |doc, offset| {
+                let (_, symbol) = doc.index.reference_at(offset)?;
+                Some(doc.location(&uri, symbol.span))
+            }),
+            "textDocument/completion" => self.at(&uri, params, |doc, offset| {
+                let items: Vec<Value> = doc
+                    .index
+                    .visible_at(offset)
+                    .into_iter()
+                    // of other files' items, only what the document can name
+                    .filter(|s| !s.name.contains("::") || doc.imported.contains(&s.name))
+                    .map(|s| {
+                        // LSP's `CompletionItemKind`s
+                        let kind = match s.kind {
+                            SymbolKind::Variable | SymbolKind::Parameter => 6,
+                            SymbolKind::Function => 3,
+                            SymbolKind::Struct => 22,
+                        };
+                        json!({ "label": s.name, "kind": kind, "detail": s.detail })
+                    })
+                    .collect();
+                Some(Value::from(items))
+            }),
+            method if msg.get("id").is_some() => {
+                let error = json!({ "code": -32601, "message": format!("unsupported method `{}`", method) });
+                return self.send(json!({ "jsonrpc": "2.0", "id": msg["id"], "error": error }));
+            }
+            // notifications the server has no use for, such as `initialized`
+            _ => return Ok(()),
+        };
+        self.send(json!({ "jsonrpc": "2.0", "id": msg["id"], "result": result }))
+    }
+
+    /// Answer a request about a position in a document with `answer`, or
+    /// with `null` when there is nothing to say.
+    fn at(&self, uri: &str, params: &Value, answer: impl Fn(&Document, usize) -> Option<Value>) -> Value {
+        let Some(doc) = self.docs.get(uri) else { return Value::Null };
+        answer(doc, offset(doc.src(), &params["position"])).unwrap_or(Value::Null)
+    }
+
+    /// Replace the text of the document at `uri` and publish its diagnostics.
+    fn update(&mut self, uri: String, text: Option<&str>) -> io::Result<()> {
+        let Some(src) = text else { return Ok(()) };
+        let (diags, doc) = analyze(&uri, src);
+        let diagnostics: Vec<Value> = diags.iter().map(|d| diagnostic(&doc, &uri, d)).collect();
+        self.docs.insert(uri.clone(), doc);
+        self.notify("textDocument/publishDiagnostics", json!({ "uri": uri, "diagnostics": diagnostics }))
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.send(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    fn send(&mut self, msg: Value) -> io::Result<()> {
+        let body = msg.to_string();
+        write!(self.out, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+        self.out.flush()
+    }
+}
+
+/// Read one message: `Content-Length` and other headers, a blank line, then
+/// a JSON body. Returns `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                len = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let len = len.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "message without a Content-Length header"))?;
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body).map(Some).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// Check the document at `uri`, containing `src`, as `mini check` does. The
+/// index covers every statement that parsed, so hover and completion keep
+/// working while a line is half typed.
+fn analyze(uri: &str, src: &str) -> (Diagnostics, Document) {
+    let (mut program, sources, parsed) = Loader::new(Loader::search_path(Vec::new())).load(&uri_path(uri), src);
+    let aliases: Vec<String> =
+        program.imports.iter().filter_map(|i| Path::new(&i.path).file_stem()).map(|s| s.to_string_lossy().into_owned()).collect();
+    let imported = program
+        .functions
+        .iter()
+        .map(|f| (&f.name.name, f.public))
+        .chain(program.structs.iter().map(|d| (&d.name.name, d.public)))
+        .filter(|(name, public)| *public && name.split_once("::").is_some_and(|(module, _)| aliases.iter().any(|a| a == module)))
+        .map(|(name, _)| name.clone())
+        .collect();
+    let (checked, index) = typeck::check_indexed(&mut program);
+    let doc = Document { sources, index, imports: program.imports.clone(), imported };
+    if parsed.has_errors() {
+        // checking what's left would report uses of names the broken statements define
+        return (parsed, doc);
+    }
+    let checked = checked.and_then(|mut warnings| {
+        warnings.extend(fold::fold(&mut program)?);
+        Ok(warnings)
+    });
+    (checked.unwrap_or_else(|diags| diags), doc)
+}
+
+/// An LSP diagnostic in the document at `uri` for `d`: its notes join the
+/// message, and its secondary labels become related locations. A problem
+/// in an imported file is shown at the `import` of that file, or at the
+/// first one when it was imported indirectly.
+fn diagnostic(doc: &Document, uri: &str, d: &Diagnostic) -> Value {
+    let mut message = d.message.clone();
+    for note in &d.notes {
+        message += &format!("\nnote: {}", note);
+    }
+    let mut related: Vec<Value> =
+        d.labels.iter().filter(|l| !l.primary).map(|l| json!({ "location": doc.location(uri, l.span), "message": l.message })).collect();
+    let severity = match d.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    let mut span = d.span().unwrap_or(Span { start: 0, end: 0 });
+    let (path, _, base) = doc.sources.file(span.start);
+    if base != 0 {
+        let (_, line, col) = doc.sources.locate(span.start);
+        message = format!("in {}:{}:{}: {}", path, line, col, message);
+        related.insert(0, json!({ "location": doc.location(uri, span), "message": d.message }));
+        let name = Path::new(path).file_name();
+        let import = doc.imports.iter().find(|i| Path::new(&i.path).file_name() == name).or(doc.imports.first());
+        span = import.map_or(span, |i| i.span);
+    }
+    json!({ "range": range(doc.src(), span), "severity": severity, "source": "mini", "message": message, "relatedInformation": related })
+}
+
+/// The path of a `file://` URI; other URIs are used as paths as they are.
+fn uri_path(uri: &str) -> PathBuf {
+    let Some(path) = uri.strip_prefix("file://") else { return PathBuf::from(uri) };
+    // undo percent-encoding, as of spaces
+    let mut bytes = Vec::new();
+    let mut rest = path.as_bytes();
+    while let Some((&b, tail)) = rest.split_first() {
+        let hex = tail.get(..2).and_then(|h| std::str::from_utf8(h).ok()).and_then(|h| u8::from_str_radix(h, 16).ok());
+        match hex {
+            Some(decoded) if b == b'%' => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(b);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The `file://` URI of `path`.
+fn file_uri(path: &str) -> String {
+    let path = std::path::absolute(path).unwrap_or_else(|_| path.into());
+    let mut uri = String::from("file://");
+    for b in path.to_string_lossy().bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => uri.push(b as char),
+            _ => uri += &format!("%{:02X}", b),
+        }
+    }
+    uri
+}
+
+fn range(src: &str, span: Span) -> Value {
+    json!({ "start": position(src, span.start), "end": position(src, span.end) })
+}
+
+/// The LSP position of byte `offset`: a line and a column in UTF-16 code
+/// units, both from zero.
+fn position(src: &str, offset: usize) -> Value {
+    let before = &src[..offset.min(src.len())];
+    let line = before.matches('\n').count();
+    let character: usize = before.rsplit('\n').next().unwrap_or_default().chars().map(char::len_utf16).sum();
+    json!({ "line": line, "character": character })
+}
+
+/// The byte offset of an LSP position, clamped to the end of its line.
+fn offset(src: &str, pos: &Value) -> usize {
+    let line = pos["line"].as_u64().unwrap_or(0) as usize;
+    let character = pos["character"].as_u64().unwrap_or(0) as usize;
+    let start: usize = src.split_inclusive('\n').take(line).map(str::len).sum();
+    let mut units = 0;
+    for (i, c) in src[start..].char_indices() {
+        if c == '\n' || units >= character {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    src.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Run a session of `requests` and return every message the server sent.
+    fn session(requests: &[Value]) -> Vec<Value> {
+        let mut input = Vec::new();
+        for r in requests {
+            let body = r.to_string();
+            input.extend(format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes());
+        }
+        let mut out = Vec::new();
+        run(&input[..], &mut out).unwrap();
+        let mut replies = Vec::new();
+        let mut rest = &out[..];
+        while let Some(msg) = read_message(&mut rest).unwrap() {
+            replies.push(msg);
+        }
+        replies
+    }
+
+    fn open(src: &str) -> Value {
+        json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "textDocument": { "uri": "file:///a.mini", "text": src } } })
+    }
+
+    fn request(id: u32, method: &str, line: u32, character: u32) -> Value {
+        let params = json!({ "textDocument": { "uri": "file:///a.mini" }, "position": { "line": line, "character": character } });
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params })
+    }
+
+    #[test]
+    fn positions_count_utf16_units() {
+        let src = "let s = \"é😀\";\nprintln s;\n";
+        assert_eq!(position(src, src.find(';').unwrap()), json!({ "line": 0, "character": 13 }));
+        assert_eq!(offset(src, &json!({ "line": 0, "character": 13 })), src.find(';').unwrap());
+        assert_eq!(offset(src, &json!({ "line": 1, "character": 8 })), src.rfind('s').unwrap());
+        // past the end of a line is its end
+        assert_eq!(offset(src, &json!({ "line": 1, "character": 99 })), src.len() - 1);
+    }
+
+    #[test]
+    fn publishes_diagnostics_as_mini_check_does() {
+        let got = session(&[open("let x = 1;\nprintln y;\nlet n = 2147483647 + 1;\n")]);
+        let diags = &got[0]["params"]["diagnostics"];
+        assert_eq!(got[0]["method"], "textDocument/publishDiagnostics");
+        assert_eq!(diags.as_array().unwrap().len(), 1);
+        assert_eq!(diags[0]["message"], "undefined variable `y`");
+        assert_eq!(diags[0]["range"], json!({ "start": { "line": 1, "character": 8 }, "end": { "line": 1, "character": 9 } }));
+
+        // without errors, constant-folding warnings show up too
+        let got = session(&[open("let n = 2147483647 + 1;\n")]);
+        assert_eq!(got[0]["params"]["diagnostics"][0]["severity"], 2);
+    }
+
+    #[test]
+    fn hover_and_definition_follow_uses_to_their_definitions() {
+        let src = "struct P { x: int }\nfn sq(n: int) -> int { return n * n; }\nlet mut total = sq(3);\nlet p = P { x: total };\nprintln total, p.x;\n";
+        let got = session(&[
+            open(src),
+            request(1, "textDocument/hover", 4, 10),
+            request(2, "textDocument/definition", 4, 10),
+            request(3, "textDocument/hover", 2, 17),
+            request(4, "textDocument/definition", 1, 30),
+            request(5, "textDocument/hover", 3, 8),
+            request(6, "textDocument/hover", 0, 0),
+        ]);
+        assert_eq!(got[1]["result"]["contents"]["value"], "```mini\nlet mut total: int\n```");
+        assert_eq!(got[2]["result"]["range"]["start"], json!({ "line": 2, "character": 8 }));
+        assert_eq!(got[3]["result"]["contents"]["value"], "```mini\nfn sq(n: int) -> int\n```");
+        assert_eq!(got[4]["result"]["range"]["start"], json!({ "line": 1, "character": 6 }));
+        assert_eq!(got[5]["result"]["contents"]["value"], "```mini\nstruct P { x: int }\n```");
+        assert_eq!(got[6]["result"], Value::Null);
+    }
+
+    #[test]
+    fn completes_the_names_in_scope() {
+        let src = "let g = 1;\nfn f(a: int) {\n    let b = a;\n    \n}\n{\n    let inner = 2;\n}\n\n";
+        let labels = |reply: &Value| -> Vec<String> {
+            reply["result"].as_array().unwrap().iter().map(|i| i["label"].as_str().unwrap().to_string()).collect()
+        };
+        let got = session(&[open(src), request(1, "textDocument/completion", 3, 4), request(2, "textDocument/completion", 8, 0)]);
+        // a function body sees its own names, not the top level's variables
+        assert_eq!(labels(&got[1]), ["a", "b", "f"]);
+        assert_eq!(labels(&got[2]), ["f", "g"]);
+    }
+
+    #[test]
+    fn keeps_answering_while_a_statement_is_half_typed() {
+        let got = session(&[open("let count = 1;\nprintln cou\n"), request(1, "textDocument/completion", 1, 11)]);
+        assert_eq!(got[0]["params"]["diagnostics"][0]["severity"], 1);
+        assert_eq!(got[1]["result"][0]["label"], "count");
+
+        // a misspelled type still reaches the checker, which must not trust it
+        let got = session(&[open("struct P { x: int }\nfn f(p: Pp) -> int { return p.x; }\n"), request(1, "textDocument/hover", 1, 28)]);
+        assert!(got[0]["params"]["diagnostics"][0]["message"].as_str().unwrap().starts_with("unknown type `Pp`"), "{}", got[0]);
+        assert_eq!(got[1]["result"]["contents"]["value"], "```mini\np: Pp\n```");
+    }
+
+    #[test]
+    fn follows_imports_into_other_files() {
+        let dir = std::env::temp_dir().join(format!("mini-lsp-imports-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("geo.mini"), "pub fn area(n: int) -> int {\n    return n * n;\n}\n\nfn secret() {}\n").unwrap();
+        std::fs::write(dir.join("bad.mini"), "pub fn f() -> int {\n    return true;\n}\n").unwrap();
+        let uri = file_uri(dir.join("main.mini").to_str().unwrap());
+        let open = |src: &str| json!({ "jsonrpc": "2.0", "method": "textDocument/didOpen", "params": { "textDocument": { "uri": uri, "text": src } } });
+        let at = |id: u32, method: &str, line: u32, character: u32| {
+            json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": { "textDocument": { "uri": uri }, "position": { "line": line, "character": character } } })
+        };
+        let got = session(&[
+            open("import \"geo.mini\";\nprintln geo::area(2);\n\n"),
+            at(1, "textDocument/definition", 1, 14),
+            at(2, "textDocument/completion", 2, 0),
+            open("import \"geo.mini\";\nimport \"bad.mini\";\n"),
+        ]);
+        assert_eq!(got[0]["params"]["diagnostics"], json!([]));
+        assert_eq!(got[1]["result"]["uri"], file_uri(dir.join("geo.mini").to_str().unwrap()));
+        assert_eq!(got[1]["result"]["range"]["start"], json!({ "line": 0, "character": 7 }));
+        // private items of imported files aren't offered
+        let labels: Vec<&str> = got[2]["result"].as_array().unwrap().iter().map(|i| i["label"].as_str().unwrap()).collect();
+        assert_eq!(labels, ["geo::area"]);
+        // a problem in an imported file shows at its import
+        let d = &got[3]["params"]["diagnostics"][0];
+        assert!(d["message"].as_str().unwrap().starts_with(&format!("in {}:2:12: mismatched types", dir.join("bad.mini").display())), "{}", d);
+        assert_eq!(d["range"]["start"], json!({ "line": 1, "character": 0 }));
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_unknown_requests() {
+        let got = session(&[json!({ "jsonrpc": "2.0", "id": 7, "method": "textDocument/rename", "params": {} })]);
+        assert_eq!(got[0]["id"], 7);
+        assert_eq!(got[0]["error"]["code"], -32601);
+    }
+}
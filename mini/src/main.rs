@@ -1,41 +1,284 @@
-//! Command-line driver: parse source, emit LLVM IR, link into a native executable.
+//! Command-line driver: parse and check source, then compile it through LLVM,
+//! run it in-process with LLVM's JIT, or run it with the interpreter.
 
 use anyhow::Context;
-use std::{env, fs, path::PathBuf};
+use clap::{Args, Parser as ClapParser, Subcommand};
+use std::{
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
 
-use mini::{ast::Program, codegen::{Codegen, host_triple}, link::link_exe, parser::Parser};
+use mini::{
+    ast::Program,
+    codegen::{host_triple, Codegen},
+    driver::{self, BuildOptions, Emit},
+    fmt::format_source,
+    fold,
+    interp::Interp,
+    jit,
+    lexer::SourceMap,
+    lsp,
+    modules::Loader,
+    parser::Parser,
+    repl, runtime, typeck,
+};
 use inkwell::context::Context as LlvmContext;
 
+/// The checker, folder, code generator and interpreter all recurse over the
+/// program (an interpreted Mini call takes tens of KiB of host stack in debug
+/// builds), so every command runs on a thread with enough for the
+/// interpreter's depth limit and for long expressions like `y + y + …`.
+const STACK_SIZE: usize = 1 << 30;
+
+/// Compiler, JIT and interpreter for the Mini language.
+#[derive(ClapParser)]
+#[command(name = "mini", version)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Compile a program to an executable, object file, assembly or LLVM IR
+    Build(BuildArgs),
+    /// Run a program with the interpreter
+    Run {
+        /// Mini source file
+        input: PathBuf,
+        #[command(flatten)]
+        search: SearchPath,
+        /// Arguments for the program, read with `args()` and `arg(i)`
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Compile a program in memory and run it with LLVM's JIT
+    Jit {
+        /// Mini source file
+        input: PathBuf,
+        /// Optimization level
+        #[arg(short = 'O', value_name = "LEVEL", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+        opt_level: u8,
+        #[command(flatten)]
+        checks: Checks,
+        #[command(flatten)]
+        search: SearchPath,
+        /// Arguments for the program, read with `args()` and `arg(i)`
+        #[arg(trailing_var_arg = true)]
+        args: Vec<String>,
+    },
+    /// Report errors and warnings without producing any output
+    Check {
+        /// Mini source file
+        input: PathBuf,
+        #[command(flatten)]
+        search: SearchPath,
+    },
+    /// Print a program in the canonical layout, keeping its comments
+    Fmt {
+        /// Mini source file
+        input: PathBuf,
+        /// Print nothing; fail if the file isn't already formatted
+        #[arg(long)]
+        check: bool,
+    },
+    /// Start an interactive session
+    Repl,
+    /// Serve the Language Server Protocol over stdin and stdout, for editors
+    Lsp,
+}
+
+#[derive(Args)]
+struct BuildArgs {
+    /// Mini source file
+    input: PathBuf,
+    /// Where to write the output [default: the input's name, with the extension for --emit]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+    /// Kind of output to produce
+    #[arg(long, value_enum, default_value_t = Emit::Exe)]
+    emit: Emit,
+    /// Optimization level
+    #[arg(short = 'O', value_name = "LEVEL", default_value_t = 0, value_parser = clap::value_parser!(u8).range(0..=3))]
+    opt_level: u8,
+    /// Print the optimized LLVM IR to stderr
+    #[arg(long)]
+    print_ir: bool,
+    /// Include DWARF debug information, for gdb and lldb
+    #[arg(short = 'g')]
+    debug: bool,
+    #[command(flatten)]
+    checks: Checks,
+    #[command(flatten)]
+    search: SearchPath,
+    /// Target triple to compile for, e.g. aarch64-unknown-linux-gnu [default: the host]
+    #[arg(long)]
+    target: Option<String>,
+    /// CPU to generate code for [default: generic]
+    #[arg(long)]
+    cpu: Option<String>,
+    /// Target features to enable or disable, e.g. +neon,-fp-armv8
+    #[arg(long)]
+    features: Option<String>,
+    /// Use Mini's built-in system-call runtime instead of libc (x86_64 and aarch64 Linux)
+    #[arg(long)]
+    freestanding: bool,
+    /// Keep intermediate files next to the output instead of in a temporary directory
+    #[arg(long)]
+    keep_temps: bool,
+}
+
+/// Whether integer arithmetic is checked; by default only at `-O0`.
+#[derive(Args)]
+struct Checks {
+    /// Stop with an error on integer overflow and division by zero [default: at -O0]
+    #[arg(long, overrides_with = "unchecked")]
+    checked: bool,
+    /// Let integer arithmetic wrap and leave division by zero undefined [default: at -O1 and up]
+    #[arg(long, overrides_with = "checked")]
+    unchecked: bool,
+}
+
+impl Checks {
+    fn enabled(&self, opt_level: u8) -> bool {
+        self.checked || (!self.unchecked && opt_level == 0)
+    }
+}
+
+/// Where `import`ed files are looked for when they aren't next to the importing file.
+#[derive(Args)]
+struct SearchPath {
+    /// Look for imported files in DIR, before the directories in $MINI_PATH; may be repeated
+    #[arg(short = 'I', value_name = "DIR")]
+    include: Vec<PathBuf>,
+}
+
 fn main() -> anyhow::Result<()> {
-    // CLI expects `<input.mini> <output-exe>` for simplicity.
-    let args = env::args().skip(1).collect::<Vec<_>>();
-    if args.len() != 2 {
-        eprintln!("Usage: mini <input.mini> <output-exe>");
+    let command = Cli::parse().command;
+    on_large_stack(|| match command {
+        Command::Build(args) => build(args),
+        Command::Run { input, search, args } => run(&input, search, args),
+        Command::Jit { input, opt_level, checks, search, args } => jit(&input, search, args, opt_level, checks.enabled(opt_level)),
+        Command::Check { input, search } => load(&input, search).map(|_| ()),
+        Command::Fmt { input, check } => {
+            let (src, program) = parse(&input)?;
+            let formatted = format_source(&program, &src);
+            if !check {
+                print!("{}", formatted);
+            } else if formatted != src {
+                let mut lines = src.lines().zip(formatted.lines());
+                let line = lines.position(|(a, b)| a != b).unwrap_or(src.lines().count().min(formatted.lines().count())) + 1;
+                anyhow::bail!("{} is not formatted: line {} differs (run `mini fmt` to see the layout)", input.display(), line);
+            }
+            Ok(())
+        }
+        Command::Repl => Ok(repl::run(io::stdin().lock(), io::stdout().lock())?),
+        Command::Lsp => Ok(lsp::run(io::stdin().lock(), io::stdout().lock())?),
+    })
+}
+
+/// Read and parse a source file; syntax errors are rendered and end the run.
+fn parse(input: &Path) -> anyhow::Result<(String, Program)> {
+    let src = fs::read_to_string(input).with_context(|| format!("reading {:?}", input))?;
+    let program = Parser::parse(&src).unwrap_or_else(|diags| {
+        eprint!("{}", diags.render(&src, &input.display().to_string()));
+        std::process::exit(1);
+    });
+    Ok((src, program))
+}
+
+/// Read, parse, type-check and constant-fold a program: `input` and the
+/// files it imports, which are looked for in `search` too.
+///
+/// Syntax and semantic errors are rendered against the source and end the run.
+fn load(input: &Path, search: SearchPath) -> anyhow::Result<(SourceMap, Program)> {
+    let src = fs::read_to_string(input).with_context(|| format!("reading {:?}", input))?;
+    let (mut program, sources, diags) = Loader::new(Loader::search_path(search.include)).load(input, &src);
+    eprint!("{}", diags.render_in(&sources));
+    if diags.has_errors() {
         std::process::exit(1);
     }
-    let input = PathBuf::from(&args[0]);
-    let out_exe = PathBuf::from(&args[1]);
+    let checked = typeck::check(&mut program).and_then(|mut warnings| {
+        warnings.extend(fold::fold(&mut program)?);
+        Ok(warnings)
+    });
+    match checked {
+        Ok(warnings) => eprint!("{}", warnings.render_in(&sources)),
+        Err(diags) => {
+            eprint!("{}", diags.render_in(&sources));
+            std::process::exit(1);
+        }
+    }
+    Ok((sources, program))
+}
+
+fn build(args: BuildArgs) -> anyhow::Result<()> {
+    let (sources, mut program) = load(&args.input, args.search)?;
+    fold::remove_dead_bindings(&mut program);
+    let output = args.output.unwrap_or_else(|| {
+        let stem = args.input.file_stem().unwrap_or_default();
+        PathBuf::from(stem).with_extension(args.emit.extension(args.target.as_deref()))
+    });
+    let opts = BuildOptions {
+        emit: args.emit,
+        opt_level: args.opt_level,
+        print_ir: args.print_ir,
+        checked: args.checks.enabled(args.opt_level),
+        debug: args.debug,
+        target: args.target,
+        cpu: args.cpu,
+        features: args.features,
+        freestanding: args.freestanding,
+        keep_temps: args.keep_temps,
+    };
+    let written = driver::build(&program, &sources, &opts, &output)?;
 
-    let src = fs::read_to_string(&input).with_context(|| format!("reading {:?}", input))?;
-    let program: Program = Parser::parse(&src)?;
+    // Basic success message so users know where the output landed.
+    println!("Built {}", written.display());
+    Ok(())
+}
+
+/// Compile `input` in memory and run it with LLVM's JIT, with `args` as its
+/// arguments; exits with the program's status.
+fn jit(input: &Path, search: SearchPath, args: Vec<String>, opt_level: u8, checked: bool) -> anyhow::Result<()> {
+    let (sources, mut program) = load(input, search)?;
+    fold::remove_dead_bindings(&mut program);
 
     let ctx = LlvmContext::create();
     let triple = host_triple();
     let mut cg = Codegen::new(&ctx, &triple);
-    cg.emit_program(&program)?;
-    let obj = out_exe.with_extension("o");
-    cg.write_object(&triple, &obj)?;
-    link_exe(&obj, &out_exe)?;
-
-    #[cfg(unix)]
-    {
-        use std::os::unix::fs::PermissionsExt;
-        let mut perm = fs::metadata(&out_exe)?.permissions();
-        perm.set_mode(0o755);
-        fs::set_permissions(&out_exe, perm)?;
+    cg.set_sources(sources);
+    if checked {
+        cg.check_arithmetic();
     }
+    cg.emit_program(&program);
+    driver::optimize(cg.module(), &driver::target_machine(&triple, "generic", "", opt_level)?, opt_level)?;
+    // the program's name comes first, as a native executable sees it
+    let argv: Vec<String> = std::iter::once(input.display().to_string()).chain(args).collect();
+    let status = jit::run_main(&cg, &argv)?;
+    std::process::exit(status);
+}
 
-    // Basic success message so users know where the binary landed.
-    println!("Built {}", out_exe.display());
+/// Interpret `input` directly with `args` as its arguments; runtime errors
+/// are reported like compile errors and exit with the compiled runtime's
+/// status.
+fn run(input: &Path, search: SearchPath, args: Vec<String>) -> anyhow::Result<()> {
+    let (sources, program) = load(input, search)?;
+    if let Err(d) = Interp::new(io::stdout().lock()).args(args).input(io::stdin().lock()).run(&program) {
+        io::stdout().flush()?;
+        eprint!("{}", d.render_in(&sources));
+        std::process::exit(runtime::FAIL_STATUS);
+    }
     Ok(())
 }
+
+/// Run `f` on a thread with a stack of `STACK_SIZE` bytes.
+fn on_large_stack<T: Send + 'static>(f: impl FnOnce() -> T + Send + 'static) -> T {
+    std::thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(f)
+        .expect("failed to spawn the compiler thread")
+        .join()
+        .expect("compiler thread panicked")
+}
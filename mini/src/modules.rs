@@ -0,0 +1,336 @@
+//! Programs in more than one file.
+//!
+//! `import "geometry.mini";` lets a file use the `pub` functions and structs
+//! of `geometry.mini` as `geometry::area` and `geometry::Point`. The
+//! [`Loader`] reads the main file and, depth first, each file it imports,
+//! once however many files import it. Every file but the main one has its
+//! items renamed into its namespace, `area` becoming `geometry::area`, and
+//! all of them are merged into the main file's [`Program`], so the checker
+//! and every backend see a single program. A file's top-level statements
+//! are its own `main`: those of imported files are dropped.
+//!
+//! The files share one offset space through a [`SourceMap`], so a diagnostic
+//! in any of them names the right file and line.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Expr, ExprKind, ForIter, Program, Stmt, StmtKind, Type};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::lexer::{SourceMap, Span};
+use crate::parser::Parser;
+
+/// Reads a program's files and merges them into one [`Program`].
+pub struct Loader {
+    /// Where to look for imported files that aren't next to the importing one.
+    search: Vec<PathBuf>,
+    sources: SourceMap,
+    program: Program,
+    diags: Diagnostics,
+    /// Every file read so far, in the order loading started.
+    modules: Vec<Module>,
+}
+
+/// A file of the program.
+struct Module {
+    /// The file's canonical path, which identifies it however it was reached.
+    path: PathBuf,
+    /// The name other files use for it: its file name without `.mini`.
+    name: String,
+    /// The `import` that first read it; `None` for the main file.
+    imported_at: Option<Span>,
+    /// Its functions and structs by their unqualified names.
+    items: HashMap<String, Item>,
+    /// Whether loading it has finished; one that hasn't is being loaded, and
+    /// importing it again would be a cycle.
+    loaded: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ItemKind {
+    Function,
+    Struct,
+}
+
+impl ItemKind {
+    fn describe(self) -> &'static str {
+        match self {
+            ItemKind::Function => "function",
+            ItemKind::Struct => "struct",
+        }
+    }
+}
+
+struct Item {
+    kind: ItemKind,
+    public: bool,
+    /// The span of its name.
+    span: Span,
+}
+
+impl Loader {
+    /// A loader that looks for imported files next to the importing file,
+    /// then in each of `search` in turn.
+    pub fn new(search: Vec<PathBuf>) -> Self {
+        Self { search, sources: SourceMap::default(), program: Program::default(), diags: Diagnostics::default(), modules: Vec::new() }
+    }
+
+    /// `dirs`, followed by the directories in the `MINI_PATH` environment variable.
+    pub fn search_path(mut dirs: Vec<PathBuf>) -> Vec<PathBuf> {
+        if let Some(paths) = std::env::var_os("MINI_PATH") {
+            dirs.extend(std::env::split_paths(&paths).filter(|p| !p.as_os_str().is_empty()));
+        }
+        dirs
+    }
+
+    /// Load the program whose main file is `path`, containing `src`, with
+    /// every file it imports. Returns as much of the program as could be
+    /// read, the text of its files, and the syntax and import errors found.
+    pub fn load(mut self, path: &Path, src: &str) -> (Program, SourceMap, Diagnostics) {
+        self.load_file(path, src, String::new(), None);
+        self.diags.0.sort_by_key(|d| d.span().map(|s| s.start));
+        (self.program, self.sources, self.diags)
+    }
+
+    /// Read the file at `path` as the module `name`, first the files it
+    /// imports, then itself; returns its index in `modules`.
+    fn load_file(&mut self, path: &Path, src: &str, name: String, imported_at: Option<Span>) -> usize {
+        let base = self.sources.add(&path.display().to_string(), src);
+        let (mut program, diags) = Parser::parse_partial_at(&self.sources.text()[..base + src.len()], base);
+        self.diags.extend(diags);
+
+        let mut items = HashMap::new();
+        for f in &program.functions {
+            items.insert(f.name.name.clone(), Item { kind: ItemKind::Function, public: f.public, span: f.name.span });
+        }
+        for s in &program.structs {
+            items.insert(s.name.name.clone(), Item { kind: ItemKind::Struct, public: s.public, span: s.name.span });
+        }
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        let index = self.modules.len();
+        self.modules.push(Module { path: canonical, name, imported_at, items, loaded: false });
+
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let mut aliases: HashMap<String, Option<usize>> = HashMap::new();
+        for import in &program.imports {
+            let Some(module) = self.import(&dir, &import.path, import.path_span, import.span) else {
+                // uses of a file that couldn't be imported aren't worth reporting too
+                let stem = Path::new(&import.path).file_stem().map(|s| s.to_string_lossy().into_owned());
+                aliases.extend(stem.map(|s| (s, None)));
+                continue;
+            };
+            if aliases.insert(self.modules[module].name.clone(), Some(module)).is_some() {
+                self.diags.push(
+                    Diagnostic::warning(format!("`{}` is imported more than once", import.path))
+                        .primary(import.span, "already imported above"),
+                );
+            }
+        }
+
+        // the main file's names stay as they are; other files' go into their namespace
+        let prefix = if index == 0 { String::new() } else { format!("{}::", self.modules[index].name) };
+        let mut resolver = Resolver { modules: &self.modules, prefix: &prefix, aliases: &aliases, diags: &mut self.diags };
+        for s in &mut program.structs {
+            s.name.name.insert_str(0, &prefix);
+            for field in &mut s.fields {
+                resolver.ty(&mut field.ty, field.span);
+            }
+        }
+        for f in &mut program.functions {
+            f.name.name.insert_str(0, &prefix);
+            for param in &mut f.params {
+                resolver.ty(&mut param.ty, param.span);
+            }
+            if let Some(ret) = &mut f.ret {
+                resolver.ty(ret, f.sig_span);
+            }
+            resolver.stmts(&mut f.body);
+        }
+        if index == 0 {
+            resolver.stmts(&mut program.stmts);
+            self.program.imports = program.imports;
+            self.program.stmts = program.stmts;
+        }
+        self.program.structs.extend(program.structs);
+        self.program.functions.extend(program.functions);
+        self.modules[index].loaded = true;
+        index
+    }
+
+    /// Find and load the file an `import` of `path` in a file in `dir`
+    /// names, unless it already has been; returns its module, or `None`
+    /// after reporting why it can't be used.
+    fn import(&mut self, dir: &Path, path: &str, path_span: Span, span: Span) -> Option<usize> {
+        let candidates: Vec<PathBuf> = std::iter::once(dir).chain(self.search.iter().map(PathBuf::as_path)).map(|d| d.join(path)).collect();
+        let Some(found) = candidates.iter().find(|p| p.is_file()) else {
+            let looked: Vec<String> = candidates.iter().map(|p| p.display().to_string()).collect();
+            self.diags.push(
+                Diagnostic::error(format!("cannot find `{}`", path))
+                    .primary(path_span, "no such file")
+                    .note(format!("looked for {}", looked.join(", "))),
+            );
+            return None;
+        };
+        let name = found.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+        if !is_identifier(&name) {
+            self.diags.push(
+                Diagnostic::error(format!("`{}` can't be imported: its name `{}` is not an identifier", path, name))
+                    .primary(path_span, "imported here")
+                    .note("files are imported under their names without `.mini`, so they must be usable in `name::item`"),
+            );
+            return None;
+        }
+
+        let canonical = fs::canonicalize(found).unwrap_or_else(|_| found.clone());
+        if let Some(i) = self.modules.iter().position(|m| m.path == canonical) {
+            if self.modules[i].loaded {
+                return Some(i);
+            }
+            // the unfinished modules are the chain of imports that led here
+            let chain: Vec<String> = self.modules[i..].iter().filter(|m| !m.loaded).map(|m| format!("`{}`", file_name(&m.path))).collect();
+            let message = match chain.as_slice() {
+                [only] => format!("import cycle: {} imports itself", only),
+                [first, rest @ ..] => format!("import cycle: {} imports {}, which imports {}", first, rest.join(", which imports "), first),
+                [] => unreachable!("the module being imported is unfinished"),
+            };
+            self.diags.push(
+                Diagnostic::error(message)
+                    .primary(span, "imported here")
+                    .note("files can't import each other; move what they share into a third file"),
+            );
+            return None;
+        }
+        if let Some(other) = self.modules.iter().find(|m| m.name == name) {
+            let mut d = Diagnostic::error(format!("`{}` is a different file from the `{}` already imported", found.display(), name))
+                .primary(path_span, "imported here");
+            if let Some(first) = other.imported_at {
+                d = d.secondary(first, format!("`{}` imported here", other.path.display()));
+            }
+            self.diags.push(d.note("files are imported under their names without `.mini`, so two files of a program can't share a name"));
+            return None;
+        }
+
+        match fs::read_to_string(found) {
+            Ok(src) => Some(self.load_file(found, &src, name, Some(span))),
+            Err(e) => {
+                self.diags.push(Diagnostic::error(format!("cannot read `{}`: {}", found.display(), e)).primary(path_span, "imported here"));
+                None
+            }
+        }
+    }
+}
+
+/// Renames the items one file declares and uses to their names in the
+/// merged program, and checks its `module::item` references.
+struct Resolver<'a> {
+    modules: &'a [Module],
+    /// `name::` for an imported file; empty for the main file.
+    prefix: &'a str,
+    /// The modules the file imports, by the name it uses for them; `None`
+    /// for those that couldn't be imported.
+    aliases: &'a HashMap<String, Option<usize>>,
+    diags: &'a mut Diagnostics,
+}
+
+impl Resolver<'_> {
+    /// Resolve `name`, a reference at `span` to a function or struct.
+    fn name(&mut self, name: &mut String, kind: ItemKind, span: Span) {
+        let Some((alias, item)) = name.split_once("::") else {
+            name.insert_str(0, self.prefix);
+            return;
+        };
+        let Some(&found) = self.aliases.get(alias) else {
+            self.diags.push(
+                Diagnostic::error(format!("no module named `{}` is imported", alias))
+                    .primary(span, "used here")
+                    .note(format!("import it with `import \"{}.mini\";` at the top of the file", alias)),
+            );
+            return;
+        };
+        let Some(module) = found else { return };
+        match self.modules[module].items.get(item) {
+            Some(found) if found.kind == kind && !found.public => self.diags.push(
+                Diagnostic::error(format!("{} `{}` is private to module `{}`", kind.describe(), item, alias))
+                    .primary(span, "used here")
+                    .secondary(found.span, "declared here without `pub`")
+                    .note(format!("mark it `pub {}` to use it from other files", if kind == ItemKind::Function { "fn" } else { "struct" })),
+            ),
+            Some(found) if found.kind == kind => {}
+            _ => self.diags.push(
+                Diagnostic::error(format!("module `{}` has no {} named `{}`", alias, kind.describe(), item)).primary(span, "not found"),
+            ),
+        }
+    }
+
+    fn ty(&mut self, ty: &mut Type, span: Span) {
+        if let Type::Struct(name) = ty {
+            self.name(name, ItemKind::Struct, span);
+        }
+    }
+
+    fn stmts(&mut self, stmts: &mut [Stmt]) {
+        for s in stmts {
+            self.stmt(s);
+        }
+    }
+
+    fn stmt(&mut self, s: &mut Stmt) {
+        match &mut s.kind {
+            StmtKind::Let { expr, .. } | StmtKind::Assign { expr, .. } | StmtKind::FieldAssign { expr, .. } | StmtKind::Expr(expr) => self.expr(expr),
+            StmtKind::IndexAssign { index, expr, .. } => {
+                self.expr(index);
+                self.expr(expr);
+            }
+            StmtKind::Print { args, .. } => args.iter_mut().for_each(|e| self.expr(e)),
+            StmtKind::If { cond, then_body, else_body } => {
+                self.expr(cond);
+                self.stmts(then_body);
+                if let Some(body) = else_body {
+                    self.stmts(body);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.expr(cond);
+                self.stmts(body);
+            }
+            StmtKind::For { iter, body, .. } => {
+                match iter {
+                    ForIter::Range(start, end) => {
+                        self.expr(start);
+                        self.expr(end);
+                    }
+                    ForIter::Array(e) => self.expr(e),
+                }
+                self.stmts(body);
+            }
+            StmtKind::Block(body) => self.stmts(body),
+            StmtKind::Return(e) => {
+                if let Some(e) = e {
+                    self.expr(e);
+                }
+            }
+        }
+    }
+
+    fn expr(&mut self, e: &mut Expr) {
+        let span = e.span;
+        match &mut e.kind {
+            ExprKind::Call { name, .. } => self.name(name, ItemKind::Function, span),
+            ExprKind::StructLit { name, .. } => self.name(&mut name.name, ItemKind::Struct, name.span),
+            _ => {}
+        }
+        for operand in e.operands_mut() {
+            self.expr(operand);
+        }
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_') && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn file_name(path: &Path) -> String {
+    path.file_name().map_or_else(|| path.display().to_string(), |n| n.to_string_lossy().into_owned())
+}
@@ -1,223 +1,1160 @@
-//! Hand-rolled parser for Mini source: statement scanning plus a Pratt expression parser.
+//! Hand-rolled parser for Mini source: recursive-descent statements plus a Pratt expression parser.
 
-use anyhow::{bail, Context, Result};
-use regex::Regex;
+use crate::ast::{Builtin, Expr, ExprKind, ForIter, Function, Ident, Import, Param, Program, Stmt, StmtKind, StrPart, StructDef, Type};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::lexer::{Lexer, Span, StrPiece, Tok, Token};
 
-use crate::ast::{Expr, Program, Stmt};
+/// Parse functions fail with the diagnostic for the first problem they hit;
+/// statement-level callers record it and resynchronize.
+type PResult<T> = Result<T, Diagnostic>;
 
-/// Entry point for turning source code into an AST.
-pub struct Parser;
+/// Recursive-descent parser over the token stream of a whole source file.
+pub struct Parser {
+    toks: Vec<Token>,
+    pos: usize,
+    /// Whether we are inside a `fn` body (where `return` is allowed).
+    in_fn: bool,
+    /// Whether `Name {` starts a block rather than a struct literal, as in the
+    /// header of an `if`, `while` or `for`; brackets and parentheses lift it.
+    no_struct: bool,
+    /// Every struct name used as a type, checked against the declarations
+    /// once the whole file has been read.
+    type_names: Vec<Ident>,
+    diags: Diagnostics,
+}
 
 impl Parser {
+    /// Tokenize `src` and prepare to parse it.
+    pub fn new(src: &str) -> Self {
+        let (toks, diags) = Lexer::new(src).tokenize();
+        Self { toks, pos: 0, in_fn: false, no_struct: false, type_names: Vec::new(), diags }
+    }
+
     /// Parse a complete Mini program from raw source text.
     ///
-    /// This handles line-oriented statements (`let`, `print`) and delegates to the
-    /// Pratt parser for arithmetic expressions.
-    pub fn parse(src: &str) -> Result<Program> {
-        let let_re = Regex::new(r#"^let\s+([A-Za-z_]\w*)\s*=\s*(.+);\s*$"#).unwrap();
-        let print_re = Regex::new(r#"^print\s+([A-Za-z_]\w*)\s*;\s*$"#).unwrap();
+    /// Statements are free-form: they may span lines or share one, as the
+    /// grammar is driven by tokens rather than line breaks. After a syntax
+    /// error the parser skips to the next statement boundary and continues,
+    /// so every error in the file is reported in one run.
+    pub fn parse(src: &str) -> Result<Program, Diagnostics> {
+        let (program, diags) = Parser::parse_partial(src);
+        if diags.has_errors() {
+            Err(diags)
+        } else {
+            Ok(program)
+        }
+    }
 
+    /// Parse `src` as far as possible: the items that parsed, which leave
+    /// out the ones with errors, and every problem found.
+    pub fn parse_partial(src: &str) -> (Program, Diagnostics) {
+        Parser::parse_partial_at(src, 0)
+    }
+
+    /// Like [`Parser::parse_partial`], for the part of `src` from byte
+    /// `start` on. Spans stay offsets into all of `src`, which is how the
+    /// files of a multi-file program share one offset space.
+    pub fn parse_partial_at(src: &str, start: usize) -> (Program, Diagnostics) {
+        let (toks, diags) = Lexer::starting_at(src, start).tokenize();
+        let mut parser = Parser { toks, pos: 0, in_fn: false, no_struct: false, type_names: Vec::new(), diags };
+        let program = parser.parse_program();
+        // lexer and parser errors were collected separately; report in source order
+        parser.diags.0.sort_by_key(|d| d.span().map(|s| s.start));
+        (program, parser.diags)
+    }
+
+    // =============== statements ==================
+    //
+    // Grammar (recursive descent):
+    //   program := (import | 'pub'? struct | 'pub'? function | stmt)* EOF
+    //   import := 'import' STRING ';'
+    //   struct := 'struct' IDENT '{' (param (',' param)* ','?)? '}'
+    //   function := 'fn' IDENT '(' (param (',' param)*)? ')' ('->' type)? block
+    //   param := IDENT ':' type
+    //   type := IDENT ('::' IDENT)? | '[' type ']'
+    //   block := '{' stmt* '}'
+    //   stmt := 'let' 'mut'? IDENT '=' expr ';'
+    //         | IDENT ('[' expr ']' | ('.' IDENT)+)? ('=' | '+=' | '-=' | '*=' | '/=') expr ';'
+    //         | 'print' expr (',' expr)* ';'
+    //         | 'println' (expr (',' expr)*)? ';'
+    //         | 'if' expr block ('else' (if-stmt | block))?
+    //         | 'while' expr block
+    //         | 'for' IDENT 'in' expr ('..' expr)? block
+    //         | 'return' expr? ';'
+    //         | block
+    //         | expr ';'
+
+    fn parse_program(&mut self) -> Program {
+        let mut imports = Vec::new();
+        let mut structs = Vec::new();
+        let mut functions = Vec::new();
         let mut stmts = Vec::new();
+        while self.peek() != &Tok::Eof {
+            let start = self.pos;
+            let public = self.peek() == &Tok::Pub;
+            if public {
+                self.bump();
+            }
+            let res = match self.peek() {
+                Tok::Import if !public => self.parse_import().map(|i| imports.push(i)),
+                // a declaration's span starts at its `pub`
+                Tok::Struct => self.parse_struct().map(|d| structs.push(StructDef { public, span: self.toks[start].span.to(d.span), ..d })),
+                Tok::Fn => self.parse_function().map(|f| functions.push(Function { public, sig_span: self.toks[start].span.to(f.sig_span), ..f })),
+                _ if public => Err(Diagnostic::error("only functions and structs can be `pub`")
+                    .primary(self.toks[start].span, "not allowed here")),
+                _ => self.parse_stmt().map(|s| stmts.push(s)),
+            };
+            if let Err(d) = res {
+                self.diags.push(d);
+                self.synchronize(start);
+            }
+        }
+        // structs may be used before they are declared
+        for ident in std::mem::take(&mut self.type_names) {
+            if !structs.iter().any(|d: &StructDef| d.name.name == ident.name) {
+                let d = Diagnostic::error(format!("unknown type `{}`", ident.name))
+                    .primary(ident.span, "not a type")
+                    .note("the available types are `int`, `i64`, `f64`, `bool`, `string`, `[int]` and declared structs");
+                self.diags.push(d);
+            }
+        }
+        Program { imports, structs, functions, stmts }
+    }
 
-        for (lineno, raw) in src.lines().enumerate() {
-            let line = raw.trim();
-            if line.is_empty() || line.starts_with("//") {
-                continue;
+    /// Skip ahead to a likely statement boundary after an error: just past the
+    /// next `;`, or before a `}` or statement keyword.
+    fn synchronize(&mut self, start: usize) {
+        // always make progress past the token that caused the error
+        if self.pos == start {
+            self.bump();
+        }
+        loop {
+            match self.peek() {
+                Tok::Eof
+                | Tok::RBrace
+                | Tok::Let
+                | Tok::Print
+                | Tok::Println
+                | Tok::If
+                | Tok::While
+                | Tok::For
+                | Tok::Fn
+                | Tok::Struct
+                | Tok::Pub
+                | Tok::Import
+                | Tok::Return => return,
+                Tok::Semi => {
+                    self.bump();
+                    return;
+                }
+                _ => {
+                    self.bump();
+                }
+            }
+        }
+    }
+
+    fn parse_function(&mut self) -> PResult<Function> {
+        let fn_span = self.expect(Tok::Fn)?;
+        let name = self.expect_ident()?;
+        self.expect(Tok::LParen)?;
+        let mut params = Vec::new();
+        if self.peek() != &Tok::RParen {
+            loop {
+                let name = self.expect_ident()?;
+                self.expect(Tok::Colon)?;
+                let ty = self.parse_type()?;
+                let span = name.span.to(self.prev_span());
+                params.push(Param { name, ty, span });
+                if !self.eat(&Tok::Comma) {
+                    break;
+                }
             }
+        }
+        self.expect(Tok::RParen)?;
+        let ret = if self.eat(&Tok::Arrow) { Some(self.parse_type()?) } else { None };
+        let sig_span = fn_span.to(self.prev_span());
+
+        let was_in_fn = std::mem::replace(&mut self.in_fn, true);
+        let body = self.parse_block();
+        self.in_fn = was_in_fn;
+        let body = body?;
+        Ok(Function { name, params, ret, body, sig_span, end_span: self.prev_span(), public: false })
+    }
 
-            if let Some(caps) = let_re.captures(line) {
-                let name = caps[1].to_string();
-                let rhs = caps[2].trim();
+    fn parse_import(&mut self) -> PResult<Import> {
+        let start = self.expect(Tok::Import)?;
+        let path_span = self.span();
+        let path = match self.bump() {
+            Tok::Str(path) => path,
+            t => {
+                return Err(Diagnostic::error(format!("expected a file name in quotes, found {}", t.describe()))
+                    .primary(path_span, "expected a string")
+                    .note("write `import \"math.mini\";`"))
+            }
+        };
+        self.expect(Tok::Semi)?;
+        Ok(Import { path, path_span, span: start.to(self.prev_span()) })
+    }
 
-                // string literal?
-                if rhs.starts_with('"') && rhs.ends_with('"') {
-                    let s = parse_string(rhs)
-                        .with_context(|| format!("line {} string literal", lineno + 1))?;
-                    stmts.push(Stmt::Let { name, expr: Expr::Str(s) });
-                    continue;
+    fn parse_struct(&mut self) -> PResult<StructDef> {
+        let start = self.expect(Tok::Struct)?;
+        let name = self.expect_ident()?;
+        self.expect(Tok::LBrace)?;
+        let mut fields = Vec::new();
+        while self.peek() != &Tok::RBrace {
+            let name = self.expect_ident()?;
+            self.expect(Tok::Colon)?;
+            let ty = self.parse_type()?;
+            fields.push(Param { span: name.span.to(self.prev_span()), name, ty });
+            if !self.eat(&Tok::Comma) {
+                break;
+            }
+        }
+        self.expect(Tok::RBrace)?;
+        Ok(StructDef { name, fields, span: start.to(self.prev_span()), public: false })
+    }
+
+    /// Resolve a type annotation. Names other than the built-in types refer
+    /// to structs, which are looked up when the file has been read.
+    fn parse_type(&mut self) -> PResult<Type> {
+        if self.peek() == &Tok::LBracket {
+            let start = self.span();
+            self.bump();
+            let elem = self.parse_type()?;
+            self.expect(Tok::RBracket)?;
+            if elem != Type::Int {
+                let d = Diagnostic::error(format!("arrays of {} are not supported", elem))
+                    .primary(start.to(self.prev_span()), "not a type")
+                    .note("arrays hold `int`s: write `[int]`");
+                self.diags.push(d);
+            }
+            return Ok(Type::Array);
+        }
+        let ident = self.expect_ident()?;
+        if self.eat(&Tok::ColonColon) {
+            // the loader checks the names of other files
+            let item = self.expect_ident()?;
+            return Ok(Type::Struct(format!("{}::{}", ident.name, item.name)));
+        }
+        Ok(Type::from_name(&ident.name).unwrap_or_else(|| {
+            let ty = Type::Struct(ident.name.as_str().into());
+            self.type_names.push(ident);
+            ty
+        }))
+    }
+
+    /// Parse `{ stmt* }`, recovering from errors in individual statements.
+    fn parse_block(&mut self) -> PResult<Vec<Stmt>> {
+        let open = self.expect(Tok::LBrace)?;
+        let mut stmts = Vec::new();
+        loop {
+            match self.peek() {
+                Tok::RBrace => break,
+                Tok::Eof => {
+                    return Err(Diagnostic::error("unclosed block")
+                        .primary(open, "this `{` is never closed")
+                        .secondary(self.span(), "file ends here"))
+                }
+                _ => {
+                    let start = self.pos;
+                    match self.parse_stmt() {
+                        Ok(s) => stmts.push(s),
+                        Err(d) => {
+                            self.diags.push(d);
+                            self.synchronize(start);
+                        }
+                    }
                 }
+            }
+        }
+        self.expect(Tok::RBrace)?;
+        Ok(stmts)
+    }
 
-                // otherwise: integer expression
-                let expr = parse_int_expr(rhs)
-                    .with_context(|| format!("line {}: bad expression `{}`", lineno + 1, rhs))?;
-                stmts.push(Stmt::Let { name, expr });
-                continue;
+    fn parse_stmt(&mut self) -> PResult<Stmt> {
+        let start = self.span();
+        let kind = match self.peek() {
+            Tok::Let => {
+                self.bump();
+                let mutable = self.eat(&Tok::Mut);
+                let name = self.expect_ident()?;
+                self.expect(Tok::Assign)?;
+                let expr = self.parse_expr()?;
+                self.expect(Tok::Semi)?;
+                StmtKind::Let { name, mutable, expr }
+            }
+            Tok::Ident(_) if is_assign_op(self.peek_second()) => self.parse_assign()?,
+            Tok::Print | Tok::Println => {
+                let newline = self.bump() == Tok::Println;
+                let mut args = Vec::new();
+                // a bare `println;` just ends the line
+                if !(newline && self.peek() == &Tok::Semi) {
+                    args.push(self.parse_expr()?);
+                    while self.eat(&Tok::Comma) {
+                        args.push(self.parse_expr()?);
+                    }
+                }
+                self.expect(Tok::Semi)?;
+                StmtKind::Print { args, newline }
+            }
+            Tok::If => return self.parse_if(),
+            Tok::While => {
+                self.bump();
+                let cond = self.parse_header()?;
+                let body = self.parse_block()?;
+                StmtKind::While { cond, body }
             }
+            Tok::For => {
+                self.bump();
+                let var = self.expect_ident()?;
+                self.expect(Tok::In)?;
+                let first = self.parse_header()?;
+                let iter = if self.eat(&Tok::DotDot) { ForIter::Range(first, self.parse_header()?) } else { ForIter::Array(first) };
+                let body = self.parse_block()?;
+                StmtKind::For { var, iter, body }
+            }
+            Tok::Return => {
+                self.bump();
+                let value = if self.peek() == &Tok::Semi { None } else { Some(self.parse_expr()?) };
+                self.expect(Tok::Semi)?;
+                if !self.in_fn {
+                    let d = Diagnostic::error("`return` outside of a function")
+                        .primary(start.to(self.prev_span()), "cannot return from top-level code");
+                    self.diags.push(d);
+                }
+                StmtKind::Return(value)
+            }
+            Tok::LBrace => StmtKind::Block(self.parse_block()?),
+            Tok::Fn => {
+                // parse the whole function so its body doesn't produce follow-on errors
+                let f = self.parse_function()?;
+                let d = Diagnostic::error("functions must be declared at the top level")
+                    .primary(f.sig_span, "nested function declaration");
+                self.diags.push(d);
+                StmtKind::Block(Vec::new())
+            }
+            Tok::Import => {
+                let i = self.parse_import()?;
+                let d = Diagnostic::error("imports must be at the top level").primary(i.span, "nested import");
+                self.diags.push(d);
+                StmtKind::Block(Vec::new())
+            }
+            Tok::Pub => {
+                self.bump();
+                // a nested declaration is reported as such
+                if !matches!(self.peek(), Tok::Fn | Tok::Struct) {
+                    self.diags.push(Diagnostic::error("only top-level functions and structs can be `pub`").primary(start, "not allowed here"));
+                }
+                return self.parse_stmt();
+            }
+            Tok::Struct => {
+                let d = self.parse_struct()?;
+                let d = Diagnostic::error("structs must be declared at the top level").primary(d.span, "nested struct declaration");
+                self.diags.push(d);
+                StmtKind::Block(Vec::new())
+            }
+            Tok::Else => {
+                // report the stray `else`, then parse its body so recovery resumes after it
+                self.bump();
+                self.diags.push(Diagnostic::error("`else` without `if`").primary(start, "no matching `if`"));
+                if self.peek() == &Tok::If {
+                    self.parse_if()?;
+                } else {
+                    self.parse_block()?;
+                }
+                StmtKind::Block(Vec::new())
+            }
+            Tok::RBrace => return Err(Diagnostic::error("unmatched `}`").primary(start, "no matching `{`")),
+            _ => {
+                // anything else is an expression statement, e.g. a call, or an
+                // assignment to an element or field such as `a[i] += 1;`
+                let expr = self.parse_expr()?;
+                if is_assign_op(self.peek()) {
+                    return Ok(Stmt::new(self.parse_place_assign(expr)?, start.to(self.prev_span())));
+                }
+                self.expect(Tok::Semi)?;
+                StmtKind::Expr(expr)
+            }
+        };
+        Ok(Stmt::new(kind, start.to(self.prev_span())))
+    }
+
+    /// Parse `x = e;`, or a compound `x op= e;` as `x = x op e;`.
+    fn parse_assign(&mut self) -> PResult<StmtKind> {
+        let name = self.expect_ident()?;
+        let op = self.bump();
+        let rhs = self.parse_expr()?;
+        self.expect(Tok::Semi)?;
+        let kind = match op {
+            Tok::Assign => return Ok(StmtKind::Assign { name, expr: rhs, compound: false }),
+            Tok::PlusEq => ExprKind::Add,
+            Tok::MinusEq => ExprKind::Sub,
+            Tok::StarEq => ExprKind::Mul,
+            _ => ExprKind::Div,
+        };
+        // the operation spans `x op= e`, so runtime errors point at the whole assignment
+        let span = name.span.to(rhs.span);
+        let lhs = Expr::new(ExprKind::Var(name.name.clone()), name.span);
+        let expr = Expr::new(kind(Box::new(lhs), Box::new(rhs)), span);
+        Ok(StmtKind::Assign { name, expr, compound: true })
+    }
+
+    /// Parse the rest of `a[i] = e;` or `p.x = e;` after the target, with
+    /// compound forms desugared as in [`Parser::parse_assign`].
+    fn parse_place_assign(&mut self, target: Expr) -> PResult<StmtKind> {
+        let invalid = |span| {
+            Diagnostic::error("invalid left-hand side of assignment")
+                .primary(span, "cannot assign to this expression")
+                .note("only variables, elements of array variables (`a[i]`) and fields of struct variables (`p.x`) can be assigned")
+        };
+        // `p.a.b` is a variable followed by a path of field names
+        let mut path = Vec::new();
+        let mut base = &target;
+        while let ExprKind::Field(inner, field) = &base.kind {
+            path.insert(0, field.clone());
+            base = inner;
+        }
+        let (name, index) = match (&base.kind, path.is_empty()) {
+            (ExprKind::Var(name), false) => (Ident { name: name.clone(), span: base.span }, None),
+            (ExprKind::Index(array, index), true) => match &array.kind {
+                ExprKind::Var(name) => (Ident { name: name.clone(), span: array.span }, Some((**index).clone())),
+                _ => return Err(invalid(array.span)),
+            },
+            _ => return Err(invalid(target.span)),
+        };
+        let op = self.bump();
+        let rhs = self.parse_expr()?;
+        self.expect(Tok::Semi)?;
+        let (expr, compound) = match op {
+            Tok::Assign => (rhs, false),
+            op => {
+                let kind = match op {
+                    Tok::PlusEq => ExprKind::Add,
+                    Tok::MinusEq => ExprKind::Sub,
+                    Tok::StarEq => ExprKind::Mul,
+                    _ => ExprKind::Div,
+                };
+                let span = target.span.to(rhs.span);
+                (Expr::new(kind(Box::new(target), Box::new(rhs)), span), true)
+            }
+        };
+        Ok(match index {
+            Some(index) => StmtKind::IndexAssign { name, index, expr, compound },
+            None => StmtKind::FieldAssign { name, path, expr, compound },
+        })
+    }
+
+    /// Parse `if cond { ... }` with any `else` / `else if` continuation.
+    fn parse_if(&mut self) -> PResult<Stmt> {
+        let start = self.expect(Tok::If)?;
+        let cond = self.parse_header()?;
+        let then_body = self.parse_block()?;
+        let else_body = if self.eat(&Tok::Else) {
+            if self.peek() == &Tok::If {
+                Some(vec![self.parse_if()?])
+            } else {
+                Some(self.parse_block()?)
+            }
+        } else {
+            None
+        };
+        Ok(Stmt::new(StmtKind::If { cond, then_body, else_body }, start.to(self.prev_span())))
+    }
+
+    // =============== expression parser ==================
+    //
+    // Grammar (Pratt parser / precedence climbing):
+    //   expr  := parse_bp(0)
+    // operators:
+    //   postfix:     '[' expr ']', '.' IDENT      (index, field) binding power: 15
+    //   postfix:     'as' type                    (conversion)   binding power: 13
+    //   prefix:      '-','!'                      (neg/not)      binding power: 11
+    //   infix left:  '*','/'                      (mul/div)      binding power: 9
+    //   infix left:  '+','-'                      (add/sub)      binding power: 7
+    //   infix left:  '==','!=','<','<=','>','>='  (comparison)   binding power: 5
+    //   infix left:  '&&'                         (logical and)  binding power: 3
+    //   infix left:  '||'                         (logical or)   binding power: 1
+    // atoms: INT, INT 'i64', FLOAT, 'true', 'false', STRING, IDENT, IDENT '(' args ')', '(' expr ')',
+    //        '[' (expr (',' expr)*)? ']', '[' expr ';' expr ']',
+    //        IDENT '{' (IDENT ':' expr (',' IDENT ':' expr)* ','?)? '}'
+    //
+    // The binding powers are shared with `fmt`, which parenthesizes by them.
+    //
+    // `as` binds tighter than the prefix operators, as in Rust: `-x as i64` is
+    // `-(x as i64)`. A `-` directly before a number literal is part of the
+    // literal, so `-2147483648` is a valid `int`. As in Rust, a struct literal
+    // in the header of an `if`, `while` or `for` must be parenthesized.
+
+    /// Parse a full expression.
+    pub fn parse_expr(&mut self) -> PResult<Expr> {
+        self.parse_bp(0)
+    }
 
-            if let Some(caps) = print_re.captures(line) {
-                stmts.push(Stmt::Print { name: caps[1].to_string() });
+    /// Parse the expression before the body of an `if`, `while` or `for`,
+    /// where `x {` is a variable followed by the body.
+    fn parse_header(&mut self) -> PResult<Expr> {
+        let outer = std::mem::replace(&mut self.no_struct, true);
+        let e = self.parse_expr();
+        self.no_struct = outer;
+        e
+    }
+
+    /// Parse an expression inside brackets or parentheses, where struct
+    /// literals are always allowed.
+    fn parse_nested(&mut self) -> PResult<Expr> {
+        let outer = std::mem::replace(&mut self.no_struct, false);
+        let e = self.parse_bp(0);
+        self.no_struct = outer;
+        e
+    }
+
+    /// Pratt-style precedence parser (a top-down operator-precedence algorithm).
+    ///
+    /// Each operator is assigned a binding power; recursive calls enforce precedence
+    /// by raising `min_bp` when stepping into tighter-binding operators. This keeps
+    /// the implementation compact compared with writing an explicit grammar, which
+    /// suits this example project.
+    fn parse_bp(&mut self, min_bp: u8) -> PResult<Expr> {
+        // prefix / atom
+        let start = self.span();
+        let mut lhs = match self.bump() {
+            Tok::Int(_) | Tok::I64(_) | Tok::Float(_) => self.number(false, start),
+            Tok::Minus if matches!(self.peek(), Tok::Int(_) | Tok::I64(_) | Tok::Float(_)) => {
+                self.bump();
+                self.number(true, start)
+            }
+            Tok::True => Expr::new(ExprKind::Bool(true), start),
+            Tok::False => Expr::new(ExprKind::Bool(false), start),
+            Tok::Str(s) => Expr::new(ExprKind::Str(s), start),
+            Tok::Interp(pieces) => self.interpolation(pieces, start)?,
+            Tok::Ident(module) if self.peek() == &Tok::ColonColon => {
+                self.bump();
+                let item = self.expect_ident()?;
+                let name = Ident { name: format!("{}::{}", module, item.name), span: start.to(item.span) };
+                match self.peek() {
+                    Tok::LParen => {
+                        self.bump();
+                        let args = self.parse_args()?;
+                        Expr::new(ExprKind::Call { name: name.name, args }, start.to(self.prev_span()))
+                    }
+                    Tok::LBrace if !self.no_struct => self.struct_literal(name)?,
+                    Tok::LBrace => {
+                        return Err(Diagnostic::error(format!("`{}` can't be used as a value", name.name))
+                            .primary(name.span, "struct literal without parentheses")
+                            .note("in the header of an `if`, `while` or `for`, put struct literals in parentheses"))
+                    }
+                    t => {
+                        return Err(Diagnostic::error(format!("expected `(` or `{{` after `{}`, found {}", name.name, t.describe()))
+                            .primary(self.span(), "expected a call or a struct literal")
+                            .note("other files share only their functions and structs"))
+                    }
+                }
+            }
+            Tok::Ident(name) if self.peek() == &Tok::LParen => {
+                self.bump(); // consume `(`
+                let args = self.parse_args()?;
+                let kind = match Builtin::from_name(&name) {
+                    Some(func) => ExprKind::Builtin { func, args },
+                    None => ExprKind::Call { name, args },
+                };
+                Expr::new(kind, start.to(self.prev_span()))
+            }
+            Tok::Ident(name) if self.peek() == &Tok::LBrace && !self.no_struct => {
+                self.struct_literal(Ident { name, span: start })?
+            }
+            Tok::Ident(name) => Expr::new(ExprKind::Var(name), start),
+            Tok::Minus => {
+                // unary minus has high binding power
+                let rhs = self.parse_bp(PREFIX_BP)?;
+                let span = start.to(rhs.span);
+                Expr::new(ExprKind::UnaryNeg(Box::new(rhs)), span)
+            }
+            Tok::Bang => {
+                let rhs = self.parse_bp(PREFIX_BP)?;
+                let span = start.to(rhs.span);
+                Expr::new(ExprKind::Not(Box::new(rhs)), span)
+            }
+            Tok::LParen => {
+                let e = self.parse_nested()?;
+                self.expect(Tok::RParen)?;
+                Expr::new(e.kind, start.to(self.prev_span()))
+            }
+            Tok::LBracket => self.array(start)?,
+            t => {
+                return Err(Diagnostic::error(format!("expected expression, found {}", t.describe()))
+                    .primary(start, "expected expression"))
+            }
+        };
+
+        // infix loop
+        loop {
+            if self.peek() == &Tok::LBracket {
+                if POSTFIX_BP < min_bp {
+                    break;
+                }
+                self.bump();
+                let index = self.parse_nested()?;
+                self.expect(Tok::RBracket)?;
+                let span = lhs.span.to(self.prev_span());
+                lhs = Expr::new(ExprKind::Index(Box::new(lhs), Box::new(index)), span);
                 continue;
             }
+            if self.peek() == &Tok::Dot {
+                if POSTFIX_BP < min_bp {
+                    break;
+                }
+                self.bump();
+                let field = self.expect_ident()?;
+                let span = lhs.span.to(field.span);
+                lhs = Expr::new(ExprKind::Field(Box::new(lhs), field), span);
+                continue;
+            }
+            if self.peek() == &Tok::As {
+                if CAST_BP < min_bp {
+                    break;
+                }
+                self.bump();
+                let ty = self.parse_type()?;
+                let span = lhs.span.to(self.prev_span());
+                lhs = Expr::new(ExprKind::Cast(Box::new(lhs), ty), span);
+                continue;
+            }
+            let Some((l_bp, r_bp)) = infix_binding_power(self.peek()) else { break };
+            if l_bp < min_bp {
+                break;
+            }
+            let op = self.bump(); // consume operator
+            let rhs = self.parse_bp(r_bp)?; // right binding power
+            let span = lhs.span.to(rhs.span);
+            let (l, r) = (Box::new(lhs), Box::new(rhs));
+            let kind = match op {
+                Tok::Plus => ExprKind::Add(l, r),
+                Tok::Minus => ExprKind::Sub(l, r),
+                Tok::Star => ExprKind::Mul(l, r),
+                Tok::Slash => ExprKind::Div(l, r),
+                Tok::EqEq => ExprKind::Eq(l, r),
+                Tok::NotEq => ExprKind::Ne(l, r),
+                Tok::Lt => ExprKind::Lt(l, r),
+                Tok::Le => ExprKind::Le(l, r),
+                Tok::Gt => ExprKind::Gt(l, r),
+                Tok::Ge => ExprKind::Ge(l, r),
+                Tok::AndAnd => ExprKind::And(l, r),
+                Tok::OrOr => ExprKind::Or(l, r),
+                _ => unreachable!(),
+            };
+            lhs = Expr::new(kind, span);
+        }
 
-            bail!("line {}: unrecognized syntax", lineno + 1);
+        Ok(lhs)
+    }
+
+    /// Build a literal from the number token just consumed, negated if it was
+    /// written after a `-` starting at `start`.
+    ///
+    /// Integer literals are range-checked here rather than in the lexer because
+    /// only the sign tells whether `2147483648` fits: `-2147483648` is `int::MIN`.
+    fn number(&mut self, negative: bool, start: Span) -> Expr {
+        let span = start.to(self.prev_span());
+        let kind = match self.toks[self.pos - 1].tok {
+            Tok::Float(v) => ExprKind::Float(if negative { -v } else { v }),
+            Tok::Int(v) => {
+                let v = if negative { -(v as i128) } else { v as i128 };
+                ExprKind::Int(i32::try_from(v).unwrap_or_else(|_| {
+                    let mut d = Diagnostic::error(format!("integer literal `{}` is out of range for `int`", v))
+                        .primary(span, "does not fit in 32 bits")
+                        .note(format!("`int` holds values from {} to {}", i32::MIN, i32::MAX));
+                    if i64::try_from(v).is_ok() {
+                        d = d.note(format!("write `{}i64` for a 64-bit integer", v));
+                    }
+                    self.diags.push(d);
+                    0
+                }))
+            }
+            Tok::I64(v) => {
+                let v = if negative { -(v as i128) } else { v as i128 };
+                ExprKind::I64(i64::try_from(v).unwrap_or_else(|_| {
+                    let d = Diagnostic::error(format!("integer literal `{}i64` is out of range for `i64`", v))
+                        .primary(span, "does not fit in 64 bits")
+                        .note(format!("`i64` holds values from {} to {}", i64::MIN, i64::MAX));
+                    self.diags.push(d);
+                    0
+                }))
+            }
+            _ => unreachable!("called after a number token"),
+        };
+        Expr::new(kind, span)
+    }
+
+    /// Parse an array literal after its `[`: a list of elements, possibly
+    /// empty, or `[value; count]`.
+    fn array(&mut self, start: Span) -> PResult<Expr> {
+        let mut items = Vec::new();
+        if !self.eat(&Tok::RBracket) {
+            let first = self.parse_nested()?;
+            if self.eat(&Tok::Semi) {
+                let count = self.parse_nested()?;
+                self.expect(Tok::RBracket)?;
+                let kind = ExprKind::Repeat { value: Box::new(first), count: Box::new(count) };
+                return Ok(Expr::new(kind, start.to(self.prev_span())));
+            }
+            items.push(first);
+            while self.eat(&Tok::Comma) {
+                items.push(self.parse_nested()?);
+            }
+            self.expect(Tok::RBracket)?;
         }
+        Ok(Expr::new(ExprKind::Array(items), start.to(self.prev_span())))
+    }
 
-        Ok(Program { stmts })
+    /// Parse a struct literal after its name: `{ field: value, ... }`.
+    fn struct_literal(&mut self, name: Ident) -> PResult<Expr> {
+        self.expect(Tok::LBrace)?;
+        let mut fields = Vec::new();
+        while self.peek() != &Tok::RBrace {
+            let field = self.expect_ident()?;
+            self.expect(Tok::Colon)?;
+            fields.push((field, self.parse_nested()?));
+            if !self.eat(&Tok::Comma) {
+                break;
+            }
+        }
+        self.expect(Tok::RBrace)?;
+        let span = name.span.to(self.prev_span());
+        Ok(Expr::new(ExprKind::StructLit { name, fields }, span))
     }
-}
 
-// =============== expression parser (integers) ==================
-//
-// Grammar (Pratt parser / precedence climbing):
-//   expr  := parse_bp(0)
-// operators:
-//   prefix:      '-'        (unary minus)     binding power: 9
-//   infix left:  '*','/'    (mul/div)         binding power: 7
-//   infix left:  '+','-'    (add/sub)         binding power: 5
-// atoms: INT, IDENT, '(' expr ')'
-
-/// Parse an arithmetic expression into an AST node, rejecting trailing tokens.
-fn parse_int_expr(s: &str) -> Result<Expr> {
-    let mut it = Lexer::new(s).peekable();
-    let expr = parse_bp(&mut it, 0)?;
-    // ensure no trailing tokens
-    if let Some(tok) = it.peek() {
-        bail!("unexpected token after expression: {:?}", tok);
-    }
-    Ok(expr)
-}
+    /// Build an interpolated string from its pieces, parsing each `{...}` as a
+    /// whole expression.
+    fn interpolation(&mut self, pieces: Vec<StrPiece>, span: Span) -> PResult<Expr> {
+        let mut parts = Vec::new();
+        for piece in pieces {
+            match piece {
+                StrPiece::Lit(s) => parts.push(StrPart::Lit(s)),
+                StrPiece::Code(toks) => {
+                    let end = toks.last().expect("token lists end in `Eof`").span;
+                    let mut inner = Parser { toks, pos: 0, in_fn: self.in_fn, no_struct: false, type_names: Vec::new(), diags: Diagnostics::default() };
+                    if inner.peek() == &Tok::Eof {
+                        return Err(Diagnostic::error("empty `{}` in string literal")
+                            .primary(Span::new(end.start - 1, end.end + 1), "expected an expression")
+                            .note("write `{{` for a literal `{`"));
+                    }
+                    let e = inner.parse_expr();
+                    self.diags.extend(std::mem::take(&mut inner.diags));
+                    self.type_names.append(&mut inner.type_names);
+                    let e = e?;
+                    if inner.peek() != &Tok::Eof {
+                        return Err(Diagnostic::error(format!("expected `}}`, found {}", inner.peek().describe()))
+                            .primary(inner.span(), "expected `}` after the interpolated expression"));
+                    }
+                    parts.push(StrPart::Expr(e));
+                }
+            }
+        }
+        Ok(Expr::new(ExprKind::Interp(parts), span))
+    }
+
+    /// Parse call arguments after the opening `(`, up to and including the `)`.
+    fn parse_args(&mut self) -> PResult<Vec<Expr>> {
+        let mut args = Vec::new();
+        if self.eat(&Tok::RParen) {
+            return Ok(args);
+        }
+        loop {
+            args.push(self.parse_nested()?);
+            if !self.eat(&Tok::Comma) {
+                self.expect(Tok::RParen)?;
+                return Ok(args);
+            }
+        }
+    }
+
+    // =============== token cursor ==================
+
+    fn peek(&self) -> &Tok {
+        &self.toks[self.pos].tok
+    }
+
+    /// The token after the current one.
+    fn peek_second(&self) -> &Tok {
+        &self.toks[(self.pos + 1).min(self.toks.len() - 1)].tok
+    }
+
+    fn span(&self) -> Span {
+        self.toks[self.pos].span
+    }
 
-#[derive(Debug, Clone, PartialEq)]
-enum Tok {
-    Int(i32),
-    Ident(String),
-    Plus,
-    Minus,
-    Star,
-    Slash,
-    LParen,
-    RParen,
+    /// Span of the most recently consumed token.
+    fn prev_span(&self) -> Span {
+        self.toks[self.pos.saturating_sub(1)].span
+    }
+
+    /// Consume the current token (the trailing `Eof` is never consumed).
+    fn bump(&mut self) -> Tok {
+        let t = self.toks[self.pos].tok.clone();
+        if t != Tok::Eof {
+            self.pos += 1;
+        }
+        t
+    }
+
+    /// Consume the current token if it equals `t`.
+    fn eat(&mut self, t: &Tok) -> bool {
+        if self.peek() == t {
+            self.bump();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Consume `t` or fail with an "expected X, found Y" error; returns its span.
+    fn expect(&mut self, t: Tok) -> PResult<Span> {
+        let span = self.span();
+        if self.eat(&t) {
+            return Ok(span);
+        }
+        let d = Diagnostic::error(format!("expected {}, found {}", t.describe(), self.peek().describe()));
+        if t == Tok::Semi && self.pos > 0 {
+            // point just past the previous token, where the `;` was forgotten
+            let end = self.prev_span().end;
+            Err(d.primary(Span::new(end, end), "expected `;`").secondary(span, "unexpected token"))
+        } else {
+            Err(d.primary(span, format!("expected {}", t.describe())))
+        }
+    }
+
+    fn expect_ident(&mut self) -> PResult<Ident> {
+        let span = self.span();
+        match self.bump() {
+            Tok::Ident(name) => Ok(Ident { name, span }),
+            t => Err(Diagnostic::error(format!("expected identifier, found {}", t.describe()))
+                .primary(span, "expected identifier")),
+        }
+    }
 }
 
-struct Lexer<'a> {
-    s: &'a str,
-    i: usize,
+/// Tokens that turn a statement starting with an identifier into an assignment.
+fn is_assign_op(t: &Tok) -> bool {
+    matches!(t, Tok::Assign | Tok::PlusEq | Tok::MinusEq | Tok::StarEq | Tok::SlashEq)
 }
-impl<'a> Lexer<'a> {
-    /// Construct a lexer over a slice of source.
-    fn new(s: &'a str) -> Self { Self { s, i: 0 } }
+
+/// Binding power of the prefix operators `-` and `!`.
+pub const PREFIX_BP: u8 = 11;
+/// Binding power of `as`.
+pub const CAST_BP: u8 = 13;
+/// Binding power of indexing and field access, the tightest operators.
+pub const POSTFIX_BP: u8 = 15;
+
+/// Left and right binding powers of an infix operator. Every infix operator
+/// is left-associative, so the right power is the higher one.
+pub fn infix_binding_power(t: &Tok) -> Option<(u8, u8)> {
+    Some(match t {
+        Tok::OrOr => (1, 2),
+        Tok::AndAnd => (3, 4),
+        Tok::EqEq | Tok::NotEq | Tok::Lt | Tok::Le | Tok::Gt | Tok::Ge => (5, 6),
+        Tok::Plus | Tok::Minus => (7, 8),
+        Tok::Star | Tok::Slash => (9, 10),
+        _ => return None,
+    })
 }
-impl<'a> Iterator for Lexer<'a> {
-    type Item = Tok;
-    fn next(&mut self) -> Option<Self::Item> {
-        let b = self.s.as_bytes();
-        let n = b.len();
-        while self.i < n && b[self.i].is_ascii_whitespace() { self.i += 1; }
-        if self.i >= n { return None; }
-        let c = b[self.i] as char;
-
-        // number (allow leading digits; unary handled in parser)
-        if c.is_ascii_digit() {
-            let start = self.i;
-            self.i += 1;
-            while self.i < n && (b[self.i] as char).is_ascii_digit() { self.i += 1; }
-            let s = &self.s[start..self.i];
-            let v = s.parse::<i32>().ok()?;
-            return Some(Tok::Int(v));
-        }
-
-        // ident
-        if c.is_ascii_alphabetic() || c == '_' {
-            let start = self.i;
-            self.i += 1;
-            while self.i < n {
-                let ch = b[self.i] as char;
-                if ch.is_ascii_alphanumeric() || ch == '_' { self.i += 1; } else { break; }
-            }
-            let name = self.s[start..self.i].to_string();
-            return Some(Tok::Ident(name));
-        }
-
-        // single-char tokens
-        self.i += 1;
-        match c {
-            '+' => Some(Tok::Plus),
-            '-' => Some(Tok::Minus),
-            '*' => Some(Tok::Star),
-            '/' => Some(Tok::Slash),
-            '(' => Some(Tok::LParen),
-            ')' => Some(Tok::RParen),
-            _ => None,
-        }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::line_col;
+
+    fn parse_expr(src: &str) -> Expr {
+        Parser::new(src).parse_expr().unwrap()
     }
-}
 
-/// Pratt-style precedence parser (a top-down operator-precedence algorithm).
-///
-/// Each operator is assigned a binding power; recursive calls enforce precedence
-/// by raising `min_bp` when stepping into tighter-binding operators. This keeps
-/// the implementation compact compared with writing an explicit grammar, which
-/// suits this example project.
-fn parse_bp<I>(it: &mut std::iter::Peekable<I>, min_bp: u8) -> Result<Expr>
-where
-    I: Iterator<Item = Tok>,
-{
-    // prefix / atom
-    let mut lhs = match it.next().ok_or_else(|| anyhow::anyhow!("expected expression"))? {
-        Tok::Int(v) => Expr::Int(v),
-        Tok::Ident(name) => Expr::Var(name),
-        Tok::Minus => {
-            // unary minus has high binding power
-            let rhs = parse_bp(it, 9)?;
-            Expr::UnaryNeg(Box::new(rhs))
-        }
-        Tok::LParen => {
-            let e = parse_bp(it, 0)?;
-            match it.next() {
-                Some(Tok::RParen) => e,
-                _ => anyhow::bail!("expected `)`"),
-            }
-        }
-        t => anyhow::bail!("unexpected token: {:?}", t),
-    };
-
-    // infix loop
-    loop {
-        let op = match it.peek() {
-            Some(Tok::Plus) => (5, 6, '+'),
-            Some(Tok::Minus) => (5, 6, '-'),
-            Some(Tok::Star) => (7, 8, '*'),
-            Some(Tok::Slash) => (7, 8, '/'),
-            _ => break,
-        };
-        if op.0 < min_bp {
-            break;
-        }
-        let _ = it.next(); // consume operator
-        let rhs = parse_bp(it, op.1)?; // right binding power
-        lhs = match op.2 {
-            '+' => Expr::Add(Box::new(lhs), Box::new(rhs)),
-            '-' => Expr::Sub(Box::new(lhs), Box::new(rhs)),
-            '*' => Expr::Mul(Box::new(lhs), Box::new(rhs)),
-            '/' => Expr::Div(Box::new(lhs), Box::new(rhs)),
+    /// Parse source expected to fail; returns `(line, column, message)` per error.
+    fn errors(src: &str) -> Vec<(usize, usize, String)> {
+        let diags = Parser::parse(src).unwrap_err();
+        diags
+            .iter()
+            .map(|d| {
+                let (line, col) = line_col(src, d.span().unwrap().start);
+                (line, col, d.message.clone())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn comparison_binds_looser_than_arithmetic() {
+        let e = parse_expr("a + 1 < b * 2 && !c || d");
+        let ExprKind::Or(lhs, _) = e.kind else { panic!("expected `||` at the root: {:?}", e) };
+        let ExprKind::And(cmp, not) = lhs.kind else { panic!("expected `&&` under `||`") };
+        assert!(matches!(cmp.kind, ExprKind::Lt(..)));
+        assert!(matches!(not.kind, ExprKind::Not(..)));
+    }
+
+    #[test]
+    fn expressions_carry_spans() {
+        let src = "let x = (a + 1) * f(2);";
+        let program = Parser::parse(src).unwrap();
+        let StmtKind::Let { name, expr, .. } = &program.stmts[0].kind else { panic!("expected let") };
+        assert_eq!(&src[name.span.start..name.span.end], "x");
+        assert_eq!(&src[expr.span.start..expr.span.end], "(a + 1) * f(2)");
+        let ExprKind::Mul(l, r) = &expr.kind else { panic!("expected `*`") };
+        assert_eq!(&src[l.span.start..l.span.end], "(a + 1)");
+        assert_eq!(&src[r.span.start..r.span.end], "f(2)");
+        assert_eq!(program.stmts[0].span, Span::new(0, src.len()));
+    }
+
+    #[test]
+    fn parses_if_else_if_chain_and_while() {
+        let src = "let mut i = 0;\nwhile i < 3 {\n  if i == 0 {\n    println i;\n  } else if i == 1 {\n  } else {\n    println i;\n  }\n  i += 1;\n}\n";
+        let program = Parser::parse(src).unwrap();
+        assert_eq!(program.stmts.len(), 2);
+        let StmtKind::While { body, .. } = &program.stmts[1].kind else { panic!("expected while") };
+        let StmtKind::If { else_body: Some(else_body), .. } = &body[0].kind else { panic!("expected if/else") };
+        assert!(matches!(&else_body[0].kind, StmtKind::If { else_body: Some(_), .. }));
+    }
+
+    #[test]
+    fn parses_mutable_lets_and_assignments() {
+        let src = "let mut n = 1;\nn = n + 1;\nn *= 2 + 3;\nf(n);\n";
+        let program = Parser::parse(src).unwrap();
+        assert!(matches!(&program.stmts[0].kind, StmtKind::Let { mutable: true, .. }));
+        let StmtKind::Assign { compound: false, .. } = &program.stmts[1].kind else { panic!("expected assignment") };
+        // `n *= 2 + 3` is `n = n * (2 + 3)`, spanning the whole assignment
+        let StmtKind::Assign { name, expr, compound: true } = &program.stmts[2].kind else { panic!("expected compound assignment") };
+        assert_eq!(name.name, "n");
+        assert_eq!(&src[expr.span.start..expr.span.end], "n *= 2 + 3");
+        let ExprKind::Mul(l, r) = &expr.kind else { panic!("expected `*`") };
+        assert!(matches!(&l.kind, ExprKind::Var(v) if v == "n"));
+        assert!(matches!(r.kind, ExprKind::Add(..)));
+        assert!(matches!(&program.stmts[3].kind, StmtKind::Expr(_)));
+    }
+
+    #[test]
+    fn parses_arrays_indexing_and_for_loops() {
+        let src = "let mut a = [1, 2, f(3)];\nlet z = [0; n + 1];\na[i + 1] += a[0][1];\nfor x in a { push(a, x); }\nfor i in 0..len(a) { }\nfn g(xs: [int]) -> [int] { return xs; }\n";
+        let program = Parser::parse(src).unwrap();
+        let StmtKind::Let { expr, .. } = &program.stmts[0].kind else { panic!("expected let") };
+        assert!(matches!(&expr.kind, ExprKind::Array(items) if items.len() == 3));
+        let StmtKind::Let { expr, .. } = &program.stmts[1].kind else { panic!("expected let") };
+        assert!(matches!(&expr.kind, ExprKind::Repeat { count, .. } if matches!(count.kind, ExprKind::Add(..))));
+        // `a[i] += e` is `a[i] = a[i] + e`, with indexing binding tighter than anything else
+        let StmtKind::IndexAssign { name, index, expr, compound: true } = &program.stmts[2].kind else { panic!("expected index assignment") };
+        assert_eq!((name.name.as_str(), &src[index.span.start..index.span.end]), ("a", "i + 1"));
+        let ExprKind::Add(l, r) = &expr.kind else { panic!("expected `+`") };
+        assert!(matches!(l.kind, ExprKind::Index(..)));
+        assert!(matches!(&r.kind, ExprKind::Index(base, _) if matches!(base.kind, ExprKind::Index(..))));
+        assert!(matches!(&program.stmts[3].kind, StmtKind::For { iter: ForIter::Array(_), .. }));
+        assert!(matches!(&program.stmts[4].kind, StmtKind::For { iter: ForIter::Range(..), .. }));
+        assert_eq!((&program.functions[0].params[0].ty, &program.functions[0].ret), (&Type::Array, &Some(Type::Array)));
+        assert_eq!(
+            errors("f()[0] = 1;\nfn h(b: [bool]) { }\n"),
+            vec![(1, 1, "invalid left-hand side of assignment".to_string()), (2, 9, "arrays of bool are not supported".to_string())]
+        );
+        assert_eq!(errors("for x a { }\n")[0], (1, 7, "expected `in`, found identifier `a`".to_string()));
+    }
+
+    #[test]
+    fn parses_structs_literals_fields_and_field_assignment() {
+        let src = "struct P { x: int, q: Q, }\nlet mut p = P { x: 1, q: Q {} };\np.q.y -= p.x;\nif p.x == (P { x: 2 }).x { }\nfn f(p: P) -> Q { return p.q; }\nstruct Q {}\n";
+        let program = Parser::parse(src).unwrap();
+        let names: Vec<&str> = program.structs.iter().map(|s| s.name.name.as_str()).collect();
+        assert_eq!(names, ["P", "Q"]);
+        assert_eq!(program.structs[0].field("q").map(|(i, ty)| (i, ty.clone())), Some((1, Type::Struct("Q".into()))));
+        let StmtKind::Let { expr, .. } = &program.stmts[0].kind else { panic!("expected let") };
+        assert!(matches!(&expr.kind, ExprKind::StructLit { name, fields } if name.name == "P" && fields.len() == 2));
+        // `p.q.y -= e` is `p.q.y = p.q.y - e`, with the path after the variable
+        let StmtKind::FieldAssign { name, path, expr, compound: true } = &program.stmts[1].kind else { panic!("expected field assignment") };
+        let path: Vec<&str> = path.iter().map(|f| f.name.as_str()).collect();
+        assert_eq!((name.name.as_str(), path), ("p", vec!["q", "y"]));
+        assert!(matches!(&expr.kind, ExprKind::Sub(l, _) if matches!(&l.kind, ExprKind::Field(base, f) if f.name == "y" && matches!(base.kind, ExprKind::Field(..)))));
+        assert!(matches!(&program.stmts[2].kind, StmtKind::If { .. }));
+        assert_eq!(program.functions[0].ret, Some(Type::Struct("Q".into())));
+        // a bare literal would swallow the block, so the condition ends at the name
+        assert_eq!(errors("if p == P { x: 1 } { }\n")[0], (1, 14, "expected `;`, found `:`".to_string()));
+        assert_eq!(
+            errors("let a = f().x = 1;\nfn g(r: R) { }\n{ struct S {} }\n"),
+            vec![
+                (1, 14, "expected `;`, found `=`".to_string()),
+                (2, 9, "unknown type `R`".to_string()),
+                (3, 3, "structs must be declared at the top level".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn statements_are_not_tied_to_lines() {
+        let src = "let a = 1; let b =\n  a +\n  2; println b; if a < b { println a; } else { println b; }";
+        let program = Parser::parse(src).unwrap();
+        assert_eq!(program.stmts.len(), 4);
+        assert!(matches!(&program.stmts[3].kind, StmtKind::If { else_body: Some(_), .. }));
+    }
+
+    #[test]
+    fn string_literals_may_contain_semicolons_and_escapes() {
+        let program = Parser::parse("let s = \"a; b \\\"c\\\"\\n\"; // trailing comment\nlog(\"x;y\");").unwrap();
+        let StmtKind::Let { expr, .. } = &program.stmts[0].kind else { panic!("expected string let") };
+        assert!(matches!(&expr.kind, ExprKind::Str(s) if s == "a; b \"c\"\n"));
+        let StmtKind::Expr(call) = &program.stmts[1].kind else { panic!("expected call") };
+        let ExprKind::Call { args, .. } = &call.kind else { panic!("expected call") };
+        assert!(matches!(&args[0].kind, ExprKind::Str(s) if s == "x;y"));
+    }
+
+    #[test]
+    fn parses_string_interpolation() {
+        let e = parse_expr("\"n = {n + 1}, {{ok}} {len(s)}\"");
+        let ExprKind::Interp(parts) = &e.kind else { panic!("expected interpolation") };
+        assert!(matches!(&parts[0], StrPart::Lit(s) if s == "n = "));
+        let StrPart::Expr(sum) = &parts[1] else { panic!("expected expression") };
+        assert_eq!(sum.span, Span::new(6, 11));
+        assert!(matches!(sum.kind, ExprKind::Add(..)));
+        assert!(matches!(&parts[2], StrPart::Lit(s) if s == ", {ok} "));
+        assert!(matches!(&parts[3], StrPart::Expr(Expr { kind: ExprKind::Builtin { func: Builtin::Len, .. }, .. })));
+        assert!(matches!(parse_expr("\"{{}}\"").kind, ExprKind::Str(s) if s == "{}"));
+
+        assert_eq!(
+            errors("let a = \"{x\";\nlet b = \"x}\";\nlet c = \"{}\";\nlet d = \"{x y}\";\n"),
+            vec![
+                (1, 10, "unclosed `{` in string literal".to_string()),
+                (2, 11, "unmatched `}` in string literal".to_string()),
+                (3, 10, "empty `{}` in string literal".to_string()),
+                (4, 13, "expected `}`, found identifier `y`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn print_takes_a_list_of_expressions() {
+        let program = Parser::parse("print x;\nprintln \"a\", x + 1, f(y);\nprintln;\n").unwrap();
+        let shapes: Vec<(usize, bool)> = program
+            .stmts
+            .iter()
+            .map(|s| match &s.kind {
+                StmtKind::Print { args, newline } => (args.len(), *newline),
+                _ => panic!("expected print"),
+            })
+            .collect();
+        assert_eq!(shapes, [(1, false), (3, true), (0, true)]);
+        assert_eq!(errors("print;\nprintln x,;\n"), vec![(1, 6, "expected expression, found `;`".to_string()), (2, 11, "expected expression, found `;`".to_string())]);
+    }
+
+    #[test]
+    fn parses_function_declarations_and_calls() {
+        let src = "let x = add(1, neg(2) * 3);\nfn add(a: int, b: int) -> int {\n  return a + b;\n}\nfn log(s: string) {\n  println s;\n  return;\n}\nlog(x);\n";
+        let program = Parser::parse(src).unwrap();
+        assert_eq!(program.functions.len(), 2);
+        assert_eq!(program.functions[0].params.len(), 2);
+        assert_eq!(program.functions[0].ret, Some(Type::Int));
+        assert_eq!(program.functions[1].ret, None);
+        assert!(matches!(&program.functions[1].body[1].kind, StmtKind::Return(None)));
+        let StmtKind::Let { expr, .. } = &program.stmts[0].kind else { panic!("expected let") };
+        let ExprKind::Call { name, args } = &expr.kind else { panic!("expected call") };
+        assert_eq!((name.as_str(), args.len()), ("add", 2));
+        assert!(matches!(&program.stmts[1].kind, StmtKind::Expr(_)));
+    }
+
+    #[test]
+    fn rejects_misplaced_functions_and_returns() {
+        assert_eq!(
+            errors("if 1 {\n  fn f() {\n}\n}\nreturn 1;\nfn g(a: float) {\n}\n"),
+            vec![
+                (2, 3, "functions must be declared at the top level".to_string()),
+                (5, 1, "`return` outside of a function".to_string()),
+                (6, 9, "unknown type `float`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_unbalanced_blocks() {
+        assert_eq!(errors("if 1 {\nprintln x;\n"), vec![(1, 6, "unclosed block".to_string())]);
+        assert_eq!(errors("println x;\n}\n"), vec![(2, 1, "unmatched `}`".to_string())]);
+        assert_eq!(errors("{\n} else {\n}\n"), vec![(2, 3, "`else` without `if`".to_string())]);
+    }
+
+    #[test]
+    fn recovers_and_reports_every_syntax_error() {
+        let src = "let x = 1\nprintln x;\nlet y = ;\nlet z = 99999999999;\nprint @;\nprintln z;\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (1, 10, "expected `;`, found `println`".to_string()),
+                (3, 9, "expected expression, found `;`".to_string()),
+                (4, 9, "integer literal `99999999999` is out of range for `int`".to_string()),
+                (5, 7, "unexpected character `@`".to_string()),
+                (5, 8, "expected expression, found `;`".to_string()),
+            ]
+        );
+        assert_eq!(errors("let s = \"open;\n")[0], (1, 9, "unterminated string literal".to_string()));
+    }
+
+    #[test]
+    fn checks_number_literals_against_their_type() {
+        let program = Parser::parse("let a = -2147483648;\nlet b = -9223372036854775808i64;\nlet c = 2.5e-3;\nlet d = -x as i64;\n").unwrap();
+        let value = |i: usize| match &program.stmts[i].kind {
+            StmtKind::Let { expr, .. } => expr.kind.clone(),
             _ => unreachable!(),
         };
+        assert!(matches!(value(0), ExprKind::Int(i32::MIN)));
+        assert!(matches!(value(1), ExprKind::I64(i64::MIN)));
+        assert!(matches!(value(2), ExprKind::Float(v) if v == 0.0025));
+        // `as` binds tighter than unary minus
+        let ExprKind::UnaryNeg(cast) = value(3) else { panic!("expected `-` at the root") };
+        assert!(matches!(cast.kind, ExprKind::Cast(_, Type::I64)));
+
+        let src = "let a = 2147483648;\nlet b = 9223372036854775808i64;\nlet c = 1e400;\nlet d = 3u8;\nlet e = 99999999999999999999;\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (1, 9, "integer literal `2147483648` is out of range for `int`".to_string()),
+                (2, 9, "integer literal `9223372036854775808i64` is out of range for `i64`".to_string()),
+                (3, 9, "floating-point literal `1e400` is out of range for `f64`".to_string()),
+                (4, 10, "invalid suffix `u8` for number literal".to_string()),
+                (5, 9, "integer literal `99999999999999999999` is out of range for `i64`".to_string()),
+            ]
+        );
     }
 
-    Ok(lhs)
-}
+    #[test]
+    fn renders_rustc_style_carets() {
+        let src = "let a = 1;\nlet b = a +;\n";
+        let out = Parser::parse(src).unwrap_err().render(src, "demo.mini");
+        let expected = "\
+error: expected expression, found `;`
+ --> demo.mini:2:12
+  |
+2 | let b = a +;
+  |            ^ expected expression
+  |
 
-// Minimal escapes for our language's string literals: \n \t \" \\
-/// Parse and unescape the limited string literal syntax Mini supports.
-fn parse_string(mut s: &str) -> Result<String> {
-    if !(s.starts_with('"') && s.ends_with('"')) { bail!("not a string literal"); }
-    s = &s[1..s.len() - 1];
-
-    let mut out = String::new();
-    let mut it = s.chars().peekable();
-    while let Some(c) = it.next() {
-        if c == '\\' {
-            match it.next() {
-                Some('n') => out.push('\n'),
-                Some('t') => out.push('\t'),
-                Some('"') => out.push('"'),
-                Some('\\') => out.push('\\'),
-                Some(other) => bail!("unsupported escape \\{}", other),
-                None => bail!("dangling backslash"),
-            }
-        } else {
-            out.push(c);
-        }
+error: aborting due to 1 previous error
+";
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    fn parses_imports_pub_items_and_qualified_names() {
+        let src = "import \"lib/geo.mini\";\npub struct P { at: geo::Point }\npub fn f(p: geo::Point) -> int { return geo::area(p); }\nlet q = geo::Point { x: 1 };\nif (geo::Point { x: 1 }).x > 0 { }\n";
+        let program = Parser::parse(src).unwrap();
+        assert_eq!(program.imports[0].path, "lib/geo.mini");
+        assert_eq!(&src[program.imports[0].path_span.start..program.imports[0].path_span.end], "\"lib/geo.mini\"");
+        assert!(program.structs[0].public && program.functions[0].public);
+        // a declaration's span starts at its `pub`
+        assert_eq!(program.functions[0].sig_span.start, src.find("pub fn").unwrap());
+        assert_eq!(program.structs[0].fields[0].ty, Type::Struct("geo::Point".into()));
+        let StmtKind::Return(Some(e)) = &program.functions[0].body[0].kind else { panic!("expected return") };
+        assert!(matches!(&e.kind, ExprKind::Call { name, .. } if name == "geo::area"));
+        let StmtKind::Let { expr, .. } = &program.stmts[0].kind else { panic!("expected let") };
+        assert!(matches!(&expr.kind, ExprKind::StructLit { name, .. } if name.name == "geo::Point"));
+        assert_eq!(
+            errors("pub let x = 1;\nfn f() { import \"a.mini\"; }\nif 1 { pub let y = 2; pub fn g() {} }\nlet y = geo::z;\nimport a;\n"),
+            vec![
+                (1, 1, "only functions and structs can be `pub`".to_string()),
+                (2, 10, "imports must be at the top level".to_string()),
+                (3, 8, "only top-level functions and structs can be `pub`".to_string()),
+                (3, 27, "functions must be declared at the top level".to_string()),
+                (4, 15, "expected `(` or `{` after `geo::z`, found `;`".to_string()),
+                (5, 8, "expected a file name in quotes, found identifier `a`".to_string()),
+            ]
+        );
+        assert_eq!(errors("while geo::P {} { }\n")[0], (1, 7, "`geo::P` can't be used as a value".to_string()));
     }
-    Ok(out)
 }
@@ -0,0 +1,175 @@
+//! Interactive read-eval-print loop on top of the interpreter.
+//!
+//! Every accepted input is appended to a session source, and each new input
+//! is parsed and type-checked together with everything before it, so
+//! functions and variables defined on earlier lines stay in scope. Only the
+//! newly entered items are executed. A bare expression entered without a
+//! trailing `;` has its value printed.
+
+use std::io::{BufRead, Write};
+
+use crate::ast::{Program, Stmt, StmtKind};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::fold;
+use crate::interp::Interp;
+use crate::lexer::{Lexer, Tok};
+use crate::parser::Parser;
+use crate::typeck;
+
+/// Name used for the session in diagnostics.
+const SESSION: &str = "<repl>";
+
+/// Read inputs from `input` until end of file or `:quit`, writing prompts,
+/// program output and diagnostics to `out`.
+pub fn run<R: BufRead, W: Write>(input: R, out: W) -> std::io::Result<()> {
+    let mut session = Session { src: String::new(), interp: Interp::new(out) };
+    let mut pending = String::new();
+    let mut lines = input.lines();
+    loop {
+        let prompt = if pending.is_empty() { "mini> " } else { "...> " };
+        write!(session.out(), "{}", prompt)?;
+        session.out().flush()?;
+
+        let Some(line) = lines.next().transpose()? else {
+            writeln!(session.out())?;
+            return Ok(());
+        };
+        if pending.is_empty() && matches!(line.trim(), ":quit" | ":q") {
+            return Ok(());
+        }
+        pending += &line;
+        pending.push('\n');
+        // keep reading while a `{` is still open
+        if !is_complete(&pending) {
+            continue;
+        }
+        let chunk = std::mem::take(&mut pending);
+        if !chunk.trim().is_empty() {
+            session.eval(chunk)?;
+        }
+    }
+}
+
+struct Session<W: Write> {
+    /// Every input accepted so far.
+    src: String,
+    interp: Interp<W>,
+}
+
+impl<W: Write> Session<W> {
+    fn out(&mut self) -> &mut W {
+        self.interp.output()
+    }
+
+    /// Check `chunk` against the session and run whatever it adds.
+    fn eval(&mut self, mut chunk: String) -> std::io::Result<()> {
+        // `1 + 2` is shorthand for evaluating and showing `1 + 2;`
+        let last = Lexer::new(&chunk).tokenize().0.into_iter().rev().nth(1).map(|t| t.tok);
+        let echo = !matches!(last, Some(Tok::Semi) | Some(Tok::RBrace));
+        if echo {
+            chunk.insert(chunk.trim_end().len(), ';');
+        }
+
+        let start = self.src.len();
+        let candidate = format!("{}{}", self.src, chunk);
+        // earlier inputs were already accepted; only report on the new one
+        let is_new = |d: &Diagnostic| d.span().is_none_or(|s| s.start >= start);
+        let checked = Parser::parse(&candidate).and_then(|mut program| {
+            if let Some(i) = program.imports.first() {
+                let d = Diagnostic::error("`import` is not available in the REPL")
+                    .primary(i.span, "imports need a file to be relative to")
+                    .note("put the program in a file and run it with `mini run`");
+                return Err(Diagnostics(vec![d]));
+            }
+            let mut warnings = typeck::check(&mut program)?;
+            warnings.extend(fold::fold(&mut program)?);
+            Ok((program, warnings))
+        });
+        let (program, mut warnings) = match checked {
+            Ok(ok) => ok,
+            Err(mut diags) => {
+                diags.0.retain(is_new);
+                let text = diags.render(&candidate, SESSION);
+                return write!(self.out(), "{}", text);
+            }
+        };
+        warnings.0.retain(is_new);
+        let text = warnings.render(&candidate, SESSION);
+        write!(self.out(), "{}", text)?;
+        self.src = candidate;
+
+        // struct declarations only describe values, so all of them are kept
+        let mut new = Program {
+            imports: Vec::new(),
+            structs: program.structs,
+            functions: program.functions.into_iter().filter(|f| f.sig_span.start >= start).collect(),
+            stmts: program.stmts.into_iter().filter(|s| s.span.start >= start).collect(),
+        };
+        let shown = match new.stmts.last() {
+            Some(Stmt { kind: StmtKind::Expr(e), .. }) if echo && e.ty.is_some() => Some(e.clone()),
+            _ => None,
+        };
+        if shown.is_some() {
+            new.stmts.pop();
+        }
+
+        let result = self.interp.run(&new).and_then(|()| shown.map(|e| self.interp.eval(&e)).transpose());
+        match result {
+            Ok(Some(Some(v))) => writeln!(self.out(), "{}", v),
+            Ok(_) => Ok(()),
+            Err(d) => {
+                let text = d.render(&self.src, SESSION);
+                write!(self.out(), "{}", text)
+            }
+        }
+    }
+}
+
+/// Whether `src` closes every `{` it opens.
+fn is_complete(src: &str) -> bool {
+    let depth = Lexer::new(src).tokenize().0.iter().fold(0i32, |depth, t| match t.tok {
+        Tok::LBrace => depth + 1,
+        Tok::RBrace => depth - 1,
+        _ => depth,
+    });
+    depth <= 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `input` to a REPL session and return everything it wrote.
+    fn session(input: &str) -> String {
+        let mut out = Vec::new();
+        run(input.as_bytes(), &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn keeps_bindings_across_lines() {
+        let out = session("let x = 20;\nfn twice(n: int) -> int { return n * 2; }\nlet y = twice(x) + 2;\nprintln y;\ny - 40\n");
+        assert_eq!(out, "mini> mini> mini> mini> 42\nmini> 2\nmini> \n");
+    }
+
+    #[test]
+    fn continues_unclosed_blocks() {
+        let out = session("let mut i = 0;\nwhile i < 2 {\n  println i;\n  i += 1;\n}\n:quit\n");
+        assert_eq!(out, "mini> mini> ...> ...> ...> 0\n1\nmini> ");
+    }
+
+    #[test]
+    fn rejected_input_does_not_change_the_session() {
+        let out = session("let x = 1;\nlet y = x + \"s\";\nprintln y;\nprintln x;\n");
+        assert!(out.contains("error: mismatched types: expected int, found string"));
+        assert!(out.contains("error: undefined variable `y`"));
+        assert!(out.ends_with("mini> 1\nmini> \n"));
+    }
+
+    #[test]
+    fn reports_runtime_errors_and_keeps_going() {
+        let out = session("let z = 0;\n10 / z\nprintln z;\n");
+        assert!(out.contains("error: attempt to divide by zero"));
+        assert!(out.ends_with("mini> 0\nmini> \n"));
+    }
+}
@@ -0,0 +1,1547 @@
+//! The routines `print`, string operations and failed runtime checks lower
+//! to, emitted into every module.
+//!
+//! With a C library, each `print` is a single `printf` call with a format
+//! string generated for it, and the rest are thin wrappers around `sprintf`,
+//! `dprintf` and `exit`. Targets without one get the same routines written
+//! directly in IR on top of `write(fd, buf, len)` and `read(buf, len)`
+//! primitives and an exit call: the `fd_write`, `fd_read` and `proc_exit`
+//! imports for WASI, or the `write`, `read` and `exit_group` system calls for
+//! freestanding Linux builds. Those targets also get a `_start` that passes
+//! the command-line arguments to `main` and its status to the exit call.
+//!
+//! Strings are NUL-terminated. The ones built at run time, and arrays, come
+//! from a bump allocator over a fixed heap in the module itself, on every
+//! target, and are never freed. Floats print and format like `printf("%g")`
+//! everywhere. `main` hands its `argc` and `argv` to [`Runtime::set_args`],
+//! and `read_line` reads stdin a byte at a time: through `getchar` with a C
+//! library, through a buffer of its own otherwise.
+
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    builder::Builder,
+    context::Context as LlvmContext,
+    module::{Linkage, Module},
+    targets::TargetTriple,
+    types::{FunctionType, StructType},
+    values::{BasicMetadataValueEnum, FloatValue, FunctionValue, IntValue, PointerValue},
+    AddressSpace, FloatPredicate, IntPredicate,
+};
+
+/// Which runtime a module is built against.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flavor {
+    /// The host's C library.
+    Libc,
+    /// WASI preview 1 imports from `wasi_snapshot_preview1`.
+    Wasi,
+    /// Linux system calls only, for static executables without libc.
+    Freestanding(Arch),
+}
+
+/// Architectures the freestanding runtime has system call stubs for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arch {
+    X86_64,
+    Aarch64,
+}
+
+impl Flavor {
+    /// The runtime a target triple implies: `wasm32-wasi` and friends use WASI.
+    pub fn for_triple(triple: &TargetTriple) -> Self {
+        let triple = triple.as_str().to_string_lossy();
+        if triple.split('-').skip(1).any(|c| c.starts_with("wasi")) {
+            Flavor::Wasi
+        } else {
+            Flavor::Libc
+        }
+    }
+
+    /// The freestanding runtime for `triple`, if it is a Linux target the
+    /// runtime supports.
+    pub fn freestanding(triple: &TargetTriple) -> Option<Self> {
+        let triple = triple.as_str().to_string_lossy();
+        let mut parts = triple.split('-');
+        let arch = match parts.next()? {
+            "x86_64" => Arch::X86_64,
+            "aarch64" => Arch::Aarch64,
+            _ => return None,
+        };
+        parts.any(|c| c == "linux").then_some(Flavor::Freestanding(arch))
+    }
+}
+
+/// Output routines available to generated code.
+#[derive(Clone, Copy)]
+pub struct Runtime<'ctx> {
+    pub flavor: Flavor,
+    /// What `print` statements lower to; see [`Runtime::print`].
+    pub output: Output<'ctx>,
+    /// `void (ptr, i32)`: write a message of the given length to stderr and
+    /// exit with status [`FAIL_STATUS`]; does not return.
+    pub fail: FunctionValue<'ctx>,
+    /// `i32 (ptr)`: the length of a string in bytes.
+    pub str_len: FunctionValue<'ctx>,
+    /// `ptr (ptr, ptr)`: a new string holding both operands.
+    pub str_concat: FunctionValue<'ctx>,
+    /// `i32 (ptr, ptr)`: negative, zero or positive as the first string sorts
+    /// before, equal to or after the second, byte by byte.
+    pub str_cmp: FunctionValue<'ctx>,
+    /// `ptr (i64)`: a new string with an integer in decimal.
+    pub i64_to_str: FunctionValue<'ctx>,
+    /// `ptr (double)`: a new string with a float as `%g` formats it.
+    pub f64_to_str: FunctionValue<'ctx>,
+    /// `ptr (i32 n)`: a new array of `n` zeros; `n` must not be negative.
+    pub array_new: FunctionValue<'ctx>,
+    /// `void (ptr a, i32 x)`: append `x` to `a`, moving its elements to a
+    /// larger block when they fill the one they have.
+    pub array_push: FunctionValue<'ctx>,
+    /// `void (ptr a)`: write `a` to stdout as `[1, 2, 3]`.
+    pub print_array: FunctionValue<'ctx>,
+    /// `void (i32 argc, ptr argv)`: record `main`'s arguments for `args` and `arg`.
+    pub set_args: FunctionValue<'ctx>,
+    /// `i32 ()`: how many arguments follow the program name.
+    pub args: FunctionValue<'ctx>,
+    /// `ptr (i32 i)`: the `i`th argument after the program name; `i` must
+    /// be below the count.
+    pub arg: FunctionValue<'ctx>,
+    /// `ptr ()`: a new string with the next line of stdin, `\n` included, or
+    /// an empty one at the end of input.
+    pub read_line: FunctionValue<'ctx>,
+    /// `i32 (ptr s, ptr out)`: parse `s` as `parse_int` does, storing the
+    /// `i32` at `out`; returns 0, or 1 or 2 for [`PARSE_INT_INVALID`] and
+    /// [`PARSE_INT_RANGE`].
+    pub parse_int: FunctionValue<'ctx>,
+}
+
+/// How a `print` statement writes to stdout.
+#[derive(Clone, Copy)]
+pub enum Output<'ctx> {
+    /// `printf`, called once per statement with a format string made for it.
+    Printf(FunctionValue<'ctx>),
+    /// One call per piece, each writing just that piece: `text` is
+    /// `void (ptr, i32)`, `i64` is `void (i64)`, `f64` is `void (double)` and
+    /// `str` is `void (ptr)` for a NUL-terminated string.
+    Write { text: FunctionValue<'ctx>, i64: FunctionValue<'ctx>, f64: FunctionValue<'ctx>, str: FunctionValue<'ctx> },
+}
+
+/// Part of what a `print` statement writes: text known at compile time, or
+/// a value.
+pub enum Piece<'ctx> {
+    Text(String),
+    Int(IntValue<'ctx>),
+    I64(IntValue<'ctx>),
+    F64(FloatValue<'ctx>),
+    Str(PointerValue<'ctx>),
+    Array(PointerValue<'ctx>),
+}
+
+/// Exit status of a program stopped by a failed runtime check.
+pub const FAIL_STATUS: i32 = 101;
+
+/// Bytes available to strings built at run time and arrays.
+pub const HEAP_SIZE: u32 = 32 << 20;
+
+/// Allocations are rounded up to a multiple of this, so every block is
+/// aligned for the pointers in array headers.
+pub const HEAP_ALIGN: u32 = 8;
+
+/// Why `parse_int` failed: its argument isn't an optional sign followed by
+/// decimal digits, or the number doesn't fit in `int`.
+pub const PARSE_INT_INVALID: &str = "invalid number for `parse_int`: expected decimal digits with an optional sign";
+pub const PARSE_INT_RANGE: &str = "number too large for `parse_int`: the result must fit in `int`";
+
+/// Bytes allocated for an array's header, [`array_type`], on every target.
+pub const ARRAY_HEADER: u32 = 16;
+
+/// An array's header: `{ i32 len, i32 cap, ptr data }`, with room for `cap`
+/// `i32` elements at `data`.
+pub fn array_type(ctx: &LlvmContext) -> StructType<'_> {
+    let i32_t = ctx.i32_type();
+    ctx.struct_type(&[i32_t.into(), i32_t.into(), ctx.i8_type().ptr_type(AddressSpace::default()).into()], false)
+}
+
+/// Room for the text of any `i64` or `%g`-formatted float.
+const NUM_BUF: u32 = 32;
+
+/// Bytes of stdin read at a time by runtimes without a C library.
+const READ_BUF: u32 = 4096;
+
+impl<'ctx> Runtime<'ctx> {
+    /// Define the runtime for `flavor` in `module`.
+    pub fn emit(ctx: &'ctx LlvmContext, module: &Module<'ctx>, flavor: Flavor) -> Self {
+        match flavor {
+            Flavor::Libc => libc(ctx, module),
+            Flavor::Wasi => {
+                let (write, read) = (wasi_write(ctx, module), wasi_read(ctx, module));
+                let exit = wasi_import(ctx, module, "proc_exit", ctx.void_type().fn_type(&[ctx.i32_type().into()], false));
+                on_write(ctx, module, flavor, write, read, exit)
+            }
+            Flavor::Freestanding(arch) => {
+                let (write, read) = (linux_write(ctx, module, arch), linux_read(ctx, module));
+                let exit = module.add_function("__mini_sys_exit", ctx.void_type().fn_type(&[ctx.i32_type().into()], false), Some(Linkage::External));
+                on_write(ctx, module, flavor, write, read, exit)
+            }
+        }
+    }
+
+    /// Write `pieces` to stdout, in order, from the position of `b`.
+    ///
+    /// With `printf` the whole statement is one call, its text and
+    /// conversions merged into a single format string; otherwise adjacent
+    /// text is merged into one write. Arrays are written by a call of their
+    /// own, between the pieces before and after them.
+    pub fn print(&self, ctx: &'ctx LlvmContext, b: &Builder<'ctx>, pieces: &[Piece<'ctx>]) {
+        for run in pieces.split_inclusive(|p| matches!(p, Piece::Array(_))) {
+            let (last, before) = run.split_last().unwrap();
+            match last {
+                Piece::Array(a) => {
+                    self.output.print(ctx, b, before);
+                    b.build_call(self.print_array, &[(*a).into()], "").unwrap();
+                }
+                _ => self.output.print(ctx, b, run),
+            }
+        }
+    }
+
+    /// Add the entry point the target starts programs at, if it isn't `main`.
+    /// (The freestanding `_start` is part of its module-level assembly.)
+    ///
+    /// On WASI the arguments are fetched onto `_start`'s stack, in the layout
+    /// of a C `argc` and `argv`.
+    pub fn emit_entry(&self, ctx: &'ctx LlvmContext, module: &Module<'ctx>, main: FunctionValue<'ctx>) {
+        if self.flavor != Flavor::Wasi {
+            return;
+        }
+        let i32_t = ctx.i32_type();
+        let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+        let proc_exit = module.get_function("proc_exit").expect("the WASI runtime imports proc_exit");
+        // i32 args_sizes_get(i32* argc, i32* buf_size) and i32 args_get(ptr* argv, ptr buf)
+        let sizes_get = wasi_import(ctx, module, "args_sizes_get", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], false));
+        let args_get = wasi_import(ctx, module, "args_get", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], false));
+        let (_, b) = define(ctx, module, "_start", ctx.void_type().fn_type(&[], false), Linkage::External);
+        let (argc_p, size_p) = (b.build_alloca(i32_t, "argc.p").unwrap(), b.build_alloca(i32_t, "size.p").unwrap());
+        // a failed call leaves the program without arguments
+        b.build_store(argc_p, i32_t.const_zero()).unwrap();
+        b.build_store(size_p, i32_t.const_zero()).unwrap();
+        b.build_call(sizes_get, &[argc_p.into(), size_p.into()], "").unwrap();
+        let argc = b.build_load(i32_t, argc_p, "argc").unwrap().into_int_value();
+        let size = b.build_load(i32_t, size_p, "size").unwrap().into_int_value();
+        let argv = b.build_array_alloca(ptr_t, argc, "argv").unwrap();
+        let buf = b.build_array_alloca(ctx.i8_type(), size, "buf").unwrap();
+        b.build_call(args_get, &[argv.into(), buf.into()], "").unwrap();
+        let status = b.build_call(main, &[argc.into(), argv.into()], "status").unwrap().try_as_basic_value().left().unwrap();
+        b.build_call(proc_exit, &[status.into()], "").unwrap();
+        b.build_unreachable().unwrap();
+    }
+}
+
+impl<'ctx> Output<'ctx> {
+    /// Write pieces other than arrays; see [`Runtime::print`].
+    fn print(self, ctx: &'ctx LlvmContext, b: &Builder<'ctx>, pieces: &[Piece<'ctx>]) {
+        match self {
+            Output::Printf(printf) => {
+                let mut fmt = String::new();
+                let mut args: Vec<BasicMetadataValueEnum<'ctx>> = vec![];
+                for piece in pieces {
+                    let (conv, arg) = match piece {
+                        Piece::Text(t) => {
+                            fmt += &t.replace('%', "%%");
+                            continue;
+                        }
+                        Piece::Int(v) => ("%d", (*v).into()),
+                        Piece::I64(v) => ("%lld", (*v).into()),
+                        Piece::F64(v) => ("%g", (*v).into()),
+                        Piece::Str(v) => ("%s", (*v).into()),
+                        Piece::Array(_) => unreachable!("`Runtime::print` writes arrays"),
+                    };
+                    fmt += conv;
+                    args.push(arg);
+                }
+                if fmt.is_empty() {
+                    return;
+                }
+                args.insert(0, b.build_global_string_ptr(&fmt, ".fmt").unwrap().as_pointer_value().into());
+                b.build_call(printf, &args, "").unwrap();
+            }
+            Output::Write { text, i64, f64, str } => {
+                let flush = |pending: &mut String| {
+                    if !pending.is_empty() {
+                        let s = b.build_global_string_ptr(pending, ".text").unwrap().as_pointer_value();
+                        let len = ctx.i32_type().const_int(pending.len() as u64, false);
+                        b.build_call(text, &[s.into(), len.into()], "").unwrap();
+                        pending.clear();
+                    }
+                };
+                let mut pending = String::new();
+                for piece in pieces {
+                    let (f, arg): (_, BasicMetadataValueEnum<'ctx>) = match piece {
+                        Piece::Text(t) => {
+                            pending += t;
+                            continue;
+                        }
+                        Piece::Int(v) => (i64, b.build_int_s_extend(*v, ctx.i64_type(), "wide").unwrap().into()),
+                        Piece::I64(v) => (i64, (*v).into()),
+                        Piece::F64(v) => (f64, (*v).into()),
+                        Piece::Str(v) => (str, (*v).into()),
+                        Piece::Array(_) => unreachable!("`Runtime::print` writes arrays"),
+                    };
+                    flush(&mut pending);
+                    b.build_call(f, &[arg], "").unwrap();
+                }
+                flush(&mut pending);
+            }
+        }
+    }
+}
+
+/// Start a function definition and return a builder positioned in its entry block.
+fn define<'ctx>(
+    ctx: &'ctx LlvmContext,
+    module: &Module<'ctx>,
+    name: &str,
+    ty: FunctionType<'ctx>,
+    linkage: Linkage,
+) -> (FunctionValue<'ctx>, Builder<'ctx>) {
+    let f = module.add_function(name, ty, Some(linkage));
+    let b = ctx.create_builder();
+    b.position_at_end(ctx.append_basic_block(f, "entry"));
+    (f, b)
+}
+
+/// Define `void __mini_fail(ptr msg, i32 len)` around `body`, which writes
+/// the message and exits. Marked `noreturn` and `cold` so checks stay off the
+/// hot path.
+fn fail<'ctx>(
+    ctx: &'ctx LlvmContext,
+    module: &Module<'ctx>,
+    body: impl FnOnce(&Builder<'ctx>, PointerValue<'ctx>, IntValue<'ctx>),
+) -> FunctionValue<'ctx> {
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    let ty = ctx.void_type().fn_type(&[ptr_t.into(), ctx.i32_type().into()], false);
+    let (f, b) = define(ctx, module, "__mini_fail", ty, Linkage::Internal);
+    for attr in ["noreturn", "cold"] {
+        f.add_attribute(AttributeLoc::Function, ctx.create_enum_attribute(Attribute::get_named_enum_kind_id(attr), 0));
+    }
+    let msg = f.get_nth_param(0).unwrap().into_pointer_value();
+    let len = f.get_nth_param(1).unwrap().into_int_value();
+    body(&b, msg, len);
+    b.build_unreachable().unwrap();
+    f
+}
+
+fn libc<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> Runtime<'ctx> {
+    let i32_t = ctx.i32_type();
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    // declare i32 @printf(i8*, ...)
+    let printf = module.add_function("printf", i32_t.fn_type(&[ptr_t.into()], true), Some(Linkage::External));
+
+    // flush what the program printed so far, so the message comes after it
+    let fflush = module.add_function("fflush", i32_t.fn_type(&[ptr_t.into()], false), Some(Linkage::External));
+    // `dprintf` rather than `write`, whose `size_t` length varies by target
+    let dprintf = module.add_function("dprintf", i32_t.fn_type(&[i32_t.into(), ptr_t.into()], true), Some(Linkage::External));
+    let exit = module.add_function("exit", ctx.void_type().fn_type(&[i32_t.into()], false), Some(Linkage::External));
+    let fail = fail(ctx, module, |b, msg, len| {
+        b.build_call(fflush, &[ptr_t.const_null().into()], "").unwrap();
+        let fmt = b.build_global_string_ptr("%.*s", ".fmt").unwrap().as_pointer_value();
+        b.build_call(dprintf, &[i32_t.const_int(2, false).into(), fmt.into(), len.into(), msg.into()], "").unwrap();
+        b.build_call(exit, &[i32_t.const_int(FAIL_STATUS as u64, false).into()], "").unwrap();
+    });
+
+    // `sprintf` into a buffer of `NUM_BUF` bytes, returning the length
+    let sprintf = module.add_function("sprintf", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], true), Some(Linkage::External));
+    let formatter = |name: &str, fmt: &str, arg: inkwell::types::BasicMetadataTypeEnum<'ctx>| {
+        let (f, b) = define(ctx, module, name, i32_t.fn_type(&[arg, ptr_t.into()], false), Linkage::Internal);
+        let fmt = b.build_global_string_ptr(fmt, ".fmt").unwrap().as_pointer_value();
+        let (v, buf) = (f.get_nth_param(0).unwrap(), f.get_nth_param(1).unwrap());
+        let n = b.build_call(sprintf, &[buf.into(), fmt.into(), v.into()], "n").unwrap().try_as_basic_value().left().unwrap();
+        b.build_return(Some(&n)).unwrap();
+        f
+    };
+    let fmt_i64 = formatter("__mini_fmt_i64", "%lld", ctx.i64_type().into());
+    let fmt_f64 = formatter("__mini_fmt_f64", "%g", ctx.f64_type().into());
+    let (alloc, copy) = (alloc(ctx, module, fail), copy(ctx, module));
+    let strings = strings(ctx, module, alloc, copy, fmt_i64, fmt_f64);
+    let arrays = arrays(ctx, module, alloc, copy);
+    let output = Output::Printf(printf);
+    let getchar = module.add_function("getchar", i32_t.fn_type(&[], false), Some(Linkage::External));
+    let input = input(ctx, module, alloc, getchar);
+
+    Runtime {
+        flavor: Flavor::Libc,
+        output,
+        fail,
+        str_len: strings.len,
+        str_concat: strings.concat,
+        str_cmp: strings.cmp,
+        i64_to_str: strings.i64_to_str,
+        f64_to_str: strings.f64_to_str,
+        array_new: arrays.new,
+        array_push: arrays.push,
+        print_array: print_array(ctx, module, output),
+        set_args: input.set_args,
+        args: input.args,
+        arg: input.arg,
+        read_line: input.read_line,
+        parse_int: input.parse_int,
+    }
+}
+
+/// The string routines every flavor shares, on top of its number formatters.
+struct Strings<'ctx> {
+    len: FunctionValue<'ctx>,
+    concat: FunctionValue<'ctx>,
+    cmp: FunctionValue<'ctx>,
+    i64_to_str: FunctionValue<'ctx>,
+    f64_to_str: FunctionValue<'ctx>,
+}
+
+/// Define the string routines on top of `alloc`. `fmt_i64` and `fmt_f64` are
+/// `i32 (T, ptr buf)`: they write a number's text to `buf`, which has room for
+/// [`NUM_BUF`] bytes, and return its length.
+fn strings<'ctx>(
+    ctx: &'ctx LlvmContext,
+    module: &Module<'ctx>,
+    alloc: FunctionValue<'ctx>,
+    copy: FunctionValue<'ctx>,
+    fmt_i64: FunctionValue<'ctx>,
+    fmt_f64: FunctionValue<'ctx>,
+) -> Strings<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let one = i32_t.const_int(1, false);
+    let at = |b: &Builder<'ctx>, base: PointerValue<'ctx>, i: IntValue<'ctx>| unsafe {
+        b.build_in_bounds_gep(i8_t, base, &[i], "at").unwrap()
+    };
+
+    // `i32 __mini_str_len(ptr s)`: count bytes up to the NUL
+    let (len, b) = define(ctx, module, "__mini_str_len", i32_t.fn_type(&[ptr_t.into()], false), Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let s = len.get_first_param().unwrap().into_pointer_value();
+    let scan_bb = ctx.append_basic_block(len, "scan");
+    let found_bb = ctx.append_basic_block(len, "found");
+    b.build_unconditional_branch(scan_bb).unwrap();
+    b.position_at_end(scan_bb);
+    let i = b.build_phi(i32_t, "i").unwrap();
+    let i_v = i.as_basic_value().into_int_value();
+    let ch = b.build_load(i8_t, at(&b, s, i_v), "ch").unwrap().into_int_value();
+    let next = b.build_int_add(i_v, one, "next").unwrap();
+    let at_end = b.build_int_compare(IntPredicate::EQ, ch, i8_t.const_zero(), "at.end").unwrap();
+    b.build_conditional_branch(at_end, found_bb, scan_bb).unwrap();
+    i.add_incoming(&[(&i32_t.const_zero(), entry), (&next, scan_bb)]);
+    b.position_at_end(found_bb);
+    b.build_return(Some(&i_v)).unwrap();
+
+    // `ptr __mini_str_concat(ptr a, ptr b)`
+    let (concat, b) = define(ctx, module, "__mini_str_concat", ptr_t.fn_type(&[ptr_t.into(), ptr_t.into()], false), Linkage::Internal);
+    let (l, r) = (concat.get_nth_param(0).unwrap(), concat.get_nth_param(1).unwrap());
+    let call = |b: &Builder<'ctx>, f: FunctionValue<'ctx>, args: &[inkwell::values::BasicMetadataValueEnum<'ctx>]| {
+        b.build_call(f, args, "").unwrap().try_as_basic_value().left().unwrap()
+    };
+    let ll = call(&b, len, &[l.into()]).into_int_value();
+    let rl = call(&b, len, &[r.into()]).into_int_value();
+    let total = b.build_int_add(ll, rl, "total").unwrap();
+    let out = call(&b, alloc, &[b.build_int_add(total, one, "size").unwrap().into()]).into_pointer_value();
+    b.build_call(copy, &[out.into(), l.into(), ll.into()], "").unwrap();
+    b.build_call(copy, &[at(&b, out, ll).into(), r.into(), rl.into()], "").unwrap();
+    b.build_store(at(&b, out, total), i8_t.const_zero()).unwrap();
+    b.build_return(Some(&out)).unwrap();
+
+    // `i32 __mini_str_cmp(ptr a, ptr b)`: the difference of the first bytes that differ
+    let (cmp, b) = define(ctx, module, "__mini_str_cmp", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], false), Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let (l, r) = (cmp.get_nth_param(0).unwrap().into_pointer_value(), cmp.get_nth_param(1).unwrap().into_pointer_value());
+    let loop_bb = ctx.append_basic_block(cmp, "loop");
+    let done_bb = ctx.append_basic_block(cmp, "done");
+    b.build_unconditional_branch(loop_bb).unwrap();
+    b.position_at_end(loop_bb);
+    let i = b.build_phi(i32_t, "i").unwrap();
+    let i_v = i.as_basic_value().into_int_value();
+    let byte = |p: PointerValue<'ctx>| {
+        let c = b.build_load(i8_t, at(&b, p, i_v), "c").unwrap().into_int_value();
+        b.build_int_z_extend(c, i32_t, "c32").unwrap()
+    };
+    let (lc, rc) = (byte(l), byte(r));
+    let diff = b.build_int_sub(lc, rc, "diff").unwrap();
+    let differ = b.build_int_compare(IntPredicate::NE, diff, i32_t.const_zero(), "differ").unwrap();
+    let ended = b.build_int_compare(IntPredicate::EQ, lc, i32_t.const_zero(), "ended").unwrap();
+    let next = b.build_int_add(i_v, one, "next").unwrap();
+    b.build_conditional_branch(b.build_or(differ, ended, "stop").unwrap(), done_bb, loop_bb).unwrap();
+    i.add_incoming(&[(&i32_t.const_zero(), entry), (&next, loop_bb)]);
+    b.position_at_end(done_bb);
+    b.build_return(Some(&diff)).unwrap();
+
+    // numbers are formatted on the stack, then copied to the heap
+    let to_str = |name: &str, fmt: FunctionValue<'ctx>| {
+        let arg = fmt.get_type().get_param_types()[0];
+        let (f, b) = define(ctx, module, name, ptr_t.fn_type(&[arg.into()], false), Linkage::Internal);
+        let buf = b.build_alloca(i8_t.array_type(NUM_BUF), "buf").unwrap();
+        let n = call(&b, fmt, &[f.get_first_param().unwrap().into(), buf.into()]).into_int_value();
+        let out = call(&b, alloc, &[b.build_int_add(n, one, "size").unwrap().into()]).into_pointer_value();
+        b.build_call(copy, &[out.into(), buf.into(), n.into()], "").unwrap();
+        b.build_store(at(&b, out, n), i8_t.const_zero()).unwrap();
+        b.build_return(Some(&out)).unwrap();
+        f
+    };
+    let i64_to_str = to_str("__mini_i64_to_str", fmt_i64);
+    let f64_to_str = to_str("__mini_f64_to_str", fmt_f64);
+    Strings { len, concat, cmp, i64_to_str, f64_to_str }
+}
+
+/// `ptr __mini_alloc(i32 n)`: bump allocation of `n` bytes, rounded up to a
+/// multiple of [`HEAP_ALIGN`]; running out stops the program.
+fn alloc<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, fail: FunctionValue<'ctx>) -> FunctionValue<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let heap_t = i8_t.array_type(HEAP_SIZE);
+    let heap = module.add_global(heap_t, None, "__mini_heap");
+    heap.set_linkage(Linkage::Internal);
+    heap.set_initializer(&heap_t.const_zero());
+    heap.set_alignment(HEAP_ALIGN);
+    let used = module.add_global(i32_t, None, "__mini_heap_used");
+    used.set_linkage(Linkage::Internal);
+    used.set_initializer(&i32_t.const_zero());
+    let (alloc, b) = define(ctx, module, "__mini_alloc", ptr_t.fn_type(&[i32_t.into()], false), Linkage::Internal);
+    let n = alloc.get_first_param().unwrap().into_int_value();
+    // callers never ask for more than a few times the heap, so this can't wrap
+    let n = b.build_int_add(n, i32_t.const_int(HEAP_ALIGN as u64 - 1, false), "").unwrap();
+    let n = b.build_and(n, i32_t.const_int(!(HEAP_ALIGN as u64 - 1), false), "size").unwrap();
+    let start = b.build_load(i32_t, used.as_pointer_value(), "start").unwrap().into_int_value();
+    let end = b.build_int_add(start, n, "end").unwrap();
+    let full = b.build_int_compare(IntPredicate::UGT, end, i32_t.const_int(HEAP_SIZE as u64, false), "full").unwrap();
+    let oom_bb = ctx.append_basic_block(alloc, "oom");
+    let ok_bb = ctx.append_basic_block(alloc, "ok");
+    b.build_conditional_branch(full, oom_bb, ok_bb).unwrap();
+    b.position_at_end(oom_bb);
+    let msg = format!("error: out of memory: strings and arrays have used up the {} MiB heap\n", HEAP_SIZE >> 20);
+    let text = b.build_global_string_ptr(&msg, ".oom").unwrap().as_pointer_value();
+    b.build_call(fail, &[text.into(), i32_t.const_int(msg.len() as u64, false).into()], "").unwrap();
+    b.build_unreachable().unwrap();
+    b.position_at_end(ok_bb);
+    b.build_store(used.as_pointer_value(), end).unwrap();
+    let at = unsafe { b.build_in_bounds_gep(i8_t, heap.as_pointer_value(), &[start], "at").unwrap() };
+    b.build_return(Some(&at)).unwrap();
+    alloc
+}
+
+/// The array routines every flavor shares.
+struct Arrays<'ctx> {
+    new: FunctionValue<'ctx>,
+    push: FunctionValue<'ctx>,
+}
+
+/// Define the array routines on top of `alloc`. Elements are `i32`s, and
+/// fresh heap memory is always zero, as the allocator never reuses it.
+fn arrays<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, alloc: FunctionValue<'ctx>, copy: FunctionValue<'ctx>) -> Arrays<'ctx> {
+    let i32_t = ctx.i32_type();
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    let header_t = array_type(ctx);
+    let int = |v: u64| i32_t.const_int(v, false);
+    let call = |b: &Builder<'ctx>, f: FunctionValue<'ctx>, args: &[BasicMetadataValueEnum<'ctx>]| {
+        b.build_call(f, args, "").unwrap().try_as_basic_value().left().unwrap()
+    };
+    let field = |b: &Builder<'ctx>, a: PointerValue<'ctx>, i: u32, name: &str| b.build_struct_gep(header_t, a, i, name).unwrap();
+
+    // `ptr __mini_array_new(i32 n)`; a length past the heap's size is
+    // clamped so the element bytes can't overflow, and fails to allocate
+    let (new, b) = define(ctx, module, "__mini_array_new", ptr_t.fn_type(&[i32_t.into()], false), Linkage::Internal);
+    let n = new.get_first_param().unwrap().into_int_value();
+    let a = call(&b, alloc, &[int(ARRAY_HEADER as u64).into()]).into_pointer_value();
+    let big = b.build_int_compare(IntPredicate::UGT, n, int(HEAP_SIZE as u64), "").unwrap();
+    let clamped = b.build_select(big, int(HEAP_SIZE as u64), n, "").unwrap().into_int_value();
+    let bytes = b.build_int_mul(clamped, int(4), "bytes").unwrap();
+    let data = call(&b, alloc, &[bytes.into()]);
+    b.build_store(field(&b, a, 0, "len"), n).unwrap();
+    b.build_store(field(&b, a, 1, "cap"), n).unwrap();
+    b.build_store(field(&b, a, 2, "data"), data).unwrap();
+    b.build_return(Some(&a)).unwrap();
+
+    // `void __mini_array_push(ptr a, i32 x)`: a full array doubles its
+    // capacity, to at least 4; the old elements stay behind on the heap
+    let ty = ctx.void_type().fn_type(&[ptr_t.into(), i32_t.into()], false);
+    let (push, b) = define(ctx, module, "__mini_array_push", ty, Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let (a, x) = (push.get_nth_param(0).unwrap().into_pointer_value(), push.get_nth_param(1).unwrap());
+    let (len_p, cap_p, data_p) = (field(&b, a, 0, "len.p"), field(&b, a, 1, "cap.p"), field(&b, a, 2, "data.p"));
+    let len = b.build_load(i32_t, len_p, "len").unwrap().into_int_value();
+    let cap = b.build_load(i32_t, cap_p, "cap").unwrap().into_int_value();
+    let data = b.build_load(ptr_t, data_p, "data").unwrap().into_pointer_value();
+    let grow_bb = ctx.append_basic_block(push, "grow");
+    let store_bb = ctx.append_basic_block(push, "store");
+    let full = b.build_int_compare(IntPredicate::EQ, len, cap, "full").unwrap();
+    b.build_conditional_branch(full, grow_bb, store_bb).unwrap();
+    b.position_at_end(grow_bb);
+    let doubled = b.build_int_mul(cap, int(2), "").unwrap();
+    let small = b.build_int_compare(IntPredicate::ULT, doubled, int(4), "").unwrap();
+    let new_cap = b.build_select(small, int(4), doubled, "new.cap").unwrap().into_int_value();
+    let moved = call(&b, alloc, &[b.build_int_mul(new_cap, int(4), "").unwrap().into()]).into_pointer_value();
+    b.build_call(copy, &[moved.into(), data.into(), b.build_int_mul(len, int(4), "").unwrap().into()], "").unwrap();
+    b.build_store(cap_p, new_cap).unwrap();
+    b.build_store(data_p, moved).unwrap();
+    b.build_unconditional_branch(store_bb).unwrap();
+    b.position_at_end(store_bb);
+    let slots = b.build_phi(ptr_t, "slots").unwrap();
+    slots.add_incoming(&[(&data, entry), (&moved, grow_bb)]);
+    let slot = unsafe { b.build_in_bounds_gep(i32_t, slots.as_basic_value().into_pointer_value(), &[len], "slot").unwrap() };
+    b.build_store(slot, x).unwrap();
+    b.build_store(len_p, b.build_int_add(len, int(1), "").unwrap()).unwrap();
+    b.build_return(None).unwrap();
+
+    Arrays { new, push }
+}
+
+/// `void __mini_print_array(ptr a)`: `[`, the elements separated by `, `, and
+/// `]`, written through `output`.
+fn print_array<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, output: Output<'ctx>) -> FunctionValue<'ctx> {
+    let i32_t = ctx.i32_type();
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    let header_t = array_type(ctx);
+    let (f, b) = define(ctx, module, "__mini_print_array", ctx.void_type().fn_type(&[ptr_t.into()], false), Linkage::Internal);
+    let a = f.get_first_param().unwrap().into_pointer_value();
+    let len = b.build_load(i32_t, b.build_struct_gep(header_t, a, 0, "").unwrap(), "len").unwrap().into_int_value();
+    let data = b.build_load(ptr_t, b.build_struct_gep(header_t, a, 2, "").unwrap(), "data").unwrap().into_pointer_value();
+    let element = |i: IntValue<'ctx>| {
+        let slot = unsafe { b.build_in_bounds_gep(i32_t, data, &[i], "slot").unwrap() };
+        b.build_load(i32_t, slot, "x").unwrap().into_int_value()
+    };
+    let first_bb = ctx.append_basic_block(f, "first");
+    let check_bb = ctx.append_basic_block(f, "check");
+    let rest_bb = ctx.append_basic_block(f, "rest");
+    let done_bb = ctx.append_basic_block(f, "done");
+    output.print(ctx, &b, &[Piece::Text("[".into())]);
+    let empty = b.build_int_compare(IntPredicate::EQ, len, i32_t.const_zero(), "empty").unwrap();
+    b.build_conditional_branch(empty, done_bb, first_bb).unwrap();
+
+    // the first element, then `, ` before each of the others
+    b.position_at_end(first_bb);
+    output.print(ctx, &b, &[Piece::Int(element(i32_t.const_zero()))]);
+    b.build_unconditional_branch(check_bb).unwrap();
+    b.position_at_end(check_bb);
+    let i = b.build_phi(i32_t, "i").unwrap();
+    let i_v = i.as_basic_value().into_int_value();
+    let more = b.build_int_compare(IntPredicate::SLT, i_v, len, "more").unwrap();
+    b.build_conditional_branch(more, rest_bb, done_bb).unwrap();
+    b.position_at_end(rest_bb);
+    output.print(ctx, &b, &[Piece::Text(", ".into()), Piece::Int(element(i_v))]);
+    let next = b.build_int_add(i_v, i32_t.const_int(1, false), "next").unwrap();
+    b.build_unconditional_branch(check_bb).unwrap();
+    i.add_incoming(&[(&i32_t.const_int(1, false), first_bb), (&next, rest_bb)]);
+
+    b.position_at_end(done_bb);
+    output.print(ctx, &b, &[Piece::Text("]".into())]);
+    b.build_return(None).unwrap();
+    f
+}
+
+/// The routines behind `args`, `arg`, `read_line` and `parse_int`.
+struct Input<'ctx> {
+    set_args: FunctionValue<'ctx>,
+    args: FunctionValue<'ctx>,
+    arg: FunctionValue<'ctx>,
+    read_line: FunctionValue<'ctx>,
+    parse_int: FunctionValue<'ctx>,
+}
+
+/// Define the input routines on top of `alloc` and `getchar`, an `i32 ()`
+/// that returns the next byte of stdin, or a negative value at its end.
+fn input<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, alloc: FunctionValue<'ctx>, getchar: FunctionValue<'ctx>) -> Input<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let int = |v: u64| i32_t.const_int(v, false);
+    let at = |b: &Builder<'ctx>, base: PointerValue<'ctx>, i: IntValue<'ctx>| unsafe {
+        b.build_in_bounds_gep(i8_t, base, &[i], "at").unwrap()
+    };
+    let global = |name: &str, ty: inkwell::types::BasicTypeEnum<'ctx>| {
+        let g = module.add_global(ty, None, name);
+        g.set_linkage(Linkage::Internal);
+        g.set_initializer(&ty.const_zero());
+        g.as_pointer_value()
+    };
+    let (argc, argv) = (global("__mini_argc", i32_t.into()), global("__mini_argv", ptr_t.into()));
+
+    // `void __mini_set_args(i32 argc, ptr argv)`
+    let ty = ctx.void_type().fn_type(&[i32_t.into(), ptr_t.into()], false);
+    let (set_args, b) = define(ctx, module, "__mini_set_args", ty, Linkage::Internal);
+    b.build_store(argc, set_args.get_nth_param(0).unwrap()).unwrap();
+    b.build_store(argv, set_args.get_nth_param(1).unwrap()).unwrap();
+    b.build_return(None).unwrap();
+
+    // `i32 __mini_args()`: `argc - 1`, though a program may be started without even a name
+    let (args, b) = define(ctx, module, "__mini_args", i32_t.fn_type(&[], false), Linkage::Internal);
+    let n = b.build_load(i32_t, argc, "argc").unwrap().into_int_value();
+    let named = b.build_int_compare(IntPredicate::SGT, n, int(0), "named").unwrap();
+    let count = b.build_select(named, b.build_int_sub(n, int(1), "").unwrap(), int(0), "count").unwrap();
+    b.build_return(Some(&count)).unwrap();
+
+    // `ptr __mini_arg(i32 i)`: `argv[i + 1]`
+    let (arg, b) = define(ctx, module, "__mini_arg", ptr_t.fn_type(&[i32_t.into()], false), Linkage::Internal);
+    let i = b.build_int_add(arg.get_first_param().unwrap().into_int_value(), int(1), "i").unwrap();
+    let table = b.build_load(ptr_t, argv, "argv").unwrap().into_pointer_value();
+    let slot = unsafe { b.build_in_bounds_gep(ptr_t, table, &[i], "slot").unwrap() };
+    b.build_return(Some(&b.build_load(ptr_t, slot, "arg").unwrap())).unwrap();
+
+    // `ptr __mini_read_line()`: the line is read straight into the heap, and
+    // grows by another block whenever it fills the ones it has; nothing else
+    // allocates meanwhile, so the blocks are contiguous
+    let (read_line, b) = define(ctx, module, "__mini_read_line", ptr_t.fn_type(&[], false), Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let block = int(HEAP_ALIGN as u64);
+    let start = b.build_call(alloc, &[block.into()], "start").unwrap().try_as_basic_value().left().unwrap().into_pointer_value();
+    let next_bb = ctx.append_basic_block(read_line, "next");
+    let store_bb = ctx.append_basic_block(read_line, "store");
+    let grow_bb = ctx.append_basic_block(read_line, "grow");
+    let check_bb = ctx.append_basic_block(read_line, "check");
+    let done_bb = ctx.append_basic_block(read_line, "done");
+    b.build_unconditional_branch(next_bb).unwrap();
+
+    b.position_at_end(next_bb);
+    let n = b.build_phi(i32_t, "n").unwrap();
+    let room = b.build_phi(i32_t, "room").unwrap();
+    let (n_v, room_v) = (n.as_basic_value().into_int_value(), room.as_basic_value().into_int_value());
+    let c = b.build_call(getchar, &[], "c").unwrap().try_as_basic_value().left().unwrap().into_int_value();
+    let eof = b.build_int_compare(IntPredicate::SLT, c, int(0), "eof").unwrap();
+    b.build_conditional_branch(eof, done_bb, store_bb).unwrap();
+
+    // there is always room for the byte and the NUL after it
+    b.position_at_end(store_bb);
+    b.build_store(at(&b, start, n_v), b.build_int_truncate(c, i8_t, "byte").unwrap()).unwrap();
+    let n_next = b.build_int_add(n_v, int(1), "n.next").unwrap();
+    let full = b.build_int_compare(IntPredicate::EQ, n_next, room_v, "full").unwrap();
+    b.build_conditional_branch(full, grow_bb, check_bb).unwrap();
+    b.position_at_end(grow_bb);
+    b.build_call(alloc, &[block.into()], "").unwrap();
+    let grown = b.build_int_add(room_v, block, "grown").unwrap();
+    b.build_unconditional_branch(check_bb).unwrap();
+    b.position_at_end(check_bb);
+    let room_next = b.build_phi(i32_t, "room.next").unwrap();
+    room_next.add_incoming(&[(&room_v, store_bb), (&grown, grow_bb)]);
+    let newline = b.build_int_compare(IntPredicate::EQ, c, int(b'\n' as u64), "newline").unwrap();
+    b.build_conditional_branch(newline, done_bb, next_bb).unwrap();
+    n.add_incoming(&[(&int(0), entry), (&n_next, check_bb)]);
+    room.add_incoming(&[(&block, entry), (&room_next.as_basic_value(), check_bb)]);
+
+    b.position_at_end(done_bb);
+    let len = b.build_phi(i32_t, "len").unwrap();
+    len.add_incoming(&[(&n_v, next_bb), (&n_next, check_bb)]);
+    b.build_store(at(&b, start, len.as_basic_value().into_int_value()), i8_t.const_zero()).unwrap();
+    b.build_return(Some(&start)).unwrap();
+
+    Input { set_args, args, arg, read_line, parse_int: parse_int(ctx, module) }
+}
+
+/// `i32 __mini_parse_int(ptr s, ptr out)`: whitespace, an optional sign,
+/// digits and whitespace again, as Rust's `char::is_ascii_whitespace`
+/// defines it. The magnitude is accumulated as an `i64` that stops growing
+/// past `i32`'s range, so the whole string is checked before the range is.
+fn parse_int<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let i64_t = ctx.i64_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let int = |v: u64| i32_t.const_int(v, false);
+    let byte = |c: u8| i8_t.const_int(c as u64, false);
+    let (f, b) = define(ctx, module, "__mini_parse_int", i32_t.fn_type(&[ptr_t.into(), ptr_t.into()], false), Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let (s, out) = (f.get_nth_param(0).unwrap().into_pointer_value(), f.get_nth_param(1).unwrap().into_pointer_value());
+    let load = |b: &Builder<'ctx>, i: IntValue<'ctx>| {
+        let p = unsafe { b.build_in_bounds_gep(i8_t, s, &[i], "at").unwrap() };
+        b.build_load(i8_t, p, "c").unwrap().into_int_value()
+    };
+    let space = |b: &Builder<'ctx>, c: IntValue<'ctx>| {
+        [b' ', b'\t', b'\n', 0x0c, b'\r']
+            .map(|w| b.build_int_compare(IntPredicate::EQ, c, byte(w), "").unwrap())
+            .into_iter()
+            .reduce(|x, y| b.build_or(x, y, "").unwrap())
+            .unwrap()
+    };
+    let lead_bb = ctx.append_basic_block(f, "lead");
+    let sign_bb = ctx.append_basic_block(f, "sign");
+    let digits_bb = ctx.append_basic_block(f, "digits");
+    let digit_bb = ctx.append_basic_block(f, "digit");
+    let after_bb = ctx.append_basic_block(f, "after");
+    let trail_bb = ctx.append_basic_block(f, "trail");
+    let end_bb = ctx.append_basic_block(f, "end");
+    b.build_unconditional_branch(lead_bb).unwrap();
+
+    b.position_at_end(lead_bb);
+    let i = b.build_phi(i32_t, "i").unwrap();
+    let i_v = i.as_basic_value().into_int_value();
+    let c = load(&b, i_v);
+    let i_next = b.build_int_add(i_v, int(1), "i.next").unwrap();
+    b.build_conditional_branch(space(&b, c), lead_bb, sign_bb).unwrap();
+    i.add_incoming(&[(&int(0), entry), (&i_next, lead_bb)]);
+
+    b.position_at_end(sign_bb);
+    let neg = b.build_int_compare(IntPredicate::EQ, c, byte(b'-'), "neg").unwrap();
+    let plus = b.build_int_compare(IntPredicate::EQ, c, byte(b'+'), "plus").unwrap();
+    let signed = b.build_or(neg, plus, "signed").unwrap();
+    let first = b.build_int_add(i_v, b.build_int_z_extend(signed, i32_t, "").unwrap(), "first").unwrap();
+    b.build_unconditional_branch(digits_bb).unwrap();
+
+    b.position_at_end(digits_bb);
+    let j = b.build_phi(i32_t, "j").unwrap();
+    let acc = b.build_phi(i64_t, "acc").unwrap();
+    let (j_v, acc_v) = (j.as_basic_value().into_int_value(), acc.as_basic_value().into_int_value());
+    let d = b.build_int_sub(load(&b, j_v), byte(b'0'), "d").unwrap();
+    let is_digit = b.build_int_compare(IntPredicate::ULE, d, byte(9), "is.digit").unwrap();
+    b.build_conditional_branch(is_digit, digit_bb, after_bb).unwrap();
+    b.position_at_end(digit_bb);
+    let cap = i64_t.const_int(1 << 32, false);
+    let sum = b.build_int_mul(acc_v, i64_t.const_int(10, false), "").unwrap();
+    let sum = b.build_int_add(sum, b.build_int_z_extend(d, i64_t, "").unwrap(), "sum").unwrap();
+    let over = b.build_int_compare(IntPredicate::UGT, sum, cap, "").unwrap();
+    let acc_next = b.build_select(over, cap, sum, "acc.next").unwrap();
+    let j_next = b.build_int_add(j_v, int(1), "j.next").unwrap();
+    b.build_unconditional_branch(digits_bb).unwrap();
+    j.add_incoming(&[(&first, sign_bb), (&j_next, digit_bb)]);
+    acc.add_incoming(&[(&i64_t.const_zero(), sign_bb), (&acc_next, digit_bb)]);
+
+    b.position_at_end(after_bb);
+    let any = b.build_int_compare(IntPredicate::NE, j_v, first, "any").unwrap();
+    b.build_unconditional_branch(trail_bb).unwrap();
+    b.position_at_end(trail_bb);
+    let k = b.build_phi(i32_t, "k").unwrap();
+    let k_v = k.as_basic_value().into_int_value();
+    let c = load(&b, k_v);
+    let k_next = b.build_int_add(k_v, int(1), "k.next").unwrap();
+    b.build_conditional_branch(space(&b, c), trail_bb, end_bb).unwrap();
+    k.add_incoming(&[(&j_v, after_bb), (&k_next, trail_bb)]);
+
+    // `i32::MIN` has one more unit of magnitude than `i32::MAX`
+    b.position_at_end(end_bb);
+    let whole = b.build_and(any, b.build_int_compare(IntPredicate::EQ, c, byte(0), "").unwrap(), "whole").unwrap();
+    let limit = b.build_int_add(i64_t.const_int(i32::MAX as u64, false), b.build_int_z_extend(neg, i64_t, "").unwrap(), "limit").unwrap();
+    let big = b.build_int_compare(IntPredicate::UGT, acc_v, limit, "big").unwrap();
+    let value = b.build_select(neg, b.build_int_neg(acc_v, "").unwrap(), acc_v, "value").unwrap().into_int_value();
+    b.build_store(out, b.build_int_truncate(value, i32_t, "").unwrap()).unwrap();
+    let status = b.build_select(big, int(2), int(0), "").unwrap().into_int_value();
+    let status = b.build_select(whole, status, int(1), "status").unwrap();
+    b.build_return(Some(&status)).unwrap();
+    f
+}
+
+/// `void __mini_copy(ptr dst, ptr src, i32 n)`: copy `n` bytes.
+fn copy<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let ty = ctx.void_type().fn_type(&[ptr_t.into(), ptr_t.into(), i32_t.into()], false);
+    let (f, b) = define(ctx, module, "__mini_copy", ty, Linkage::Internal);
+    // the optimizer would otherwise turn the loop into a `memcpy` call, which
+    // WASI and freestanding builds have nothing to link against
+    f.add_attribute(AttributeLoc::Function, ctx.create_string_attribute("no-builtins", ""));
+    let entry = b.get_insert_block().unwrap();
+    let (dst, src) = (f.get_nth_param(0).unwrap().into_pointer_value(), f.get_nth_param(1).unwrap().into_pointer_value());
+    let n = f.get_nth_param(2).unwrap().into_int_value();
+    let check_bb = ctx.append_basic_block(f, "check");
+    let body_bb = ctx.append_basic_block(f, "body");
+    let done_bb = ctx.append_basic_block(f, "done");
+    b.build_unconditional_branch(check_bb).unwrap();
+    b.position_at_end(check_bb);
+    let i = b.build_phi(i32_t, "i").unwrap();
+    let i_v = i.as_basic_value().into_int_value();
+    let more = b.build_int_compare(IntPredicate::SLT, i_v, n, "more").unwrap();
+    b.build_conditional_branch(more, body_bb, done_bb).unwrap();
+    b.position_at_end(body_bb);
+    let from = unsafe { b.build_in_bounds_gep(i8_t, src, &[i_v], "from").unwrap() };
+    let to = unsafe { b.build_in_bounds_gep(i8_t, dst, &[i_v], "to").unwrap() };
+    b.build_store(to, b.build_load(i8_t, from, "c").unwrap()).unwrap();
+    let next = b.build_int_add(i_v, i32_t.const_int(1, false), "next").unwrap();
+    b.build_unconditional_branch(check_bb).unwrap();
+    i.add_incoming(&[(&i32_t.const_zero(), entry), (&next, body_bb)]);
+    b.position_at_end(done_bb);
+    b.build_return(None).unwrap();
+    f
+}
+
+/// Declare a function imported from `wasi_snapshot_preview1`.
+fn wasi_import<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, name: &str, ty: FunctionType<'ctx>) -> FunctionValue<'ctx> {
+    let f = module.add_function(name, ty, Some(Linkage::External));
+    f.add_attribute(AttributeLoc::Function, ctx.create_string_attribute("wasm-import-module", "wasi_snapshot_preview1"));
+    f.add_attribute(AttributeLoc::Function, ctx.create_string_attribute("wasm-import-name", name));
+    f
+}
+
+/// `void __mini_write(i32 fd, ptr buf, i32 len)`: call `write_once` until all
+/// of `buf` is written to `fd`. `write_once` emits one attempt and yields the
+/// number of bytes written, or a value below 1 on failure, which abandons the
+/// rest.
+fn write_loop<'ctx>(
+    ctx: &'ctx LlvmContext,
+    module: &Module<'ctx>,
+    write_once: impl Fn(&Builder<'ctx>, IntValue<'ctx>, PointerValue<'ctx>, IntValue<'ctx>) -> IntValue<'ctx>,
+) -> FunctionValue<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let ty = ctx.void_type().fn_type(&[i32_t.into(), ptr_t.into(), i32_t.into()], false);
+    let (f, b) = define(ctx, module, "__mini_write", ty, Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let again = ctx.append_basic_block(f, "again");
+    let wrote = ctx.append_basic_block(f, "wrote");
+    let done = ctx.append_basic_block(f, "done");
+    b.build_unconditional_branch(again).unwrap();
+
+    b.position_at_end(again);
+    let buf = b.build_phi(ptr_t, "buf").unwrap();
+    let len = b.build_phi(i32_t, "len").unwrap();
+    let (buf_v, len_v) = (buf.as_basic_value().into_pointer_value(), len.as_basic_value().into_int_value());
+    let fd = f.get_nth_param(0).unwrap().into_int_value();
+    let n = write_once(&b, fd, buf_v, len_v);
+    let ok = b.build_int_compare(IntPredicate::SGT, n, i32_t.const_zero(), "ok").unwrap();
+    b.build_conditional_branch(ok, wrote, done).unwrap();
+
+    // a short write leaves the rest for another round
+    b.position_at_end(wrote);
+    let rest = b.build_int_sub(len_v, n, "rest").unwrap();
+    let next = unsafe { b.build_in_bounds_gep(i8_t, buf_v, &[n], "next").unwrap() };
+    let more = b.build_int_compare(IntPredicate::SGT, rest, i32_t.const_zero(), "more").unwrap();
+    b.build_conditional_branch(more, again, done).unwrap();
+
+    buf.add_incoming(&[(&f.get_nth_param(1).unwrap(), entry), (&next, wrote)]);
+    len.add_incoming(&[(&f.get_nth_param(2).unwrap(), entry), (&rest, wrote)]);
+
+    b.position_at_end(done);
+    b.build_return(None).unwrap();
+    f
+}
+
+/// `i32 __mini_getchar()` on top of `read`, an `i32 (ptr buf, i32 len)` that
+/// reads up to `len` bytes of stdin and returns how many, or a value below 1
+/// at the end of input or on failure. Reads fill a buffer of [`READ_BUF`]
+/// bytes; the end of input isn't remembered, so a terminal can send more.
+fn buffered_getchar<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, read: FunctionValue<'ctx>) -> FunctionValue<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let buf_t = i8_t.array_type(READ_BUF);
+    let buf = module.add_global(buf_t, None, "__mini_in_buf");
+    buf.set_linkage(Linkage::Internal);
+    buf.set_initializer(&buf_t.const_zero());
+    let counter = |name: &str| {
+        let g = module.add_global(i32_t, None, name);
+        g.set_linkage(Linkage::Internal);
+        g.set_initializer(&i32_t.const_zero());
+        g.as_pointer_value()
+    };
+    let (pos_p, end_p) = (counter("__mini_in_pos"), counter("__mini_in_end"));
+    let (f, b) = define(ctx, module, "__mini_getchar", i32_t.fn_type(&[], false), Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let refill_bb = ctx.append_basic_block(f, "refill");
+    let filled_bb = ctx.append_basic_block(f, "filled");
+    let take_bb = ctx.append_basic_block(f, "take");
+    let eof_bb = ctx.append_basic_block(f, "eof");
+    let pos = b.build_load(i32_t, pos_p, "pos").unwrap().into_int_value();
+    let end = b.build_load(i32_t, end_p, "end").unwrap().into_int_value();
+    let empty = b.build_int_compare(IntPredicate::EQ, pos, end, "empty").unwrap();
+    b.build_conditional_branch(empty, refill_bb, take_bb).unwrap();
+
+    b.position_at_end(refill_bb);
+    let args = [buf.as_pointer_value().into(), i32_t.const_int(READ_BUF as u64, false).into()];
+    let n = b.build_call(read, &args, "n").unwrap().try_as_basic_value().left().unwrap().into_int_value();
+    let got = b.build_int_compare(IntPredicate::SGT, n, i32_t.const_zero(), "got").unwrap();
+    b.build_conditional_branch(got, filled_bb, eof_bb).unwrap();
+    b.position_at_end(filled_bb);
+    b.build_store(end_p, n).unwrap();
+    b.build_unconditional_branch(take_bb).unwrap();
+
+    b.position_at_end(take_bb);
+    let at = b.build_phi(i32_t, "at").unwrap();
+    at.add_incoming(&[(&pos, entry), (&i32_t.const_zero(), filled_bb)]);
+    let at = at.as_basic_value().into_int_value();
+    let slot = unsafe { b.build_in_bounds_gep(i8_t, buf.as_pointer_value(), &[at], "slot").unwrap() };
+    let c = b.build_load(i8_t, slot, "c").unwrap().into_int_value();
+    b.build_store(pos_p, b.build_int_add(at, i32_t.const_int(1, false), "").unwrap()).unwrap();
+    b.build_return(Some(&b.build_int_z_extend(c, i32_t, "byte").unwrap())).unwrap();
+
+    b.position_at_end(eof_bb);
+    b.build_return(Some(&i32_t.const_all_ones())).unwrap();
+    f
+}
+
+/// `__mini_write` on WASI: `fd_write` with a single iovec.
+fn wasi_write<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    let i32_t = ctx.i32_type();
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    // i32 fd_write(i32 fd, ciovec* iovs, i32 iovs_len, i32* nwritten)
+    let fd_write_ty = i32_t.fn_type(&[i32_t.into(), ptr_t.into(), i32_t.into(), ptr_t.into()], false);
+    let fd_write = wasi_import(ctx, module, "fd_write", fd_write_ty);
+    let iovec_t = ctx.struct_type(&[ptr_t.into(), i32_t.into()], false);
+
+    write_loop(ctx, module, |b, fd, buf, len| {
+        // the slots go in the entry block so retries don't grow the stack
+        let entry = b.get_insert_block().unwrap().get_parent().unwrap().get_first_basic_block().unwrap();
+        let eb = ctx.create_builder();
+        eb.position_before(&entry.get_first_instruction().unwrap());
+        let iov = eb.build_alloca(iovec_t, "iov").unwrap();
+        let written = eb.build_alloca(i32_t, "nwritten").unwrap();
+        b.build_store(b.build_struct_gep(iovec_t, iov, 0, "iov.buf").unwrap(), buf).unwrap();
+        b.build_store(b.build_struct_gep(iovec_t, iov, 1, "iov.len").unwrap(), len).unwrap();
+        let one = i32_t.const_int(1, false);
+        let args = [fd.into(), iov.into(), one.into(), written.into()];
+        let errno = b.build_call(fd_write, &args, "errno").unwrap().try_as_basic_value().left().unwrap();
+        let ok = b.build_int_compare(IntPredicate::EQ, errno.into_int_value(), i32_t.const_zero(), "ok").unwrap();
+        let n = b.build_load(i32_t, written, "n").unwrap().into_int_value();
+        b.build_select(ok, n, i32_t.const_all_ones(), "n.or.err").unwrap().into_int_value()
+    })
+}
+
+/// `i32 __mini_read(ptr buf, i32 len)` on WASI: `fd_read` of stdin with a
+/// single iovec.
+fn wasi_read<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    let i32_t = ctx.i32_type();
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    // i32 fd_read(i32 fd, iovec* iovs, i32 iovs_len, i32* nread)
+    let fd_read = wasi_import(ctx, module, "fd_read", i32_t.fn_type(&[i32_t.into(), ptr_t.into(), i32_t.into(), ptr_t.into()], false));
+    let iovec_t = ctx.struct_type(&[ptr_t.into(), i32_t.into()], false);
+    let (f, b) = define(ctx, module, "__mini_read", i32_t.fn_type(&[ptr_t.into(), i32_t.into()], false), Linkage::Internal);
+    let iov = b.build_alloca(iovec_t, "iov").unwrap();
+    let nread = b.build_alloca(i32_t, "nread").unwrap();
+    b.build_store(b.build_struct_gep(iovec_t, iov, 0, "iov.buf").unwrap(), f.get_nth_param(0).unwrap()).unwrap();
+    b.build_store(b.build_struct_gep(iovec_t, iov, 1, "iov.len").unwrap(), f.get_nth_param(1).unwrap()).unwrap();
+    let args = [i32_t.const_zero().into(), iov.into(), i32_t.const_int(1, false).into(), nread.into()];
+    let errno = b.build_call(fd_read, &args, "errno").unwrap().try_as_basic_value().left().unwrap();
+    let ok = b.build_int_compare(IntPredicate::EQ, errno.into_int_value(), i32_t.const_zero(), "ok").unwrap();
+    let n = b.build_load(i32_t, nread, "n").unwrap().into_int_value();
+    b.build_return(Some(&b.build_select(ok, n, i32_t.const_all_ones(), "n.or.err").unwrap())).unwrap();
+    f
+}
+
+/// System call numbers and entry code for Linux without a C library.
+fn linux_asm(arch: Arch) -> String {
+    // `__mini_sys_write(fd, buf, len)`, `__mini_sys_read(fd, buf, len)` and
+    // `__mini_sys_exit(status)` take their arguments in the registers the
+    // kernel expects them in, so they only have to load the call number.
+    // `_start` passes `argc` and `argv`, which the kernel leaves at the top
+    // of the stack, to `main`, and its status to `exit_group`.
+    match arch {
+        Arch::X86_64 => "\
+.text
+.p2align 4
+__mini_sys_write:
+    movl $1, %eax
+    syscall
+    ret
+.p2align 4
+__mini_sys_read:
+    xorl %eax, %eax
+    syscall
+    ret
+.p2align 4
+__mini_sys_exit:
+    movl $231, %eax
+    syscall
+    hlt
+.globl _start
+.p2align 4
+_start:
+    xorl %ebp, %ebp
+    movl (%rsp), %edi
+    leaq 8(%rsp), %rsi
+    andq $-16, %rsp
+    callq main
+    movl %eax, %edi
+    movl $231, %eax
+    syscall
+    hlt
+"
+        .into(),
+        Arch::Aarch64 => "\
+.text
+.p2align 2
+__mini_sys_write:
+    mov x8, #64
+    svc #0
+    ret
+.p2align 2
+__mini_sys_read:
+    mov x8, #63
+    svc #0
+    ret
+.p2align 2
+__mini_sys_exit:
+    mov x8, #94
+    svc #0
+    brk #0
+.globl _start
+.p2align 2
+_start:
+    mov x29, #0
+    mov x30, #0
+    ldr x0, [sp]
+    add x1, sp, #8
+    bl main
+    mov x8, #94
+    svc #0
+    brk #0
+"
+        .into(),
+    }
+}
+
+/// `__mini_write` for freestanding Linux: the `write` system call.
+fn linux_write<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, arch: Arch) -> FunctionValue<'ctx> {
+    let i32_t = ctx.i32_type();
+    let i64_t = ctx.i64_type();
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    module.set_inline_assembly(&linux_asm(arch));
+    // returns the byte count, or a negated errno
+    let sys_write_ty = i64_t.fn_type(&[i32_t.into(), ptr_t.into(), i64_t.into()], false);
+    let sys_write = module.add_function("__mini_sys_write", sys_write_ty, Some(Linkage::External));
+
+    write_loop(ctx, module, |b, fd, buf, len| {
+        let len = b.build_int_z_extend(len, i64_t, "len64").unwrap();
+        let args = [fd.into(), buf.into(), len.into()];
+        let n = b.build_call(sys_write, &args, "n").unwrap().try_as_basic_value().left().unwrap();
+        // a write never exceeds `len`, which fits in i32, and errors stay negative
+        b.build_int_truncate(n.into_int_value(), i32_t, "n32").unwrap()
+    })
+}
+
+/// `i32 __mini_read(ptr buf, i32 len)` for freestanding Linux: the `read`
+/// system call on stdin.
+fn linux_read<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    let i32_t = ctx.i32_type();
+    let i64_t = ctx.i64_type();
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    // returns the byte count, or a negated errno, like `write`
+    let sys_read_ty = i64_t.fn_type(&[i32_t.into(), ptr_t.into(), i64_t.into()], false);
+    let sys_read = module.add_function("__mini_sys_read", sys_read_ty, Some(Linkage::External));
+    let (f, b) = define(ctx, module, "__mini_read", i32_t.fn_type(&[ptr_t.into(), i32_t.into()], false), Linkage::Internal);
+    let len = b.build_int_z_extend(f.get_nth_param(1).unwrap().into_int_value(), i64_t, "len64").unwrap();
+    let args = [i32_t.const_zero().into(), f.get_nth_param(0).unwrap().into(), len.into()];
+    let n = b.build_call(sys_read, &args, "n").unwrap().try_as_basic_value().left().unwrap();
+    b.build_return(Some(&b.build_int_truncate(n.into_int_value(), i32_t, "n32").unwrap())).unwrap();
+    f
+}
+
+/// Build the runtime on top of a `void (i32 fd, ptr, i32)` write routine, an
+/// `i32 (ptr, i32)` routine reading stdin (see [`buffered_getchar`]) and a
+/// `void (i32)` exit call.
+fn on_write<'ctx>(
+    ctx: &'ctx LlvmContext,
+    module: &Module<'ctx>,
+    flavor: Flavor,
+    write_fd: FunctionValue<'ctx>,
+    read: FunctionValue<'ctx>,
+    exit: FunctionValue<'ctx>,
+) -> Runtime<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let void_t = ctx.void_type();
+    let (stdout, stderr) = (i32_t.const_int(1, false), i32_t.const_int(2, false));
+
+    let fail = fail(ctx, module, |b, msg, len| {
+        b.build_call(write_fd, &[stderr.into(), msg.into(), len.into()], "").unwrap();
+        b.build_call(exit, &[i32_t.const_int(FAIL_STATUS as u64, false).into()], "").unwrap();
+    });
+    // everything else prints to stdout
+    let write = stdout_write(ctx, module, write_fd, stdout);
+    let (alloc, copy) = (alloc(ctx, module, fail), copy(ctx, module));
+    let fmt_i64 = fmt_i64(ctx, module);
+    let fmt_f64 = fmt_g(ctx, module, copy);
+    let strings = strings(ctx, module, alloc, copy, fmt_i64, fmt_f64);
+    let arrays = arrays(ctx, module, alloc, copy);
+    let input = input(ctx, module, alloc, buffered_getchar(ctx, module, read));
+
+    // numbers are formatted into a buffer, then written
+    let printer = |name: &str, fmt: FunctionValue<'ctx>| {
+        let arg = fmt.get_type().get_param_types()[0];
+        let (f, b) = define(ctx, module, name, void_t.fn_type(&[arg.into()], false), Linkage::Internal);
+        let buf = b.build_alloca(i8_t.array_type(NUM_BUF), "buf").unwrap();
+        let args = [f.get_first_param().unwrap().into(), buf.into()];
+        let n = b.build_call(fmt, &args, "n").unwrap().try_as_basic_value().left().unwrap();
+        b.build_call(write, &[buf.into(), n.into()], "").unwrap();
+        b.build_return(None).unwrap();
+        f
+    };
+    let print_i64 = printer("__mini_print_i64", fmt_i64);
+    let print_f64 = printer("__mini_print_f64", fmt_f64);
+
+    // strings: the bytes up to the NUL
+    let (print_str, b) = define(ctx, module, "__mini_print_str", void_t.fn_type(&[ptr_t.into()], false), Linkage::Internal);
+    let s = print_str.get_first_param().unwrap();
+    let n = b.build_call(strings.len, &[s.into()], "n").unwrap().try_as_basic_value().left().unwrap();
+    b.build_call(write, &[s.into(), n.into()], "").unwrap();
+    b.build_return(None).unwrap();
+
+    let output = Output::Write { text: write, i64: print_i64, f64: print_f64, str: print_str };
+    Runtime {
+        flavor,
+        output,
+        fail,
+        str_len: strings.len,
+        str_concat: strings.concat,
+        str_cmp: strings.cmp,
+        i64_to_str: strings.i64_to_str,
+        f64_to_str: strings.f64_to_str,
+        array_new: arrays.new,
+        array_push: arrays.push,
+        print_array: print_array(ctx, module, output),
+        set_args: input.set_args,
+        args: input.args,
+        arg: input.arg,
+        read_line: input.read_line,
+        parse_int: input.parse_int,
+    }
+}
+
+/// `i32 __mini_fmt_i64(i64 v, ptr buf)`: `v` in decimal at the start of `buf`.
+///
+/// The digits are counted first, then written backwards from the end; the
+/// magnitude is taken as unsigned so i64::MIN works.
+fn fmt_i64<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let i64_t = ctx.i64_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let (f, b) = define(ctx, module, "__mini_fmt_i64", i32_t.fn_type(&[i64_t.into(), ptr_t.into()], false), Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let v = f.get_nth_param(0).unwrap().into_int_value();
+    let buf = f.get_nth_param(1).unwrap().into_pointer_value();
+    let ten = i64_t.const_int(10, false);
+    let neg = b.build_int_compare(IntPredicate::SLT, v, i64_t.const_zero(), "neg").unwrap();
+    let minus_v = b.build_int_neg(v, "abs").unwrap();
+    let mag = b.build_select(neg, minus_v, v, "mag").unwrap().into_int_value();
+    // the digits of a negative number start after the `-`, so it is never overwritten
+    b.build_store(buf, i8_t.const_int(b'-' as u64, false)).unwrap();
+    let sign_len = b.build_int_z_extend(neg, i32_t, "sign.len").unwrap();
+    let count_bb = ctx.append_basic_block(f, "count");
+    let digits_bb = ctx.append_basic_block(f, "digits");
+    let digit_bb = ctx.append_basic_block(f, "digit");
+    let done_bb = ctx.append_basic_block(f, "done");
+    b.build_unconditional_branch(count_bb).unwrap();
+
+    b.position_at_end(count_bb);
+    let len = b.build_phi(i32_t, "len").unwrap();
+    let rest = b.build_phi(i64_t, "rest").unwrap();
+    let (len_v, rest_v) = (len.as_basic_value().into_int_value(), rest.as_basic_value().into_int_value());
+    let len_next = b.build_int_add(len_v, i32_t.const_int(1, false), "len.next").unwrap();
+    let rest_next = b.build_int_unsigned_div(rest_v, ten, "rest.next").unwrap();
+    let counted = b.build_int_compare(IntPredicate::EQ, rest_v, i64_t.const_zero(), "counted").unwrap();
+    b.build_conditional_branch(counted, digits_bb, count_bb).unwrap();
+    len.add_incoming(&[(&sign_len, entry), (&len_next, count_bb)]);
+    rest.add_incoming(&[(&mag, entry), (&rest_next, count_bb)]);
+
+    // zero has no digits to count, but is written as `0`
+    b.position_at_end(digits_bb);
+    let zero = b.build_int_compare(IntPredicate::EQ, mag, i64_t.const_zero(), "zero").unwrap();
+    let total = b.build_int_add(len_v, b.build_int_z_extend(zero, i32_t, "").unwrap(), "total").unwrap();
+    let end = unsafe { b.build_in_bounds_gep(i8_t, buf, &[total], "end").unwrap() };
+    b.build_unconditional_branch(digit_bb).unwrap();
+
+    b.position_at_end(digit_bb);
+    let pos = b.build_phi(ptr_t, "pos").unwrap();
+    let rest = b.build_phi(i64_t, "rest").unwrap();
+    let (pos_v, rest_v) = (pos.as_basic_value().into_pointer_value(), rest.as_basic_value().into_int_value());
+    let d = b.build_int_unsigned_rem(rest_v, ten, "d").unwrap();
+    let q = b.build_int_unsigned_div(rest_v, ten, "q").unwrap();
+    let at = unsafe { b.build_in_bounds_gep(i8_t, pos_v, &[i32_t.const_all_ones()], "at").unwrap() };
+    let c = b.build_int_add(b.build_int_truncate(d, i8_t, "d8").unwrap(), i8_t.const_int(b'0' as u64, false), "c").unwrap();
+    b.build_store(at, c).unwrap();
+    let more = b.build_int_compare(IntPredicate::NE, q, i64_t.const_zero(), "more").unwrap();
+    b.build_conditional_branch(more, digit_bb, done_bb).unwrap();
+    pos.add_incoming(&[(&end, digits_bb), (&at, digit_bb)]);
+    rest.add_incoming(&[(&mag, digits_bb), (&q, digit_bb)]);
+
+    b.position_at_end(done_bb);
+    b.build_return(Some(&total)).unwrap();
+    f
+}
+
+/// `void __mini_write_out(ptr, i32)`: `write_fd` bound to stdout.
+fn stdout_write<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, write_fd: FunctionValue<'ctx>, stdout: IntValue<'ctx>) -> FunctionValue<'ctx> {
+    let ptr_t = ctx.i8_type().ptr_type(AddressSpace::default());
+    let ty = ctx.void_type().fn_type(&[ptr_t.into(), ctx.i32_type().into()], false);
+    let (f, b) = define(ctx, module, "__mini_write_out", ty, Linkage::Internal);
+    let args = [stdout.into(), f.get_nth_param(0).unwrap().into(), f.get_nth_param(1).unwrap().into()];
+    b.build_call(write_fd, &args, "").unwrap();
+    b.build_return(None).unwrap();
+    f
+}
+
+/// `double __mini_round10(double a, i32 k)`: `a * 10^k` rounded to an
+/// integer, half to even, for results below 2^52.
+///
+/// The scaling runs in steps of at most 1e22 (the largest power of ten that
+/// is an exact double) on a double-double `hi + lo`: each step also computes
+/// its own rounding error exactly (Dekker's two-product), so ties that the
+/// rounded `hi` alone would break the wrong way, such as 99999.95 (really
+/// 99999.9499...) times ten, are still resolved correctly.
+fn round10<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>) -> FunctionValue<'ctx> {
+    let i32_t = ctx.i32_type();
+    let f64_t = ctx.f64_type();
+    let (f, b) = define(ctx, module, "__mini_round10", f64_t.fn_type(&[f64_t.into(), i32_t.into()], false), Linkage::Internal);
+    let entry = b.get_insert_block().unwrap();
+    let powers: Vec<_> = (0..=22).map(|i| f64_t.const_float(10f64.powi(i))).collect();
+    let table = module.add_global(f64_t.array_type(23), None, "__mini_pow10");
+    table.set_initializer(&f64_t.const_array(&powers));
+    table.set_constant(true);
+    table.set_linkage(Linkage::Internal);
+    let int = |v: i64| i32_t.const_int(v as u64, true);
+    let float = |v: f64| f64_t.const_float(v);
+
+    let loop_bb = ctx.append_basic_block(f, "loop");
+    let step_bb = ctx.append_basic_block(f, "step");
+    let last_bb = ctx.append_basic_block(f, "last");
+    // the error terms of values near f64::MAX overflow, so those are scaled
+    // down by 2^64 first, and back up (exactly) before rounding
+    let a = f.get_first_param().unwrap().into_float_value();
+    let huge = b.build_float_compare(FloatPredicate::OGT, a, float(2f64.powi(960)), "huge").unwrap();
+    let down = b.build_select(huge, float(2f64.powi(-64)), float(1.0), "").unwrap().into_float_value();
+    let up = b.build_select(huge, float(2f64.powi(64)), float(1.0), "unscale").unwrap().into_float_value();
+    let a = b.build_float_mul(a, down, "a").unwrap();
+    b.build_unconditional_branch(loop_bb).unwrap();
+
+    b.position_at_end(loop_bb);
+    let hi = b.build_phi(f64_t, "hi").unwrap();
+    let lo = b.build_phi(f64_t, "lo").unwrap();
+    let k = b.build_phi(i32_t, "k").unwrap();
+    let (hi_v, lo_v) = (hi.as_basic_value().into_float_value(), lo.as_basic_value().into_float_value());
+    let k_v = k.as_basic_value().into_int_value();
+    let done = b.build_int_compare(IntPredicate::EQ, k_v, int(0), "done").unwrap();
+    b.build_conditional_branch(done, last_bb, step_bb).unwrap();
+
+    b.position_at_end(step_bb);
+    let neg = b.build_int_compare(IntPredicate::SLT, k_v, int(0), "neg").unwrap();
+    let abs = b.build_select(neg, b.build_int_neg(k_v, "").unwrap(), k_v, "abs").unwrap().into_int_value();
+    let far = b.build_int_compare(IntPredicate::UGT, abs, int(22), "").unwrap();
+    let n = b.build_select(far, int(22), abs, "n").unwrap().into_int_value();
+    let slot = unsafe { b.build_in_bounds_gep(f64_t, table.as_pointer_value(), &[n], "slot").unwrap() };
+    let p = b.build_load(f64_t, slot, "p").unwrap().into_float_value();
+
+    // x*y - fl(x*y), exactly, by splitting both factors into 26-bit halves
+    let split = |x| {
+        let c = b.build_float_mul(float(134217729.0), x, "").unwrap();
+        let hi = b.build_float_sub(c, b.build_float_sub(c, x, "").unwrap(), "").unwrap();
+        (hi, b.build_float_sub(x, hi, "").unwrap())
+    };
+    let product_error = |x, y, xy| {
+        let ((xh, xl), (yh, yl)) = (split(x), split(y));
+        let e = b.build_float_sub(b.build_float_mul(xh, yh, "").unwrap(), xy, "").unwrap();
+        let e = b.build_float_add(e, b.build_float_mul(xh, yl, "").unwrap(), "").unwrap();
+        let e = b.build_float_add(e, b.build_float_mul(xl, yh, "").unwrap(), "").unwrap();
+        b.build_float_add(e, b.build_float_mul(xl, yl, "").unwrap(), "").unwrap()
+    };
+    // (hi + lo) * p
+    let mul = b.build_float_mul(hi_v, p, "mul").unwrap();
+    let mul_lo = b.build_float_add(product_error(hi_v, p, mul), b.build_float_mul(lo_v, p, "").unwrap(), "").unwrap();
+    // (hi + lo) / p: the remainder hi - q*p is exact, as q*p is within an ulp of hi
+    let div = b.build_float_div(hi_v, p, "div").unwrap();
+    let back = b.build_float_mul(div, p, "").unwrap();
+    let rem = b.build_float_sub(b.build_float_sub(hi_v, back, "").unwrap(), product_error(div, p, back), "").unwrap();
+    let div_lo = b.build_float_div(b.build_float_add(rem, lo_v, "").unwrap(), p, "").unwrap();
+    let h = b.build_select(neg, div, mul, "").unwrap().into_float_value();
+    let l = b.build_select(neg, div_lo, mul_lo, "").unwrap().into_float_value();
+    // renormalize so that `lo` stays below half an ulp of `hi`
+    let hi_next = b.build_float_add(h, l, "hi.next").unwrap();
+    let lo_next = b.build_float_sub(l, b.build_float_sub(hi_next, h, "").unwrap(), "lo.next").unwrap();
+    let k_up = b.build_int_add(k_v, n, "").unwrap();
+    let k_down = b.build_int_sub(k_v, n, "").unwrap();
+    let k_next = b.build_select(neg, k_up, k_down, "k.next").unwrap();
+    b.build_unconditional_branch(loop_bb).unwrap();
+    hi.add_incoming(&[(&a, entry), (&hi_next, step_bb)]);
+    lo.add_incoming(&[(&float(0.0), entry), (&lo_next, step_bb)]);
+    k.add_incoming(&[(&f.get_nth_param(1).unwrap(), entry), (&k_next, step_bb)]);
+
+    // adding and removing 2^52 rounds `hi` to nearest even; a tie in `hi`
+    // goes the way `lo` says the exact value lies
+    b.position_at_end(last_bb);
+    let hi_v = b.build_float_mul(hi_v, up, "").unwrap();
+    let lo_v = b.build_float_mul(lo_v, up, "").unwrap();
+    let two52 = float(4503599627370496.0);
+    let r = b.build_float_sub(b.build_float_add(hi_v, two52, "").unwrap(), two52, "r").unwrap();
+    let d = b.build_float_sub(hi_v, r, "d").unwrap();
+    let cmp = |pred, x, y| b.build_float_compare(pred, x, y, "").unwrap();
+    let tie_up = b.build_and(cmp(FloatPredicate::OEQ, d, float(0.5)), cmp(FloatPredicate::OGT, lo_v, float(0.0)), "").unwrap();
+    let tie_down = b.build_and(cmp(FloatPredicate::OEQ, d, float(-0.5)), cmp(FloatPredicate::OLT, lo_v, float(0.0)), "").unwrap();
+    let adj = b.build_select(tie_down, float(-1.0), float(0.0), "").unwrap().into_float_value();
+    let adj = b.build_select(tie_up, float(1.0), adj, "adj").unwrap().into_float_value();
+    b.build_return(Some(&b.build_float_add(r, adj, "rounded").unwrap())).unwrap();
+    f
+}
+
+/// `i32 __mini_fmt_f64(double v, ptr buf)`: C's `%g` without a C library.
+///
+/// A finite value is scaled by a power of ten until it rounds to six
+/// significant digits `1e5 <= r < 1e6` with decimal exponent `x` (see
+/// `round10`); `r` is then laid out in fixed notation for `-4 <= x < 6` and
+/// scientific notation otherwise, with trailing zeros removed, as `%g` does.
+fn fmt_g<'ctx>(ctx: &'ctx LlvmContext, module: &Module<'ctx>, copy: FunctionValue<'ctx>) -> FunctionValue<'ctx> {
+    let i8_t = ctx.i8_type();
+    let i32_t = ctx.i32_type();
+    let i64_t = ctx.i64_type();
+    let f64_t = ctx.f64_type();
+    let ptr_t = i8_t.ptr_type(AddressSpace::default());
+    let round = round10(ctx, module);
+    let (f, b) = define(ctx, module, "__mini_fmt_f64", i32_t.fn_type(&[f64_t.into(), ptr_t.into()], false), Linkage::Internal);
+    let int = |v: i64| i32_t.const_int(v as u64, true);
+    let byte = |c: u8| i8_t.const_int(c as u64, false);
+    let buf = f.get_nth_param(1).unwrap().into_pointer_value();
+    let digits = b.build_alloca(i8_t.array_type(6), "digits").unwrap();
+    let at = |b: &Builder<'ctx>, base: PointerValue<'ctx>, i: IntValue<'ctx>| unsafe {
+        b.build_in_bounds_gep(i8_t, base, &[i], "at").unwrap()
+    };
+
+    let v = f.get_first_param().unwrap().into_float_value();
+    let bits = b.build_bitcast(v, i64_t, "bits").unwrap().into_int_value();
+    let neg = b.build_int_compare(IntPredicate::SLT, bits, i64_t.const_zero(), "neg").unwrap();
+    let a = b.build_select(neg, b.build_float_neg(v, "minus").unwrap(), v, "a").unwrap().into_float_value();
+
+    // NaN, infinities and zeros print as words; the sign is skipped for positive values
+    let is_nan = b.build_float_compare(FloatPredicate::UNO, a, a, "nan").unwrap();
+    let is_inf = b.build_float_compare(FloatPredicate::OEQ, a, f64_t.const_float(f64::INFINITY), "inf").unwrap();
+    let is_zero = b.build_float_compare(FloatPredicate::OEQ, a, f64_t.const_zero(), "zero").unwrap();
+    let special_bb = ctx.append_basic_block(f, "special");
+    let finite_bb = ctx.append_basic_block(f, "finite");
+    let special = b.build_or(is_nan, b.build_or(is_inf, is_zero, "").unwrap(), "special").unwrap();
+    b.build_conditional_branch(special, special_bb, finite_bb).unwrap();
+
+    b.position_at_end(special_bb);
+    let word = |s: &str| b.build_global_string_ptr(s, ".word").unwrap().as_pointer_value();
+    let (nan_s, inf_s, zero_s) = (word("-nan"), word("-inf"), word("-0"));
+    let text = b.build_select(is_inf, inf_s, zero_s, "").unwrap();
+    let text = b.build_select(is_nan, nan_s, text.into_pointer_value(), "text").unwrap().into_pointer_value();
+    let len = b.build_select(b.build_or(is_nan, is_inf, "").unwrap(), int(4), int(2), "len").unwrap().into_int_value();
+    let skip = b.build_int_z_extend(b.build_not(neg, "pos").unwrap(), i32_t, "skip").unwrap();
+    let start = at(&b, text, skip);
+    let n = b.build_int_sub(len, skip, "n").unwrap();
+    b.build_call(copy, &[buf.into(), start.into(), n.into()], "").unwrap();
+    b.build_return(Some(&n)).unwrap();
+
+    // estimate the decimal exponent from the binary one; the loop below corrects it
+    b.position_at_end(finite_bb);
+    let e2 = b.build_right_shift(bits, i64_t.const_int(52, false), false, "e2.raw").unwrap();
+    let e2 = b.build_and(e2, i64_t.const_int(0x7ff, false), "e2.bits").unwrap();
+    let e2 = b.build_int_sub(b.build_int_truncate(e2, i32_t, "").unwrap(), int(1023), "e2").unwrap();
+    let est = b.build_signed_int_to_float(e2, f64_t, "").unwrap();
+    let est = b.build_float_mul(est, f64_t.const_float(std::f64::consts::LOG10_2), "").unwrap();
+    let est = b.build_float_to_signed_int(est, i32_t, "est").unwrap();
+    let scale_bb = ctx.append_basic_block(f, "scale");
+    let up_bb = ctx.append_basic_block(f, "up");
+    let check_bb = ctx.append_basic_block(f, "check");
+    let down_bb = ctx.append_basic_block(f, "down");
+    let strip_bb = ctx.append_basic_block(f, "strip");
+    let strip_more_bb = ctx.append_basic_block(f, "strip.more");
+    let layout_bb = ctx.append_basic_block(f, "layout");
+    let emit_bb = ctx.append_basic_block(f, "emit");
+    let exp_check_bb = ctx.append_basic_block(f, "exp.check");
+    let exp_bb = ctx.append_basic_block(f, "exp");
+    let finish_bb = ctx.append_basic_block(f, "finish");
+    b.build_unconditional_branch(scale_bb).unwrap();
+
+    // r = round(a * 10^(5 - x))
+    b.position_at_end(scale_bb);
+    let x = b.build_phi(i32_t, "x").unwrap();
+    let x_v = x.as_basic_value().into_int_value();
+    let k = b.build_int_sub(int(5), x_v, "k").unwrap();
+    let r = b.build_call(round, &[a.into(), k.into()], "r").unwrap().try_as_basic_value().left().unwrap().into_float_value();
+    let too_big = b.build_float_compare(FloatPredicate::OGE, r, f64_t.const_float(1e6), "").unwrap();
+    b.build_conditional_branch(too_big, up_bb, check_bb).unwrap();
+    b.position_at_end(up_bb);
+    let x_up = b.build_int_add(x_v, int(1), "x.up").unwrap();
+    b.build_unconditional_branch(scale_bb).unwrap();
+    b.position_at_end(check_bb);
+    let too_small = b.build_float_compare(FloatPredicate::OLT, r, f64_t.const_float(1e5), "").unwrap();
+    let ri = b.build_float_to_unsigned_int(r, i32_t, "ri").unwrap();
+    b.build_conditional_branch(too_small, down_bb, strip_bb).unwrap();
+    b.position_at_end(down_bb);
+    let x_down = b.build_int_sub(x_v, int(1), "x.down").unwrap();
+    b.build_unconditional_branch(scale_bb).unwrap();
+    x.add_incoming(&[(&est, finite_bb), (&x_up, up_bb), (&x_down, down_bb)]);
+
+    // nd = significant digits left after dropping trailing zeros
+    b.position_at_end(strip_bb);
+    let ten = int(10);
+    let q = b.build_phi(i32_t, "q").unwrap();
+    let nd = b.build_phi(i32_t, "nd").unwrap();
+    let (q_v, nd_v) = (q.as_basic_value().into_int_value(), nd.as_basic_value().into_int_value());
+    let last = b.build_int_unsigned_rem(q_v, ten, "").unwrap();
+    let zero = b.build_int_compare(IntPredicate::EQ, last, int(0), "").unwrap();
+    let more = b.build_int_compare(IntPredicate::SGT, nd_v, int(1), "").unwrap();
+    b.build_conditional_branch(b.build_and(zero, more, "").unwrap(), strip_more_bb, layout_bb).unwrap();
+    b.position_at_end(strip_more_bb);
+    let q_next = b.build_int_unsigned_div(q_v, ten, "").unwrap();
+    let nd_next = b.build_int_sub(nd_v, int(1), "").unwrap();
+    b.build_unconditional_branch(strip_bb).unwrap();
+    q.add_incoming(&[(&ri, check_bb), (&q_next, strip_more_bb)]);
+    nd.add_incoming(&[(&int(6), check_bb), (&nd_next, strip_more_bb)]);
+
+    // the six digits as text, then the sign
+    b.position_at_end(layout_bb);
+    let mut rest = ri;
+    for i in (0..6).rev() {
+        let d = b.build_int_unsigned_rem(rest, ten, "").unwrap();
+        let c = b.build_int_add(b.build_int_truncate(d, i8_t, "").unwrap(), byte(b'0'), "").unwrap();
+        b.build_store(at(&b, digits, int(i)), c).unwrap();
+        rest = b.build_int_unsigned_div(rest, ten, "").unwrap();
+    }
+    b.build_store(buf, byte(b'-')).unwrap();
+    let pos0 = b.build_int_z_extend(neg, i32_t, "pos0").unwrap();
+    // scientific notation lays out the digits as for x = 0, then appends the exponent
+    let lt = b.build_int_compare(IntPredicate::SLT, x_v, int(-4), "").unwrap();
+    let ge = b.build_int_compare(IntPredicate::SGE, x_v, int(6), "").unwrap();
+    let sci = b.build_or(lt, ge, "sci").unwrap();
+    let xe = b.build_select(sci, int(0), x_v, "xe").unwrap().into_int_value();
+    // digit positions run from 10^hi down to 10^lo, covering the integer part
+    // and every significant digit
+    let hi = b.build_select(b.build_int_compare(IntPredicate::SGT, xe, int(0), "").unwrap(), xe, int(0), "hi").unwrap();
+    let low = b.build_int_add(b.build_int_sub(xe, nd_v, "").unwrap(), int(1), "").unwrap();
+    let lo = b.build_select(b.build_int_compare(IntPredicate::SLT, low, int(0), "").unwrap(), low, int(0), "lo").unwrap();
+    b.build_unconditional_branch(emit_bb).unwrap();
+
+    b.position_at_end(emit_bb);
+    let p = b.build_phi(i32_t, "p").unwrap();
+    let pos = b.build_phi(i32_t, "pos").unwrap();
+    let (p_v, pos_v) = (p.as_basic_value().into_int_value(), pos.as_basic_value().into_int_value());
+    // a `.` goes before position 10^-1; otherwise the digit overwrites it
+    b.build_store(at(&b, buf, pos_v), byte(b'.')).unwrap();
+    let point = b.build_int_compare(IntPredicate::EQ, p_v, int(-1), "point").unwrap();
+    let pos1 = b.build_int_add(pos_v, b.build_int_z_extend(point, i32_t, "").unwrap(), "pos1").unwrap();
+    let idx = b.build_int_sub(xe, p_v, "idx").unwrap();
+    let has = b.build_int_compare(IntPredicate::ULT, idx, nd_v, "has").unwrap();
+    let slot = b.build_select(has, idx, int(0), "slot").unwrap().into_int_value();
+    let d = b.build_load(i8_t, at(&b, digits, slot), "d").unwrap();
+    let c = b.build_select(has, d.into_int_value(), byte(b'0'), "c").unwrap();
+    b.build_store(at(&b, buf, pos1), c).unwrap();
+    let pos2 = b.build_int_add(pos1, int(1), "pos2").unwrap();
+    let p_next = b.build_int_sub(p_v, int(1), "p.next").unwrap();
+    let done = b.build_int_compare(IntPredicate::EQ, p_v, lo.into_int_value(), "done").unwrap();
+    b.build_conditional_branch(done, exp_check_bb, emit_bb).unwrap();
+    p.add_incoming(&[(&hi, layout_bb), (&p_next, emit_bb)]);
+    pos.add_incoming(&[(&pos0, layout_bb), (&pos2, emit_bb)]);
+
+    b.position_at_end(exp_check_bb);
+    b.build_conditional_branch(sci, exp_bb, finish_bb).unwrap();
+
+    // `e`, the exponent's sign and at least two of its digits
+    b.position_at_end(exp_bb);
+    b.build_store(at(&b, buf, pos2), byte(b'e')).unwrap();
+    let x_neg = b.build_int_compare(IntPredicate::SLT, x_v, int(0), "").unwrap();
+    let sign = b.build_select(x_neg, byte(b'-'), byte(b'+'), "").unwrap();
+    b.build_store(at(&b, buf, b.build_int_add(pos2, int(1), "").unwrap()), sign).unwrap();
+    let ax = b.build_select(x_neg, b.build_int_neg(x_v, "").unwrap(), x_v, "ax").unwrap().into_int_value();
+    let digit = |n: IntValue<'ctx>| {
+        let d = b.build_int_unsigned_rem(n, ten, "").unwrap();
+        b.build_int_add(b.build_int_truncate(d, i8_t, "").unwrap(), byte(b'0'), "").unwrap()
+    };
+    let hundreds = b.build_int_unsigned_div(ax, int(100), "").unwrap();
+    let tens = b.build_int_unsigned_div(ax, ten, "").unwrap();
+    b.build_store(at(&b, buf, b.build_int_add(pos2, int(2), "").unwrap()), digit(hundreds)).unwrap();
+    let three = b.build_int_compare(IntPredicate::UGE, ax, int(100), "three").unwrap();
+    let p3 = b.build_int_add(pos2, int(2), "").unwrap();
+    let p3 = b.build_int_add(p3, b.build_int_z_extend(three, i32_t, "").unwrap(), "p3").unwrap();
+    b.build_store(at(&b, buf, p3), digit(tens)).unwrap();
+    b.build_store(at(&b, buf, b.build_int_add(p3, int(1), "").unwrap()), digit(ax)).unwrap();
+    let pe = b.build_int_add(p3, int(2), "pe").unwrap();
+    b.build_unconditional_branch(finish_bb).unwrap();
+
+    b.position_at_end(finish_bb);
+    let end = b.build_phi(i32_t, "end").unwrap();
+    end.add_incoming(&[(&pos2, exp_check_bb), (&pe, exp_bb)]);
+    b.build_return(Some(&end.as_basic_value())).unwrap();
+    f
+}
@@ -0,0 +1,1190 @@
+//! Semantic analysis: resolves names, infers the type of every expression and
+//! rejects ill-typed programs before code generation.
+//!
+//! The checker annotates the AST in place (`Expr::ty`), so once [`check`]
+//! succeeds later passes can rely on every name being defined and every
+//! operand having the type its operator expects.
+
+use std::collections::HashMap;
+
+use crate::ast::{Builtin, Expr, ExprKind, ForIter, Function, Ident, Program, Stmt, StmtKind, StrPart, StructDef, Type};
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::lexer::Span;
+
+/// Checks fail with the first problem in a statement; the statement loop
+/// records it and moves on to the next one.
+type CkResult<T> = Result<T, Diagnostic>;
+
+/// Type-check `program`, filling in the type of every expression.
+///
+/// Every error in the program is reported at once, in source order. On
+/// success the returned diagnostics hold any warnings; on failure they hold
+/// everything found.
+pub fn check(program: &mut Program) -> Result<Diagnostics, Diagnostics> {
+    check_indexed(program).0
+}
+
+/// Type-check `program` like [`check`], also returning an [`Index`] of the
+/// names it defines and uses. The index covers whatever checked, so it is
+/// useful for programs with errors too.
+pub fn check_indexed(program: &mut Program) -> (Result<Diagnostics, Diagnostics>, Index) {
+    let mut ck = Checker { globals: collect_lets(&program.stmts), ..Checker::default() };
+    for d in &program.structs {
+        if let Err(d) = ck.declare_struct(d) {
+            ck.diags.push(d);
+        }
+    }
+    for d in &program.structs {
+        // a rejected redeclaration was already reported
+        let declared = ck.structs.get(&d.name.name).is_some_and(|s| s.name.span == d.name.span);
+        if declared && ck.contains(&d.name.name, &Type::Struct(d.name.name.as_str().into())) {
+            let d = Diagnostic::error(format!("recursive struct `{}` has infinite size", d.name.name))
+                .primary(d.name.span, "contains itself")
+                .note("a struct cannot contain itself, directly or through the fields of other structs");
+            ck.diags.push(d);
+        }
+    }
+    for f in &program.functions {
+        if let Err(d) = ck.declare_function(f) {
+            ck.diags.push(d);
+        }
+    }
+    for f in &mut program.functions {
+        // a rejected redeclaration is still checked, against its own signature
+        ck.check_function(f);
+    }
+
+    ck.current = None;
+    ck.lets = std::mem::take(&mut ck.globals);
+    ck.scopes = vec![HashMap::new()];
+    ck.scope_ends = vec![usize::MAX];
+    ck.check_stmts(&mut program.stmts);
+
+    let mut diags = ck.diags;
+    diags.0.sort_by_key(|d| d.span().map(|s| s.start));
+    let checked = if diags.has_errors() { Err(diags) } else { Ok(diags) };
+    (checked, ck.index)
+}
+
+/// What kind of thing a [`Symbol`] names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Variable,
+    Parameter,
+    Function,
+    Struct,
+}
+
+/// A name the program defines.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// The definition as source, e.g. `let mut n: int` or `fn sq(n: int) -> int`.
+    pub detail: String,
+    /// The name at its definition.
+    pub span: Span,
+    /// Where uses of the name resolve to this definition.
+    pub scope: Span,
+}
+
+/// Every definition in a checked program and every use the checker resolved.
+#[derive(Debug, Default)]
+pub struct Index {
+    pub symbols: Vec<Symbol>,
+    /// The span of each use and the symbol it refers to.
+    pub refs: Vec<(Span, usize)>,
+    /// Function bodies with their parameters, which see no top-level variables.
+    pub bodies: Vec<Span>,
+}
+
+impl Index {
+    /// The name at `offset`, which may be a use or the definition itself,
+    /// and the symbol it names.
+    pub fn reference_at(&self, offset: usize) -> Option<(Span, &Symbol)> {
+        let covers = |span: &Span| span.start <= offset && offset <= span.end;
+        let used = self.refs.iter().find(|(span, _)| covers(span)).map(|(span, i)| (*span, &self.symbols[*i]));
+        used.or_else(|| self.symbols.iter().find(|s| covers(&s.span)).map(|s| (s.span, s)))
+    }
+
+    /// The symbols a name written at `offset` could refer to, one per name:
+    /// the innermost definition wins.
+    pub fn visible_at(&self, offset: usize) -> Vec<&Symbol> {
+        let body = self.bodies.iter().find(|b| b.start <= offset && offset <= b.end);
+        let mut seen: Vec<&Symbol> = Vec::new();
+        // later definitions of a name in scope shadow earlier ones
+        for s in self.symbols.iter().rev() {
+            let in_scope = s.scope.start <= offset && offset <= s.scope.end;
+            let local = matches!(s.kind, SymbolKind::Variable | SymbolKind::Parameter);
+            let reachable = !local || body.is_none_or(|b| b.start <= s.span.start && s.span.end <= b.end);
+            if in_scope && reachable && !seen.iter().any(|v| v.name == s.name) {
+                seen.push(s);
+            }
+        }
+        seen.sort_by(|a, b| a.name.cmp(&b.name));
+        seen
+    }
+}
+
+/// Signature of a declared Mini function.
+struct FnSig {
+    params: Vec<(Type, Span)>,
+    ret: Option<Type>,
+    /// Span of the declaration's signature.
+    span: Span,
+    /// The function in the checker's [`Index`].
+    symbol: usize,
+}
+
+/// A variable in scope.
+#[derive(Clone)]
+struct Binding {
+    ty: Type,
+    /// Where the name was bound, for "defined here" labels.
+    span: Span,
+    /// Declared with `let mut`, so assignments are allowed.
+    mutable: bool,
+    /// A function parameter rather than a `let`.
+    param: bool,
+    /// The variable in the checker's [`Index`].
+    symbol: usize,
+}
+
+/// A `let` somewhere in the body being checked: (name, name span, statement span).
+type LetSite = (String, Span, Span);
+
+#[derive(Default)]
+struct Checker {
+    structs: HashMap<String, StructDef>,
+    functions: HashMap<String, FnSig>,
+    /// Innermost scope last; a function body is a scope nested in the one
+    /// holding its parameters, so a `let` may shadow a parameter.
+    scopes: Vec<HashMap<String, Binding>>,
+    /// Every `let` in the current body, to explain why a lookup failed.
+    lets: Vec<LetSite>,
+    /// Top-level `let`s, which function bodies cannot see.
+    globals: Vec<LetSite>,
+    /// Name and signature span of the function being checked (`None` at the top level).
+    current: Option<(String, Option<Type>, Span)>,
+    /// Where each scope in `scopes` ends in the source.
+    scope_ends: Vec<usize>,
+    diags: Diagnostics,
+    index: Index,
+}
+
+impl Checker {
+    /// Record a struct's fields so literals and field accesses anywhere in the
+    /// file can be checked.
+    fn declare_struct(&mut self, d: &StructDef) -> CkResult<()> {
+        let name = &d.name.name;
+        if Type::from_name(name).is_some() {
+            return Err(Diagnostic::error(format!("`{}` is a built-in type", name))
+                .primary(d.name.span, format!("cannot declare a struct named `{}`", name)));
+        }
+        if let Some(prev) = self.structs.get(name) {
+            return Err(Diagnostic::error(format!("struct `{}` is declared more than once", name))
+                .primary(d.name.span, "redeclared here")
+                .secondary(prev.name.span, "first declared here"));
+        }
+        // the struct is recorded even with a repeated field, whose first declaration wins
+        self.structs.insert(name.clone(), d.clone());
+        let fields: Vec<String> = d.fields.iter().map(|f| format!("{}: {}", f.name.name, f.ty)).collect();
+        let detail = format!("{}struct {} {{ {} }}", if d.public { "pub " } else { "" }, name, fields.join(", "));
+        self.define(&d.name, SymbolKind::Struct, detail, Span { start: 0, end: usize::MAX });
+        for (i, f) in d.fields.iter().enumerate() {
+            if let Some(prev) = d.fields[..i].iter().find(|p| p.name.name == f.name.name) {
+                return Err(Diagnostic::error(format!("field `{}` is declared more than once", f.name.name))
+                    .primary(f.name.span, "redeclared here")
+                    .secondary(prev.name.span, "first declared here"));
+            }
+        }
+        Ok(())
+    }
+
+    /// Whether a value of type `ty` holds a struct called `name`, in its
+    /// fields or theirs.
+    fn contains(&self, name: &str, ty: &Type) -> bool {
+        let mut seen = Vec::new();
+        let mut todo = vec![ty.clone()];
+        while let Some(Type::Struct(s)) = todo.pop() {
+            let Some(d) = self.structs.get(&*s) else { continue };
+            for f in &d.fields {
+                match &f.ty {
+                    Type::Struct(inner) if &**inner == name => return true,
+                    Type::Struct(inner) if !seen.contains(inner) => {
+                        seen.push(inner.clone());
+                        todo.push(f.ty.clone());
+                    }
+                    _ => {}
+                }
+            }
+        }
+        false
+    }
+
+    /// The declaration of the struct type `ty`, or the error for looking up
+    /// `field` in a value of another type. The parser reports unknown struct
+    /// names but keeps them in the tree, which the language server checks.
+    fn struct_def(&self, ty: &Type, field: &Ident) -> CkResult<&StructDef> {
+        match ty {
+            Type::Struct(name) => self.structs.get(&**name).ok_or_else(|| {
+                Diagnostic::error(format!("unknown type `{}`", name)).primary(field.span, "field of a value whose type isn't declared")
+            }),
+            _ => Err(Diagnostic::error(format!("no field `{}` on a value of type {}", field.name, ty))
+                .primary(field.span, "unknown field")
+                .note("only structs have fields")),
+        }
+    }
+
+    /// The type of `field` in a value of type `ty`.
+    fn field_type(&self, ty: &Type, field: &Ident) -> CkResult<Type> {
+        let d = self.struct_def(ty, field)?;
+        match d.field(&field.name) {
+            Some((_, ty)) => Ok(ty.clone()),
+            None => {
+                let names: Vec<String> = d.fields.iter().map(|f| format!("`{}`", f.name.name)).collect();
+                let have = match names.len() {
+                    0 => format!("`{}` has no fields", d.name.name),
+                    _ => format!("`{}` has fields {}", d.name.name, names.join(", ")),
+                };
+                Err(Diagnostic::error(format!("no field `{}` on a value of type {}", field.name, ty))
+                    .primary(field.span, "unknown field")
+                    .secondary(d.name.span, format!("`{}` declared here", d.name.name))
+                    .note(have))
+            }
+        }
+    }
+
+    /// Record a function's signature so calls anywhere in the file can be checked.
+    fn declare_function(&mut self, f: &Function) -> CkResult<()> {
+        let name = &f.name.name;
+        if name == "main" {
+            return Err(Diagnostic::error("`main` is reserved")
+                .primary(f.name.span, "cannot declare a function named `main`")
+                .note("top-level statements form the program entry point"));
+        }
+        if Builtin::from_name(name).is_some() {
+            return Err(Diagnostic::error(format!("`{}` is a built-in function", name))
+                .primary(f.name.span, format!("cannot declare a function named `{}`", name)));
+        }
+        if let Some(prev) = self.functions.get(name) {
+            return Err(Diagnostic::error(format!("function `{}` is declared more than once", name))
+                .primary(f.name.span, "redeclared here")
+                .secondary(prev.span, "first declared here"));
+        }
+        let params = f.params.iter().map(|p| (p.ty.clone(), p.span)).collect();
+        let symbol = self.define(&f.name, SymbolKind::Function, signature(f), Span { start: 0, end: usize::MAX });
+        self.functions.insert(name.clone(), FnSig { params, ret: f.ret.clone(), span: f.sig_span, symbol });
+        Ok(())
+    }
+
+    fn check_function(&mut self, f: &mut Function) {
+        let body = Span { start: f.sig_span.start, end: f.end_span.end };
+        self.index.bodies.push(body);
+        let mut params = HashMap::new();
+        for p in &f.params {
+            let detail = format!("{}: {}", p.name.name, p.ty);
+            let symbol = self.define(&p.name, SymbolKind::Parameter, detail, Span { start: f.sig_span.end, end: body.end });
+            let binding = Binding { ty: p.ty.clone(), span: p.name.span, mutable: false, param: true, symbol };
+            if let Some(prev) = params.insert(p.name.name.clone(), binding) {
+                let d = Diagnostic::error(format!("parameter `{}` is declared more than once", p.name.name))
+                    .primary(p.name.span, "redeclared here")
+                    .secondary(prev.span, "first declared here");
+                self.diags.push(d);
+            }
+        }
+        self.scopes = vec![params, HashMap::new()];
+        self.scope_ends = vec![body.end; 2];
+        self.lets = collect_lets(&f.body);
+        self.current = Some((f.name.name.clone(), f.ret.clone(), f.sig_span));
+
+        self.check_stmts(&mut f.body);
+
+        if let Some(ty) = &f.ret {
+            if !always_returns(&f.body) {
+                let d = Diagnostic::error(format!(
+                    "function `{}` may reach its end without returning a value of type {}",
+                    f.name.name, ty
+                ))
+                .primary(f.end_span, "missing `return` before the end of the body")
+                .secondary(f.sig_span, format!("declared to return {}", ty));
+                self.diags.push(d);
+            }
+        }
+    }
+
+    /// Check a statement list in the current scope, recording errors per statement.
+    fn check_stmts(&mut self, stmts: &mut [Stmt]) {
+        // the first statement that always returns, and whether it is a `return`
+        let mut returned: Option<(Span, bool)> = None;
+        let mut warned = false;
+        for stmt in stmts {
+            if let (Some((span, is_return)), false) = (returned, warned) {
+                let why = if is_return {
+                    "any code following this `return` is unreachable"
+                } else {
+                    "any code following this statement is unreachable"
+                };
+                let d = Diagnostic::warning("unreachable statement")
+                    .primary(stmt.span, "unreachable statement")
+                    .secondary(span, why);
+                self.diags.push(d);
+                warned = true;
+            }
+            if let Err(d) = self.check_stmt(stmt) {
+                self.diags.push(d);
+            }
+            if returned.is_none() && always_returns(std::slice::from_ref(stmt)) {
+                returned = Some((stmt.span, matches!(stmt.kind, StmtKind::Return(_))));
+            }
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &mut Stmt) -> CkResult<()> {
+        let span = stmt.span;
+        match &mut stmt.kind {
+            StmtKind::Let { name, mutable, expr } => {
+                let res = self.value(expr);
+                // still bind the name so later uses don't report it as undefined
+                let ty = res.as_ref().unwrap_or(&Type::Int).clone();
+                let bound = self.bind(&name.name, ty, name.span, *mutable);
+                res?;
+                bound?;
+            }
+            StmtKind::Assign { name, expr, .. } => {
+                let b = self.mutable(name, span, "assign to")?;
+                self.expect(expr, b.ty.clone(), &format!("assignment to `{}`", name.name))
+                    .map_err(|d| d.secondary(b.span, format!("`{}` defined here as {}", name.name, b.ty)))?;
+            }
+            StmtKind::FieldAssign { name, path, expr, .. } => {
+                let b = self.mutable(name, span, "assign to a field of")?;
+                let mut ty = b.ty;
+                for field in path.iter() {
+                    ty = self.field_type(&ty, field)?;
+                }
+                let place: Vec<&str> = std::iter::once(&name.name).chain(path.iter().map(|f| &f.name)).map(String::as_str).collect();
+                self.expect(expr, ty, &format!("assignment to `{}`", place.join(".")))?;
+            }
+            StmtKind::IndexAssign { name, index, expr, .. } => {
+                let b = self.mutable(name, span, "assign to an element of")?;
+                if b.ty != Type::Array {
+                    return Err(self.not_indexable(&name.name, name.span, b.ty));
+                }
+                // a compound assignment's `expr` reads the element, checking the index again
+                self.expect(index, Type::Int, "array index")?;
+                self.expect(expr, Type::Int, &format!("assignment to an element of `{}`", name.name))?;
+            }
+            StmtKind::Print { args, .. } => {
+                // each value is checked on its own, so every bad one is reported
+                for a in args {
+                    if let Err(d) = self.value(a) {
+                        self.diags.push(d);
+                    }
+                }
+            }
+            StmtKind::If { cond, then_body, else_body } => {
+                self.check_cond(cond);
+                let then_end = else_body.as_ref().and_then(|b| b.first()).map_or(span.end, |s| s.span.start);
+                self.check_block(then_body, then_end);
+                if let Some(body) = else_body {
+                    self.check_block(body, span.end);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                self.check_cond(cond);
+                self.warn_shadowed_loop_vars(cond, body);
+                self.check_block(body, span.end);
+            }
+            StmtKind::For { var, iter, body } => {
+                // a bad range still leaves the variable bound, so the body is checked too
+                let ty = self.for_iter(iter).unwrap_or_else(|d| {
+                    self.diags.push(d);
+                    Type::Int
+                });
+                let detail = format!("let {}: {}", var.name, ty);
+                let symbol = self.define(var, SymbolKind::Variable, detail, Span { start: var.span.end, end: span.end });
+                let binding = Binding { ty, span: var.span, mutable: false, param: false, symbol };
+                self.scopes.push(HashMap::from([(var.name.clone(), binding)]));
+                self.scope_ends.push(span.end);
+                self.check_block(body, span.end);
+                self.scopes.pop();
+                self.scope_ends.pop();
+            }
+            StmtKind::Block(body) => self.check_block(body, span.end),
+            StmtKind::Return(value) => {
+                let Some((fname, ret, sig)) = self.current.clone() else {
+                    return Err(Diagnostic::error("`return` outside of a function").primary(span, "not inside a `fn`"));
+                };
+                match (value, ret) {
+                    (None, None) => {}
+                    (Some(e), Some(ty)) => {
+                        self.expect(e, ty.clone(), &format!("return value of `{}`", fname))
+                            .map_err(|d| d.secondary(sig, format!("declared to return {}", ty)))?;
+                    }
+                    (Some(e), None) => {
+                        return Err(Diagnostic::error(format!(
+                            "function `{}` has no return type but returns a value",
+                            fname
+                        ))
+                        .primary(e.span, "unexpected return value")
+                        .secondary(sig, "declared without `-> type`"))
+                    }
+                    (None, Some(ty)) => {
+                        return Err(Diagnostic::error(format!("function `{}` must return a value of type {}", fname, ty))
+                            .primary(span, "return value missing")
+                            .secondary(sig, format!("declared to return {}", ty)))
+                    }
+                }
+            }
+            StmtKind::Expr(e) => {
+                // calls to functions without a return type are fine here
+                self.expr(e)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check what a `for` loop runs over; returns the type of the loop variable.
+    fn for_iter(&mut self, iter: &mut ForIter) -> CkResult<Type> {
+        match iter {
+            ForIter::Range(start, end) => {
+                let ty = self.value(start)?;
+                if !matches!(ty, Type::Int | Type::I64) {
+                    return Err(self.mismatch(start, Type::Int, ty).note("ranges count through `int`s or `i64`s"));
+                }
+                let end_ty = self.value(end)?;
+                if end_ty != ty {
+                    return Err(self.mismatch(end, ty, end_ty).note("both ends of a range have the same type"));
+                }
+                Ok(ty)
+            }
+            ForIter::Array(e) => {
+                let ty = self.value(e)?;
+                if ty != Type::Array {
+                    return Err(Diagnostic::error(format!("cannot loop over a value of type {}", ty))
+                        .primary(e.span, "expected an array or a range")
+                        .note("to count, loop over a range: `for i in 0..n { ... }`"));
+                }
+                Ok(Type::Int)
+            }
+        }
+    }
+
+    /// Resolve a variable the statement at `span` changes, which must have
+    /// been declared with `let mut`; `action` says how, e.g. "assign to".
+    fn mutable(&mut self, name: &Ident, span: Span, action: &str) -> CkResult<Binding> {
+        let b = self.lookup(&name.name, name.span)?;
+        self.refer(name.span, b.symbol);
+        if b.mutable {
+            return Ok(b);
+        }
+        let d = Diagnostic::error(format!("cannot {} immutable variable `{}`", action, name.name));
+        Err(if b.param {
+            d.primary(span, format!("cannot {} a parameter", action))
+                .secondary(b.span, "parameters are immutable")
+                .note(format!("copy it into a variable first: `let mut {0} = {0};`", name.name))
+        } else {
+            let (label, allow) = match action {
+                "assign to" => ("cannot assign twice".to_string(), "assignment"),
+                _ => (format!("cannot {} it", action), "changes"),
+            };
+            d.primary(span, label)
+                .secondary(b.span, format!("`{}` defined here without `mut`", name.name))
+                .note(format!("declare it with `let mut {}` to allow {}", name.name, allow))
+        })
+    }
+
+    /// The error for indexing a value of type `ty` named `what` at `span`.
+    fn not_indexable(&self, what: &str, span: Span, ty: Type) -> Diagnostic {
+        Diagnostic::error(format!("cannot index into a value of type {}", ty))
+            .primary(span, format!("{} is not an array", what))
+            .note("only arrays, e.g. `[1, 2, 3]`, can be indexed")
+    }
+
+    /// Check a nested block ending at source offset `end`; names first bound
+    /// inside it are dropped afterwards.
+    fn check_block(&mut self, stmts: &mut [Stmt], end: usize) {
+        self.scopes.push(HashMap::new());
+        self.scope_ends.push(end);
+        self.check_stmts(stmts);
+        self.scopes.pop();
+        self.scope_ends.pop();
+    }
+
+    /// Warn about a `let` directly in a loop body that shadows a variable the
+    /// condition reads: the condition keeps seeing the outer variable, which is
+    /// how a loop that was meant to count ends up running forever.
+    fn warn_shadowed_loop_vars(&mut self, cond: &Expr, body: &[Stmt]) {
+        for s in body {
+            let StmtKind::Let { name, .. } = &s.kind else { continue };
+            if self.find(&name.name).is_some() && cond.reads(&name.name) {
+                let d = Diagnostic::warning(format!("`{}` is shadowed inside the loop it controls", name.name))
+                    .primary(name.span, format!("this declares a new `{}` for the rest of the body", name.name))
+                    .secondary(cond.span, format!("the condition still reads the outer `{}`", name.name))
+                    .note(format!("to update it, declare it with `let mut` and assign with `{} = ...`", name.name));
+                self.diags.push(d);
+            }
+        }
+    }
+
+    /// Conditions are `bool`s, or integers where non-zero is true. A bad
+    /// condition is recorded and the statement's bodies are still checked.
+    fn check_cond(&mut self, cond: &mut Expr) {
+        if let Err(d) = self.truthy(cond, "condition") {
+            self.diags.push(d);
+        }
+    }
+
+    /// Bind `name` for a `let` in the innermost scope.
+    ///
+    /// A `let` shadows a variable of any type from an enclosing block until
+    /// the end of its own block; a second `let` of a name in the same scope
+    /// is an error, as it would hide the first for no reason.
+    fn bind(&mut self, name: &str, ty: Type, span: Span, mutable: bool) -> CkResult<()> {
+        if let Some(prev) = self.scopes.last().unwrap().get(name) {
+            let d = Diagnostic::error(format!("`{}` is already defined as {} in this scope", name, prev.ty))
+                .primary(span, format!("redefined here as {}", ty))
+                .secondary(prev.span, format!("first defined here as {}", prev.ty));
+            return Err(if prev.ty == ty {
+                d.note(format!("to change its value, declare it with `let mut` and assign with `{} = ...`", name))
+            } else {
+                d.note("use a new name, or a nested `{ }` block to shadow it")
+            });
+        }
+        let detail = format!("let {}{}: {}", if mutable { "mut " } else { "" }, name, ty);
+        let scope = Span { start: span.end, end: *self.scope_ends.last().unwrap() };
+        let symbol = self.define(&Ident { name: name.to_string(), span }, SymbolKind::Variable, detail, scope);
+        self.scopes.last_mut().unwrap().insert(name.to_string(), Binding { ty, span, mutable, param: false, symbol });
+        Ok(())
+    }
+
+    /// Add a definition of `name` to the index; returns its position there.
+    fn define(&mut self, name: &Ident, kind: SymbolKind, detail: String, scope: Span) -> usize {
+        let symbol = Symbol { name: name.name.clone(), kind, detail, span: name.span, scope };
+        self.index.symbols.push(symbol);
+        self.index.symbols.len() - 1
+    }
+
+    /// Record that the name at `span` refers to `symbol`.
+    fn refer(&mut self, span: Span, symbol: usize) {
+        self.index.refs.push((span, symbol));
+    }
+
+    fn find(&self, name: &str) -> Option<Binding> {
+        self.scopes.iter().rev().find_map(|s| s.get(name)).cloned()
+    }
+
+    /// Resolve a variable, explaining the failure when the name is defined
+    /// somewhere it can't be seen from `span`.
+    fn lookup(&self, name: &str, span: Span) -> CkResult<Binding> {
+        if let Some(b) = self.find(name) {
+            return Ok(b);
+        }
+        let site = self.lets.iter().find(|(n, ..)| n == name);
+        Err(match site {
+            Some((_, def, stmt)) if stmt.start <= span.start && span.end <= stmt.end => {
+                Diagnostic::error(format!("`{}` is used in its own definition", name))
+                    .primary(span, "not yet defined")
+                    .secondary(*def, "defined by this `let`")
+            }
+            Some((_, def, _)) if def.start > span.start => {
+                Diagnostic::error(format!("cannot use `{}` before it is defined", name))
+                    .primary(span, "used here")
+                    .secondary(*def, "defined later here")
+            }
+            Some((_, def, _)) => Diagnostic::error(format!("undefined variable `{}`", name))
+                .primary(span, "not found in this scope")
+                .secondary(*def, "defined here, in a block that has already ended"),
+            None => {
+                let d = Diagnostic::error(format!("undefined variable `{}`", name))
+                    .primary(span, "not found in this scope");
+                match self.globals.iter().find(|(n, ..)| n == name) {
+                    Some((_, def, _)) if self.current.is_some() => d
+                        .secondary(*def, "defined at the top level")
+                        .note("functions can only use their parameters and their own variables"),
+                    _ => d,
+                }
+            }
+        })
+    }
+
+    fn lookup_fn(&self, name: &str, span: Span) -> CkResult<&FnSig> {
+        self.functions.get(name).ok_or_else(|| {
+            Diagnostic::error(format!("undefined function `{}`", name)).primary(span, "not found in this program")
+        })
+    }
+
+    /// Infer and record the type of `e`; `None` for calls without a value.
+    fn expr(&mut self, e: &mut Expr) -> CkResult<Option<Type>> {
+        let ty = match &mut e.kind {
+            ExprKind::Int(_) => Type::Int,
+            ExprKind::I64(_) => Type::I64,
+            ExprKind::Float(_) => Type::F64,
+            ExprKind::Bool(_) => Type::Bool,
+            ExprKind::Str(_) => Type::Str,
+            ExprKind::Var(name) => {
+                let b = self.lookup(name, e.span)?;
+                self.refer(e.span, b.symbol);
+                b.ty
+            }
+            ExprKind::UnaryNeg(x) => {
+                let ty = self.value(x)?;
+                if !ty.is_numeric() {
+                    return Err(self.mismatch(x, Type::Int, ty));
+                }
+                ty
+            }
+            ExprKind::Not(x) => {
+                self.truthy(x, "operand of `!`")?;
+                Type::Bool
+            }
+            // `+` also concatenates strings
+            ExprKind::Add(a, b) => self.operands(a, b, |t| t.is_numeric() || *t == Type::Str)?,
+            ExprKind::Sub(a, b) | ExprKind::Mul(a, b) | ExprKind::Div(a, b) => self.operands(a, b, Type::is_numeric)?,
+            // strings compare byte by byte
+            ExprKind::Lt(a, b) | ExprKind::Le(a, b) | ExprKind::Gt(a, b) | ExprKind::Ge(a, b) => {
+                self.operands(a, b, |t| t.is_numeric() || *t == Type::Str)?;
+                Type::Bool
+            }
+            ExprKind::Eq(a, b) | ExprKind::Ne(a, b) => match self.operands(a, b, |_| true)? {
+                Type::Array => {
+                    return Err(Diagnostic::error("arrays cannot be compared with `==` or `!=`")
+                        .primary(e.span, "comparison of two arrays")
+                        .note("compare their lengths and elements instead"))
+                }
+                Type::Struct(_) => {
+                    return Err(Diagnostic::error("structs cannot be compared with `==` or `!=`")
+                        .primary(e.span, "comparison of two structs")
+                        .note("compare their fields instead"))
+                }
+                _ => Type::Bool,
+            },
+            ExprKind::Interp(parts) => {
+                // any value but an array or a struct can be embedded
+                for part in parts {
+                    if let StrPart::Expr(x) = part {
+                        let what = match self.value(x)? {
+                            Type::Array => "array",
+                            Type::Struct(_) => "struct",
+                            _ => continue,
+                        };
+                        return Err(Diagnostic::error(format!("{}s cannot be interpolated into strings", what))
+                            .primary(x.span, format!("this is {} {}", if what == "array" { "an" } else { "a" }, what))
+                            .note(format!("print the {} on its own instead, e.g. `println \"x =\", x;`", what)));
+                    }
+                }
+                Type::Str
+            }
+            ExprKind::StructLit { name, fields } => {
+                let Some(d) = self.structs.get(&name.name) else {
+                    return Err(Diagnostic::error(format!("undefined struct `{}`", name.name))
+                        .primary(name.span, "not found in this program"));
+                };
+                let d = d.clone();
+                if let Some(i) = self.index.symbols.iter().position(|s| s.kind == SymbolKind::Struct && s.span == d.name.span) {
+                    self.refer(name.span, i);
+                }
+                let ty = Type::Struct(d.name.name.as_str().into());
+                for (i, (field, _)) in fields.iter().enumerate() {
+                    if let Some((prev, _)) = fields[..i].iter().find(|(f, _)| f.name == field.name) {
+                        return Err(Diagnostic::error(format!("field `{}` is specified more than once", field.name))
+                            .primary(field.span, "specified again here")
+                            .secondary(prev.span, "first specified here"));
+                    }
+                }
+                for (field, value) in fields.iter_mut() {
+                    let field_ty = self.field_type(&ty, field)?;
+                    self.expect(value, field_ty, &format!("field `{}` of `{}`", field.name, d.name.name))?;
+                }
+                let missing: Vec<String> = d
+                    .fields
+                    .iter()
+                    .filter(|f| !fields.iter().any(|(g, _)| g.name == f.name.name))
+                    .map(|f| format!("`{}`", f.name.name))
+                    .collect();
+                if !missing.is_empty() {
+                    let s = if missing.len() == 1 { "" } else { "s" };
+                    return Err(Diagnostic::error(format!("missing field{} {} in `{}` literal", s, missing.join(", "), d.name.name))
+                        .primary(e.span, format!("missing {}", missing.join(", ")))
+                        .secondary(d.name.span, format!("`{}` declared here", d.name.name)));
+                }
+                ty
+            }
+            ExprKind::Field(base, field) => {
+                let ty = self.value(base)?;
+                self.field_type(&ty, field)?
+            }
+            ExprKind::Array(items) => {
+                for item in items {
+                    self.expect(item, Type::Int, "array element")?;
+                }
+                Type::Array
+            }
+            ExprKind::Repeat { value, count } => {
+                self.expect(value, Type::Int, "array element")?;
+                self.expect(count, Type::Int, "array length")?;
+                Type::Array
+            }
+            ExprKind::Index(base, index) => {
+                let ty = self.value(base)?;
+                if ty != Type::Array {
+                    let what = match &base.kind {
+                        ExprKind::Var(name) => format!("`{}`", name),
+                        _ => "this".to_string(),
+                    };
+                    return Err(self.not_indexable(&what, base.span, ty));
+                }
+                self.expect(index, Type::Int, "array index")?;
+                Type::Int
+            }
+            ExprKind::And(a, b) | ExprKind::Or(a, b) => {
+                self.truthy(a, "operand of a logical operator")?;
+                self.truthy(b, "operand of a logical operator")?;
+                Type::Bool
+            }
+            ExprKind::Cast(x, to) => {
+                let from = self.value(x)?;
+                let to = to.clone();
+                let ok = from == to || (to.is_numeric() && (from.is_numeric() || from == Type::Bool));
+                if !ok {
+                    let mut d = Diagnostic::error(format!("cannot convert {} to {} with `as`", from, to))
+                        .primary(e.span, "invalid conversion");
+                    if to == Type::Bool && from.is_numeric() {
+                        d = d.note("compare with zero instead, e.g. `x != 0`");
+                    } else {
+                        d = d.note("`as` converts between `int`, `i64` and `f64`, and from `bool` to a number");
+                    }
+                    return Err(d);
+                }
+                to
+            }
+            ExprKind::Call { name, args } => {
+                let sig = self.lookup_fn(name, e.span)?;
+                let symbol = sig.symbol;
+                self.refer(Span { start: e.span.start, end: e.span.start + name.len() }, symbol);
+                let sig = &self.functions[&*name];
+                if args.len() != sig.params.len() {
+                    return Err(Diagnostic::error(format!(
+                        "function `{}` expects {} argument(s), found {}",
+                        name,
+                        sig.params.len(),
+                        args.len()
+                    ))
+                    .primary(e.span, format!("expected {} argument(s)", sig.params.len()))
+                    .secondary(sig.span, format!("`{}` declared here", name)));
+                }
+                let (params, ret) = (sig.params.clone(), sig.ret.clone());
+                for (i, (arg, (ty, param_span))) in args.iter_mut().zip(params).enumerate() {
+                    self.expect(arg, ty, &format!("argument {} of `{}`", i + 1, name))
+                        .map_err(|d| d.secondary(param_span, "parameter declared here"))?;
+                }
+                match ret {
+                    Some(ty) => ty,
+                    None => return Ok(None),
+                }
+            }
+            ExprKind::Builtin { func, args } => {
+                let want = match func {
+                    Builtin::Args | Builtin::ReadLine => 0,
+                    Builtin::Len | Builtin::Arg | Builtin::ParseInt => 1,
+                    Builtin::Push => 2,
+                };
+                if args.len() != want {
+                    return Err(Diagnostic::error(format!(
+                        "function `{}` expects {} argument(s), found {}",
+                        func.name(),
+                        want,
+                        args.len()
+                    ))
+                    .primary(e.span, format!("expected {} argument(s)", want)));
+                }
+                match func {
+                    Builtin::Len => {
+                        let ty = self.value(&mut args[0])?;
+                        if !matches!(ty, Type::Str | Type::Array) {
+                            return Err(Diagnostic::error(format!(
+                                "mismatched types: argument 1 of `len` expects a string or an array, found {}",
+                                ty
+                            ))
+                            .primary(args[0].span, format!("expected string or [int], found {}", ty)));
+                        }
+                        Type::Int
+                    }
+                    Builtin::Push => {
+                        // the array must be a variable the program may change
+                        let ExprKind::Var(name) = &args[0].kind else {
+                            return Err(Diagnostic::error("the first argument of `push` must be an array variable")
+                                .primary(args[0].span, "not a variable")
+                                .note("store the array in a `let mut` variable first"));
+                        };
+                        let name = Ident { name: name.clone(), span: args[0].span };
+                        let b = self.mutable(&name, e.span, "push to")?;
+                        if b.ty != Type::Array {
+                            return Err(self.mismatch(&args[0], Type::Array, b.ty));
+                        }
+                        args[0].ty = Some(Type::Array);
+                        self.expect(&mut args[1], Type::Int, "argument 2 of `push`")?;
+                        return Ok(None);
+                    }
+                    Builtin::Args => Type::Int,
+                    Builtin::Arg => {
+                        self.expect(&mut args[0], Type::Int, "argument 1 of `arg`")?;
+                        Type::Str
+                    }
+                    Builtin::ReadLine => Type::Str,
+                    Builtin::ParseInt => {
+                        self.expect(&mut args[0], Type::Str, "argument 1 of `parse_int`")?;
+                        Type::Int
+                    }
+                }
+            }
+        };
+        e.ty = Some(ty.clone());
+        Ok(Some(ty))
+    }
+
+    /// Check an expression whose value is used.
+    fn value(&mut self, e: &mut Expr) -> CkResult<Type> {
+        match self.expr(e)? {
+            Some(ty) => Ok(ty),
+            None => match &e.kind {
+                ExprKind::Call { name, .. } => {
+                    let sig = &self.functions[name];
+                    Err(Diagnostic::error(format!("function `{}` does not return a value", name))
+                        .primary(e.span, "used as a value here")
+                        .secondary(sig.span, "declared without `-> type`"))
+                }
+                ExprKind::Builtin { func, .. } => {
+                    Err(Diagnostic::error(format!("function `{}` does not return a value", func.name()))
+                        .primary(e.span, "used as a value here"))
+                }
+                _ => unreachable!("only calls lack a value"),
+            },
+        }
+    }
+
+    /// Check that `e` has type `expected`; `what` names the value in the message.
+    fn expect(&mut self, e: &mut Expr, expected: Type, what: &str) -> CkResult<()> {
+        let found = self.value(e)?;
+        if found != expected {
+            return Err(Diagnostic::error(format!("mismatched types: {} expects {}, found {}", what, expected, found))
+                .primary(e.span, format!("expected {}, found {}", expected, found)));
+        }
+        Ok(())
+    }
+
+    /// Check the operands of a binary operator: both must have the same type,
+    /// one that `accepts` allows. Returns that type.
+    ///
+    /// Numbers are never converted implicitly, so `1 + 2i64` is an error.
+    fn operands(&mut self, a: &mut Expr, b: &mut Expr, accepts: fn(&Type) -> bool) -> CkResult<Type> {
+        let ta = self.value(a)?;
+        let tb = self.value(b)?;
+        if !accepts(&ta) {
+            // report the left side against what the right side suggests
+            let expected = if accepts(&tb) { tb } else { Type::Int };
+            return Err(self.mismatch(a, expected, ta));
+        }
+        if ta != tb {
+            let mut d = self.mismatch(b, ta.clone(), tb.clone());
+            if ta.is_numeric() && tb.is_numeric() {
+                d = d.note(format!("numbers are not converted implicitly; use `as {}` to convert", ta));
+            } else if ta == Type::Str || tb == Type::Str {
+                d = d.note("values are not converted to strings implicitly; interpolate them instead, e.g. `\"n = {n}\"`");
+            }
+            return Err(d);
+        }
+        Ok(ta)
+    }
+
+    /// Conditions and logical operands: a `bool`, or an integer that is true when non-zero.
+    fn truthy(&mut self, e: &mut Expr, what: &str) -> CkResult<()> {
+        let found = self.value(e)?;
+        if matches!(found, Type::Bool | Type::Int | Type::I64) {
+            return Ok(());
+        }
+        Err(Diagnostic::error(format!("mismatched types: {} expects bool, found {}", what, found))
+            .primary(e.span, format!("expected bool, found {}", found)))
+    }
+
+    /// "expected X, found Y" at `e`, pointing at the definition when `e` is a variable.
+    fn mismatch(&self, e: &Expr, expected: Type, found: Type) -> Diagnostic {
+        let mut d = Diagnostic::error(format!("mismatched types: expected {}, found {}", expected, found))
+            .primary(e.span, format!("expected {}, found {}", expected, found));
+        if let ExprKind::Var(name) = &e.kind {
+            if let Some(b) = self.find(name) {
+                d = d.secondary(b.span, format!("`{}` defined here", name));
+            }
+        }
+        d
+    }
+}
+
+/// A function's signature as it is written, without the body.
+fn signature(f: &Function) -> String {
+    let params: Vec<String> = f.params.iter().map(|p| format!("{}: {}", p.name.name, p.ty)).collect();
+    let ret = f.ret.as_ref().map_or(String::new(), |t| format!(" -> {}", t));
+    format!("{}fn {}({}){}", if f.public { "pub " } else { "" }, f.name.name, params.join(", "), ret)
+}
+
+/// Whether every path through `stmts` ends in a `return`.
+///
+/// Loops never count: their condition may be false on entry.
+fn always_returns(stmts: &[Stmt]) -> bool {
+    stmts.iter().any(|s| match &s.kind {
+        StmtKind::Return(_) => true,
+        StmtKind::Block(body) => always_returns(body),
+        StmtKind::If { then_body, else_body: Some(else_body), .. } => {
+            always_returns(then_body) && always_returns(else_body)
+        }
+        _ => false,
+    })
+}
+
+/// Every `let` in `stmts`, including those in nested blocks, in source order.
+fn collect_lets(stmts: &[Stmt]) -> Vec<LetSite> {
+    let mut out = Vec::new();
+    for s in stmts {
+        match &s.kind {
+            StmtKind::Let { name, .. } => out.push((name.name.clone(), name.span, s.span)),
+            StmtKind::If { then_body, else_body, .. } => {
+                out.extend(collect_lets(then_body));
+                out.extend(else_body.as_deref().map(collect_lets).unwrap_or_default());
+            }
+            StmtKind::While { body, .. } | StmtKind::For { body, .. } | StmtKind::Block(body) => out.extend(collect_lets(body)),
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Severity;
+    use crate::lexer::line_col;
+    use crate::parser::Parser;
+
+    /// Check `src` and return each diagnostic as (severity, line, col, message).
+    fn diags(src: &str) -> Vec<(Severity, usize, usize, String)> {
+        let mut program = Parser::parse(src).expect("test source must parse");
+        let diags = check(&mut program).unwrap_or_else(|d| d);
+        diags
+            .iter()
+            .map(|d| {
+                let (line, col) = line_col(src, d.span().unwrap().start);
+                (d.severity, line, col, d.message.clone())
+            })
+            .collect()
+    }
+
+    /// Just the (line, col, message) of each error.
+    fn errors(src: &str) -> Vec<(usize, usize, String)> {
+        diags(src).into_iter().filter(|d| d.0 == Severity::Error).map(|(_, l, c, m)| (l, c, m)).collect()
+    }
+
+    #[test]
+    fn annotates_every_expression_with_its_type() {
+        let mut program = Parser::parse("fn name() -> string { return \"mini\"; }\nlet n = name();\nlet x = 1 + 2 * 3;\nlet t = x < 2;\n").unwrap();
+        check(&mut program).unwrap();
+        let types: Vec<Type> = program
+            .stmts
+            .iter()
+            .map(|s| match &s.kind {
+                StmtKind::Let { expr, .. } => expr.ty(),
+                _ => panic!("expected let"),
+            })
+            .collect();
+        assert_eq!(types, vec![Type::Str, Type::Int, Type::Bool]);
+        let StmtKind::Let { expr, .. } = &program.stmts[1].kind else { unreachable!() };
+        let ExprKind::Add(_, rhs) = &expr.kind else { panic!("expected `+`") };
+        assert_eq!(rhs.ty, Some(Type::Int));
+    }
+
+    #[test]
+    fn reports_every_type_error_in_source_order() {
+        let src = "fn two(a: int, b: int) -> int {\n  return a;\n}\nlet s = \"hi\";\nlet x = two(1);\nlet y = s + 1;\nprintln z;\nwhile s { }\nprintln x;\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (5, 9, "function `two` expects 2 argument(s), found 1".to_string()),
+                (6, 13, "mismatched types: expected string, found int".to_string()),
+                (7, 9, "undefined variable `z`".to_string()),
+                (8, 7, "mismatched types: condition expects bool, found string".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn numbers_of_different_types_need_explicit_conversion() {
+        let src = "let a = 1;\nlet b = 2i64;\nlet c = a + b;\nlet d = a as i64 + b;\nlet e = 1.5 < a;\nlet f = d as f64 / 2.0;\nlet g = a as bool;\nlet h = \"s\" as int;\nlet i = -true;\nlet j = (a < 2) as int + a;\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (3, 13, "mismatched types: expected int, found i64".to_string()),
+                (5, 15, "mismatched types: expected f64, found int".to_string()),
+                (7, 9, "cannot convert int to bool with `as`".to_string()),
+                (8, 9, "cannot convert string to int with `as`".to_string()),
+                (9, 10, "mismatched types: expected int, found bool".to_string()),
+            ]
+        );
+        let mut program = Parser::parse("let x = 3 as f64 * 1.5;\nlet y = !(x > 1.0) || false;\nlet z = 7i64 / 2i64;\n").unwrap();
+        check(&mut program).unwrap();
+        let types: Vec<Type> = program.stmts.iter().map(|s| match &s.kind {
+            StmtKind::Let { expr, .. } => expr.ty(),
+            _ => unreachable!(),
+        }).collect();
+        assert_eq!(types, vec![Type::F64, Type::Bool, Type::I64]);
+    }
+
+    #[test]
+    fn strings_support_concatenation_comparison_and_len_only() {
+        let src = "let s = \"a\" + \"b\";\nlet t = s < \"c\" && s != \"d\";\nlet n = len(s) + 1;\nlet i = \"n = {n}, t = {t}\";\nlet bad = s - \"b\";\nlet m = len(n);\nlet k = len(s, s);\nlet j = s + n;\nfn len(x: int) { }\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (5, 11, "mismatched types: expected int, found string".to_string()),
+                (6, 13, "mismatched types: argument 1 of `len` expects a string or an array, found int".to_string()),
+                (7, 9, "function `len` expects 1 argument(s), found 2".to_string()),
+                (8, 13, "mismatched types: expected string, found int".to_string()),
+                (9, 4, "`len` is a built-in function".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn detects_use_before_definition() {
+        assert_eq!(
+            errors("println x;\nlet x = 1;\nlet y = y + 1;\nif 1 { let z = 2; }\nprintln z;\n"),
+            vec![
+                (1, 9, "cannot use `x` before it is defined".to_string()),
+                (3, 9, "`y` is used in its own definition".to_string()),
+                (5, 9, "undefined variable `z`".to_string()),
+            ]
+        );
+        // functions only see their own parameters and locals
+        let got = diags("let g = 1;\nfn f() -> int { return g; }\n");
+        assert_eq!(got[0].3, "undefined variable `g`");
+    }
+
+    #[test]
+    fn detects_redeclarations() {
+        let src = "fn f(a: int, a: int) { }\nfn f() { }\nfn main() { }\nlet x = 1;\nlet x = \"one\";\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (1, 14, "parameter `a` is declared more than once".to_string()),
+                (2, 4, "function `f` is declared more than once".to_string()),
+                (3, 4, "`main` is reserved".to_string()),
+                (5, 5, "`x` is already defined as int in this scope".to_string()),
+            ]
+        );
+        // a `let` may shadow a variable of any type from an inner block, but not in the same scope
+        assert!(errors("let x = 1;\n{ let x = \"s\"; println x; }\n{ let x = x + 1; println x; }\nprintln x;\n").is_empty());
+        assert_eq!(errors("let x = 1;\nlet x = x + 1;\n"), vec![(2, 5, "`x` is already defined as int in this scope".to_string())]);
+        // a function body may shadow its parameters
+        assert!(errors("fn f(n: int) -> int { let mut n = n; n += 1; return n; }\n").is_empty());
+    }
+
+    #[test]
+    fn assignments_need_a_mutable_variable_of_the_same_type() {
+        let src = "let a = 1;\na = 2;\nlet mut b = 1;\nb = 2;\nb += 3;\nb = 2.5;\nb -= 1i64;\nc = 1;\nfn f(n: int) { n = 0; }\n{ let mut a = 0; a = 1; }\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (2, 1, "cannot assign to immutable variable `a`".to_string()),
+                (6, 5, "mismatched types: assignment to `b` expects int, found f64".to_string()),
+                (7, 6, "mismatched types: expected int, found i64".to_string()),
+                (8, 1, "undefined variable `c`".to_string()),
+                (9, 16, "cannot assign to immutable variable `n`".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn arrays_hold_ints_and_change_only_through_mutable_variables() {
+        let src = "let a = [1, 2];\na[0] = 3;\npush(a, 4);\nlet mut b = [0; 3];\nb[1] += a[0];\npush(b, len(a));\nlet c = [1, true];\nlet d = b[1.5];\nlet e = a == b;\nfor x in \"ab\" { }\nfor i in 0..2i64 { i = 1; }\nlet s = \"{a}\";\nlet n = push(b, 1);\nlet f = 3[0];\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (2, 1, "cannot assign to an element of immutable variable `a`".to_string()),
+                (3, 1, "cannot push to immutable variable `a`".to_string()),
+                (7, 13, "mismatched types: array element expects int, found bool".to_string()),
+                (8, 11, "mismatched types: array index expects int, found f64".to_string()),
+                (9, 9, "arrays cannot be compared with `==` or `!=`".to_string()),
+                (10, 10, "cannot loop over a value of type string".to_string()),
+                (11, 13, "mismatched types: expected int, found i64".to_string()),
+                (11, 20, "cannot assign to immutable variable `i`".to_string()),
+                (12, 11, "arrays cannot be interpolated into strings".to_string()),
+                (13, 9, "function `push` does not return a value".to_string()),
+                (14, 9, "cannot index into a value of type int".to_string()),
+            ]
+        );
+        let src = "let a = arg(\"0\");\nlet n = parse_int(1);\nlet s = read_line(1);\nlet t = len(args());\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (1, 13, "mismatched types: argument 1 of `arg` expects int, found string".to_string()),
+                (2, 19, "mismatched types: argument 1 of `parse_int` expects string, found int".to_string()),
+                (3, 9, "function `read_line` expects 0 argument(s), found 1".to_string()),
+                (4, 13, "mismatched types: argument 1 of `len` expects a string or an array, found int".to_string()),
+            ]
+        );
+        // the loop variable is scoped to the loop
+        assert_eq!(errors("for i in 0..3 { }\nprintln i;\n"), vec![(2, 9, "undefined variable `i`".to_string())]);
+    }
+
+    #[test]
+    fn struct_literals_name_every_field_once_and_fields_change_through_mutable_variables() {
+        let src = "struct P { x: int, y: f64 }\nlet p = P { y: 1.0, x: 2 };\np.x = 3;\nlet mut q = P { x: 1, y: 0.0 };\nq.y = 1;\nlet r = P { x: 1 };\nlet s = Q { };\nlet t = p.z;\nlet u = p == q;\nlet v = \"{p}\";\nq.x.y = 1;\nlet w = P { x: 1, x: 2, y: 0.0 };\nlet n = 1;\nlet z = n.x;\n";
+        assert_eq!(
+            errors(src),
+            vec![
+                (3, 1, "cannot assign to a field of immutable variable `p`".to_string()),
+                (5, 7, "mismatched types: assignment to `q.y` expects f64, found int".to_string()),
+                (6, 9, "missing field `y` in `P` literal".to_string()),
+                (7, 9, "undefined struct `Q`".to_string()),
+                (8, 11, "no field `z` on a value of type P".to_string()),
+                (9, 9, "structs cannot be compared with `==` or `!=`".to_string()),
+                (10, 11, "structs cannot be interpolated into strings".to_string()),
+                (11, 5, "no field `y` on a value of type int".to_string()),
+                (12, 19, "field `x` is specified more than once".to_string()),
+                (14, 11, "no field `x` on a value of type int".to_string()),
+            ]
+        );
+        assert_eq!(
+            errors("struct A { b: B }\nstruct B { a: A, n: int, n: int }\nstruct int { }\nstruct A { }\nfn f(b: B) -> int { return b.n; }\n"),
+            vec![
+                (1, 8, "recursive struct `A` has infinite size".to_string()),
+                (2, 8, "recursive struct `B` has infinite size".to_string()),
+                (2, 26, "field `n` is declared more than once".to_string()),
+                (3, 8, "`int` is a built-in type".to_string()),
+                (4, 8, "struct `A` is declared more than once".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn warns_when_a_loop_body_shadows_its_condition_variable() {
+        let got = diags("let mut i = 0;\nwhile i < 3 {\n  let i = i + 1;\n  let j = i;\n}\n");
+        assert_eq!(got, vec![(Severity::Warning, 3, 7, "`i` is shadowed inside the loop it controls".to_string())]);
+        assert!(diags("let mut i = 0;\nwhile i < 3 {\n  i += 1;\n  { let i = 5; println i; }\n}\n").is_empty());
+    }
+
+    #[test]
+    fn checks_returns_on_every_path() {
+        let src = "fn sign(n: int) -> int {\n  if n < 0 { return -1; } else if n > 0 { return 1; }\n}\nfn ok(n: int) -> int {\n  if n { return 1; } else { return 0; }\n}\n";
+        assert_eq!(
+            errors(src),
+            vec![(3, 1, "function `sign` may reach its end without returning a value of type int".to_string())]
+        );
+        // a bad `return` isn't also reported as a missing one
+        assert_eq!(errors("fn name() -> string {\n  return 1;\n}\n").len(), 1);
+    }
+
+    #[test]
+    fn warns_about_code_after_return() {
+        let got = diags("fn f(n: int) -> int {\n  if n { return 1; } else { return 2; }\n  println n;\n  return 3;\n}\n");
+        assert_eq!(got, vec![(Severity::Warning, 3, 3, "unreachable statement".to_string())]);
+    }
+}
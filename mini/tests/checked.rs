@@ -0,0 +1,130 @@
+mod common;
+
+use std::process::Command;
+
+use common::{have_linker, mini, scratch_dir, stdout};
+
+const OVERFLOW: &str = "fn scale(n: int) -> int {\n    return n * 65536;\n}\nlet a = scale(2);\nprintln a;\nlet b = scale(65536);\nprintln b;\n";
+
+/// Write `src` to `name` in a fresh scratch directory for `test`.
+fn source(test: &str, name: &str, src: &str) -> std::path::PathBuf {
+    let path = scratch_dir(test).join(name);
+    std::fs::write(&path, src).unwrap();
+    path
+}
+
+#[test]
+fn checked_builds_stop_at_the_failing_expression() {
+    if !have_linker() {
+        return;
+    }
+    let src = source("checked", "scale.mini", OVERFLOW);
+    let exe = src.with_extension("");
+    // checks are on by default at -O0, and on request when optimizing
+    for args in [&["-O0"][..], &["-O2", "--checked"], &["--unchecked", "--checked"]] {
+        let mut cmd = vec!["build", src.to_str().unwrap(), "-o", exe.to_str().unwrap()];
+        cmd.extend(args);
+        stdout(&mini(&cmd));
+        let out = Command::new(&exe).output().unwrap();
+        assert_eq!(out.status.code(), Some(101), "{:?}", args);
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "131072\n");
+        assert_eq!(String::from_utf8_lossy(&out.stderr), "error: attempt to multiply with overflow\n --> ".to_string() + src.to_str().unwrap() + ":2:12\n");
+    }
+}
+
+#[test]
+fn division_by_zero_is_checked_for_every_integer_type() {
+    if !have_linker() {
+        return;
+    }
+    let cases = [
+        ("let z = 0;\nlet q = 1 / z;\n", "attempt to divide by zero"),
+        ("let z = 0i64;\nlet q = 1i64 / z;\n", "attempt to divide by zero"),
+        ("let m = -2147483648;\nlet q = m / -1;\n", "attempt to divide with overflow"),
+        ("let m = -9223372036854775807i64 - 1i64;\nlet n = -m;\n", "attempt to negate with overflow"),
+    ];
+    for (i, (program, message)) in cases.iter().enumerate() {
+        let src = source(&format!("checked-div-{}", i), "div.mini", program);
+        let exe = src.with_extension("");
+        stdout(&mini(&["build", src.to_str().unwrap(), "-o", exe.to_str().unwrap()]));
+        let out = Command::new(&exe).output().unwrap();
+        assert_eq!(out.status.code(), Some(101));
+        let stderr = String::from_utf8_lossy(&out.stderr);
+        assert!(stderr.starts_with(&format!("error: {}\n", message)), "{}", stderr);
+        assert!(stderr.contains("div.mini:2:9"), "{}", stderr);
+    }
+}
+
+#[test]
+fn unchecked_and_optimized_builds_wrap() {
+    if !have_linker() {
+        return;
+    }
+    let src = source("unchecked", "scale.mini", OVERFLOW);
+    let exe = src.with_extension("");
+    for args in [&["--unchecked"][..], &["-O2"], &["-O2", "--checked", "--unchecked"]] {
+        let mut cmd = vec!["build", src.to_str().unwrap(), "-o", exe.to_str().unwrap()];
+        cmd.extend(args);
+        stdout(&mini(&cmd));
+        assert_eq!(stdout(&Command::new(&exe).output().unwrap()), "131072\n0\n", "{:?}", args);
+    }
+}
+
+#[test]
+fn jit_and_interpreter_report_the_same_failure() {
+    let src = source("checked-jit", "scale.mini", OVERFLOW);
+    let jitted = mini(&["jit", src.to_str().unwrap()]);
+    let interpreted = mini(&["run", src.to_str().unwrap()]);
+    assert_eq!(jitted.status.code(), Some(101));
+    assert_eq!(interpreted.status.code(), Some(101));
+    assert_eq!(jitted.stdout, interpreted.stdout);
+    // the interpreter also quotes the source line; the first two lines agree
+    let head = |out: &std::process::Output| String::from_utf8_lossy(&out.stderr).lines().take(2).collect::<Vec<_>>().join("\n");
+    assert_eq!(head(&jitted), head(&interpreted));
+    assert_eq!(stdout(&mini(&["jit", "--unchecked", src.to_str().unwrap()])), "131072\n0\n");
+}
+
+#[test]
+fn strings_stop_the_program_when_the_heap_runs_out() {
+    // each round doubles the string: 2^25 bytes no longer fit in 32 MiB
+    let src = source("heap", "double.mini", "let mut s = \"x\";\nlet mut i = 0;\nwhile i < 30 {\n    s += s;\n    i += 1;\n}\nlet n = len(s);\nprintln n;\n");
+    let message = "error: out of memory: strings and arrays have used up the 32 MiB heap\n";
+    let mut runs = vec![mini(&["jit", src.to_str().unwrap()])];
+    if have_linker() {
+        let exe = src.with_extension("");
+        stdout(&mini(&["build", src.to_str().unwrap(), "-o", exe.to_str().unwrap()]));
+        runs.push(Command::new(&exe).output().unwrap());
+    }
+    for out in runs {
+        assert_eq!(out.status.code(), Some(101));
+        assert_eq!(String::from_utf8_lossy(&out.stderr), message);
+    }
+    // the interpreter counts string bytes the same way and points at the culprit
+    let interpreted = mini(&["run", src.to_str().unwrap()]);
+    let stderr = String::from_utf8_lossy(&interpreted.stderr);
+    assert!(stderr.starts_with(message) && stderr.contains("double.mini:4:5"), "{}", stderr);
+}
+
+#[test]
+fn out_of_bounds_indexing_stops_every_build() {
+    let src = source("bounds", "bounds.mini", "let mut a = [1, 2, 3];\npush(a, 4);\nlet i = len(a);\nprintln a;\na[i - 5] = 0;\n");
+    let message = format!("error: index out of bounds: the length is 4 but the index is -1\n --> {}:5:1\n", src.display());
+    let mut runs = vec![mini(&["jit", src.to_str().unwrap()]), mini(&["jit", "--unchecked", src.to_str().unwrap()])];
+    // unlike arithmetic checks, bounds checks stay in unchecked and optimized builds
+    if have_linker() {
+        let exe = src.with_extension("");
+        for args in [&["-O0"][..], &["-O2"], &["--unchecked"]] {
+            let mut cmd = vec!["build", src.to_str().unwrap(), "-o", exe.to_str().unwrap()];
+            cmd.extend(args);
+            stdout(&mini(&cmd));
+            runs.push(Command::new(&exe).output().unwrap());
+        }
+    }
+    for out in runs {
+        assert_eq!(out.status.code(), Some(101));
+        assert_eq!(String::from_utf8_lossy(&out.stdout), "[1, 2, 3, 4]\n");
+        assert_eq!(String::from_utf8_lossy(&out.stderr), message);
+    }
+    let interpreted = mini(&["run", src.to_str().unwrap()]);
+    assert!(String::from_utf8_lossy(&interpreted.stderr).starts_with(&message), "{}", String::from_utf8_lossy(&interpreted.stderr));
+}
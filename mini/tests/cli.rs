@@ -0,0 +1,199 @@
+mod common;
+
+use std::process::Command;
+
+use common::{have_linker, mini, scratch_dir, stdout};
+
+const PROGRAM: &str = "fn sq(n: int) -> int { return n * n; }\nlet x = sq(7);\nprintln x;\n";
+
+/// Write `PROGRAM` into a fresh scratch dir and return (dir, source path).
+fn setup(test: &str) -> (std::path::PathBuf, String) {
+    let dir = scratch_dir(test);
+    let src = dir.join("prog.mini");
+    std::fs::write(&src, PROGRAM).unwrap();
+    (dir, src.to_str().unwrap().to_string())
+}
+
+#[test]
+fn emits_ir_bitcode_assembly_and_objects() {
+    let (dir, src) = setup("cli-emit");
+    let out = |ext: &str| dir.join(format!("prog.{}", ext)).to_str().unwrap().to_string();
+
+    stdout(&mini(&["build", &src, "--emit=ir", "-o", &out("ll")]));
+    let ir = std::fs::read_to_string(out("ll")).unwrap();
+    assert!(ir.contains("define i32 @main(i32 %0, ptr %1)"), "{}", ir);
+    assert!(ir.contains("define internal i32 @sq(i32"), "{}", ir);
+
+    stdout(&mini(&["build", &src, "--emit", "bc", "-o", &out("bc")]));
+    assert_eq!(&std::fs::read(out("bc")).unwrap()[..4], b"BC\xc0\xde");
+
+    stdout(&mini(&["build", &src, "--emit=asm", "-O2", "-o", &out("s")]));
+    let asm = std::fs::read_to_string(out("s")).unwrap();
+    assert!(asm.contains("main:"), "{}", asm);
+
+    stdout(&mini(&["build", &src, "--emit=obj", "-o", &out("o")]));
+    let obj = std::fs::read(out("o")).unwrap();
+    assert!(obj.starts_with(b"\x7fELF") || obj.starts_with(b"\xcf\xfa\xed\xfe") || obj.starts_with(b"\x64\x86"));
+}
+
+#[test]
+fn optimization_promotes_locals_and_inlines_calls() {
+    let (dir, src) = setup("cli-opt");
+    let ir_at = |level: &str| {
+        let out = mini(&["build", &src, level, "--print-ir", "--emit=obj", "-o", dir.join("prog.o").to_str().unwrap()]);
+        stdout(&out);
+        String::from_utf8(out.stderr).unwrap()
+    };
+
+    let unoptimized = ir_at("-O0");
+    assert!(unoptimized.contains("alloca i32"), "{}", unoptimized);
+    assert!(unoptimized.contains("call i32 @sq("), "{}", unoptimized);
+
+    for level in ["-O1", "-O2", "-O3"] {
+        let optimized = ir_at(level);
+        assert!(!optimized.contains("alloca"), "{}: {}", level, optimized);
+        assert!(!optimized.contains("call i32 @sq("), "{}: {}", level, optimized);
+        assert!(optimized.contains("i32 49)"), "{}: {}", level, optimized);
+    }
+}
+
+#[test]
+fn default_output_name_follows_the_input_and_emit_kind() {
+    let (dir, src) = setup("cli-default-name");
+    let out = Command::new(env!("CARGO_BIN_EXE_mini"))
+        .args(["build", &src, "--emit=ir"])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(stdout(&out), "Built prog.ll\n");
+    assert!(dir.join("prog.ll").exists());
+}
+
+#[test]
+fn executables_leave_no_stray_object_files_unless_asked() {
+    if !have_linker() {
+        return;
+    }
+    let (dir, src) = setup("cli-temps");
+    let exe = dir.join("prog");
+    stdout(&mini(&["build", &src, "-o", exe.to_str().unwrap()]));
+    assert_eq!(stdout(&Command::new(&exe).output().unwrap()), "49\n");
+    assert!(!dir.join("prog.o").exists());
+
+    stdout(&mini(&["build", &src, "-O3", "--keep-temps", "-o", exe.to_str().unwrap()]));
+    assert_eq!(stdout(&Command::new(&exe).output().unwrap()), "49\n");
+    assert!(dir.join("prog.o").exists());
+}
+
+#[test]
+fn check_reports_errors_and_produces_nothing() {
+    let (dir, src) = setup("cli-check");
+    let out = mini(&["check", &src]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty() && out.stderr.is_empty());
+
+    let bad = dir.join("bad.mini");
+    std::fs::write(&bad, "println nope;\n").unwrap();
+    let out = mini(&["check", bad.to_str().unwrap()]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("undefined variable `nope`"));
+}
+
+#[test]
+fn long_expressions_do_not_overflow_the_stack() {
+    let dir = scratch_dir("cli-long-expr");
+    let src = dir.join("long.mini");
+    std::fs::write(&src, format!("let y = 1;\nprintln {};\n", vec!["y"; 4000].join(" + "))).unwrap();
+    let src = src.to_str().unwrap();
+
+    stdout(&mini(&["check", src]));
+    assert_eq!(stdout(&mini(&["run", src])), "4000\n");
+    assert_eq!(stdout(&mini(&["jit", src])), "4000\n");
+    let ll = dir.join("long.ll");
+    stdout(&mini(&["build", src, "--emit=ir", "-o", ll.to_str().unwrap()]));
+    assert!(std::fs::read_to_string(ll).unwrap().contains("define i32 @main"));
+}
+
+#[test]
+fn fmt_prints_the_canonical_layout() {
+    let (_, src) = setup("cli-fmt");
+    let want = "fn sq(n: int) -> int {\n    return n * n;\n}\n\nlet x = sq(7);\nprintln x;\n";
+    assert_eq!(stdout(&mini(&["fmt", &src])), want);
+}
+
+#[test]
+fn fmt_check_fails_on_unformatted_files() {
+    let (dir, src) = setup("cli-fmt-check");
+    let out = mini(&["fmt", "--check", &src]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("prog.mini is not formatted: line 1 differs"), "{}", String::from_utf8_lossy(&out.stderr));
+
+    let tidy = dir.join("tidy.mini");
+    std::fs::write(&tidy, "// squares\nfn sq(n: int) -> int {\n    return n * n; // n²\n}\n\nprintln sq(7);\n").unwrap();
+    let out = mini(&["fmt", "--check", tidy.to_str().unwrap()]);
+    assert!(out.status.success());
+    assert!(out.stdout.is_empty());
+}
+
+#[test]
+fn rejects_bad_options() {
+    let (_, src) = setup("cli-bad-options");
+    assert!(!mini(&["build", &src, "-O4"]).status.success());
+    assert!(!mini(&["build", &src, "--emit=wat"]).status.success());
+    let out = mini(&["build", &src, "--target", "not-a-real-target"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not-a-real-target"));
+}
+
+#[test]
+fn constants_are_folded_before_codegen() {
+    let dir = scratch_dir("cli-fold");
+    let src = dir.join("fold.mini");
+    std::fs::write(&src, "let unused = 2.0 * 4.0;\nlet b = (2 + 3) * 5 - 4 / 2;\nprintln b;\n").unwrap();
+    let ll = dir.join("fold.ll");
+    stdout(&mini(&["build", src.to_str().unwrap(), "--emit=ir", "-o", ll.to_str().unwrap()]));
+    let ir = std::fs::read_to_string(ll).unwrap();
+    assert!(ir.contains("store i32 23, ptr %b"), "{}", ir);
+    assert!(!ir.contains("with.overflow") && !ir.contains("%unused"), "{}", ir);
+
+    std::fs::write(&src, "let n = 4;\nlet q = n / (1 - 1);\n").unwrap();
+    let out = mini(&["check", src.to_str().unwrap()]);
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("error: attempt to divide by zero\n --> ") && stderr.contains("fold.mini:2:9"), "{}", stderr);
+}
+
+#[test]
+fn debug_builds_describe_functions_lines_and_variables() {
+    let (dir, src) = setup("cli-debug");
+    let obj = |flags: &[&str]| {
+        let out = dir.join("prog.o").to_str().unwrap().to_string();
+        stdout(&mini(&[&["build", &src, "--emit=obj", "-o", &out], flags].concat()));
+        std::fs::read(&out).unwrap()
+    };
+    let has = |bytes: &[u8], needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(!has(&obj(&[]), b".debug_info"));
+    let plain = obj(&["-g"]);
+    assert!(has(&plain, b".debug_info") && has(&plain, b".debug_line"));
+
+    if which::which("llvm-dwarfdump").is_err() {
+        eprintln!("skipping: no llvm-dwarfdump found");
+        return;
+    }
+    let dump = |what: &str| stdout(&Command::new("llvm-dwarfdump").args([what, dir.join("prog.o").to_str().unwrap()]).output().unwrap());
+    let row = |lines: &str, line: u32| lines.lines().any(|l| l.split_whitespace().nth(1) == Some(&line.to_string()));
+    obj(&["-g"]);
+    let info = dump("--debug-info");
+    for name in ["\"prog.mini\"", "\"sq\"", "\"main\"", "\"n\"", "\"x\"", "\"int\""] {
+        assert!(info.contains(name), "{}: {}", name, info);
+    }
+    // rows for `sq` on line 1 and `main` on lines 2 and 3
+    let lines = dump("--debug-line");
+    assert!([1, 2, 3].iter().all(|&l| row(&lines, l)), "{}", lines);
+
+    // optimized code folds `sq` into `main`, which still maps back to the source
+    obj(&["-g", "-O2"]);
+    let info = dump("--debug-info");
+    assert!(info.contains("\"main\"") && info.contains("\"x\""), "{}", info);
+    assert!(row(&dump("--debug-line"), 3));
+}
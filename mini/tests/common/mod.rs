@@ -0,0 +1,70 @@
+// Each integration test compiles this module separately and uses only some helpers.
+#![allow(dead_code)]
+
+use std::{
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+/// Run the `mini` binary built for this test run.
+pub fn mini(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_mini")).args(args).output().expect("failed to run mini")
+}
+
+/// A fresh scratch directory for one test.
+pub fn scratch_dir(test: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("mini-{}-{}", test, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Every program in `examples/`.
+pub fn examples() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut files: Vec<_> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).collect();
+    files.sort();
+    files
+}
+
+/// Native builds need a system linker; tests that link are skipped without one.
+pub fn have_linker() -> bool {
+    let found = ["gcc", "ld.lld", "ld", "link.exe"].iter().any(|l| which::which(l).is_ok());
+    if !found {
+        eprintln!("skipping: no system linker found");
+    }
+    found
+}
+
+/// A command that runs a WASI module given as its last argument: wasmtime,
+/// wasmer, or Node's built-in WASI support. Tests that need one are skipped
+/// without it.
+pub fn wasm_runtime() -> Option<Command> {
+    if which::which("wasmtime").is_ok() {
+        return Some(Command::new("wasmtime"));
+    }
+    if which::which("wasmer").is_ok() {
+        let mut cmd = Command::new("wasmer");
+        cmd.arg("run");
+        return Some(cmd);
+    }
+    if which::which("node").is_ok() {
+        let mut cmd = Command::new("node");
+        cmd.args(["--no-warnings", "-e", NODE_WASI]);
+        return Some(cmd);
+    }
+    eprintln!("skipping: no wasm runtime found");
+    None
+}
+
+const NODE_WASI: &str = "
+const { WASI } = require('node:wasi');
+const wasi = new WASI({ version: 'preview1', args: process.argv.slice(1), env: {}, returnOnExit: true });
+WebAssembly.instantiate(require('fs').readFileSync(process.argv[1]), wasi.getImportObject())
+  .then(({ instance }) => { process.exitCode = wasi.start(instance); });
+";
+
+pub fn stdout(out: &Output) -> String {
+    assert!(out.status.success(), "command failed: {}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout.clone()).unwrap()
+}
@@ -0,0 +1,83 @@
+mod common;
+
+use common::{mini, scratch_dir, stdout};
+
+const PROGRAM: &str = "fn sq(n: int) -> int { return n * n; }\nlet mut i = 0;\nwhile i < 4 { let x = sq(i); println x; i += 1; }\n";
+
+/// ELF `e_machine` values.
+const EM_X86_64: u16 = 62;
+const EM_AARCH64: u16 = 183;
+const EM_RISCV: u16 = 243;
+
+/// Build `PROGRAM` for `target` with extra `args`, returning the bytes written.
+fn build_for(test: &str, target: &str, args: &[&str]) -> Vec<u8> {
+    let dir = scratch_dir(test);
+    let src = dir.join("prog.mini");
+    std::fs::write(&src, PROGRAM).unwrap();
+    let obj = dir.join("prog.o");
+    let mut cmd = vec!["build", src.to_str().unwrap(), "--emit=obj", "--target", target, "-o", obj.to_str().unwrap()];
+    cmd.extend(args);
+    stdout(&mini(&cmd));
+    std::fs::read(obj).unwrap()
+}
+
+/// The `e_machine` of a little-endian ELF64 relocatable object.
+fn elf64_machine(obj: &[u8]) -> u16 {
+    assert_eq!(&obj[..4], b"\x7fELF", "not an ELF file");
+    assert_eq!(obj[4], 2, "not ELF64");
+    assert_eq!(obj[5], 1, "not little-endian");
+    assert_eq!(u16::from_le_bytes([obj[16], obj[17]]), 1, "not a relocatable object");
+    u16::from_le_bytes([obj[18], obj[19]])
+}
+
+fn elf64_flags(obj: &[u8]) -> u32 {
+    u32::from_le_bytes(obj[48..52].try_into().unwrap())
+}
+
+#[test]
+fn objects_for_linux_targets_have_the_right_machine_type() {
+    for (target, machine) in [
+        ("x86_64-unknown-linux-gnu", EM_X86_64),
+        ("aarch64-unknown-linux-gnu", EM_AARCH64),
+        ("riscv64-unknown-linux-gnu", EM_RISCV),
+    ] {
+        let obj = build_for(&format!("cross-{}", target), target, &[]);
+        assert_eq!(elf64_machine(&obj), machine, "{}", target);
+    }
+}
+
+#[test]
+fn wasm32_produces_a_wasm_object() {
+    let obj = build_for("cross-wasm32", "wasm32-unknown-unknown", &[]);
+    assert_eq!(&obj[..8], b"\0asm\x01\0\0\0");
+}
+
+#[test]
+fn cpu_and_features_reach_the_code_generator() {
+    // the compressed-instruction extension is recorded in the ELF header flags
+    const EF_RISCV_RVC: u32 = 1;
+    let plain = build_for("cross-rv-plain", "riscv64-unknown-linux-gnu", &[]);
+    let compressed = build_for("cross-rv-c", "riscv64-unknown-linux-gnu", &["--features=+m,+c"]);
+    assert_eq!(elf64_flags(&plain) & EF_RISCV_RVC, 0);
+    assert_eq!(elf64_flags(&compressed) & EF_RISCV_RVC, EF_RISCV_RVC);
+
+    let tuned = build_for("cross-a72", "aarch64-unknown-linux-gnu", &["--cpu", "cortex-a72", "-O2"]);
+    assert_eq!(elf64_machine(&tuned), EM_AARCH64);
+}
+
+#[test]
+fn foreign_executables_fall_back_to_objects_without_a_cross_linker() {
+    let target = "aarch64-unknown-linux-gnu";
+    if mini::link::cross_linker(target).is_some() {
+        eprintln!("skipping: a cross linker for {} is installed", target);
+        return;
+    }
+    let dir = scratch_dir("cross-fallback");
+    let src = dir.join("prog.mini");
+    std::fs::write(&src, PROGRAM).unwrap();
+    let out = mini(&["build", src.to_str().unwrap(), "--target", target, "-o", dir.join("prog").to_str().unwrap()]);
+    assert_eq!(stdout(&out), format!("Built {}\n", dir.join("prog.o").display()));
+    assert!(String::from_utf8_lossy(&out.stderr).contains("no linker for `aarch64-unknown-linux-gnu` found"));
+    assert!(!dir.join("prog").exists());
+    assert_eq!(elf64_machine(&std::fs::read(dir.join("prog.o")).unwrap()), EM_AARCH64);
+}
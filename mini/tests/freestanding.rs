@@ -0,0 +1,119 @@
+mod common;
+
+use std::process::Command;
+
+use common::{examples, mini, scratch_dir, stdout};
+
+/// ELF program header type of the dynamic loader request.
+const PT_INTERP: u32 = 3;
+
+/// Freestanding executables run only on x86_64/aarch64 Linux hosts with `ld`.
+fn can_run() -> bool {
+    let host = mini::codegen::host_triple();
+    let ok = mini::runtime::Flavor::freestanding(&host).is_some() && mini::link::static_linker(true).is_some();
+    if !ok {
+        eprintln!("skipping: freestanding executables need an x86_64/aarch64 Linux host with ld");
+    }
+    ok
+}
+
+/// Program header types of a little-endian ELF64 file.
+fn program_headers(elf: &[u8]) -> Vec<u32> {
+    let read = |at: usize, n: usize| elf[at..at + n].iter().rev().fold(0u64, |v, b| v << 8 | *b as u64) as usize;
+    let (phoff, phentsize, phnum) = (read(32, 8), read(54, 2), read(56, 2));
+    (0..phnum).map(|i| read(phoff + i * phentsize, 4) as u32).collect()
+}
+
+#[test]
+fn freestanding_executables_print_what_the_interpreter_prints() {
+    if !can_run() {
+        return;
+    }
+    let dir = scratch_dir("freestanding");
+    for example in examples() {
+        let src = example.to_str().unwrap();
+        let exe = dir.join(example.file_stem().unwrap());
+        let interpreted = stdout(&mini(&["run", src]));
+        for level in ["-O0", "-O2"] {
+            stdout(&mini(&["build", src, "--freestanding", level, "-o", exe.to_str().unwrap()]));
+            let native = stdout(&Command::new(&exe).output().unwrap());
+            assert_eq!(native, interpreted, "{} at {}", example.display(), level);
+        }
+    }
+}
+
+#[test]
+fn freestanding_executables_are_static_and_libc_free() {
+    if !can_run() {
+        return;
+    }
+    let dir = scratch_dir("freestanding-static");
+    let src = dir.join("ints.mini");
+    std::fs::write(&src, "let min = -2147483647 - 1;\nprintln min;\nlet zero = 0;\nprintln zero;\nlet s = \"\";\nprintln s;\n").unwrap();
+    let exe = dir.join("ints");
+    stdout(&mini(&["build", src.to_str().unwrap(), "--freestanding", "-o", exe.to_str().unwrap()]));
+    assert_eq!(stdout(&Command::new(&exe).output().unwrap()), "-2147483648\n0\n\n");
+
+    let elf = std::fs::read(&exe).unwrap();
+    assert_eq!(u16::from_le_bytes([elf[16], elf[17]]), 2, "not a static (ET_EXEC) executable");
+    assert!(!program_headers(&elf).contains(&PT_INTERP), "asks for a dynamic loader");
+    let has = |needle: &[u8]| elf.windows(needle.len()).any(|w| w == needle);
+    assert!(!has(b"printf") && !has(b"libc.so"));
+}
+
+#[test]
+fn freestanding_rejects_unsupported_targets() {
+    let dir = scratch_dir("freestanding-target");
+    let src = dir.join("p.mini");
+    std::fs::write(&src, "let x = 1;\nprintln x;\n").unwrap();
+    let out = mini(&["build", src.to_str().unwrap(), "--freestanding", "--target", "wasm32-wasi", "--emit=obj"]);
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("not `wasm32-wasi`"));
+
+    // cross-compiling a freestanding object needs no linker at all
+    let obj = dir.join("p.o");
+    let out = mini(&["build", src.to_str().unwrap(), "--freestanding", "--target", "aarch64-unknown-linux-gnu", "--emit=obj", "-o", obj.to_str().unwrap()]);
+    stdout(&out);
+    let bytes = std::fs::read(obj).unwrap();
+    assert_eq!(u16::from_le_bytes([bytes[18], bytes[19]]), 183);
+    assert!(bytes.windows(6).any(|w| w == b"_start"));
+}
+
+#[test]
+fn freestanding_numbers_print_like_printf() {
+    if !can_run() || !common::have_linker() {
+        return;
+    }
+    let dir = scratch_dir("freestanding-printf");
+    let src = dir.join("numbers.mini");
+    let values = [
+        "-9223372036854775808i64", "9223372036854775807i64", "0i64", "0.5", "2.5", "999999.5", "99999.95", "123456.5",
+        "1234565.0", "0.0001", "0.00001", "100000.0", "1e22", "1e23", "5e-324", "2.2250738585072014e-308",
+        "1.7976931348623157e308", "-0.0", "1e300 * 1e300", "-1e300 * 1e300",
+    ];
+    let program: String = values.iter().enumerate().map(|(i, v)| format!("let v{i} = {v};\nprintln v{i};\n")).collect();
+    std::fs::write(&src, program).unwrap();
+    let (libc, bare) = (dir.join("libc"), dir.join("bare"));
+    stdout(&mini(&["build", src.to_str().unwrap(), "-o", libc.to_str().unwrap()]));
+    stdout(&mini(&["build", src.to_str().unwrap(), "--freestanding", "-o", bare.to_str().unwrap()]));
+    let printf = stdout(&Command::new(&libc).output().unwrap());
+    assert_eq!(stdout(&Command::new(&bare).output().unwrap()), printf);
+    assert!(printf.starts_with("-9223372036854775808\n9223372036854775807\n0\n0.5\n2.5\n1e+06\n99999.9\n"), "{}", printf);
+    assert_eq!(stdout(&mini(&["run", src.to_str().unwrap()])), printf);
+}
+
+#[test]
+fn freestanding_checks_report_on_stderr() {
+    if !can_run() {
+        return;
+    }
+    let dir = scratch_dir("freestanding-checked");
+    let src = dir.join("div.mini");
+    std::fs::write(&src, "let a = 7;\nprintln a;\nlet z = a - 7;\nlet b = a / z;\n").unwrap();
+    let exe = dir.join("div");
+    stdout(&mini(&["build", src.to_str().unwrap(), "--freestanding", "-o", exe.to_str().unwrap()]));
+    let out = Command::new(&exe).output().unwrap();
+    assert_eq!(out.status.code(), Some(101));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "7\n");
+    assert_eq!(String::from_utf8_lossy(&out.stderr), format!("error: attempt to divide by zero\n --> {}:4:9\n", src.display()));
+}
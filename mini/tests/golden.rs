@@ -0,0 +1,132 @@
+mod common;
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+
+use common::{have_linker, mini, scratch_dir, stdout, wasm_runtime};
+
+/// A program in `tests/golden/`: run with the lines of `NAME.args` as its
+/// arguments and `NAME.in` on stdin (each optional), it must print exactly
+/// `NAME.out`.
+struct Golden {
+    src: PathBuf,
+    args: Vec<String>,
+    input: Vec<u8>,
+    out: String,
+}
+
+fn goldens() -> Vec<Golden> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut sources: Vec<PathBuf> = std::fs::read_dir(dir).unwrap().map(|e| e.unwrap().path()).filter(|p| p.extension().is_some_and(|e| e == "mini")).collect();
+    sources.sort();
+    sources
+        .into_iter()
+        .map(|src| {
+            let args = std::fs::read_to_string(src.with_extension("args")).map_or(vec![], |a| a.lines().map(String::from).collect());
+            let input = std::fs::read(src.with_extension("in")).unwrap_or_default();
+            let out = std::fs::read_to_string(src.with_extension("out")).unwrap();
+            Golden { src, args, input, out }
+        })
+        .collect()
+}
+
+/// Run `cmd` with `input` piped into its stdin.
+fn piped(cmd: &mut Command, input: &[u8]) -> Output {
+    let mut child = cmd.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().unwrap();
+    let mut stdin = child.stdin.take().unwrap();
+    let input = input.to_vec();
+    // written from another thread, so a program that prints as it reads can't block on a full pipe
+    let writer = std::thread::spawn(move || stdin.write_all(&input));
+    let out = child.wait_with_output().unwrap();
+    // a program may stop before reading all of its input
+    let _ = writer.join().unwrap();
+    out
+}
+
+/// `mini <command> <src> -- <args>`: options go before `--`, the program's
+/// arguments after it.
+fn mini_cmd(command: &str, src: &Path, args: &[String]) -> Command {
+    let mut cmd = Command::new(env!("CARGO_BIN_EXE_mini"));
+    cmd.args([command, src.to_str().unwrap(), "--"]).args(args);
+    cmd
+}
+
+#[test]
+fn interpreter_and_jit_print_the_golden_output() {
+    for g in goldens() {
+        for command in ["run", "jit"] {
+            assert_eq!(stdout(&piped(&mut mini_cmd(command, &g.src, &g.args), &g.input)), g.out, "{} with {}", g.src.display(), command);
+        }
+    }
+}
+
+#[test]
+fn executables_print_the_golden_output() {
+    if !have_linker() {
+        return;
+    }
+    let dir = scratch_dir("golden");
+    let host = mini::codegen::host_triple();
+    let freestanding = mini::runtime::Flavor::freestanding(&host).is_some() && mini::link::static_linker(true).is_some();
+    if !freestanding {
+        eprintln!("skipping freestanding builds: they need an x86_64/aarch64 Linux host with ld");
+    }
+    for g in goldens() {
+        let exe = dir.join(g.src.file_stem().unwrap());
+        for level in ["-O0", "-O2"] {
+            let mut flags = vec![vec![level]];
+            if freestanding {
+                flags.push(vec![level, "--freestanding"]);
+            }
+            for flags in flags {
+                let mut build = vec!["build", g.src.to_str().unwrap(), "-o", exe.to_str().unwrap()];
+                build.extend(&flags);
+                stdout(&mini(&build));
+                let out = piped(Command::new(&exe).args(&g.args), &g.input);
+                assert_eq!(stdout(&out), g.out, "{} built with {:?}", g.src.display(), flags);
+            }
+        }
+    }
+}
+
+#[test]
+fn wasm_modules_print_the_golden_output() {
+    if wasm_runtime().is_none() {
+        return;
+    }
+    let dir = scratch_dir("golden-wasm");
+    for g in goldens() {
+        let module = dir.join(g.src.file_stem().unwrap()).with_extension("wasm");
+        stdout(&mini(&["build", g.src.to_str().unwrap(), "--target", "wasm32-wasi", "-o", module.to_str().unwrap()]));
+        let out = piped(wasm_runtime().unwrap().arg(&module).args(&g.args), &g.input);
+        assert_eq!(stdout(&out), g.out, "{}", g.src.display());
+    }
+}
+
+#[test]
+fn bad_input_stops_the_program_with_the_same_error_everywhere() {
+    let dir = scratch_dir("golden-errors");
+    let src = dir.join("bad.mini");
+    std::fs::write(&src, "let n = parse_int(read_line());\nprintln n;\nprintln arg(n);\n").unwrap();
+    let exe = dir.join("bad");
+    let linked = have_linker() && mini(&["build", src.to_str().unwrap(), "-o", exe.to_str().unwrap()]).status.success();
+    let args = ["only".to_string()];
+    let cases = [
+        ("12x\n", "invalid number for `parse_int`: expected decimal digits with an optional sign", "bad.mini:1:9", ""),
+        ("99999999999\n", "number too large for `parse_int`: the result must fit in `int`", "bad.mini:1:9", ""),
+        (" 1 \n", "argument index out of bounds: the count is 1 but the index is 1", "bad.mini:3:9", "1\n"),
+    ];
+    for (input, message, at, printed) in cases {
+        let mut runs = vec![piped(&mut mini_cmd("run", &src, &args), input.as_bytes()), piped(&mut mini_cmd("jit", &src, &args), input.as_bytes())];
+        if linked {
+            runs.push(piped(Command::new(&exe).args(&args), input.as_bytes()));
+        }
+        for out in runs {
+            let stderr = String::from_utf8_lossy(&out.stderr);
+            assert!(!out.status.success(), "{:?} succeeded", input);
+            assert_eq!(String::from_utf8_lossy(&out.stdout), printed, "{:?}", input);
+            assert!(stderr.contains(&format!("error: {}", message)) && stderr.contains(at), "{:?}: {}", input, stderr);
+        }
+    }
+}
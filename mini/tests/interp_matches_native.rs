@@ -0,0 +1,37 @@
+mod common;
+
+use std::process::Command;
+
+use common::{examples, have_linker, mini, scratch_dir, stdout};
+
+#[test]
+fn interpreter_prints_what_the_compiled_program_prints() {
+    if !have_linker() {
+        return;
+    }
+    let dir = scratch_dir("interp");
+    for example in examples() {
+        let src = example.to_str().unwrap();
+        let exe = dir.join(example.file_stem().unwrap());
+        let interpreted = stdout(&mini(&["run", src]));
+        // optimization must not change what a program prints
+        for level in ["-O0", "-O2"] {
+            stdout(&mini(&["build", src, level, "-o", exe.to_str().unwrap()]));
+            let native = stdout(&Command::new(&exe).output().unwrap());
+            assert_eq!(interpreted, native, "{} at {}", example.display(), level);
+        }
+    }
+}
+
+#[test]
+fn run_reports_runtime_errors_with_source_location() {
+    let dir = scratch_dir("interp-error");
+    let src = dir.join("div.mini");
+    std::fs::write(&src, "let a = 7;\nprintln a;\nlet z = a - 7;\nlet b = a / z;\n").unwrap();
+    let out = mini(&["run", src.to_str().unwrap()]);
+    assert_eq!(out.status.code(), Some(101));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "7\n");
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("error: attempt to divide by zero"), "{}", stderr);
+    assert!(stderr.contains("div.mini:4:9"), "{}", stderr);
+}
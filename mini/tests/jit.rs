@@ -0,0 +1,37 @@
+mod common;
+
+use common::{examples, mini, scratch_dir, stdout};
+
+#[test]
+fn jit_runs_every_example_without_a_linker() {
+    for example in examples() {
+        let src = example.to_str().unwrap();
+        let jitted = stdout(&mini(&["jit", src]));
+        let interpreted = stdout(&mini(&["run", src]));
+        assert_eq!(jitted, interpreted, "{}", example.display());
+    }
+}
+
+#[test]
+fn jit_output_is_captured_in_order() {
+    let dir = scratch_dir("jit");
+    let src = dir.join("count.mini");
+    std::fs::write(
+        &src,
+        "fn tri(n: int) -> int { if n == 0 { return 0; } return n + tri(n - 1); }\n\
+         let mut i = 1;\nwhile i <= 3 { let t = tri(i * 100); println t; i += 1; }\n",
+    )
+    .unwrap();
+    assert_eq!(stdout(&mini(&["jit", src.to_str().unwrap()])), "5050\n20100\n45150\n");
+}
+
+#[test]
+fn jit_rejects_ill_typed_programs_before_running() {
+    let dir = scratch_dir("jit-error");
+    let src = dir.join("bad.mini");
+    std::fs::write(&src, "let s = \"a\";\nprintln s;\nlet n = s * 2;\n").unwrap();
+    let out = mini(&["jit", src.to_str().unwrap()]);
+    assert!(!out.status.success());
+    assert!(out.stdout.is_empty());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("bad.mini:3:9"));
+}
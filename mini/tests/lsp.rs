@@ -0,0 +1,81 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Frame `msg` the way LSP clients do.
+fn frame(msg: &str) -> String {
+    format!("Content-Length: {}\r\n\r\n{}", msg.len(), msg)
+}
+
+/// Feed `script` to `mini lsp` on stdin and return everything it wrote.
+fn serve(script: &[String]) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_mini"))
+        .arg("lsp")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("failed to run mini lsp");
+    let mut stdin = child.stdin.take().unwrap();
+    for msg in script {
+        stdin.write_all(frame(msg).as_bytes()).unwrap();
+    }
+    drop(stdin);
+    let out = child.wait_with_output().unwrap();
+    assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+    String::from_utf8(out.stdout).unwrap()
+}
+
+#[test]
+fn serves_a_scripted_editor_session_over_stdio() {
+    let uri = "file:///tmp/prog.mini";
+    let script = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#.to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"{}","languageId":"mini","version":1,"text":"let n = 6 * 7;\nprintln m;\n"}}}}}}"#,
+            uri
+        ),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didChange","params":{{"textDocument":{{"uri":"{}","version":2}},"contentChanges":[{{"text":"let n = 6 * 7;\nprintln n;\n"}}]}}}}"#,
+            uri
+        ),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{{"textDocument":{{"uri":"{}"}},"position":{{"line":1,"character":8}}}}}}"#,
+            uri
+        ),
+        format!(
+            r#"{{"jsonrpc":"2.0","id":3,"method":"textDocument/definition","params":{{"textDocument":{{"uri":"{}"}},"position":{{"line":1,"character":8}}}}}}"#,
+            uri
+        ),
+        r#"{"jsonrpc":"2.0","id":4,"method":"shutdown"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string(),
+    ];
+
+    let out = serve(&script);
+    let replies: Vec<&str> = out.split("Content-Length: ").skip(1).map(|m| m.split_once("\r\n\r\n").unwrap().1).collect();
+    assert_eq!(replies.len(), 6, "{}", out);
+    assert!(replies[0].contains(r#""hoverProvider":true"#), "{}", replies[0]);
+    // the typo is reported, then cleared once fixed
+    assert!(replies[1].contains("undefined variable `m`"), "{}", replies[1]);
+    assert!(replies[2].contains(r#""diagnostics":[]"#), "{}", replies[2]);
+    assert!(replies[3].contains("let n: int"), "{}", replies[3]);
+    assert!(replies[4].contains(r#""range":{"end":{"character":5,"line":0},"start":{"character":4,"line":0}}"#), "{}", replies[4]);
+    assert_eq!(replies[5], r#"{"id":4,"jsonrpc":"2.0","result":null}"#);
+}
+
+#[test]
+fn checks_long_expressions_without_overflowing_the_stack() {
+    let text = format!("let y = 1;\\nprintln {};\\n", vec!["y"; 4000].join(" + "));
+    let script = [
+        r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"capabilities":{}}}"#.to_string(),
+        format!(
+            r#"{{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{{"textDocument":{{"uri":"file:///tmp/long.mini","languageId":"mini","version":1,"text":"{}"}}}}}}"#,
+            text
+        ),
+        r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#.to_string(),
+        r#"{"jsonrpc":"2.0","method":"exit"}"#.to_string(),
+    ];
+
+    let out = serve(&script);
+    assert!(out.contains(r#""diagnostics":[]"#), "{}", out);
+}
@@ -0,0 +1,102 @@
+mod common;
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+use common::{have_linker, mini, scratch_dir, stdout};
+
+/// Write each `(path, source)` of a program under a fresh scratch dir.
+fn program(test: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = scratch_dir(test);
+    for (path, src) in files {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, src).unwrap();
+    }
+    dir
+}
+
+fn path(dir: &Path, file: &str) -> String {
+    dir.join(file).to_str().unwrap().to_string()
+}
+
+/// The stderr of a command that must fail.
+fn stderr(out: &Output) -> String {
+    assert!(!out.status.success(), "command succeeded: {}", String::from_utf8_lossy(&out.stdout));
+    String::from_utf8(out.stderr.clone()).unwrap()
+}
+
+const MAIN: &str = "import \"geometry.mini\";\n\nlet a = geometry::Point { x: 1, y: 2 };\nprintln geometry::dist2(a, geometry::Point { x: 4, y: 6 });\n";
+const GEOMETRY: &str = "\
+import \"util.mini\";
+
+pub struct Point {
+    x: int,
+    y: int,
+}
+
+pub fn dist2(a: Point, b: Point) -> int {
+    return util::sq(a.x - b.x) + util::sq(a.y - b.y);
+}
+
+println \"only when geometry.mini is the program\";
+";
+const UTIL: &str = "pub fn sq(n: int) -> int {\n    return n * n;\n}\n";
+
+#[test]
+fn runs_and_builds_programs_of_several_files() {
+    let dir = program("modules-run", &[("main.mini", MAIN), ("geometry.mini", GEOMETRY), ("util.mini", UTIL)]);
+    let main = path(&dir, "main.mini");
+    // an imported file's top-level statements don't run
+    assert_eq!(stdout(&mini(&["run", &main])), "25\n");
+    assert_eq!(stdout(&mini(&["jit", &main])), "25\n");
+    assert_eq!(stdout(&mini(&["run", &path(&dir, "geometry.mini")])), "only when geometry.mini is the program\n");
+    if have_linker() {
+        let exe = path(&dir, "main");
+        stdout(&mini(&["build", &main, "-g", "-o", &exe]));
+        assert_eq!(stdout(&Command::new(&exe).output().unwrap()), "25\n");
+    }
+}
+
+#[test]
+fn finds_imports_on_the_search_path() {
+    let dir = program("modules-search", &[("app/main.mini", MAIN), ("app/geometry.mini", GEOMETRY), ("lib/util.mini", UTIL)]);
+    let main = path(&dir, "app/main.mini");
+    let err = stderr(&mini(&["check", &main]));
+    assert!(err.contains("error: cannot find `util.mini`"), "{}", err);
+    assert!(err.contains("geometry.mini:1:8"), "{}", err);
+
+    assert_eq!(stdout(&mini(&["run", "-I", &path(&dir, "lib"), &main])), "25\n");
+    let with_env = Command::new(env!("CARGO_BIN_EXE_mini")).args(["run", &main]).env("MINI_PATH", path(&dir, "lib")).output().unwrap();
+    assert_eq!(stdout(&with_env), "25\n");
+}
+
+#[test]
+fn only_pub_items_can_be_used_from_other_files() {
+    let util = "fn sq(n: int) -> int {\n    return n * n;\n}\n";
+    let dir = program("modules-private", &[("main.mini", "import \"util.mini\";\nprintln util::sq(3);\nprintln util::cube(3);\n"), ("util.mini", util)]);
+    let err = stderr(&mini(&["check", &path(&dir, "main.mini")]));
+    assert!(err.contains("error: function `sq` is private to module `util`"), "{}", err);
+    // the definition is shown in its own file
+    assert!(err.contains("::: ") && err.contains("util.mini:1:4"), "{}", err);
+    assert!(err.contains("declared here without `pub`"), "{}", err);
+    assert!(err.contains("error: module `util` has no function named `cube`"), "{}", err);
+
+    let dir = program("modules-unimported", &[("main.mini", "println util::sq(3);\n")]);
+    let err = stderr(&mini(&["check", &path(&dir, "main.mini")]));
+    assert!(err.contains("error: no module named `util` is imported"), "{}", err);
+}
+
+#[test]
+fn rejects_import_cycles_and_clashing_names() {
+    let dir = program("modules-cycle", &[("a.mini", "import \"b.mini\";\n"), ("b.mini", "import \"c.mini\";\n"), ("c.mini", "import \"a.mini\";\n")]);
+    let err = stderr(&mini(&["check", &path(&dir, "a.mini")]));
+    assert!(err.contains("error: import cycle: `a.mini` imports `b.mini`, which imports `c.mini`, which imports `a.mini`"), "{}", err);
+    assert!(err.contains("c.mini:1:1"), "{}", err);
+
+    let files = [("main.mini", "import \"util.mini\";\nimport \"lib/util.mini\";\nimport \"util.mini\";\n"), ("util.mini", ""), ("lib/util.mini", "")];
+    let dir = program("modules-clash", &files);
+    let err = stderr(&mini(&["check", &path(&dir, "main.mini")]));
+    assert!(err.contains("is a different file from the `util` already imported"), "{}", err);
+    assert!(err.contains("warning: `util.mini` is imported more than once"), "{}", err);
+}
@@ -0,0 +1,87 @@
+mod common;
+
+use common::{examples, mini, scratch_dir, stdout, wasm_runtime};
+
+/// Build `src` as a WASI module and run it, or `None` if the tools are missing.
+fn run_wasi(dir: &std::path::Path, src: &std::path::Path) -> Option<String> {
+    if mini::link::wasm_linker().is_none() {
+        eprintln!("skipping: no wasm linker found");
+        return None;
+    }
+    let mut runtime = wasm_runtime()?;
+    let module = dir.join(src.file_stem().unwrap()).with_extension("wasm");
+    let built = stdout(&mini(&["build", src.to_str().unwrap(), "--target", "wasm32-wasi", "-o", module.to_str().unwrap()]));
+    assert_eq!(built, format!("Built {}\n", module.display()));
+    Some(stdout(&runtime.arg(&module).output().unwrap()))
+}
+
+#[test]
+fn wasi_modules_print_what_the_interpreter_prints() {
+    let dir = scratch_dir("wasm-examples");
+    for example in examples() {
+        let Some(wasm) = run_wasi(&dir, &example) else { return };
+        let interpreted = stdout(&mini(&["run", example.to_str().unwrap()]));
+        assert_eq!(wasm, interpreted, "{}", example.display());
+    }
+}
+
+#[test]
+fn wasi_number_printing_covers_the_edge_cases() {
+    let dir = scratch_dir("wasm-ints");
+    let src = dir.join("ints.mini");
+    let program = "let min = -2147483647 - 1;\nprintln min;\nlet max = 2147483647;\nprintln max;\nlet zero = 0;\nprintln zero;\nlet neg = -7;\nprintln neg;\nlet empty = \"\";\nprintln empty;\nlet wide = -9223372036854775808i64;\nprintln wide;\nlet x = 1e-300 / 3.0;\nprintln x;\nlet y = -2.5e15;\nprintln y;\nlet z = 0.1 as int;\nprintln z;\n";
+    std::fs::write(&src, program).unwrap();
+    let Some(out) = run_wasi(&dir, &src) else { return };
+    assert_eq!(out, "-2147483648\n2147483647\n0\n-7\n\n-9223372036854775808\n3.33333e-301\n-2.5e+15\n0\n");
+}
+
+#[test]
+fn wasi_objects_need_no_c_library() {
+    let dir = scratch_dir("wasm-obj");
+    let src = dir.join("p.mini");
+    std::fs::write(&src, "let s = \"hi\";\nprintln s;\n").unwrap();
+    let obj = dir.join("p.o");
+    stdout(&mini(&["build", src.to_str().unwrap(), "--target", "wasm32-wasi", "--emit=obj", "-o", obj.to_str().unwrap()]));
+    let bytes = std::fs::read(obj).unwrap();
+    assert!(bytes.starts_with(b"\0asm"));
+    let has = |needle: &[u8]| bytes.windows(needle.len()).any(|w| w == needle);
+    assert!(has(b"wasi_snapshot_preview1") && has(b"fd_write"));
+    assert!(!has(b"printf"));
+}
+
+#[test]
+fn wasi_checks_report_on_stderr_and_exit_with_101() {
+    if mini::link::wasm_linker().is_none() {
+        eprintln!("skipping: no wasm linker found");
+        return;
+    }
+    let Some(mut runtime) = wasm_runtime() else { return };
+    let dir = scratch_dir("wasm-checked");
+    let src = dir.join("add.mini");
+    std::fs::write(&src, "let a = 2147483647;\nprintln a;\nlet b = a + 1;\n").unwrap();
+    let module = dir.join("add.wasm");
+    stdout(&mini(&["build", src.to_str().unwrap(), "--target", "wasm32-wasi", "-o", module.to_str().unwrap()]));
+    let out = runtime.arg(&module).output().unwrap();
+    assert_eq!(out.status.code(), Some(101));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "2147483647\n");
+    assert!(String::from_utf8_lossy(&out.stderr).contains(&format!("error: attempt to add with overflow\n --> {}:3:9\n", src.display())));
+}
+
+#[test]
+fn wasi_bounds_checks_report_the_length_and_index() {
+    if mini::link::wasm_linker().is_none() {
+        eprintln!("skipping: no wasm linker found");
+        return;
+    }
+    let Some(mut runtime) = wasm_runtime() else { return };
+    let dir = scratch_dir("wasm-bounds");
+    let src = dir.join("index.mini");
+    std::fs::write(&src, "let a = [7; 3];\nprintln a;\nlet x = a[len(a)];\n").unwrap();
+    let module = dir.join("index.wasm");
+    stdout(&mini(&["build", src.to_str().unwrap(), "--target", "wasm32-wasi", "-o", module.to_str().unwrap()]));
+    let out = runtime.arg(&module).output().unwrap();
+    assert_eq!(out.status.code(), Some(101));
+    assert_eq!(String::from_utf8_lossy(&out.stdout), "[7, 7, 7]\n");
+    let message = format!("error: index out of bounds: the length is 3 but the index is 3\n --> {}:3:9\n", src.display());
+    assert!(String::from_utf8_lossy(&out.stderr).contains(&message), "{}", String::from_utf8_lossy(&out.stderr));
+}